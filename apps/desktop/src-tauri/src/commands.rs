@@ -26,6 +26,7 @@ use tracing::{error, info};
 use winfsp::host::{FileSystemHost, VolumeParams};
 
 use teleport_core::crypto::generate_join_code;
+use teleport_daemon::bandwidth::{BandwidthLimiter, BandwidthLimits};
 use teleport_daemon::bridge::FuseAsyncBridge;
 use teleport_daemon::client::{ClientConfig, WormholeClient};
 #[cfg(unix)]
@@ -34,6 +35,8 @@ use teleport_daemon::global::{
     connect_global, start_host_global, GlobalEvent, GlobalHostConfig, GlobalMountConfig,
 };
 use teleport_daemon::host::{HostConfig, WormholeHost};
+use teleport_daemon::identity::Identity;
+use teleport_daemon::share_limits::ShareLimits;
 #[cfg(windows)]
 use teleport_daemon::winfsp::WormholeWinFS;
 
@@ -113,6 +116,8 @@ pub struct AppState {
     client_handles: Mutex<HashMap<String, ClientHandle>>,
     /// Counter for auto-incrementing ports
     next_port: Mutex<u16>,
+    /// Bandwidth budgets shared by all hosts; shares are named by their ID
+    bandwidth: Arc<BandwidthLimiter>,
     /// Tokio runtime for async operations
     runtime: Runtime,
 }
@@ -123,6 +128,7 @@ impl Default for AppState {
             host_handles: Mutex::new(HashMap::new()),
             client_handles: Mutex::new(HashMap::new()),
             next_port: Mutex::new(4433),
+            bandwidth: Arc::new(BandwidthLimiter::default()),
             runtime: Runtime::new().expect("Failed to create tokio runtime"),
         }
     }
@@ -136,6 +142,18 @@ pub async fn start_hosting_with_id(
     id: String,
     path: String,
     port: Option<u16>,
+) -> Result<HostInfo, String> {
+    start_host(app, state, id, path, port, ShareLimits::default()).await
+}
+
+/// Start a host that stops by itself once `limits` are reached
+async fn start_host(
+    app: AppHandle,
+    state: State<'_, Arc<AppState>>,
+    id: String,
+    path: String,
+    port: Option<u16>,
+    limits: ShareLimits,
 ) -> Result<HostInfo, String> {
     info!("Starting host {} for path: {}", id, path);
 
@@ -186,11 +204,18 @@ pub async fn start_hosting_with_id(
         host_name: hostname::get()
             .map(|h| h.to_string_lossy().into_owned())
             .unwrap_or_else(|_| "wormhole-host".into()),
+        writable: true,
     };
 
+    let app_state = state.inner().clone();
+    let bandwidth = state.bandwidth.clone();
+
     // Spawn the host task in the runtime
     let host_task = state.runtime.spawn(async move {
-        let host = WormholeHost::new(config);
+        let host = WormholeHost::new(config)
+            .with_limits(limits)
+            .with_bandwidth_limiter(bandwidth, id_clone.clone());
+        let usage = host.usage();
 
         // Emit host started event
         let _ = app_clone.emit(
@@ -204,15 +229,30 @@ pub async fn start_hosting_with_id(
 
         info!("Host {} serving {:?} on port {}", id_clone, path_clone, port);
 
-        // This blocks until the host is stopped
-        if let Err(e) = host.serve().await {
-            error!("Host {} error: {:?}", id_clone, e);
-            let _ = app_clone.emit(
-                "host-event",
-                ServiceEvent::Error {
-                    message: format!("Host error: {:?}", e),
-                },
-            );
+        // This blocks until the host is stopped or the share expires
+        match host.serve().await {
+            Ok(()) if usage.is_expired() => {
+                info!("Share {} has expired", id_clone);
+                if let Some(h) = app_state.host_handles.lock().await.remove(&id_clone) {
+                    let _ = app_clone.emit(
+                        "share-expired",
+                        serde_json::json!({
+                            "id": id_clone,
+                            "share_path": h.info.share_path,
+                        }),
+                    );
+                }
+            }
+            Ok(()) => {}
+            Err(e) => {
+                error!("Host {} error: {:?}", id_clone, e);
+                let _ = app_clone.emit(
+                    "host-event",
+                    ServiceEvent::Error {
+                        message: format!("Host error: {:?}", e),
+                    },
+                );
+            }
         }
     });
 
@@ -239,6 +279,25 @@ pub async fn start_hosting_with_id(
     Ok(host_info)
 }
 
+/// Current bandwidth budgets for hosted shares
+#[tauri::command]
+pub async fn get_bandwidth_limits(
+    state: State<'_, Arc<AppState>>,
+) -> Result<BandwidthLimits, String> {
+    Ok(state.bandwidth.limits())
+}
+
+/// Change bandwidth budgets for hosted shares; running transfers pick them up immediately
+#[tauri::command]
+pub async fn set_bandwidth_limits(
+    state: State<'_, Arc<AppState>>,
+    limits: BandwidthLimits,
+) -> Result<(), String> {
+    info!("Setting bandwidth limits: {:?}", limits);
+    state.bandwidth.set_limits(limits);
+    Ok(())
+}
+
 /// Stop hosting by ID
 #[tauri::command]
 pub async fn stop_hosting_by_id(
@@ -326,6 +385,10 @@ pub async fn connect_to_peer(
             server_addr,
             mount_point: mount_point_clone.clone(),
             request_timeout: Duration::from_secs(30),
+            expected_fingerprint: None,
+            known_peers_path: None,
+            client_identity: Identity::default_client().ok().map(Arc::new),
+            password: None,
         };
 
         // Create a new runtime for this thread
@@ -467,6 +530,10 @@ pub async fn connect_to_peer(
             server_addr,
             mount_point: mount_point.clone(),
             request_timeout: Duration::from_secs(30),
+            expected_fingerprint: None,
+            known_peers_path: None,
+            client_identity: Identity::default_client().ok().map(Arc::new),
+            password: None,
         };
 
         // Create a new runtime for this thread
@@ -959,6 +1026,10 @@ pub async fn connect_with_code_and_id(
             server_addr,
             mount_point: mount_point_for_client.clone(),
             request_timeout: Duration::from_secs(30),
+            expected_fingerprint: None,
+            known_peers_path: None,
+            client_identity: Identity::default_client().ok().map(Arc::new),
+            password: None,
         };
 
         let rt = match Runtime::new() {
@@ -1191,6 +1262,10 @@ pub async fn connect_with_code_and_id(
             server_addr,
             mount_point: mount_point_for_client.clone(),
             request_timeout: Duration::from_secs(30),
+            expected_fingerprint: None,
+            known_peers_path: None,
+            client_identity: Identity::default_client().ok().map(Arc::new),
+            password: None,
         };
 
         let rt = match Runtime::new() {
@@ -1852,37 +1927,13 @@ pub async fn start_hosting_with_expiration(
         id, path, expires_in_ms
     );
 
-    // Start the host normally first
-    let host_info = start_hosting_with_id(app.clone(), state.clone(), id.clone(), path.clone(), port).await?;
-
-    // If expiration is set, spawn a task to auto-stop the share
+    // The host ends client sessions and emits "share-expired" itself
+    let mut limits = ShareLimits::default();
     if let Some(ms) = expires_in_ms {
-        let state_clone = state.inner().clone();
-        let app_clone = app.clone();
-        let id_clone = id.clone();
-
-        tokio::spawn(async move {
-            tokio::time::sleep(Duration::from_millis(ms)).await;
-
-            info!("Share {} has expired, stopping...", id_clone);
-
-            // Stop the host
-            let mut handles = state_clone.host_handles.lock().await;
-            if let Some(h) = handles.remove(&id_clone) {
-                h.abort_handle.abort();
-
-                // Emit expired event to frontend
-                let _ = app_clone.emit("share-expired", serde_json::json!({
-                    "id": id_clone,
-                    "share_path": h.info.share_path,
-                }));
-
-                info!("Expired share {} stopped successfully", id_clone);
-            }
-        });
+        limits = limits.with_lifetime(Duration::from_millis(ms));
     }
 
-    Ok(host_info)
+    start_host(app, state, id, path, port, limits).await
 }
 
 // === Phase 8: High-Performance Bulk Export ===
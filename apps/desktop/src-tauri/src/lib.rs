@@ -118,6 +118,9 @@ pub fn run() {
             commands::check_fuse_installed,
             // Share expiration
             commands::start_hosting_with_expiration,
+            // Bandwidth limits
+            commands::get_bandwidth_limits,
+            commands::set_bandwidth_limits,
             // Updates
             commands::check_for_updates,
             // Phase 8: High-performance bulk export
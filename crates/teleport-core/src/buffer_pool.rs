@@ -143,6 +143,30 @@ impl BufferPool {
         }
     }
 
+    /// Allocates buffers until `count` exist (at most the pool's maximum).
+    ///
+    /// Returns the address of every buffer waiting in the pool, so their memory
+    /// can be registered with the kernel. Buffers keep their address for the
+    /// life of the pool unless resized.
+    pub fn preallocate(&self, count: usize) -> Vec<*mut u8> {
+        let mut pool = self.pool.lock();
+        let target = count.min(self.max_buffers);
+        loop {
+            let current = self.allocated.load(Ordering::Relaxed);
+            if current >= target {
+                break;
+            }
+            if self
+                .allocated
+                .compare_exchange(current, current + 1, Ordering::SeqCst, Ordering::Relaxed)
+                .is_ok()
+            {
+                pool.push(vec![0u8; self.buffer_size]);
+            }
+        }
+        pool.iter_mut().map(|buffer| buffer.as_mut_ptr()).collect()
+    }
+
     /// Returns a buffer to the pool.
     fn release(&self, mut buffer: Vec<u8>) {
         // Clear buffer contents for security (optional, can be disabled for performance)
@@ -274,16 +298,13 @@ impl Deref for PooledBuffer {
     type Target = [u8];
 
     fn deref(&self) -> &Self::Target {
-        self.buffer.as_ref().map(|b| b.as_slice()).unwrap_or(&[])
+        self.buffer.as_deref().unwrap_or(&[])
     }
 }
 
 impl DerefMut for PooledBuffer {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        self.buffer
-            .as_mut()
-            .map(|b| b.as_mut_slice())
-            .unwrap_or(&mut [])
+        self.buffer.as_deref_mut().unwrap_or(&mut [])
     }
 }
 
@@ -385,6 +406,21 @@ mod tests {
         assert!((stats.hit_rate - 0.5).abs() < 0.001);
     }
 
+    #[test]
+    fn test_preallocate() {
+        let pool = BufferPool::new(3, 1024);
+        let held = pool.try_acquire().unwrap();
+
+        let addresses = pool.preallocate(8);
+        assert_eq!(pool.allocated(), 3);
+        assert_eq!(addresses.len(), 2);
+
+        // Acquired buffers are the preallocated ones
+        let buf = pool.try_acquire().unwrap();
+        assert!(addresses.contains(&(buf.as_ptr() as *mut u8)));
+        drop(held);
+    }
+
     #[test]
     fn test_bulk_pool() {
         let pool = BufferPool::new_bulk();
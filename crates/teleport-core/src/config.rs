@@ -5,9 +5,16 @@
 //! - macOS: ~/Library/Application Support/wormhole/config.toml
 //! - Linux: ~/.config/wormhole/config.toml
 //! - Windows: %APPDATA%/wormhole/config.toml
+//!
+//! Binaries build their settings with `LayeredConfig`: defaults, then the
+//! config file, then `WORMHOLE_<SECTION>_<KEY>` environment variables, then
+//! command-line flags, each layer overriding the ones before it.
 
+use std::collections::BTreeMap;
+use std::fmt;
 use std::net::{IpAddr, Ipv4Addr};
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 use directories::ProjectDirs;
 use serde::{Deserialize, Serialize};
@@ -27,6 +34,8 @@ pub struct Config {
     pub signal: SignalConfig,
     /// Network settings
     pub network: NetworkConfig,
+    /// Update check settings
+    pub update: UpdateConfig,
 }
 
 /// Host/server configuration
@@ -41,6 +50,16 @@ pub struct HostConfig {
     pub writable: bool,
     /// Auto-generate certificates if missing
     pub auto_cert: bool,
+    /// Bandwidth limit for all clients in MB/s (0 = unlimited)
+    pub bandwidth_limit_mbps: u64,
+    /// Bandwidth limit per client in MB/s (0 = unlimited)
+    pub client_bandwidth_limit_mbps: u64,
+    /// Failed handshakes from one IP before it is blocked
+    pub max_failed_attempts: u32,
+    /// Window in which failed handshakes are counted (seconds)
+    pub failed_attempt_window_secs: u64,
+    /// How long a first block lasts; repeat offenders get longer (seconds)
+    pub block_secs: u64,
 }
 
 impl Default for HostConfig {
@@ -50,6 +69,11 @@ impl Default for HostConfig {
             bind: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
             writable: false,
             auto_cert: true,
+            bandwidth_limit_mbps: 0,
+            client_bandwidth_limit_mbps: 0,
+            max_failed_attempts: 5,
+            failed_attempt_window_secs: 60,
+            block_secs: 60,
         }
     }
 }
@@ -68,6 +92,8 @@ pub struct ClientConfig {
     pub dir_ttl_secs: u64,
     /// Sync interval for dirty chunks (seconds)
     pub sync_interval_secs: u64,
+    /// Directory levels below the share root to snapshot on connect (0 = off)
+    pub snapshot_depth: u32,
 }
 
 impl Default for ClientConfig {
@@ -78,6 +104,7 @@ impl Default for ClientConfig {
             attr_ttl_secs: 1,
             dir_ttl_secs: 1,
             sync_interval_secs: 1,
+            snapshot_depth: 4,
         }
     }
 }
@@ -169,6 +196,28 @@ impl Default for NetworkConfig {
     }
 }
 
+/// Update check configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UpdateConfig {
+    /// Check for updates automatically
+    pub auto_check: bool,
+    /// Release channel: stable, beta or nightly
+    pub channel: String,
+    /// Hours between automatic checks
+    pub check_interval_hours: u64,
+}
+
+impl Default for UpdateConfig {
+    fn default() -> Self {
+        Self {
+            auto_check: true,
+            channel: "stable".into(),
+            check_interval_hours: 24,
+        }
+    }
+}
+
 impl Config {
     /// Load configuration from the default path
     pub fn load() -> Self {
@@ -189,18 +238,9 @@ impl Config {
 
     /// Load configuration from a specific path
     pub fn load_from(path: &Path) -> Result<Self, ConfigError> {
-        if !path.exists() {
-            debug!("Config file {:?} not found, using defaults", path);
-            return Ok(Self::default());
-        }
-
-        let content = std::fs::read_to_string(path).map_err(|e| ConfigError::Io(e.to_string()))?;
-
-        let config: Config =
-            toml::from_str(&content).map_err(|e| ConfigError::Parse(e.to_string()))?;
-
-        info!("Loaded config from {:?}", path);
-        Ok(config)
+        let mut layered = LayeredConfig::default();
+        layered.apply_file(path)?;
+        Ok(layered.config)
     }
 
     /// Save configuration to the default path
@@ -252,6 +292,566 @@ impl Config {
         let config = Self::default();
         toml::to_string_pretty(&config).unwrap_or_else(|_| String::new())
     }
+
+    /// Every setting, named `section.key` as in the config file
+    pub const KEYS: &'static [&'static str] = &[
+        "host.port",
+        "host.bind",
+        "host.writable",
+        "host.auto_cert",
+        "host.bandwidth_limit_mbps",
+        "host.client_bandwidth_limit_mbps",
+        "host.max_failed_attempts",
+        "host.failed_attempt_window_secs",
+        "host.block_secs",
+        "client.mount_point",
+        "client.read_ahead_chunks",
+        "client.attr_ttl_secs",
+        "client.dir_ttl_secs",
+        "client.sync_interval_secs",
+        "client.snapshot_depth",
+        "cache.max_disk_bytes",
+        "cache.max_ram_bytes",
+        "cache.cache_dir",
+        "cache.chunk_ttl_secs",
+        "cache.gc_interval_secs",
+        "signal.port",
+        "signal.bind",
+        "signal.db_path",
+        "signal.room_idle_timeout_secs",
+        "signal.max_peers_per_room",
+        "signal.public_url",
+        "network.connect_timeout_secs",
+        "network.request_timeout_secs",
+        "network.keepalive_secs",
+        "network.max_streams",
+        "network.enable_0rtt",
+        "update.auto_check",
+        "update.channel",
+        "update.check_interval_hours",
+    ];
+
+    /// Environment variable that overrides `key`, e.g. `WORMHOLE_HOST_PORT`
+    pub fn env_var(key: &str) -> String {
+        format!("WORMHOLE_{}", key.replace('.', "_").to_uppercase())
+    }
+
+    /// Value of `key` as text, or `None` for an optional setting that is unset
+    pub fn get(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        let path = |p: &Option<PathBuf>| p.as_ref().map(|p| p.display().to_string());
+        let value = match key {
+            "host.port" => self.host.port.to_string(),
+            "host.bind" => self.host.bind.to_string(),
+            "host.writable" => self.host.writable.to_string(),
+            "host.auto_cert" => self.host.auto_cert.to_string(),
+            "host.bandwidth_limit_mbps" => self.host.bandwidth_limit_mbps.to_string(),
+            "host.client_bandwidth_limit_mbps" => self.host.client_bandwidth_limit_mbps.to_string(),
+            "host.max_failed_attempts" => self.host.max_failed_attempts.to_string(),
+            "host.failed_attempt_window_secs" => self.host.failed_attempt_window_secs.to_string(),
+            "host.block_secs" => self.host.block_secs.to_string(),
+            "client.mount_point" => return Ok(path(&self.client.mount_point)),
+            "client.read_ahead_chunks" => self.client.read_ahead_chunks.to_string(),
+            "client.attr_ttl_secs" => self.client.attr_ttl_secs.to_string(),
+            "client.dir_ttl_secs" => self.client.dir_ttl_secs.to_string(),
+            "client.sync_interval_secs" => self.client.sync_interval_secs.to_string(),
+            "client.snapshot_depth" => self.client.snapshot_depth.to_string(),
+            "cache.max_disk_bytes" => self.cache.max_disk_bytes.to_string(),
+            "cache.max_ram_bytes" => self.cache.max_ram_bytes.to_string(),
+            "cache.cache_dir" => return Ok(path(&self.cache.cache_dir)),
+            "cache.chunk_ttl_secs" => self.cache.chunk_ttl_secs.to_string(),
+            "cache.gc_interval_secs" => self.cache.gc_interval_secs.to_string(),
+            "signal.port" => self.signal.port.to_string(),
+            "signal.bind" => self.signal.bind.to_string(),
+            "signal.db_path" => return Ok(path(&self.signal.db_path)),
+            "signal.room_idle_timeout_secs" => self.signal.room_idle_timeout_secs.to_string(),
+            "signal.max_peers_per_room" => self.signal.max_peers_per_room.to_string(),
+            "signal.public_url" => return Ok(self.signal.public_url.clone()),
+            "network.connect_timeout_secs" => self.network.connect_timeout_secs.to_string(),
+            "network.request_timeout_secs" => self.network.request_timeout_secs.to_string(),
+            "network.keepalive_secs" => self.network.keepalive_secs.to_string(),
+            "network.max_streams" => self.network.max_streams.to_string(),
+            "network.enable_0rtt" => self.network.enable_0rtt.to_string(),
+            "update.auto_check" => self.update.auto_check.to_string(),
+            "update.channel" => self.update.channel.clone(),
+            "update.check_interval_hours" => self.update.check_interval_hours.to_string(),
+            _ => return Err(ConfigError::UnknownKey(key.to_string())),
+        };
+        Ok(Some(value))
+    }
+
+    /// Parse `value` into `key`; an empty value unsets an optional setting
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        let value = value.trim();
+        let path = || (!value.is_empty()).then(|| PathBuf::from(value));
+        match key {
+            "host.port" => self.host.port = parse(key, value)?,
+            "host.bind" => self.host.bind = parse(key, value)?,
+            "host.writable" => self.host.writable = parse_bool(key, value)?,
+            "host.auto_cert" => self.host.auto_cert = parse_bool(key, value)?,
+            "host.bandwidth_limit_mbps" => self.host.bandwidth_limit_mbps = parse(key, value)?,
+            "host.client_bandwidth_limit_mbps" => {
+                self.host.client_bandwidth_limit_mbps = parse(key, value)?
+            }
+            "host.max_failed_attempts" => self.host.max_failed_attempts = parse(key, value)?,
+            "host.failed_attempt_window_secs" => {
+                self.host.failed_attempt_window_secs = parse(key, value)?
+            }
+            "host.block_secs" => self.host.block_secs = parse(key, value)?,
+            "client.mount_point" => self.client.mount_point = path(),
+            "client.read_ahead_chunks" => self.client.read_ahead_chunks = parse(key, value)?,
+            "client.attr_ttl_secs" => self.client.attr_ttl_secs = parse(key, value)?,
+            "client.dir_ttl_secs" => self.client.dir_ttl_secs = parse(key, value)?,
+            "client.sync_interval_secs" => self.client.sync_interval_secs = parse(key, value)?,
+            "client.snapshot_depth" => self.client.snapshot_depth = parse(key, value)?,
+            "cache.max_disk_bytes" => self.cache.max_disk_bytes = parse(key, value)?,
+            "cache.max_ram_bytes" => self.cache.max_ram_bytes = parse(key, value)?,
+            "cache.cache_dir" => self.cache.cache_dir = path(),
+            "cache.chunk_ttl_secs" => self.cache.chunk_ttl_secs = parse(key, value)?,
+            "cache.gc_interval_secs" => self.cache.gc_interval_secs = parse(key, value)?,
+            "signal.port" => self.signal.port = parse(key, value)?,
+            "signal.bind" => self.signal.bind = parse(key, value)?,
+            "signal.db_path" => self.signal.db_path = path(),
+            "signal.room_idle_timeout_secs" => {
+                self.signal.room_idle_timeout_secs = parse(key, value)?
+            }
+            "signal.max_peers_per_room" => self.signal.max_peers_per_room = parse(key, value)?,
+            "signal.public_url" => {
+                self.signal.public_url = (!value.is_empty()).then(|| value.to_string())
+            }
+            "network.connect_timeout_secs" => {
+                self.network.connect_timeout_secs = parse(key, value)?
+            }
+            "network.request_timeout_secs" => {
+                self.network.request_timeout_secs = parse(key, value)?
+            }
+            "network.keepalive_secs" => self.network.keepalive_secs = parse(key, value)?,
+            "network.max_streams" => self.network.max_streams = parse(key, value)?,
+            "network.enable_0rtt" => self.network.enable_0rtt = parse_bool(key, value)?,
+            "update.auto_check" => self.update.auto_check = parse_bool(key, value)?,
+            "update.channel" => self.update.channel = value.to_ascii_lowercase(),
+            "update.check_interval_hours" => self.update.check_interval_hours = parse(key, value)?,
+            _ => return Err(ConfigError::UnknownKey(key.to_string())),
+        }
+        Ok(())
+    }
+
+    /// Keys whose values differ between `self` and `other`
+    pub fn diff(&self, other: &Config) -> Vec<&'static str> {
+        Self::KEYS
+            .iter()
+            .copied()
+            .filter(|key| self.get(key).ok() != other.get(key).ok())
+            .collect()
+    }
+
+    /// Check settings that parse but can't work, naming the first bad key
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let at_least_one = [
+            (
+                "host.max_failed_attempts",
+                self.host.max_failed_attempts as u64,
+            ),
+            (
+                "host.failed_attempt_window_secs",
+                self.host.failed_attempt_window_secs,
+            ),
+            ("host.block_secs", self.host.block_secs),
+            ("client.sync_interval_secs", self.client.sync_interval_secs),
+            ("cache.gc_interval_secs", self.cache.gc_interval_secs),
+            (
+                "signal.room_idle_timeout_secs",
+                self.signal.room_idle_timeout_secs,
+            ),
+            (
+                "network.connect_timeout_secs",
+                self.network.connect_timeout_secs,
+            ),
+            (
+                "network.request_timeout_secs",
+                self.network.request_timeout_secs,
+            ),
+            ("network.keepalive_secs", self.network.keepalive_secs),
+            ("network.max_streams", self.network.max_streams as u64),
+            (
+                "update.check_interval_hours",
+                self.update.check_interval_hours,
+            ),
+        ];
+        for (key, value) in at_least_one {
+            if value == 0 {
+                return Err(ConfigError::invalid(key, "must be at least 1"));
+            }
+        }
+
+        if self.cache.max_ram_bytes < crate::CHUNK_SIZE as u64 {
+            return Err(ConfigError::invalid(
+                "cache.max_ram_bytes",
+                format!("must hold at least one chunk ({} bytes)", crate::CHUNK_SIZE),
+            ));
+        }
+        if self.signal.max_peers_per_room < 2 {
+            return Err(ConfigError::invalid(
+                "signal.max_peers_per_room",
+                "must be at least 2 (a host and a client)",
+            ));
+        }
+        if let Some(url) = &self.signal.public_url {
+            if !url.starts_with("ws://") && !url.starts_with("wss://") {
+                return Err(ConfigError::invalid(
+                    "signal.public_url",
+                    format!("{:?} is not a ws:// or wss:// URL", url),
+                ));
+            }
+        }
+        if !["stable", "beta", "nightly"].contains(&self.update.channel.as_str()) {
+            return Err(ConfigError::invalid(
+                "update.channel",
+                format!("{:?} is not stable, beta or nightly", self.update.channel),
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn parse<T>(key: &str, value: &str) -> Result<T, ConfigError>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    value
+        .parse()
+        .map_err(|e| ConfigError::invalid(key, format!("{:?}: {}", value, e)))
+}
+
+fn parse_bool(key: &str, value: &str) -> Result<bool, ConfigError> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "1" | "yes" | "on" => Ok(true),
+        "false" | "0" | "no" | "off" => Ok(false),
+        _ => Err(ConfigError::invalid(
+            key,
+            format!("{:?} is not a boolean (true/false)", value),
+        )),
+    }
+}
+
+/// Layer a setting's effective value came from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigSource {
+    /// Built-in default
+    #[default]
+    Default,
+    /// Config file
+    File,
+    /// `WORMHOLE_*` environment variable
+    Env,
+    /// Command-line flag
+    Cli,
+}
+
+impl fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ConfigSource::Default => "default",
+            ConfigSource::File => "file",
+            ConfigSource::Env => "env",
+            ConfigSource::Cli => "cli",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A `Config` built up in layers, remembering which layer set each value
+#[derive(Debug, Clone, Default)]
+pub struct LayeredConfig {
+    config: Config,
+    sources: BTreeMap<String, ConfigSource>,
+    path: Option<PathBuf>,
+}
+
+impl LayeredConfig {
+    /// Defaults, then the config file, then `WORMHOLE_*` environment variables
+    ///
+    /// `path` overrides the default config file location. Command-line flags
+    /// go on top with `apply_cli`, followed by `validate`.
+    pub fn load(path: Option<&Path>) -> Result<Self, ConfigError> {
+        let mut layered = Self::default();
+        if let Some(path) = path.map(Path::to_path_buf).or_else(Config::default_path) {
+            layered.apply_file(&path)?;
+            layered.path = Some(path);
+        }
+        layered.apply_env(std::env::vars())?;
+        Ok(layered)
+    }
+
+    /// Apply the settings in the TOML file at `path`, if it exists
+    pub fn apply_file(&mut self, path: &Path) -> Result<(), ConfigError> {
+        if !path.exists() {
+            debug!("Config file {:?} not found, using defaults", path);
+            return Ok(());
+        }
+
+        let content = std::fs::read_to_string(path).map_err(|e| ConfigError::Io(e.to_string()))?;
+        self.apply_toml(&content, ConfigSource::File)
+            .map_err(|e| e.context(&format!("in {}", path.display())))?;
+
+        info!("Loaded config from {:?}", path);
+        Ok(())
+    }
+
+    /// Apply every `[section]` key in the TOML text `content`
+    fn apply_toml(&mut self, content: &str, source: ConfigSource) -> Result<(), ConfigError> {
+        let table: toml::Table =
+            toml::from_str(content).map_err(|e| ConfigError::Parse(e.to_string()))?;
+
+        for (section, values) in table {
+            let toml::Value::Table(values) = values else {
+                return Err(ConfigError::invalid(&section, "expected a [section] table"));
+            };
+            for (name, value) in values {
+                let key = format!("{}.{}", section, name);
+                let value = match value {
+                    toml::Value::String(value) => value,
+                    toml::Value::Integer(_) | toml::Value::Float(_) | toml::Value::Boolean(_) => {
+                        value.to_string()
+                    }
+                    _ => {
+                        return Err(ConfigError::invalid(
+                            &key,
+                            "expected a string, number or boolean",
+                        ))
+                    }
+                };
+                self.set(&key, &value, source)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply `WORMHOLE_<SECTION>_<KEY>` variables found in `vars`
+    pub fn apply_env(
+        &mut self,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<(), ConfigError> {
+        let vars: BTreeMap<String, String> = vars.into_iter().collect();
+        for key in Config::KEYS {
+            let var = Config::env_var(key);
+            if let Some(value) = vars.get(&var) {
+                self.set(key, value, ConfigSource::Env)
+                    .map_err(|e| e.context(&format!("from {}", var)))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply command-line flags as `(key, value)`; `None` means not given
+    pub fn apply_cli<'a>(
+        &mut self,
+        flags: impl IntoIterator<Item = (&'a str, Option<String>)>,
+    ) -> Result<(), ConfigError> {
+        for (key, value) in flags {
+            if let Some(value) = value {
+                self.set(key, &value, ConfigSource::Cli)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Set `key` from `source`
+    pub fn set(&mut self, key: &str, value: &str, source: ConfigSource) -> Result<(), ConfigError> {
+        self.config.set(key, value)?;
+        self.sources.insert(key.to_string(), source);
+        Ok(())
+    }
+
+    /// Check the merged settings
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        self.config.validate()
+    }
+
+    /// The merged settings
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Config file the settings were loaded from (which may not exist)
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Layer that set `key`
+    pub fn source(&self, key: &str) -> ConfigSource {
+        self.sources.get(key).copied().unwrap_or_default()
+    }
+
+    /// Every setting with its effective value and the layer that set it
+    pub fn entries(&self) -> Vec<(&'static str, Option<String>, ConfigSource)> {
+        Config::KEYS
+            .iter()
+            .map(|key| {
+                let value = self.config.get(key).expect("KEYS are all known");
+                (*key, value, self.source(key))
+            })
+            .collect()
+    }
+}
+
+/// A config file edited in place, keeping its comments and layout
+///
+/// Edits go through `Config::set` and `Config::validate`, so the file only
+/// ever gains values the binaries will accept.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigFile {
+    doc: toml_edit::Document,
+}
+
+impl ConfigFile {
+    /// Read the file at `path`; a missing file is an empty one
+    pub fn open(path: &Path) -> Result<Self, ConfigError> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        std::fs::read_to_string(path)
+            .map_err(|e| ConfigError::Io(e.to_string()))?
+            .parse()
+    }
+
+    /// Write the file to `path`, creating its directory if needed
+    pub fn save_to(&self, path: &Path) -> Result<(), ConfigError> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| ConfigError::Io(e.to_string()))?;
+        }
+        std::fs::write(path, self.to_string()).map_err(|e| ConfigError::Io(e.to_string()))?;
+
+        info!("Saved config to {:?}", path);
+        Ok(())
+    }
+
+    /// Defaults with the file's settings on top
+    pub fn config(&self) -> Result<Config, ConfigError> {
+        Ok(self.layered()?.config)
+    }
+
+    /// Keys the file sets, in `Config::KEYS` order
+    pub fn keys(&self) -> Result<Vec<&'static str>, ConfigError> {
+        let layered = self.layered()?;
+        Ok(Config::KEYS
+            .iter()
+            .copied()
+            .filter(|key| layered.source(key) == ConfigSource::File)
+            .collect())
+    }
+
+    /// Set `key` to `value`, checking its type and range first
+    ///
+    /// An empty value unsets an optional setting. An existing entry keeps its
+    /// place and trailing comment.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        let mut config = self.config()?;
+        config.set(key, value)?;
+        config.validate()?;
+
+        let Some(value) = config.get(key)? else {
+            self.reset(key)?;
+            return Ok(());
+        };
+        let (section, name) = split_key(key)?;
+        let table = self
+            .doc
+            .as_table_mut()
+            .entry(section)
+            .or_insert(toml_edit::table())
+            .as_table_like_mut()
+            .ok_or_else(|| ConfigError::invalid(section, "expected a [section] table"))?;
+
+        let mut value = toml_value(key, value);
+        match table.get_mut(name) {
+            // Replace in place: inserting afresh would drop the comment above the key
+            Some(item) => {
+                if let Some(old) = item.as_value() {
+                    *value.decor_mut() = old.decor().clone();
+                }
+                *item = toml_edit::Item::Value(value);
+            }
+            None => {
+                table.insert(name, toml_edit::Item::Value(value));
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove `key` so it falls back to its default; false if it wasn't set
+    pub fn reset(&mut self, key: &str) -> Result<bool, ConfigError> {
+        let (section, name) = split_key(key)?;
+        Ok(self
+            .doc
+            .get_mut(section)
+            .and_then(toml_edit::Item::as_table_like_mut)
+            .and_then(|table| table.remove(name))
+            .is_some())
+    }
+
+    /// Remove every setting
+    pub fn reset_all(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Copy every setting `other` makes into this file
+    pub fn merge(&mut self, other: &ConfigFile) -> Result<(), ConfigError> {
+        let config = other.config()?;
+        for key in other.keys()? {
+            let value = config.get(key)?.unwrap_or_default();
+            self.set(key, &value)?;
+        }
+        Ok(())
+    }
+
+    fn layered(&self) -> Result<LayeredConfig, ConfigError> {
+        let mut layered = LayeredConfig::default();
+        layered.apply_toml(&self.doc.to_string(), ConfigSource::File)?;
+        Ok(layered)
+    }
+}
+
+impl FromStr for ConfigFile {
+    type Err = ConfigError;
+
+    fn from_str(content: &str) -> Result<Self, Self::Err> {
+        let doc = content
+            .parse()
+            .map_err(|e: toml_edit::TomlError| ConfigError::Parse(e.to_string()))?;
+        Ok(Self { doc })
+    }
+}
+
+impl fmt::Display for ConfigFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.doc)
+    }
+}
+
+/// `section.name` split in two, for a known key
+fn split_key(key: &str) -> Result<(&str, &str), ConfigError> {
+    match key.split_once('.') {
+        Some(parts) if Config::KEYS.contains(&key) => Ok(parts),
+        _ => Err(ConfigError::UnknownKey(key.to_string())),
+    }
+}
+
+/// Canonical text for `key` as the TOML type its section expects
+fn toml_value(key: &str, value: String) -> toml_edit::Value {
+    match key {
+        "host.writable" | "host.auto_cert" | "network.enable_0rtt" | "update.auto_check" => {
+            (value == "true").into()
+        }
+        "host.bind" | "client.mount_point" | "cache.cache_dir" | "signal.bind"
+        | "signal.db_path" | "signal.public_url" | "update.channel" => value.into(),
+        // Sizes past i64::MAX don't fit a TOML integer, but load from a string
+        _ => match value.parse::<i64>() {
+            Ok(n) => n.into(),
+            Err(_) => value.into(),
+        },
+    }
 }
 
 /// Configuration errors
@@ -265,6 +865,33 @@ pub enum ConfigError {
     Serialize(String),
     /// No config directory available
     NoConfigDir,
+    /// Setting that doesn't exist
+    UnknownKey(String),
+    /// Setting with a value it can't take
+    Invalid { key: String, message: String },
+}
+
+impl ConfigError {
+    fn invalid(key: &str, message: impl Into<String>) -> Self {
+        ConfigError::Invalid {
+            key: key.to_string(),
+            message: message.into(),
+        }
+    }
+
+    /// Say where a bad setting came from
+    fn context(self, origin: &str) -> Self {
+        match self {
+            ConfigError::UnknownKey(key) => {
+                ConfigError::UnknownKey(format!("{} ({})", key, origin))
+            }
+            ConfigError::Invalid { key, message } => ConfigError::Invalid {
+                key,
+                message: format!("{} ({})", message, origin),
+            },
+            other => other,
+        }
+    }
 }
 
 impl std::fmt::Display for ConfigError {
@@ -274,6 +901,10 @@ impl std::fmt::Display for ConfigError {
             ConfigError::Parse(e) => write!(f, "Parse error: {}", e),
             ConfigError::Serialize(e) => write!(f, "Serialization error: {}", e),
             ConfigError::NoConfigDir => write!(f, "No configuration directory available"),
+            ConfigError::UnknownKey(key) => write!(f, "Unknown configuration key: {}", key),
+            ConfigError::Invalid { key, message } => {
+                write!(f, "Invalid value for {}: {}", key, message)
+            }
         }
     }
 }
@@ -325,4 +956,176 @@ mod tests {
         let config = Config::load_from(Path::new("/nonexistent/config.toml")).unwrap();
         assert_eq!(config.host.port, 4433); // Should use defaults
     }
+
+    #[test]
+    fn test_layers_override_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(
+            &path,
+            "[host]\nport = 5000\nbind = \"127.0.0.1\"\n[cache]\ngc_interval_secs = 30\n",
+        )
+        .unwrap();
+
+        let mut layered = LayeredConfig::default();
+        layered.apply_file(&path).unwrap();
+        layered
+            .apply_env([
+                ("WORMHOLE_HOST_PORT".to_string(), "6000".to_string()),
+                ("WORMHOLE_CLIENT_ATTR_TTL_SECS".to_string(), "3".to_string()),
+                ("WORMHOLE_PASSWORD".to_string(), "not a setting".to_string()),
+            ])
+            .unwrap();
+        layered
+            .apply_cli([
+                ("host.port", Some("7000".to_string())),
+                ("host.writable", None),
+            ])
+            .unwrap();
+        layered.validate().unwrap();
+
+        let config = layered.config();
+        assert_eq!(config.host.port, 7000);
+        assert_eq!(config.host.bind, IpAddr::V4(Ipv4Addr::LOCALHOST));
+        assert_eq!(config.client.attr_ttl_secs, 3);
+        assert_eq!(config.cache.gc_interval_secs, 30);
+        assert!(!config.host.writable);
+
+        assert_eq!(layered.source("host.port"), ConfigSource::Cli);
+        assert_eq!(layered.source("host.bind"), ConfigSource::File);
+        assert_eq!(layered.source("client.attr_ttl_secs"), ConfigSource::Env);
+        assert_eq!(layered.source("host.writable"), ConfigSource::Default);
+        assert_eq!(layered.entries().len(), Config::KEYS.len());
+    }
+
+    #[test]
+    fn test_errors_name_the_key() {
+        let mut layered = LayeredConfig::default();
+        match layered.apply_env([("WORMHOLE_HOST_PORT".to_string(), "lots".to_string())]) {
+            Err(ConfigError::Invalid { key, message }) => {
+                assert_eq!(key, "host.port");
+                assert!(message.contains("WORMHOLE_HOST_PORT"));
+            }
+            other => panic!("expected an invalid host.port, got {:?}", other),
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "[host]\nprot = 5000\n").unwrap();
+        match layered.apply_file(&path) {
+            Err(ConfigError::UnknownKey(key)) => assert!(key.starts_with("host.prot")),
+            other => panic!("expected an unknown key, got {:?}", other),
+        }
+
+        let mut config = Config::default();
+        config.signal.max_peers_per_room = 1;
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::Invalid { key, .. }) if key == "signal.max_peers_per_room"
+        ));
+        config.signal.max_peers_per_room = 2;
+        config.signal.public_url = Some("https://signal.example".into());
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::Invalid { key, .. }) if key == "signal.public_url"
+        ));
+    }
+
+    #[test]
+    fn test_every_key_round_trips() {
+        let mut config = Config::default();
+        config.cache.cache_dir = Some(PathBuf::from("/var/cache/wormhole"));
+        for key in Config::KEYS {
+            let value = config.get(key).unwrap().unwrap_or_default();
+            let mut copy = Config::default();
+            copy.set(key, &value).unwrap();
+            assert_eq!(copy.get(key).unwrap(), config.get(key).unwrap(), "{}", key);
+        }
+        config.set("cache.cache_dir", "").unwrap();
+        assert_eq!(config.cache.cache_dir, None);
+        assert!(matches!(
+            config.get("host.nope"),
+            Err(ConfigError::UnknownKey(_))
+        ));
+    }
+
+    #[test]
+    fn test_config_file_edits_keep_comments() {
+        let original = "# Wormhole settings\n\n[host]\n# QUIC port\nport = 5000 # firewall rule\n";
+        let mut file: ConfigFile = original.parse().unwrap();
+
+        file.set("host.port", "6000").unwrap();
+        file.set("client.attr_ttl_secs", "3").unwrap();
+        file.set("update.channel", "Beta").unwrap();
+        let text = file.to_string();
+        assert!(text.starts_with(
+            "# Wormhole settings\n\n[host]\n# QUIC port\nport = 6000 # firewall rule\n"
+        ));
+        assert!(text.contains("[client]\nattr_ttl_secs = 3\n"));
+        assert!(text.contains("channel = \"beta\""));
+
+        let config = file.config().unwrap();
+        assert_eq!(config.host.port, 6000);
+        assert_eq!(config.client.attr_ttl_secs, 3);
+        assert_eq!(
+            file.keys().unwrap(),
+            ["host.port", "client.attr_ttl_secs", "update.channel"]
+        );
+
+        assert!(file.reset("host.port").unwrap());
+        assert!(!file.reset("host.port").unwrap());
+        assert_eq!(file.config().unwrap().host.port, 4433);
+    }
+
+    #[test]
+    fn test_config_file_rejects_bad_values() {
+        let mut file = ConfigFile::default();
+        assert!(matches!(
+            file.set("host.port", "70000"),
+            Err(ConfigError::Invalid { key, .. }) if key == "host.port"
+        ));
+        assert!(matches!(
+            file.set("network.max_streams", "0"),
+            Err(ConfigError::Invalid { key, .. }) if key == "network.max_streams"
+        ));
+        assert!(matches!(
+            file.set("host.prot", "1"),
+            Err(ConfigError::UnknownKey(_))
+        ));
+        assert!(file.to_string().is_empty());
+
+        file.set("cache.cache_dir", "/var/cache/wormhole").unwrap();
+        file.set("cache.cache_dir", "").unwrap();
+        assert!(file.keys().unwrap().is_empty());
+
+        let mut imported: ConfigFile = "[signal]\nmax_peers_per_room = 20\n".parse().unwrap();
+        imported
+            .merge(&"[host]\nwritable = true\n".parse().unwrap())
+            .unwrap();
+        let config = imported.config().unwrap();
+        assert_eq!(config.signal.max_peers_per_room, 20);
+        assert!(config.host.writable);
+        assert!(imported
+            .merge(&"[host]\nport = \"x\"\n".parse().unwrap())
+            .is_err());
+    }
+
+    #[test]
+    fn test_diff_names_changed_keys() {
+        let config = Config::default();
+        assert!(config.diff(&config.clone()).is_empty());
+
+        let mut changed = config.clone();
+        changed.host.bandwidth_limit_mbps = 10;
+        changed.client.attr_ttl_secs = 5;
+        changed.cache.cache_dir = Some(PathBuf::from("/var/cache/wormhole"));
+        assert_eq!(
+            config.diff(&changed),
+            [
+                "host.bandwidth_limit_mbps",
+                "client.attr_ttl_secs",
+                "cache.cache_dir"
+            ]
+        );
+    }
 }
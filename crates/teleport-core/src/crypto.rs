@@ -63,8 +63,11 @@ pub const WORMHOLE_BASE_URL: &str = "https://wormhole.byronwade.com";
 /// - `wormhole://join/ABC-123` (deep link)
 /// - `wormhole://j/ABC-123` (deep link short)
 /// - `wormhole://ABC-123` (deep link direct)
+///
+/// Any `?query` or `#fragment` (such as a pinned fingerprint) is ignored.
 pub fn extract_join_code(input: &str) -> Option<String> {
     let input = input.trim();
+    let input = input.split(['?', '#']).next().unwrap_or(input);
 
     // Check if it's a wormhole:// deep link
     if let Some(path) = input
@@ -131,6 +134,87 @@ pub fn make_share_link(join_code: &str) -> String {
     format!("{}/j/{}", WORMHOLE_BASE_URL, formatted)
 }
 
+/// Generate a share link that also pins the host's certificate fingerprint
+///
+/// The fingerprint goes in the URL fragment (`#fp=<hex>`), which browsers
+/// never send to the web server.
+pub fn make_pinned_share_link(join_code: &str, fingerprint: &[u8; 32]) -> String {
+    format!(
+        "{}#fp={}",
+        make_share_link(join_code),
+        blake3::Hash::from(*fingerprint).to_hex()
+    )
+}
+
+/// Extract a pinned certificate fingerprint (`fp=<hex>`) from a share link
+pub fn extract_fingerprint(input: &str) -> Option<[u8; 32]> {
+    let (_, params) = input.trim().split_once(['?', '#'])?;
+    params
+        .split(['&', '#', '?'])
+        .find_map(|param| param.strip_prefix("fp="))
+        .and_then(|hex| blake3::Hash::from_hex(hex).ok())
+        .map(|hash| *hash.as_bytes())
+}
+
+/// Size of the random salt in a LAN join code hash
+pub const LAN_SALT_SIZE: usize = 16;
+
+/// Size of a LAN join code hash
+pub const LAN_HASH_SIZE: usize = 2;
+
+/// Hash a join code for advertising on the local network
+///
+/// LAN announcements carry this instead of the code itself so a client that
+/// already knows the code can pick out the matching host. Join codes are
+/// short enough to brute-force, so the hash is salted per announcement and
+/// cut to `LAN_HASH_SIZE` bytes: thousands of codes share each value, and an
+/// announcement does not give its code away. It is a filter, not proof that
+/// the announcer knows the code.
+pub fn join_code_lan_hash(join_code: &str, salt: &[u8; LAN_SALT_SIZE]) -> [u8; LAN_HASH_SIZE] {
+    let normalized = normalize_join_code(join_code);
+    let mut hasher = Hasher::new_derive_key("wormhole 2024 lan join code");
+    hasher.update(salt);
+    hasher.update(normalized.as_bytes());
+    let mut out = [0u8; LAN_HASH_SIZE];
+    out.copy_from_slice(&hasher.finalize().as_bytes()[..LAN_HASH_SIZE]);
+    out
+}
+
+/// Size of the nonce a host sends to challenge a share password
+pub const PASSWORD_NONCE_SIZE: usize = 32;
+
+/// TLS exporter label for the channel binding mixed into password proofs
+pub const PASSWORD_CHANNEL_LABEL: &[u8] = b"EXPORTER-wormhole-share-password";
+
+/// Answer a host's password challenge
+///
+/// The password itself never goes over the wire: the proof is a keyed hash
+/// of the host's random nonce and `channel`, keying material exported from
+/// this TLS session under `PASSWORD_CHANNEL_LABEL`. Both ends of a relayed
+/// connection derive different material, so a proof captured by a
+/// man-in-the-middle does not verify on its own session with the host.
+pub fn password_proof(
+    password: &str,
+    nonce: &[u8; PASSWORD_NONCE_SIZE],
+    channel: &[u8; 32],
+) -> [u8; 32] {
+    let key = blake3::derive_key("wormhole 2024 share password", password.as_bytes());
+    let mut hasher = Hasher::new_keyed(&key);
+    hasher.update(nonce);
+    hasher.update(channel);
+    *hasher.finalize().as_bytes()
+}
+
+/// Check a password proof (constant time)
+pub fn verify_password_proof(
+    password: &str,
+    nonce: &[u8; PASSWORD_NONCE_SIZE],
+    channel: &[u8; 32],
+    proof: &[u8; 32],
+) -> bool {
+    blake3::Hash::from(password_proof(password, nonce, channel)) == blake3::Hash::from(*proof)
+}
+
 /// Compute BLAKE3 checksum of data
 pub fn checksum(data: &[u8]) -> [u8; 32] {
     *blake3::hash(data).as_bytes()
@@ -466,9 +550,102 @@ mod tests {
         assert_eq!(make_share_link("abcdef"), "https://wormhole.byronwade.com/j/ABC-DEF");
     }
 
+    #[test]
+    fn test_pinned_share_link() {
+        let fp = [0xabu8; 32];
+        let link = make_pinned_share_link("abcdef", &fp);
+        assert!(link.starts_with("https://wormhole.byronwade.com/j/ABC-DEF#fp=abab"));
+
+        assert_eq!(extract_join_code(&link), Some("ABC-DEF".to_string()));
+        assert_eq!(extract_fingerprint(&link), Some(fp));
+        assert_eq!(
+            extract_fingerprint(&format!("wormhole://j/ABC-DEF?fp={}", "cd".repeat(32))),
+            Some([0xcdu8; 32])
+        );
+
+        assert_eq!(
+            extract_fingerprint("https://wormhole.byronwade.com/j/ABC-DEF"),
+            None
+        );
+        assert_eq!(extract_fingerprint("ABC-DEF#fp=nothex"), None);
+    }
+
+    #[test]
+    fn test_join_code_lan_hash() {
+        let salt = [3u8; LAN_SALT_SIZE];
+        // Formatting differences must not change the hash
+        assert_eq!(
+            join_code_lan_hash("abc-xyz", &salt),
+            join_code_lan_hash("ABCXYZ", &salt)
+        );
+        // Announcements with different salts advertise different hashes
+        let salts = (0..32u8).map(|i| [i; LAN_SALT_SIZE]);
+        let hashes: std::collections::HashSet<_> =
+            salts.map(|salt| join_code_lan_hash("ABCXYZ", &salt)).collect();
+        assert!(hashes.len() > 1);
+    }
+
+    #[test]
+    fn test_password_proof() {
+        let nonce = [7u8; PASSWORD_NONCE_SIZE];
+        let channel = [1u8; 32];
+        let proof = password_proof("hunter2", &nonce, &channel);
+        assert!(verify_password_proof("hunter2", &nonce, &channel, &proof));
+        assert!(!verify_password_proof("hunter3", &nonce, &channel, &proof));
+        // A proof is bound to the nonce it answers
+        let other_nonce = [8u8; PASSWORD_NONCE_SIZE];
+        assert!(!verify_password_proof("hunter2", &other_nonce, &channel, &proof));
+        // ...and to the TLS session it was made on
+        assert!(!verify_password_proof("hunter2", &nonce, &[2u8; 32], &proof));
+    }
+
     #[test]
     fn test_format_join_code() {
         assert_eq!(format_join_code("ABCDEF"), "ABC-DEF");
         assert_eq!(format_join_code("ABC"), "ABC"); // Too short, return as-is
     }
+
+    mod properties {
+        use super::*;
+        use proptest::prelude::*;
+
+        /// Valid codes, as typed by users in any case and spacing
+        fn typed_code() -> impl Strategy<Value = String> {
+            let chars: Vec<char> = JOIN_CODE_CHARS.iter().map(|&b| b as char).collect();
+            proptest::collection::vec(
+                (proptest::sample::select(chars), any::<bool>(), "[ -]?"),
+                JOIN_CODE_LENGTH,
+            )
+            .prop_map(|chars| {
+                chars
+                    .into_iter()
+                    .map(|(c, lower, sep)| {
+                        let c = if lower { c.to_ascii_lowercase() } else { c };
+                        format!("{}{}", c, sep)
+                    })
+                    .collect()
+            })
+        }
+
+        proptest! {
+            #[test]
+            fn prop_extracted_codes_are_canonical(
+                input in "(https?://\\PC{0,8}/j(oin)?/|wormhole:(//)?(j/|join/)?)?\\PC{0,32}"
+            ) {
+                if let Some(code) = extract_join_code(&input) {
+                    prop_assert!(validate_join_code(&code));
+                    prop_assert_eq!(extract_join_code(&code), Some(code.clone()));
+                }
+            }
+
+            #[test]
+            fn prop_share_links_carry_their_code(code in typed_code()) {
+                let canonical = format_join_code(&normalize_join_code(&code));
+                prop_assert_eq!(extract_join_code(&code), Some(canonical.clone()));
+                prop_assert_eq!(extract_join_code(&make_share_link(&code)), Some(canonical.clone()));
+                let deep_link = format!("wormhole://join/{}", code);
+                prop_assert_eq!(extract_join_code(&deep_link), Some(canonical));
+            }
+        }
+    }
 }
@@ -1,9 +1,11 @@
 //! Fallback I/O implementation using standard Rust/tokio APIs.
 
 use super::AsyncIO;
+use crate::buffer_pool::PooledBuffer;
 use std::fs::File;
 use std::io::{self, Read, Seek, SeekFrom, Write};
 use std::path::Path;
+use std::sync::Arc;
 
 /// Fallback I/O implementation using standard Rust APIs.
 pub struct FallbackIO;
@@ -34,7 +36,7 @@ impl AsyncIO for FallbackIO {
             Ok::<_, io::Error>((local_buf, n))
         })
         .await
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))??;
+        .map_err(io::Error::other)??;
 
         buf[..result.1].copy_from_slice(&result.0[..result.1]);
         Ok(result.1)
@@ -56,7 +58,7 @@ impl AsyncIO for FallbackIO {
             Ok::<_, io::Error>(n)
         })
         .await
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
+        .map_err(io::Error::other)?
     }
 
     async fn writev(&self, file: &File, bufs: &[&[u8]], offset: u64) -> io::Result<usize> {
@@ -72,10 +74,161 @@ impl AsyncIO for FallbackIO {
             Ok::<_, io::Error>(total)
         })
         .await
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
+        .map_err(io::Error::other)?
+    }
+
+    async fn read_at(
+        &self,
+        file: Arc<File>,
+        offset: u64,
+        mut buf: PooledBuffer,
+    ) -> io::Result<(PooledBuffer, usize)> {
+        tokio::task::spawn_blocking(move || {
+            let n = read_full_at(&file, offset, &mut buf)?;
+            Ok((buf, n))
+        })
+        .await
+        .map_err(io::Error::other)?
+    }
+
+    async fn write_at(&self, file: Arc<File>, offset: u64, data: Vec<u8>) -> io::Result<()> {
+        tokio::task::spawn_blocking(move || write_all_at(&file, offset, &data))
+            .await
+            .map_err(io::Error::other)?
     }
 
     fn name(&self) -> &'static str {
         "Fallback (standard I/O)"
     }
 }
+
+/// Read at `offset` until `buf` is full or the file ends, returning the bytes read.
+pub fn read_full_at(file: &File, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+    let mut total = 0;
+    while total < buf.len() {
+        match read_once_at(file, offset + total as u64, &mut buf[total..]) {
+            Ok(0) => break,
+            Ok(n) => total += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(total)
+}
+
+/// Write all of `data` at `offset`.
+pub fn write_all_at(file: &File, offset: u64, data: &[u8]) -> io::Result<()> {
+    let mut written = 0;
+    while written < data.len() {
+        match write_once_at(file, offset + written as u64, &data[written..]) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => written += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Copy up to `len` bytes between open files through a buffer, stopping early at the end of `src`.
+pub fn copy_range(
+    src: &File,
+    src_offset: u64,
+    dst: &File,
+    dst_offset: u64,
+    len: u64,
+) -> io::Result<u64> {
+    let mut buf = vec![0u8; COPY_BUFFER_SIZE.min(len as usize)];
+    let mut copied = 0u64;
+    while copied < len {
+        let want = (len - copied).min(buf.len() as u64) as usize;
+        let n = read_full_at(src, src_offset + copied, &mut buf[..want])?;
+        if n == 0 {
+            break;
+        }
+        write_all_at(dst, dst_offset + copied, &buf[..n])?;
+        copied += n as u64;
+    }
+    Ok(copied)
+}
+
+/// Buffer size for `copy_range`.
+const COPY_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// First byte of data at or after `offset`, treating the whole file as data.
+///
+/// `None` when `offset` is at or past the end of the file.
+pub fn seek_data(file: &File, offset: u64) -> io::Result<Option<u64>> {
+    let size = file.metadata()?.len();
+    Ok((offset < size).then_some(offset))
+}
+
+/// First hole at or after `offset`, treating the end of the file as the only hole.
+///
+/// `None` when `offset` is at or past the end of the file.
+pub fn seek_hole(file: &File, offset: u64) -> io::Result<Option<u64>> {
+    let size = file.metadata()?.len();
+    Ok((offset < size).then_some(size))
+}
+
+/// Make room for `len` bytes at `offset`, growing the file unless `keep_size`.
+///
+/// Without a way to reserve blocks this only sets the size; space is taken
+/// when the range is written.
+pub fn allocate(file: &File, offset: u64, len: u64, keep_size: bool) -> io::Result<()> {
+    let end = offset.saturating_add(len);
+    if !keep_size && end > file.metadata()?.len() {
+        file.set_len(end)?;
+    }
+    Ok(())
+}
+
+/// Make `len` bytes at `offset` read as zeros, by writing zeros over them.
+///
+/// The file keeps its size, so only the part of the range inside it is written.
+pub fn punch_hole(file: &File, offset: u64, len: u64) -> io::Result<()> {
+    let end = offset.saturating_add(len).min(file.metadata()?.len());
+    if offset >= end {
+        return Ok(());
+    }
+    let zeros = vec![0u8; COPY_BUFFER_SIZE.min((end - offset) as usize)];
+    let mut pos = offset;
+    while pos < end {
+        let n = (end - pos).min(zeros.len() as u64) as usize;
+        write_all_at(file, pos, &zeros[..n])?;
+        pos += n as u64;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn read_once_at(file: &File, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+    std::os::unix::fs::FileExt::read_at(file, buf, offset)
+}
+
+#[cfg(unix)]
+fn write_once_at(file: &File, offset: u64, data: &[u8]) -> io::Result<usize> {
+    std::os::unix::fs::FileExt::write_at(file, data, offset)
+}
+
+#[cfg(windows)]
+fn read_once_at(file: &File, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+    std::os::windows::fs::FileExt::seek_read(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn write_once_at(file: &File, offset: u64, data: &[u8]) -> io::Result<usize> {
+    std::os::windows::fs::FileExt::seek_write(file, data, offset)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn read_once_at(mut file: &File, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+    file.seek(SeekFrom::Start(offset))?;
+    file.read(buf)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn write_once_at(mut file: &File, offset: u64, data: &[u8]) -> io::Result<usize> {
+    file.seek(SeekFrom::Start(offset))?;
+    file.write(data)
+}
@@ -7,11 +7,14 @@
 //! Linux sendfile is simpler than macOS:
 //! `ssize_t sendfile(int out_fd, int in_fd, off_t *offset, size_t count);`
 
+use super::fallback::{self, copy_range, read_full_at, write_all_at};
 use super::AsyncIO;
+use crate::buffer_pool::PooledBuffer;
 use std::fs::File;
 use std::io;
 use std::os::unix::io::AsRawFd;
 use std::path::Path;
+use std::sync::Arc;
 
 /// Linux I/O implementation using sendfile and other optimized syscalls.
 pub struct LinuxIO;
@@ -47,6 +50,102 @@ impl LinuxIO {
         }
     }
 
+    /// Copy up to `len` bytes between open files using copy_file_range(2).
+    ///
+    /// The kernel copies without going through user space, and clones the
+    /// extents instead on file systems with reflinks (Btrfs, XFS). Falls back to
+    /// a buffered copy where the files can't be copied that way.
+    pub fn copy_file_range_sync(
+        src: &File,
+        src_offset: u64,
+        dst: &File,
+        dst_offset: u64,
+        len: u64,
+    ) -> io::Result<u64> {
+        let mut copied = 0u64;
+        while copied < len {
+            let mut off_in = (src_offset + copied) as i64;
+            let mut off_out = (dst_offset + copied) as i64;
+            let want = (len - copied).min(isize::MAX as u64) as usize;
+            let result = unsafe {
+                libc::copy_file_range(
+                    src.as_raw_fd(),
+                    &mut off_in,
+                    dst.as_raw_fd(),
+                    &mut off_out,
+                    want,
+                    0,
+                )
+            };
+
+            if result < 0 {
+                let err = io::Error::last_os_error();
+                match err.raw_os_error() {
+                    Some(libc::EINTR) => continue,
+                    // Different file systems, or one that can't do it
+                    Some(libc::EXDEV | libc::ENOSYS | libc::EOPNOTSUPP | libc::EINVAL)
+                        if copied == 0 =>
+                    {
+                        return copy_range(src, src_offset, dst, dst_offset, len);
+                    }
+                    _ => return Err(err),
+                }
+            }
+            if result == 0 {
+                // End of the source file
+                break;
+            }
+            copied += result as u64;
+        }
+        Ok(copied)
+    }
+
+    /// Find the next data (`SEEK_DATA`) or hole (`SEEK_HOLE`) at or after `offset`.
+    ///
+    /// Returns `None` where lseek(2) fails with ENXIO: `offset` is at or past
+    /// the end of the file, or there is no data after it.
+    pub fn seek_sync(file: &File, offset: u64, whence: i32) -> io::Result<Option<u64>> {
+        let result = unsafe { libc::lseek(file.as_raw_fd(), offset as i64, whence) };
+
+        if result < 0 {
+            let err = io::Error::last_os_error();
+            match err.raw_os_error() {
+                Some(libc::ENXIO) => Ok(None),
+                _ => Err(err),
+            }
+        } else {
+            Ok(Some(result as u64))
+        }
+    }
+
+    /// Allocate or deallocate a range of a file using fallocate(2).
+    ///
+    /// `mode` takes the `FALLOC_FL_*` flags. Where the file system doesn't
+    /// support them, allocating only sets the size and punching a hole writes
+    /// zeros.
+    pub fn fallocate_sync(file: &File, mode: i32, offset: u64, len: u64) -> io::Result<()> {
+        loop {
+            let result =
+                unsafe { libc::fallocate(file.as_raw_fd(), mode, offset as i64, len as i64) };
+            if result == 0 {
+                return Ok(());
+            }
+
+            let err = io::Error::last_os_error();
+            match err.raw_os_error() {
+                Some(libc::EINTR) => continue,
+                Some(libc::EOPNOTSUPP) if mode & libc::FALLOC_FL_PUNCH_HOLE != 0 => {
+                    return fallback::punch_hole(file, offset, len);
+                }
+                Some(libc::EOPNOTSUPP) => {
+                    let keep_size = mode & libc::FALLOC_FL_KEEP_SIZE != 0;
+                    return fallback::allocate(file, offset, len, keep_size);
+                }
+                _ => return Err(err),
+            }
+        }
+    }
+
     /// Read file at offset using pread.
     fn pread_sync(file: &File, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
         let fd = file.as_raw_fd();
@@ -109,7 +208,7 @@ impl AsyncIO for LinuxIO {
             Ok::<_, io::Error>((local_buf, n))
         })
         .await
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))??;
+        .map_err(io::Error::other)??;
 
         buf[..result.1].copy_from_slice(&result.0[..result.1]);
         Ok(result.1)
@@ -128,7 +227,7 @@ impl AsyncIO for LinuxIO {
             Self::sendfile_sync(file_fd, socket_fd, offset, len)
         })
         .await
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
+        .map_err(io::Error::other)?
     }
 
     async fn writev(&self, file: &File, bufs: &[&[u8]], offset: u64) -> io::Result<usize> {
@@ -147,7 +246,28 @@ impl AsyncIO for LinuxIO {
             result
         })
         .await
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
+        .map_err(io::Error::other)?
+    }
+
+    // std's positional reads and writes are pread(2) and pwrite(2) here
+    async fn read_at(
+        &self,
+        file: Arc<File>,
+        offset: u64,
+        mut buf: PooledBuffer,
+    ) -> io::Result<(PooledBuffer, usize)> {
+        tokio::task::spawn_blocking(move || {
+            let n = read_full_at(&file, offset, &mut buf)?;
+            Ok((buf, n))
+        })
+        .await
+        .map_err(io::Error::other)?
+    }
+
+    async fn write_at(&self, file: Arc<File>, offset: u64, data: Vec<u8>) -> io::Result<()> {
+        tokio::task::spawn_blocking(move || write_all_at(&file, offset, &data))
+            .await
+            .map_err(io::Error::other)?
     }
 
     fn name(&self) -> &'static str {
@@ -1,10 +1,13 @@
 //! macOS-specific I/O implementation using sendfile.
 
+use super::fallback::{read_full_at, write_all_at};
 use super::AsyncIO;
+use crate::buffer_pool::PooledBuffer;
 use std::fs::File;
 use std::io;
 use std::os::unix::io::AsRawFd;
 use std::path::Path;
+use std::sync::Arc;
 
 /// macOS I/O implementation using sendfile and other optimized syscalls.
 pub struct MacOSIO;
@@ -144,6 +147,27 @@ impl AsyncIO for MacOSIO {
         .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
     }
 
+    // std's positional reads and writes are pread(2) and pwrite(2) here
+    async fn read_at(
+        &self,
+        file: Arc<File>,
+        offset: u64,
+        mut buf: PooledBuffer,
+    ) -> io::Result<(PooledBuffer, usize)> {
+        tokio::task::spawn_blocking(move || {
+            let n = read_full_at(&file, offset, &mut buf)?;
+            Ok((buf, n))
+        })
+        .await
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
+    }
+
+    async fn write_at(&self, file: Arc<File>, offset: u64, data: Vec<u8>) -> io::Result<()> {
+        tokio::task::spawn_blocking(move || write_all_at(&file, offset, &data))
+            .await
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
+    }
+
     fn name(&self) -> &'static str {
         "macOS (sendfile)"
     }
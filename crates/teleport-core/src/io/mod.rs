@@ -6,6 +6,9 @@
 use std::fs::File;
 use std::io;
 use std::path::Path;
+use std::sync::Arc;
+
+use crate::buffer_pool::{BufferPool, PooledBuffer};
 
 #[cfg(target_os = "macos")]
 pub mod macos;
@@ -13,6 +16,9 @@ pub mod macos;
 #[cfg(target_os = "linux")]
 pub mod linux;
 
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+pub mod uring;
+
 #[cfg(target_os = "windows")]
 pub mod windows;
 
@@ -39,12 +45,42 @@ pub trait AsyncIO: Send + Sync {
     /// Write multiple buffers to a file at a specific offset (scatter-gather I/O).
     async fn writev(&self, file: &File, bufs: &[&[u8]], offset: u64) -> io::Result<usize>;
 
+    /// Read from an open file at `offset` until `buf` is full or the file ends.
+    ///
+    /// The file and buffer are handed over so the read can run off the calling
+    /// task; the buffer comes back with the number of bytes read.
+    async fn read_at(
+        &self,
+        file: Arc<File>,
+        offset: u64,
+        buf: PooledBuffer,
+    ) -> io::Result<(PooledBuffer, usize)>;
+
+    /// Write all of `data` to an open file at `offset`.
+    async fn write_at(&self, file: Arc<File>, offset: u64, data: Vec<u8>) -> io::Result<()>;
+
     /// Get the name of this I/O backend for logging/debugging.
     fn name(&self) -> &'static str;
 }
 
 /// Get the best available I/O implementation for the current platform.
+///
+/// With the `io-uring` feature, Linux uses io_uring where the kernel supports
+/// it and `LinuxIO` otherwise.
 pub fn platform_io() -> Box<dyn AsyncIO> {
+    platform_io_with(None)
+}
+
+/// Like `platform_io`, for reads into buffers from `buffers`.
+///
+/// io_uring registers the pool's buffers with the kernel, which makes reads
+/// into them cheaper.
+pub fn platform_io_for(buffers: &Arc<BufferPool>) -> Box<dyn AsyncIO> {
+    platform_io_with(Some(buffers))
+}
+
+#[allow(unused_variables)]
+fn platform_io_with(buffers: Option<&Arc<BufferPool>>) -> Box<dyn AsyncIO> {
     #[cfg(target_os = "macos")]
     {
         Box::new(macos::MacOSIO::new())
@@ -52,7 +88,18 @@ pub fn platform_io() -> Box<dyn AsyncIO> {
 
     #[cfg(target_os = "linux")]
     {
-        Box::new(fallback::FallbackIO::new())
+        #[cfg(feature = "io-uring")]
+        {
+            let uring = match buffers {
+                Some(buffers) => uring::IoUringIO::with_buffers(buffers),
+                None => uring::IoUringIO::new(),
+            };
+            match uring {
+                Ok(io) => return Box::new(io),
+                Err(e) => tracing::debug!("io_uring unavailable, using pread/pwrite: {}", e),
+            }
+        }
+        Box::new(linux::LinuxIO::new())
     }
 
     #[cfg(target_os = "windows")]
@@ -66,6 +113,90 @@ pub fn platform_io() -> Box<dyn AsyncIO> {
     }
 }
 
+/// Copy up to `len` bytes from `src` at `src_offset` to `dst` at `dst_offset`.
+///
+/// Returns the bytes copied, fewer than `len` only where `src` ends. Linux
+/// copies in the kernel (see `LinuxIO::copy_file_range_sync`); elsewhere the
+/// data goes through a buffer. Blocks until done.
+pub fn copy_range(
+    src: &File,
+    src_offset: u64,
+    dst: &File,
+    dst_offset: u64,
+    len: u64,
+) -> io::Result<u64> {
+    #[cfg(target_os = "linux")]
+    {
+        linux::LinuxIO::copy_file_range_sync(src, src_offset, dst, dst_offset, len)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        fallback::copy_range(src, src_offset, dst, dst_offset, len)
+    }
+}
+
+/// First byte of data at or after `offset`, skipping holes in sparse files.
+///
+/// `None` when there is no data at or after `offset`. Platforms that can't
+/// find holes treat the whole file as data.
+pub fn seek_data(file: &File, offset: u64) -> io::Result<Option<u64>> {
+    #[cfg(target_os = "linux")]
+    {
+        linux::LinuxIO::seek_sync(file, offset, libc::SEEK_DATA)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        fallback::seek_data(file, offset)
+    }
+}
+
+/// First hole at or after `offset`; the end of the file counts as a hole.
+///
+/// `None` when `offset` is at or past the end of the file.
+pub fn seek_hole(file: &File, offset: u64) -> io::Result<Option<u64>> {
+    #[cfg(target_os = "linux")]
+    {
+        linux::LinuxIO::seek_sync(file, offset, libc::SEEK_HOLE)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        fallback::seek_hole(file, offset)
+    }
+}
+
+/// Reserve space for `len` bytes at `offset`, growing the file unless `keep_size`.
+pub fn allocate(file: &File, offset: u64, len: u64, keep_size: bool) -> io::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        let mode = if keep_size { libc::FALLOC_FL_KEEP_SIZE } else { 0 };
+        linux::LinuxIO::fallocate_sync(file, mode, offset, len)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        fallback::allocate(file, offset, len, keep_size)
+    }
+}
+
+/// Deallocate `len` bytes at `offset`, which then read as zeros.
+///
+/// The file keeps its size. Where holes can't be punched, zeros are written.
+pub fn punch_hole(file: &File, offset: u64, len: u64) -> io::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        let mode = libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE;
+        linux::LinuxIO::fallocate_sync(file, mode, offset, len)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        fallback::punch_hole(file, offset, len)
+    }
+}
+
 /// Statistics for I/O operations.
 #[derive(Debug, Clone, Default)]
 pub struct IoStats {
@@ -75,3 +206,102 @@ pub struct IoStats {
     pub sendfile_bytes: u64,
     pub fallback_count: u64,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_platform_io_reads_and_writes_open_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.bin");
+        std::fs::write(&path, b"hello world").unwrap();
+        let file = Arc::new(
+            std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(&path)
+                .unwrap(),
+        );
+        let io = platform_io();
+        let pool = BufferPool::new(1, 8);
+
+        io.write_at(file.clone(), 6, b"wormhole".to_vec())
+            .await
+            .unwrap();
+        let (buf, n) = io
+            .read_at(file.clone(), 6, pool.acquire().await)
+            .await
+            .unwrap();
+        assert_eq!(&buf[..n], b"wormhole");
+
+        // Reads stop at the end of the file
+        let (buf, n) = io.read_at(file, 10, buf).await.unwrap();
+        assert_eq!(&buf[..n], b"hole");
+        assert_eq!(std::fs::read(&path).unwrap(), b"hello wormhole");
+    }
+
+    #[test]
+    fn test_copy_range_copies_between_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let content: Vec<u8> = (0..3 * 1024 * 1024).map(|i| (i % 253) as u8).collect();
+        std::fs::write(dir.path().join("src.bin"), &content).unwrap();
+        std::fs::write(dir.path().join("dst.bin"), b"header").unwrap();
+        let src = File::open(dir.path().join("src.bin")).unwrap();
+        let dst = std::fs::OpenOptions::new()
+            .write(true)
+            .open(dir.path().join("dst.bin"))
+            .unwrap();
+
+        let copied = copy_range(&src, 10, &dst, 6, u64::MAX).unwrap();
+        assert_eq!(copied, content.len() as u64 - 10);
+        let copy = std::fs::read(dir.path().join("dst.bin")).unwrap();
+        assert_eq!(&copy[..6], b"header");
+        assert_eq!(&copy[6..], &content[10..]);
+
+        // The buffered copy gives the same result
+        let copied = fallback::copy_range(&src, 0, &dst, 6, 100).unwrap();
+        assert_eq!(copied, 100);
+        let copy = std::fs::read(dir.path().join("dst.bin")).unwrap();
+        assert_eq!(&copy[6..106], &content[..100]);
+    }
+
+    #[test]
+    fn test_sparse_files() {
+        const MIB: u64 = 1024 * 1024;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sparse.bin");
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        file.set_len(4 * MIB).unwrap();
+        fallback::write_all_at(&file, 2 * MIB, &[7u8; 4096]).unwrap();
+
+        // File systems without hole tracking report the whole file as data
+        let data = seek_data(&file, 0).unwrap().unwrap();
+        assert!(data <= 2 * MIB);
+        let hole = seek_hole(&file, 2 * MIB).unwrap().unwrap();
+        assert!(hole > 2 * MIB && hole <= 4 * MIB);
+        assert_eq!(seek_data(&file, 4 * MIB).unwrap(), None);
+        assert_eq!(seek_hole(&file, 4 * MIB).unwrap(), None);
+
+        punch_hole(&file, 2 * MIB, 4096).unwrap();
+        assert_eq!(file.metadata().unwrap().len(), 4 * MIB);
+        assert!(std::fs::read(&path).unwrap().iter().all(|&b| b == 0));
+
+        allocate(&file, 4 * MIB, MIB, true).unwrap();
+        assert_eq!(file.metadata().unwrap().len(), 4 * MIB);
+        allocate(&file, 4 * MIB, MIB, false).unwrap();
+        assert_eq!(file.metadata().unwrap().len(), 5 * MIB);
+
+        // The fallbacks agree on what the file reads as
+        fallback::write_all_at(&file, 0, b"data").unwrap();
+        fallback::punch_hole(&file, 1, 2).unwrap();
+        assert_eq!(&std::fs::read(&path).unwrap()[..4], b"d\0\0a");
+        assert_eq!(fallback::seek_hole(&file, 0).unwrap(), Some(5 * MIB));
+    }
+}
@@ -0,0 +1,508 @@
+//! Linux io_uring I/O implementation (`io-uring` feature).
+//!
+//! `LinuxIO` hands every read and write to tokio's blocking pool. Here one
+//! thread owns an io_uring instance instead: tasks queue operations on a
+//! channel and wake the thread through an eventfd, the thread submits
+//! everything queued in a single `io_uring_enter` and sends each completion
+//! back to the task waiting for it.
+//!
+//! Buffers from a `BufferPool` can be registered with the kernel up front;
+//! reads into them use `IORING_OP_READ_FIXED`, which skips mapping the buffer
+//! on every read. Other buffers are read into with plain `IORING_OP_READ`.
+//!
+//! Operations own their file and buffer until the kernel is done with them, so
+//! a task that stops waiting can't free memory the kernel is writing to.
+
+use super::linux::LinuxIO;
+use super::AsyncIO;
+use crate::buffer_pool::{BufferPool, PooledBuffer};
+use io_uring::{opcode, squeue, types, IoUring, Probe};
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+use tokio::sync::oneshot;
+use tracing::{debug, error};
+
+/// Submission queue size; more operations wait on the channel
+const RING_ENTRIES: u32 = 256;
+
+/// Most buffers registered with the kernel.
+///
+/// Registered memory is pinned and counts against `RLIMIT_MEMLOCK`; 32 random
+/// access buffers (4 MB) stay within the usual default limit.
+pub const MAX_REGISTERED_BUFFERS: usize = 32;
+
+/// `user_data` of the eventfd poll that wakes the ring thread
+const WAKE: u64 = u64::MAX;
+
+/// io_uring I/O implementation, with `LinuxIO` for what io_uring doesn't cover.
+pub struct IoUringIO {
+    ops: Option<Sender<Op>>,
+    wake: Arc<OwnedFd>,
+    /// Registered buffers by address: index and length
+    registered: HashMap<usize, (u16, usize)>,
+    fallback: LinuxIO,
+}
+
+impl IoUringIO {
+    /// Set up a ring, failing if the kernel lacks io_uring or the operations used here.
+    pub fn new() -> io::Result<Self> {
+        Self::build(None)
+    }
+
+    /// Like `new`, registering up to `MAX_REGISTERED_BUFFERS` of `buffers` with the kernel.
+    ///
+    /// Registration is best effort; reads into buffers that couldn't be
+    /// registered work as they do with `new`.
+    pub fn with_buffers(buffers: &Arc<BufferPool>) -> io::Result<Self> {
+        Self::build(Some(buffers))
+    }
+
+    fn build(buffers: Option<&Arc<BufferPool>>) -> io::Result<Self> {
+        let ring = IoUring::new(RING_ENTRIES)?;
+
+        let mut probe = Probe::new();
+        ring.submitter().register_probe(&mut probe)?;
+        let needed = [
+            opcode::Read::CODE,
+            opcode::ReadFixed::CODE,
+            opcode::Write::CODE,
+            opcode::PollAdd::CODE,
+        ];
+        if !needed.iter().all(|&code| probe.is_supported(code)) {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "kernel io_uring lacks read, write or poll",
+            ));
+        }
+
+        let mut registered = HashMap::new();
+        if let Some(pool) = buffers {
+            let len = pool.buffer_size();
+            let iovecs: Vec<libc::iovec> = pool
+                .preallocate(MAX_REGISTERED_BUFFERS)
+                .into_iter()
+                .take(MAX_REGISTERED_BUFFERS)
+                .map(|base| libc::iovec {
+                    iov_base: base as *mut libc::c_void,
+                    iov_len: len,
+                })
+                .collect();
+            // SAFETY: The buffers live as long as the pool, which the ring
+            // thread keeps alive; the kernel pins their pages until the ring closes
+            match unsafe { ring.submitter().register_buffers(&iovecs) } {
+                Ok(()) => {
+                    registered = iovecs
+                        .iter()
+                        .enumerate()
+                        .map(|(i, iov)| (iov.iov_base as usize, (i as u16, iov.iov_len)))
+                        .collect();
+                }
+                Err(e) => debug!("Not registering io_uring buffers: {}", e),
+            }
+        }
+
+        // SAFETY: eventfd returns a new descriptor we own, or -1
+        let fd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let wake = Arc::new(unsafe { OwnedFd::from_raw_fd(fd) });
+
+        let (sender, receiver) = mpsc::channel();
+        let pool = buffers.cloned();
+        let thread_wake = wake.clone();
+        std::thread::Builder::new()
+            .name("io-uring".into())
+            .spawn(move || {
+                let _pool = pool;
+                run(ring, receiver, &thread_wake)
+            })?;
+
+        Ok(Self {
+            ops: Some(sender),
+            wake,
+            registered,
+            fallback: LinuxIO::new(),
+        })
+    }
+
+    /// Number of buffers registered with the kernel.
+    pub fn registered_buffers(&self) -> usize {
+        self.registered.len()
+    }
+
+    /// Queue an operation and wake the ring thread.
+    fn queue(&self, op: Op) -> io::Result<()> {
+        self.ops
+            .as_ref()
+            .and_then(|ops| ops.send(op).ok())
+            .ok_or_else(stopped)?;
+        wake(self.wake.as_raw_fd());
+        Ok(())
+    }
+}
+
+impl Drop for IoUringIO {
+    fn drop(&mut self) {
+        // The thread finishes what is in flight and exits once the channel closes
+        self.ops = None;
+        wake(self.wake.as_raw_fd());
+    }
+}
+
+#[async_trait::async_trait]
+impl AsyncIO for IoUringIO {
+    async fn read_file(&self, path: &Path, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self.fallback.read_file(path, offset, buf).await
+    }
+
+    async fn sendfile(
+        &self,
+        file: &File,
+        socket_fd: i32,
+        offset: u64,
+        len: usize,
+    ) -> io::Result<usize> {
+        self.fallback.sendfile(file, socket_fd, offset, len).await
+    }
+
+    async fn writev(&self, file: &File, bufs: &[&[u8]], offset: u64) -> io::Result<usize> {
+        self.fallback.writev(file, bufs, offset).await
+    }
+
+    async fn read_at(
+        &self,
+        file: Arc<File>,
+        offset: u64,
+        buf: PooledBuffer,
+    ) -> io::Result<(PooledBuffer, usize)> {
+        let fixed = self
+            .registered
+            .get(&(buf.as_ptr() as usize))
+            .filter(|(_, len)| buf.len() <= *len)
+            .map(|(index, _)| *index);
+        let (done, result) = oneshot::channel();
+        self.queue(Op::Read {
+            file,
+            offset,
+            buf,
+            fixed,
+            filled: 0,
+            done,
+        })?;
+        result.await.map_err(|_| stopped())?
+    }
+
+    async fn write_at(&self, file: Arc<File>, offset: u64, data: Vec<u8>) -> io::Result<()> {
+        let (done, result) = oneshot::channel();
+        self.queue(Op::Write {
+            file,
+            offset,
+            data,
+            written: 0,
+            done,
+        })?;
+        result.await.map_err(|_| stopped())?
+    }
+
+    fn name(&self) -> &'static str {
+        "Linux (io_uring)"
+    }
+}
+
+/// An operation, resubmitted until its buffer is full or the file ends
+enum Op {
+    Read {
+        file: Arc<File>,
+        offset: u64,
+        buf: PooledBuffer,
+        /// Registered buffer index
+        fixed: Option<u16>,
+        filled: usize,
+        done: oneshot::Sender<io::Result<(PooledBuffer, usize)>>,
+    },
+    Write {
+        file: Arc<File>,
+        offset: u64,
+        data: Vec<u8>,
+        written: usize,
+        done: oneshot::Sender<io::Result<()>>,
+    },
+}
+
+impl Op {
+    /// Submission for the rest of the operation
+    fn entry(&mut self) -> squeue::Entry {
+        match self {
+            Op::Read {
+                file,
+                offset,
+                buf,
+                fixed,
+                filled,
+                ..
+            } => {
+                let fd = types::Fd(file.as_raw_fd());
+                let rest = &mut buf[*filled..];
+                let len = rest.len().min(u32::MAX as usize) as u32;
+                let at = *offset + *filled as u64;
+                match fixed {
+                    Some(index) => opcode::ReadFixed::new(fd, rest.as_mut_ptr(), len, *index)
+                        .offset(at)
+                        .build(),
+                    None => opcode::Read::new(fd, rest.as_mut_ptr(), len)
+                        .offset(at)
+                        .build(),
+                }
+            }
+            Op::Write {
+                file,
+                offset,
+                data,
+                written,
+                ..
+            } => {
+                let rest = &data[*written..];
+                let len = rest.len().min(u32::MAX as usize) as u32;
+                opcode::Write::new(types::Fd(file.as_raw_fd()), rest.as_ptr(), len)
+                    .offset(*offset + *written as u64)
+                    .build()
+            }
+        }
+    }
+
+    /// Account for a completion, returning the operation if there is more to do
+    fn complete(self, result: i32) -> Option<Op> {
+        let error = (result < 0).then(|| io::Error::from_raw_os_error(-result));
+        match self {
+            Op::Read {
+                file,
+                offset,
+                buf,
+                fixed,
+                mut filled,
+                done,
+            } => {
+                if let Some(e) = error {
+                    if e.kind() != io::ErrorKind::Interrupted {
+                        let _ = done.send(Err(e));
+                        return None;
+                    }
+                } else if result == 0 || filled + result as usize >= buf.len() {
+                    filled += result as usize;
+                    let _ = done.send(Ok((buf, filled)));
+                    return None;
+                } else {
+                    filled += result as usize;
+                }
+                Some(Op::Read {
+                    file,
+                    offset,
+                    buf,
+                    fixed,
+                    filled,
+                    done,
+                })
+            }
+            Op::Write {
+                file,
+                offset,
+                data,
+                mut written,
+                done,
+            } => {
+                match error {
+                    Some(e) if e.kind() != io::ErrorKind::Interrupted => {
+                        let _ = done.send(Err(e));
+                        return None;
+                    }
+                    Some(_) => {}
+                    None if result == 0 => {
+                        let _ = done.send(Err(io::ErrorKind::WriteZero.into()));
+                        return None;
+                    }
+                    None => written += result as usize,
+                }
+                if written >= data.len() {
+                    let _ = done.send(Ok(()));
+                    return None;
+                }
+                Some(Op::Write {
+                    file,
+                    offset,
+                    data,
+                    written,
+                    done,
+                })
+            }
+        }
+    }
+
+    fn fail(self, e: io::Error) {
+        let _ = match self {
+            Op::Read { done, .. } => done.send(Err(e)).map_err(drop),
+            Op::Write { done, .. } => done.send(Err(e)).map_err(drop),
+        };
+    }
+}
+
+/// The ring thread: submit queued operations in batches and deliver completions
+fn run(mut ring: IoUring, receiver: Receiver<Op>, wake: &OwnedFd) {
+    let mut in_flight: HashMap<u64, Op> = HashMap::new();
+    let mut pending: VecDeque<Op> = VecDeque::new();
+    let mut next_id: u64 = 0;
+    let mut armed = false;
+    let mut closed = false;
+
+    loop {
+        loop {
+            match receiver.try_recv() {
+                Ok(op) => pending.push_back(op),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    closed = true;
+                    break;
+                }
+            }
+        }
+        if closed && pending.is_empty() && in_flight.is_empty() {
+            return;
+        }
+
+        // Queue a poll on the eventfd so new operations wake us
+        if !armed && !closed {
+            let poll = opcode::PollAdd::new(types::Fd(wake.as_raw_fd()), libc::POLLIN as u32)
+                .build()
+                .user_data(WAKE);
+            // SAFETY: The eventfd outlives the ring
+            if unsafe { ring.submission().push(&poll) }.is_ok() {
+                armed = true;
+            }
+        }
+
+        let mut submission = ring.submission();
+        while let Some(mut op) = pending.pop_front() {
+            let entry = op.entry().user_data(next_id);
+            // SAFETY: `op` owns the file and buffer the entry points to, and
+            // stays in `in_flight` until the kernel completes it
+            if unsafe { submission.push(&entry) }.is_err() {
+                pending.push_front(op);
+                break;
+            }
+            in_flight.insert(next_id, op);
+            next_id = (next_id + 1) % WAKE;
+        }
+        drop(submission);
+
+        match ring.submit_and_wait(1) {
+            Ok(_) => {}
+            Err(e) if e.raw_os_error() == Some(libc::EINTR) => {}
+            // Completion queue full; reap below before submitting more
+            Err(e) if e.raw_os_error() == Some(libc::EBUSY) => {}
+            Err(e) => {
+                error!("io_uring failed, stopping: {}", e);
+                for (_, op) in in_flight.drain() {
+                    op.fail(io::Error::new(e.kind(), e.to_string()));
+                }
+                for op in pending.drain(..) {
+                    op.fail(io::Error::new(e.kind(), e.to_string()));
+                }
+                return;
+            }
+        }
+
+        let completed: Vec<(u64, i32)> = ring
+            .completion()
+            .map(|cqe| (cqe.user_data(), cqe.result()))
+            .collect();
+        for (id, result) in completed {
+            if id == WAKE {
+                armed = false;
+                drain_eventfd(wake.as_raw_fd());
+                continue;
+            }
+            if let Some(op) = in_flight.remove(&id).and_then(|op| op.complete(result)) {
+                pending.push_back(op);
+            }
+        }
+    }
+}
+
+fn wake(fd: RawFd) {
+    let one: u64 = 1;
+    // SAFETY: Writes 8 bytes from a live u64 to an eventfd
+    unsafe { libc::write(fd, &one as *const u64 as *const libc::c_void, 8) };
+}
+
+fn drain_eventfd(fd: RawFd) {
+    let mut count: u64 = 0;
+    // SAFETY: Reads 8 bytes into a live u64; the eventfd is non-blocking
+    unsafe { libc::read(fd, &mut count as *mut u64 as *mut libc::c_void, 8) };
+}
+
+fn stopped() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "io_uring thread stopped")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn io() -> Option<IoUringIO> {
+        // Kernels and sandboxes without io_uring are covered by `platform_io`'s fallback
+        IoUringIO::new().ok()
+    }
+
+    #[tokio::test]
+    async fn test_reads_and_writes() {
+        let Some(io) = io() else { return };
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.bin");
+        std::fs::write(&path, b"hello world").unwrap();
+        let file = Arc::new(
+            std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(&path)
+                .unwrap(),
+        );
+        let pool = BufferPool::new(1, 64);
+
+        io.write_at(file.clone(), 6, b"wormhole".to_vec())
+            .await
+            .unwrap();
+        let (buf, n) = io.read_at(file, 0, pool.acquire().await).await.unwrap();
+        assert_eq!(&buf[..n], b"hello wormhole");
+    }
+
+    #[tokio::test]
+    async fn test_reads_into_registered_buffers() {
+        let pool = BufferPool::new(4, 4096);
+        let Ok(io) = IoUringIO::with_buffers(&pool) else {
+            return;
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.bin");
+        let content: Vec<u8> = (0..10_000).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&path, &content).unwrap();
+        let file = Arc::new(File::open(&path).unwrap());
+
+        let read = |i: u64| {
+            let (io, file, pool) = (&io, file.clone(), pool.clone());
+            async move {
+                let buf = pool.acquire().await;
+                let (buf, n) = io.read_at(file, i * 4096, buf).await.unwrap();
+                buf[..n].to_vec()
+            }
+        };
+        let (first, second, third) = tokio::join!(read(0), read(1), read(2));
+        assert!(io.registered_buffers() > 0);
+        let read = [first, second, third].concat();
+        assert_eq!(read, content);
+    }
+}
@@ -8,9 +8,11 @@
 
 use super::fallback::FallbackIO;
 use super::AsyncIO;
+use crate::buffer_pool::PooledBuffer;
 use std::fs::File;
 use std::io;
 use std::path::Path;
+use std::sync::Arc;
 
 /// Windows I/O implementation.
 ///
@@ -56,6 +58,19 @@ impl AsyncIO for WindowsIO {
         self.fallback.writev(file, bufs, offset).await
     }
 
+    async fn read_at(
+        &self,
+        file: Arc<File>,
+        offset: u64,
+        buf: PooledBuffer,
+    ) -> io::Result<(PooledBuffer, usize)> {
+        self.fallback.read_at(file, offset, buf).await
+    }
+
+    async fn write_at(&self, file: Arc<File>, offset: u64, data: Vec<u8>) -> io::Result<()> {
+        self.fallback.write_at(file, offset, data).await
+    }
+
     fn name(&self) -> &'static str {
         "Windows (fallback)"
     }
@@ -16,9 +16,12 @@ pub mod types;
 // Phase 8: High-Performance Transfer Engine
 pub use buffer_pool::{BufferPool, BufferPoolStats, PooledBuffer, BULK_CHUNK_SIZE, RANDOM_CHUNK_SIZE};
 pub use compression::{CompressionResult, CompressionStats, SmartCompressor};
-pub use io::{platform_io, AsyncIO, IoStats};
+pub use io::{platform_io, platform_io_for, AsyncIO, IoStats};
 
-pub use config::{CacheConfig, ClientConfig, Config, HostConfig, NetworkConfig, SignalConfig};
+pub use config::{
+    CacheConfig, ClientConfig, Config, ConfigFile, ConfigSource, HostConfig, LayeredConfig,
+    NetworkConfig, SignalConfig, UpdateConfig,
+};
 pub use error::*;
 pub use protocol::*;
 pub use types::*;
@@ -27,7 +30,12 @@ pub use types::*;
 pub const CHUNK_SIZE: usize = 128 * 1024;
 
 /// Protocol version
-pub const PROTOCOL_VERSION: u32 = 1;
+///
+/// Peers refuse each other at Hello unless they match, so this goes up with
+/// every change to the layout of an existing message.
+/// - 2: `ReadChunkResponse::zeroed`
+/// - 3: `ListDirRequest::with_attrs` and `ListDirResponse::attrs`
+pub const PROTOCOL_VERSION: u32 = 3;
 
 /// Maximum path length in bytes
 pub const MAX_PATH_LEN: usize = 4096;
@@ -360,4 +360,70 @@ mod tests {
         let result = safe_real_path(base, &symlink_path);
         assert!(result.is_ok());
     }
+
+    mod properties {
+        use super::*;
+        use proptest::collection::vec;
+        use proptest::prelude::*;
+
+        /// Path components, weighted towards the ones `safe_path` must reject
+        fn component() -> impl Strategy<Value = String> {
+            prop_oneof![
+                Just("..".to_string()),
+                Just(".".to_string()),
+                Just(String::new()),
+                "[a-z.]{1,8}",
+                "\\PC{1,12}",
+            ]
+        }
+
+        fn relative_path() -> impl Strategy<Value = String> {
+            (any::<bool>(), vec(component(), 0..8)).prop_map(|(absolute, parts)| {
+                let joined = parts.join("/");
+                if absolute {
+                    format!("/{}", joined)
+                } else {
+                    joined
+                }
+            })
+        }
+
+        proptest! {
+            #[test]
+            fn prop_accepted_paths_stay_inside_base(relative in relative_path()) {
+                match safe_path(&base(), &relative) {
+                    Ok(resolved) => {
+                        prop_assert!(resolved.starts_with(base()));
+                        prop_assert!(resolved
+                            .strip_prefix(base())
+                            .unwrap()
+                            .components()
+                            .all(|c| matches!(c, Component::Normal(_))));
+                        prop_assert!(is_safe_path(&relative));
+                    }
+                    Err(_) => prop_assert!(!is_safe_path(&relative)),
+                }
+            }
+
+            #[test]
+            fn prop_parent_components_are_rejected(relative in relative_path()) {
+                if Path::new(&relative)
+                    .components()
+                    .any(|c| c == Component::ParentDir)
+                {
+                    prop_assert!(safe_path(&base(), &relative).is_err());
+                }
+            }
+
+            #[test]
+            fn prop_valid_filenames_are_single_children(name in "\\PC{0,16}|\\.\\.?") {
+                if validate_filename(&name).is_ok() {
+                    let base = base();
+                    let joined = base.join(&name);
+                    prop_assert_eq!(joined.parent(), Some(base.as_path()));
+                    prop_assert_eq!(joined.file_name().and_then(|n| n.to_str()), Some(name.as_str()));
+                }
+            }
+        }
+    }
 }
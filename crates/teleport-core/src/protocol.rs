@@ -5,7 +5,7 @@
 
 use serde::{Deserialize, Serialize};
 
-use crate::error::ErrorCode;
+use crate::error::{ErrorCode, ProtocolError};
 use crate::types::{
     ChunkId, ContentHash, DirEntry, FileAttr, FileManifest, Inode, LockToken, LockType, ShareId,
     ShareInfo,
@@ -76,6 +76,32 @@ pub enum NetMessage {
     MissingChunksResponse(MissingChunksResponseMsg),
     BulkChunkRequest(BulkChunkRequestMsg),
     BulkChunkResponse(BulkChunkResponseMsg),
+
+    // Share password (sent between Hello and HelloAck)
+    AuthChallenge(AuthChallengeMessage),
+    AuthResponse(AuthResponseMessage),
+
+    // Write sessions: chunks are staged and replace the file on commit
+    BeginWrite(BeginWriteRequest),
+    BeginWriteResponse(BeginWriteResponse),
+    CommitWrite(CommitWriteRequest),
+    CommitWriteResponse(CommitWriteResponse),
+    AbortWrite(AbortWriteRequest),
+    AbortWriteResponse(AbortWriteResponse),
+
+    // Server-side copy
+    CopyRange(CopyRangeRequest),
+    CopyRangeResponse(CopyRangeResponse),
+
+    // Sparse files
+    Seek(SeekRequest),
+    SeekResponse(SeekResponse),
+    Fallocate(FallocateRequest),
+    FallocateResponse(FallocateResponse),
+
+    // Tree snapshots: a subtree's metadata in a few round trips
+    TreeSnapshot(TreeSnapshotRequest),
+    TreeSnapshotResponse(TreeSnapshotResponse),
 }
 
 // === Handshake Messages ===
@@ -96,6 +122,18 @@ pub struct HelloAckMessage {
     pub capabilities: Vec<String>,
 }
 
+/// Sent in reply to Hello when the share requires a password
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuthChallengeMessage {
+    pub nonce: [u8; 32],
+}
+
+/// Answer to an `AuthChallenge`: `crypto::password_proof(password, nonce, channel)`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuthResponseMessage {
+    pub proof: [u8; 32],
+}
+
 // === Metadata Messages ===
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -103,6 +141,9 @@ pub struct ListDirRequest {
     pub inode: Inode,
     pub offset: u64,
     pub limit: u32,
+    /// Return each entry's attributes too, so listing a directory and
+    /// stat-ing everything in it takes one round trip
+    pub with_attrs: bool,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -110,8 +151,16 @@ pub struct ListDirResponse {
     pub entries: Vec<DirEntry>,
     pub has_more: bool,
     pub next_offset: u64,
+    /// Attributes of `entries`, in the same order, when `with_attrs` was set
+    /// and empty otherwise. `None` where an entry vanished before it was
+    /// stat-ed.
+    pub attrs: Vec<Option<FileAttr>>,
 }
 
+/// Most entries a `ListDir` request with `with_attrs` returns at once, which
+/// keeps the response well under the message size limit
+pub const MAX_LIST_DIR_ATTRS: u32 = 1024;
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct GetAttrRequest {
     pub inode: Inode,
@@ -135,9 +184,16 @@ pub struct LookupResponse {
 
 // === Data Messages ===
 
+/// `ReadChunkRequest::priority` for reads an application is waiting on
+pub const PRIORITY_INTERACTIVE: u8 = 128;
+
+/// `ReadChunkRequest::priority` for prefetch and bulk reads
+pub const PRIORITY_BULK: u8 = 0;
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ReadChunkRequest {
     pub chunk_id: ChunkId,
+    /// Higher = more urgent; hosts serve `PRIORITY_INTERACTIVE` and above first
     pub priority: u8,
 }
 
@@ -145,8 +201,30 @@ pub struct ReadChunkRequest {
 pub struct ReadChunkResponse {
     pub chunk_id: ChunkId,
     pub data: Vec<u8>,
+    /// Checksum of the chunk's contents, zeros included
     pub checksum: [u8; 32],
     pub is_final: bool,
+    /// Set when every byte of the chunk is zero, as in holes of sparse files:
+    /// `data` is then left empty and this is the chunk's length
+    pub zeroed: Option<u32>,
+}
+
+impl ReadChunkResponse {
+    /// Length of the chunk, counting the bytes a zeroed chunk leaves out
+    pub fn chunk_len(&self) -> usize {
+        match self.zeroed {
+            Some(len) => len as usize,
+            None => self.data.len(),
+        }
+    }
+
+    /// The chunk's contents, with a zeroed chunk filled back in
+    pub fn into_data(self) -> Vec<u8> {
+        match self.zeroed {
+            Some(len) => vec![0; len as usize],
+            None => self.data,
+        }
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -164,6 +242,231 @@ pub struct WriteChunkResponse {
     pub new_size: Option<u64>,
 }
 
+// === Write Session Messages ===
+
+/// Start staging writes made with `lock_token`, which must lock `inode` exclusively
+///
+/// Until the session is committed, chunk writes with the token go to an empty
+/// file beside the target; the target is untouched. Committing replaces the
+/// target with exactly what was written, so sessions suit whole-file saves.
+/// Aborting, releasing or losing the lock, or disconnecting discards them.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BeginWriteRequest {
+    pub inode: Inode,
+    pub lock_token: LockToken,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BeginWriteResponse {
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+/// Replace the file with the session's writes, atomically
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CommitWriteRequest {
+    pub inode: Inode,
+    pub lock_token: LockToken,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CommitWriteResponse {
+    pub success: bool,
+    /// Attributes of the replaced file
+    pub attr: Option<FileAttr>,
+    pub error: Option<String>,
+}
+
+/// Discard the session's writes, leaving the file as it was
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AbortWriteRequest {
+    pub inode: Inode,
+    pub lock_token: LockToken,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AbortWriteResponse {
+    /// Whether a session was open
+    pub success: bool,
+}
+
+// === Copy Messages ===
+
+/// Copy bytes between files of a share on the host, without sending them
+///
+/// Copies at most `MAX_COPY_RANGE` bytes; clients repeat the request for the
+/// rest. `lock_token` must lock the destination exclusively.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CopyRangeRequest {
+    pub src_inode: Inode,
+    pub src_offset: u64,
+    pub dst_inode: Inode,
+    pub dst_offset: u64,
+    pub len: u64,
+    pub lock_token: LockToken,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CopyRangeResponse {
+    pub success: bool,
+    /// Bytes copied, fewer than requested where the source ends
+    pub copied: u64,
+    /// Size of the destination afterwards
+    pub new_size: Option<u64>,
+}
+
+/// Most bytes a `CopyRange` request copies
+pub const MAX_COPY_RANGE: u64 = 1 << 30;
+
+// === Sparse File Messages ===
+
+/// What a `SeekRequest` looks for
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SeekWhence {
+    /// The next byte of data (`SEEK_DATA`)
+    Data,
+    /// The next hole; the end of the file counts as one (`SEEK_HOLE`)
+    Hole,
+}
+
+/// Find data or a hole at or after `offset`, like `lseek(SEEK_DATA/SEEK_HOLE)`
+///
+/// Lets clients map the holes of sparse files instead of reading them.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SeekRequest {
+    pub inode: Inode,
+    pub offset: u64,
+    pub whence: SeekWhence,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SeekResponse {
+    /// Where the data or hole starts; `None` if there is none at or after
+    /// `offset` (`ENXIO`)
+    pub offset: Option<u64>,
+}
+
+/// How a `FallocateRequest` changes its range
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FallocateMode {
+    /// Reserve space for the range, growing the file to cover it
+    Allocate,
+    /// Reserve space for the range without changing the file size
+    AllocateKeepSize,
+    /// Deallocate the range, which then reads as zeros; the size is unchanged
+    PunchHole,
+}
+
+/// Allocate or deallocate part of a file, like `fallocate(2)`
+///
+/// `lock_token` must lock the file exclusively.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FallocateRequest {
+    pub inode: Inode,
+    pub offset: u64,
+    pub len: u64,
+    pub mode: FallocateMode,
+    pub lock_token: LockToken,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FallocateResponse {
+    pub success: bool,
+    pub attr: Option<FileAttr>,
+    pub error: Option<String>,
+}
+
+// === Tree Snapshot Messages ===
+
+/// Most entries a `TreeSnapshot` page covers; larger directories are left out
+/// of snapshots, along with everything below them
+pub const MAX_TREE_SNAPSHOT_ENTRIES: u32 = 2048;
+
+/// Largest a page of a tree snapshot may decompress to
+pub const MAX_TREE_SNAPSHOT_PAGE: usize = 16 * 1024 * 1024;
+
+/// Zstd level tree snapshot pages are compressed with
+const TREE_SNAPSHOT_LEVEL: i32 = 3;
+
+/// Point in a host's history a tree snapshot was taken at
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotMark {
+    /// The host's inode numbering (see `ShareBackend::epoch`); inodes from
+    /// another epoch mean nothing to it
+    pub epoch: u64,
+    /// Host clock, in seconds since the Unix epoch, when the walk started
+    pub taken_at: u64,
+}
+
+/// List every directory within `max_depth` levels of `root`, with attributes
+///
+/// Directories are walked breadth first and sent a page at a time: `cursor`
+/// is the previous page's `next_cursor`, 0 to start a walk. Cursors are
+/// opaque; the host keeps each unfinished walk for the session, and only the
+/// newest few. With `since`, only directories that changed after it are
+/// sent, unless it is from another epoch.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TreeSnapshotRequest {
+    pub root: Inode,
+    /// Levels below `root` to descend; 0 lists `root` alone
+    pub max_depth: u32,
+    pub cursor: u64,
+    pub since: Option<SnapshotMark>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TreeSnapshotResponse {
+    /// The page's `TreeDir`s, packed by `TreeDir::pack`
+    pub dirs: Vec<u8>,
+    /// Directories in this page too large to snapshot, to be listed with
+    /// `ListDir` instead
+    pub left_out: Vec<Inode>,
+    pub has_more: bool,
+    pub next_cursor: u64,
+    /// Pass as `since` to get what changes after this walk
+    pub mark: SnapshotMark,
+    /// Only changed directories were sent; otherwise this is a full snapshot
+    /// and anything cached from an earlier one should be dropped
+    pub incremental: bool,
+}
+
+impl TreeSnapshotResponse {
+    /// The directories in this page
+    pub fn dirs(&self) -> Result<Vec<TreeDir>, ProtocolError> {
+        TreeDir::unpack(&self.dirs)
+    }
+}
+
+/// One directory of a tree snapshot, listed in full
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TreeDir {
+    /// Attributes of the directory itself
+    pub attr: FileAttr,
+    pub entries: Vec<DirEntry>,
+    /// Attributes of `entries`, in the same order; `None` where an entry
+    /// couldn't be stat-ed
+    pub attrs: Vec<Option<FileAttr>>,
+}
+
+impl TreeDir {
+    /// Serialize and compress a page of directories
+    pub fn pack(dirs: &[TreeDir]) -> Result<Vec<u8>, ProtocolError> {
+        let raw =
+            bincode::serialize(dirs).map_err(|e| ProtocolError::Serialization(e.to_string()))?;
+        zstd::bulk::compress(&raw, TREE_SNAPSHOT_LEVEL)
+            .map_err(|e| ProtocolError::Serialization(e.to_string()))
+    }
+
+    /// Inverse of `pack`
+    ///
+    /// SECURITY: Pages decompressing past `MAX_TREE_SNAPSHOT_PAGE` are rejected
+    pub fn unpack(packed: &[u8]) -> Result<Vec<TreeDir>, ProtocolError> {
+        let raw = zstd::bulk::decompress(packed, MAX_TREE_SNAPSHOT_PAGE)
+            .map_err(|e| ProtocolError::Deserialization(e.to_string()))?;
+        bincode::deserialize(&raw).map_err(|e| ProtocolError::Deserialization(e.to_string()))
+    }
+}
+
 // === Lock Messages ===
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -331,6 +634,8 @@ pub enum DisconnectReason {
     IdleTimeout,
     ProtocolError,
     AuthenticationFailed,
+    /// The share expired or used up its limits
+    ShareExpired,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -482,6 +787,7 @@ mod tests {
             data: data.clone(),
             checksum: *checksum.as_bytes(),
             is_final: true,
+            zeroed: None,
         });
 
         let bytes = serialize_message(&msg).unwrap();
@@ -497,4 +803,839 @@ mod tests {
             _ => panic!("wrong message type"),
         }
     }
+
+    #[test]
+    fn test_zeroed_chunk_fills_back_in() {
+        let chunk = ReadChunkResponse {
+            chunk_id: ChunkId::new(42, 0),
+            data: Vec::new(),
+            checksum: *blake3::hash(&[0u8; 300]).as_bytes(),
+            is_final: true,
+            zeroed: Some(300),
+        };
+
+        assert_eq!(chunk.chunk_len(), 300);
+        let data = chunk.clone().into_data();
+        assert_eq!(data, vec![0u8; 300]);
+        assert_eq!(*blake3::hash(&data).as_bytes(), chunk.checksum);
+    }
+
+    #[test]
+    fn test_tree_dirs_pack() {
+        let dir = TreeDir {
+            attr: FileAttr::directory(1),
+            entries: vec![DirEntry::new("src", 2, crate::types::FileType::Directory)],
+            attrs: vec![Some(FileAttr::directory(2))],
+        };
+        let packed = TreeDir::pack(&[dir.clone(), dir]).unwrap();
+        let dirs = TreeDir::unpack(&packed).unwrap();
+        assert_eq!(dirs.len(), 2);
+        assert_eq!(dirs[1].entries[0].name, "src");
+        assert_eq!(dirs[1].attrs[0].as_ref().unwrap().inode, 2);
+
+        // A small page can't expand into a huge one
+        let bomb = zstd::bulk::compress(&vec![0u8; MAX_TREE_SNAPSHOT_PAGE + 1], 3).unwrap();
+        assert!(bomb.len() < 4096);
+        assert!(matches!(
+            TreeDir::unpack(&bomb),
+            Err(ProtocolError::Deserialization(_))
+        ));
+    }
+
+    mod properties {
+        use super::*;
+        use crate::types::{ContentChunk, ContentHash, FileType, LockType, ShareId, ShareInfo};
+        use crate::ErrorCode;
+        use proptest::collection::vec;
+        use proptest::prelude::*;
+        use proptest::sample::select;
+        use proptest::strategy::{Union, ValueTree};
+        use proptest::test_runner::TestRunner;
+
+        /// Number of `NetMessage` variants, all of which `message()` generates
+        const VARIANTS: usize = 59;
+
+        /// Position of the message's variant; fails to compile when one is added
+        fn variant_index(message: &NetMessage) -> usize {
+            match message {
+                NetMessage::Hello(_) => 0,
+                NetMessage::HelloAck(_) => 1,
+                NetMessage::ListDir(_) => 2,
+                NetMessage::ListDirResponse(_) => 3,
+                NetMessage::GetAttr(_) => 4,
+                NetMessage::GetAttrResponse(_) => 5,
+                NetMessage::Lookup(_) => 6,
+                NetMessage::LookupResponse(_) => 7,
+                NetMessage::ReadChunk(_) => 8,
+                NetMessage::ReadChunkResponse(_) => 9,
+                NetMessage::WriteChunk(_) => 10,
+                NetMessage::WriteChunkResponse(_) => 11,
+                NetMessage::AcquireLock(_) => 12,
+                NetMessage::AcquireLockResponse(_) => 13,
+                NetMessage::ReleaseLock(_) => 14,
+                NetMessage::ReleaseLockResponse(_) => 15,
+                NetMessage::CreateFile(_) => 16,
+                NetMessage::CreateFileResponse(_) => 17,
+                NetMessage::DeleteFile(_) => 18,
+                NetMessage::DeleteFileResponse(_) => 19,
+                NetMessage::CreateDir(_) => 20,
+                NetMessage::CreateDirResponse(_) => 21,
+                NetMessage::DeleteDir(_) => 22,
+                NetMessage::DeleteDirResponse(_) => 23,
+                NetMessage::Rename(_) => 24,
+                NetMessage::RenameResponse(_) => 25,
+                NetMessage::Truncate(_) => 26,
+                NetMessage::TruncateResponse(_) => 27,
+                NetMessage::SetAttr(_) => 28,
+                NetMessage::SetAttrResponse(_) => 29,
+                NetMessage::Ping(_) => 30,
+                NetMessage::Pong(_) => 31,
+                NetMessage::Error(_) => 32,
+                NetMessage::Goodbye(_) => 33,
+                NetMessage::Invalidate(_) => 34,
+                NetMessage::ListShares(_) => 35,
+                NetMessage::ListSharesResponse(_) => 36,
+                NetMessage::ManifestRequest(_) => 37,
+                NetMessage::ManifestResponse(_) => 38,
+                NetMessage::MissingChunksRequest(_) => 39,
+                NetMessage::MissingChunksResponse(_) => 40,
+                NetMessage::BulkChunkRequest(_) => 41,
+                NetMessage::BulkChunkResponse(_) => 42,
+                NetMessage::AuthChallenge(_) => 43,
+                NetMessage::AuthResponse(_) => 44,
+                NetMessage::BeginWrite(_) => 45,
+                NetMessage::BeginWriteResponse(_) => 46,
+                NetMessage::CommitWrite(_) => 47,
+                NetMessage::CommitWriteResponse(_) => 48,
+                NetMessage::AbortWrite(_) => 49,
+                NetMessage::AbortWriteResponse(_) => 50,
+                NetMessage::CopyRange(_) => 51,
+                NetMessage::CopyRangeResponse(_) => 52,
+                NetMessage::Seek(_) => 53,
+                NetMessage::SeekResponse(_) => 54,
+                NetMessage::Fallocate(_) => 55,
+                NetMessage::FallocateResponse(_) => 56,
+                NetMessage::TreeSnapshot(_) => 57,
+                NetMessage::TreeSnapshotResponse(_) => 58,
+            }
+        }
+
+        fn name() -> impl Strategy<Value = String> {
+            "\\PC{0,24}"
+        }
+
+        fn data() -> impl Strategy<Value = Vec<u8>> {
+            vec(any::<u8>(), 0..256)
+        }
+
+        fn token() -> impl Strategy<Value = LockToken> {
+            any::<[u8; 16]>().prop_map(LockToken)
+        }
+
+        fn maybe_token() -> impl Strategy<Value = Option<LockToken>> {
+            proptest::option::of(token())
+        }
+
+        fn hash() -> impl Strategy<Value = ContentHash> {
+            any::<[u8; 32]>().prop_map(ContentHash)
+        }
+
+        fn chunk_id() -> impl Strategy<Value = ChunkId> {
+            (any::<u64>(), any::<u64>()).prop_map(|(inode, index)| ChunkId::new(inode, index))
+        }
+
+        fn file_type() -> impl Strategy<Value = FileType> {
+            select(vec![FileType::File, FileType::Directory, FileType::Symlink])
+        }
+
+        fn mark() -> impl Strategy<Value = SnapshotMark> {
+            any::<(u64, u64)>().prop_map(|(epoch, taken_at)| SnapshotMark { epoch, taken_at })
+        }
+
+        fn attr() -> impl Strategy<Value = FileAttr> {
+            (
+                any::<u64>(),
+                file_type(),
+                any::<u64>(),
+                any::<[u32; 4]>(),
+                any::<[u64; 3]>(),
+                any::<[u32; 3]>(),
+            )
+                .prop_map(|(inode, file_type, size, ids, times, nsecs)| FileAttr {
+                    inode,
+                    file_type,
+                    size,
+                    mode: ids[0],
+                    nlink: ids[1],
+                    uid: ids[2],
+                    gid: ids[3],
+                    atime: times[0],
+                    atime_nsec: nsecs[0],
+                    mtime: times[1],
+                    mtime_nsec: nsecs[1],
+                    ctime: times[2],
+                    ctime_nsec: nsecs[2],
+                })
+        }
+
+        fn entry() -> impl Strategy<Value = DirEntry> {
+            (name(), any::<u64>(), file_type())
+                .prop_map(|(name, inode, file_type)| DirEntry::new(name, inode, file_type))
+        }
+
+        fn share() -> impl Strategy<Value = ShareInfo> {
+            (
+                any::<[u8; 8]>(),
+                name(),
+                name(),
+                any::<u64>(),
+                any::<(Option<u64>, Option<u64>, bool)>(),
+                proptest::option::of(name()),
+            )
+                .prop_map(
+                    |(
+                        id,
+                        name,
+                        host_name,
+                        root_inode,
+                        (total_size, file_count, writable),
+                        code,
+                    )| {
+                        ShareInfo {
+                            id: ShareId(id),
+                            name,
+                            host_name,
+                            root_inode,
+                            total_size,
+                            file_count,
+                            writable,
+                            join_code: code,
+                        }
+                    },
+                )
+        }
+
+        fn manifest() -> impl Strategy<Value = FileManifest> {
+            (
+                any::<u64>(),
+                any::<u64>(),
+                vec((hash(), any::<u32>(), any::<u64>()), 0..8),
+                proptest::option::of(hash()),
+            )
+                .prop_map(|(inode, total_size, chunks, file_hash)| FileManifest {
+                    inode,
+                    total_size,
+                    chunks: chunks
+                        .into_iter()
+                        .map(|(hash, size, offset)| ContentChunk { hash, size, offset })
+                        .collect(),
+                    file_hash,
+                })
+        }
+
+        fn error_code() -> impl Strategy<Value = ErrorCode> {
+            select(vec![
+                ErrorCode::Ok,
+                ErrorCode::Unknown,
+                ErrorCode::ProtocolError,
+                ErrorCode::NotImplemented,
+                ErrorCode::Timeout,
+                ErrorCode::FileNotFound,
+                ErrorCode::NotADirectory,
+                ErrorCode::NotAFile,
+                ErrorCode::PermissionDenied,
+                ErrorCode::PathTraversal,
+                ErrorCode::NameTooLong,
+                ErrorCode::AlreadyExists,
+                ErrorCode::NotEmpty,
+                ErrorCode::IoError,
+                ErrorCode::ChecksumMismatch,
+                ErrorCode::ChunkOutOfRange,
+                ErrorCode::LockNotHeld,
+                ErrorCode::LockExpired,
+                ErrorCode::LockConflict,
+                ErrorCode::LockRequired,
+                ErrorCode::SessionExpired,
+                ErrorCode::RateLimited,
+                ErrorCode::HostShuttingDown,
+                ErrorCode::AuthFailed,
+            ])
+        }
+
+        /// Outcome fields shared by the file operation responses
+        fn outcome() -> impl Strategy<Value = (bool, Option<FileAttr>, Option<String>)> {
+            (
+                any::<bool>(),
+                proptest::option::of(attr()),
+                proptest::option::of(name()),
+            )
+        }
+
+        fn message() -> impl Strategy<Value = NetMessage> {
+            let variants: Vec<BoxedStrategy<NetMessage>> = vec![
+                (any::<u32>(), any::<[u8; 16]>(), vec(name(), 0..4))
+                    .prop_map(|(protocol_version, client_id, capabilities)| {
+                        NetMessage::Hello(HelloMessage {
+                            protocol_version,
+                            client_id,
+                            capabilities,
+                        })
+                    })
+                    .boxed(),
+                (
+                    any::<u32>(),
+                    any::<[u8; 16]>(),
+                    any::<u64>(),
+                    name(),
+                    vec(name(), 0..4),
+                )
+                    .prop_map(
+                        |(protocol_version, session_id, root_inode, host_name, capabilities)| {
+                            NetMessage::HelloAck(HelloAckMessage {
+                                protocol_version,
+                                session_id,
+                                root_inode,
+                                host_name,
+                                capabilities,
+                            })
+                        },
+                    )
+                    .boxed(),
+                any::<(u64, u64, u32, bool)>()
+                    .prop_map(|(inode, offset, limit, with_attrs)| {
+                        NetMessage::ListDir(ListDirRequest {
+                            inode,
+                            offset,
+                            limit,
+                            with_attrs,
+                        })
+                    })
+                    .boxed(),
+                (
+                    vec(entry(), 0..8),
+                    any::<bool>(),
+                    any::<u64>(),
+                    vec(proptest::option::of(attr()), 0..8),
+                )
+                    .prop_map(|(entries, has_more, next_offset, attrs)| {
+                        NetMessage::ListDirResponse(ListDirResponse {
+                            entries,
+                            has_more,
+                            next_offset,
+                            attrs,
+                        })
+                    })
+                    .boxed(),
+                any::<u64>()
+                    .prop_map(|inode| NetMessage::GetAttr(GetAttrRequest { inode }))
+                    .boxed(),
+                proptest::option::of(attr())
+                    .prop_map(|attr| NetMessage::GetAttrResponse(GetAttrResponse { attr }))
+                    .boxed(),
+                (any::<u64>(), name())
+                    .prop_map(|(parent, name)| NetMessage::Lookup(LookupRequest { parent, name }))
+                    .boxed(),
+                proptest::option::of(attr())
+                    .prop_map(|attr| NetMessage::LookupResponse(LookupResponse { attr }))
+                    .boxed(),
+                (chunk_id(), any::<u8>())
+                    .prop_map(|(chunk_id, priority)| {
+                        NetMessage::ReadChunk(ReadChunkRequest { chunk_id, priority })
+                    })
+                    .boxed(),
+                (
+                    chunk_id(),
+                    data(),
+                    any::<[u8; 32]>(),
+                    any::<bool>(),
+                    any::<Option<u32>>(),
+                )
+                    .prop_map(|(chunk_id, data, checksum, is_final, zeroed)| {
+                        NetMessage::ReadChunkResponse(ReadChunkResponse {
+                            chunk_id,
+                            data,
+                            checksum,
+                            is_final,
+                            zeroed,
+                        })
+                    })
+                    .boxed(),
+                (chunk_id(), data(), any::<[u8; 32]>(), token())
+                    .prop_map(|(chunk_id, data, checksum, lock_token)| {
+                        NetMessage::WriteChunk(WriteChunkRequest {
+                            chunk_id,
+                            data,
+                            checksum,
+                            lock_token,
+                        })
+                    })
+                    .boxed(),
+                (chunk_id(), any::<bool>(), any::<Option<u64>>())
+                    .prop_map(|(chunk_id, success, new_size)| {
+                        NetMessage::WriteChunkResponse(WriteChunkResponse {
+                            chunk_id,
+                            success,
+                            new_size,
+                        })
+                    })
+                    .boxed(),
+                (
+                    any::<u64>(),
+                    select(vec![LockType::Shared, LockType::Exclusive]),
+                    any::<u32>(),
+                )
+                    .prop_map(|(inode, lock_type, timeout_ms)| {
+                        NetMessage::AcquireLock(LockRequest {
+                            inode,
+                            lock_type,
+                            timeout_ms,
+                        })
+                    })
+                    .boxed(),
+                (
+                    any::<bool>(),
+                    maybe_token(),
+                    proptest::option::of(name()),
+                    any::<Option<u32>>(),
+                )
+                    .prop_map(|(granted, token, holder, retry_after_ms)| {
+                        NetMessage::AcquireLockResponse(LockResponse {
+                            granted,
+                            token,
+                            holder,
+                            retry_after_ms,
+                        })
+                    })
+                    .boxed(),
+                token()
+                    .prop_map(|token| NetMessage::ReleaseLock(ReleaseRequest { token }))
+                    .boxed(),
+                any::<bool>()
+                    .prop_map(|success| {
+                        NetMessage::ReleaseLockResponse(ReleaseResponse { success })
+                    })
+                    .boxed(),
+                (any::<u64>(), name(), any::<u32>(), maybe_token())
+                    .prop_map(|(parent, name, mode, lock_token)| {
+                        NetMessage::CreateFile(CreateFileRequest {
+                            parent,
+                            name,
+                            mode,
+                            lock_token,
+                        })
+                    })
+                    .boxed(),
+                outcome()
+                    .prop_map(|(success, attr, error)| {
+                        NetMessage::CreateFileResponse(CreateFileResponse {
+                            success,
+                            attr,
+                            error,
+                        })
+                    })
+                    .boxed(),
+                (any::<u64>(), name(), maybe_token())
+                    .prop_map(|(parent, name, lock_token)| {
+                        NetMessage::DeleteFile(DeleteFileRequest {
+                            parent,
+                            name,
+                            lock_token,
+                        })
+                    })
+                    .boxed(),
+                outcome()
+                    .prop_map(|(success, _, error)| {
+                        NetMessage::DeleteFileResponse(DeleteFileResponse { success, error })
+                    })
+                    .boxed(),
+                (any::<u64>(), name(), any::<u32>())
+                    .prop_map(|(parent, name, mode)| {
+                        NetMessage::CreateDir(CreateDirRequest { parent, name, mode })
+                    })
+                    .boxed(),
+                outcome()
+                    .prop_map(|(success, attr, error)| {
+                        NetMessage::CreateDirResponse(CreateDirResponse {
+                            success,
+                            attr,
+                            error,
+                        })
+                    })
+                    .boxed(),
+                (any::<u64>(), name())
+                    .prop_map(|(parent, name)| {
+                        NetMessage::DeleteDir(DeleteDirRequest { parent, name })
+                    })
+                    .boxed(),
+                outcome()
+                    .prop_map(|(success, _, error)| {
+                        NetMessage::DeleteDirResponse(DeleteDirResponse { success, error })
+                    })
+                    .boxed(),
+                (any::<u64>(), name(), any::<u64>(), name(), maybe_token())
+                    .prop_map(|(old_parent, old_name, new_parent, new_name, lock_token)| {
+                        NetMessage::Rename(RenameRequest {
+                            old_parent,
+                            old_name,
+                            new_parent,
+                            new_name,
+                            lock_token,
+                        })
+                    })
+                    .boxed(),
+                outcome()
+                    .prop_map(|(success, _, error)| {
+                        NetMessage::RenameResponse(RenameResponse { success, error })
+                    })
+                    .boxed(),
+                (any::<u64>(), any::<u64>(), maybe_token())
+                    .prop_map(|(inode, size, lock_token)| {
+                        NetMessage::Truncate(TruncateRequest {
+                            inode,
+                            size,
+                            lock_token,
+                        })
+                    })
+                    .boxed(),
+                outcome()
+                    .prop_map(|(success, new_attr, error)| {
+                        NetMessage::TruncateResponse(TruncateResponse {
+                            success,
+                            new_attr,
+                            error,
+                        })
+                    })
+                    .boxed(),
+                (
+                    any::<u64>(),
+                    any::<(Option<u64>, Option<u32>, Option<u64>, Option<u64>)>(),
+                    maybe_token(),
+                )
+                    .prop_map(|(inode, (size, mode, mtime, atime), lock_token)| {
+                        NetMessage::SetAttr(SetAttrRequest {
+                            inode,
+                            size,
+                            mode,
+                            mtime,
+                            atime,
+                            lock_token,
+                        })
+                    })
+                    .boxed(),
+                outcome()
+                    .prop_map(|(success, attr, error)| {
+                        NetMessage::SetAttrResponse(SetAttrResponse {
+                            success,
+                            attr,
+                            error,
+                        })
+                    })
+                    .boxed(),
+                any::<(u64, [u8; 8])>()
+                    .prop_map(|(timestamp, payload)| {
+                        NetMessage::Ping(PingMessage { timestamp, payload })
+                    })
+                    .boxed(),
+                any::<(u64, u64, [u8; 8])>()
+                    .prop_map(|(client_timestamp, server_timestamp, payload)| {
+                        NetMessage::Pong(PongMessage {
+                            client_timestamp,
+                            server_timestamp,
+                            payload,
+                        })
+                    })
+                    .boxed(),
+                (error_code(), name(), any::<Option<u64>>())
+                    .prop_map(|(code, message, related_inode)| {
+                        NetMessage::Error(ErrorMessage {
+                            code,
+                            message,
+                            related_inode,
+                        })
+                    })
+                    .boxed(),
+                select(vec![
+                    DisconnectReason::ClientShutdown,
+                    DisconnectReason::HostShutdown,
+                    DisconnectReason::IdleTimeout,
+                    DisconnectReason::ProtocolError,
+                    DisconnectReason::AuthenticationFailed,
+                    DisconnectReason::ShareExpired,
+                ])
+                .prop_map(|reason| NetMessage::Goodbye(GoodbyeMessage { reason }))
+                .boxed(),
+                (
+                    vec(any::<u64>(), 0..8),
+                    select(vec![
+                        InvalidateReason::Modified,
+                        InvalidateReason::Deleted,
+                        InvalidateReason::Renamed,
+                        InvalidateReason::AttributeChanged,
+                    ]),
+                )
+                    .prop_map(|(inodes, reason)| {
+                        NetMessage::Invalidate(InvalidateMessage { inodes, reason })
+                    })
+                    .boxed(),
+                any::<Option<[u8; 8]>>()
+                    .prop_map(|filter| {
+                        NetMessage::ListShares(ListSharesRequest {
+                            filter_id: filter.map(ShareId),
+                        })
+                    })
+                    .boxed(),
+                vec(share(), 0..4)
+                    .prop_map(|shares| {
+                        NetMessage::ListSharesResponse(ListSharesResponse { shares })
+                    })
+                    .boxed(),
+                any::<(u64, u64)>()
+                    .prop_map(|(inode, file_size)| {
+                        NetMessage::ManifestRequest(ManifestRequestMsg { inode, file_size })
+                    })
+                    .boxed(),
+                (manifest(), proptest::option::of(name()))
+                    .prop_map(|(manifest, error)| {
+                        NetMessage::ManifestResponse(ManifestResponseMsg { manifest, error })
+                    })
+                    .boxed(),
+                manifest()
+                    .prop_map(|manifest| {
+                        NetMessage::MissingChunksRequest(MissingChunksRequestMsg { manifest })
+                    })
+                    .boxed(),
+                (vec(hash(), 0..8), any::<u64>())
+                    .prop_map(|(missing_hashes, missing_bytes)| {
+                        NetMessage::MissingChunksResponse(MissingChunksResponseMsg {
+                            missing_hashes,
+                            missing_bytes,
+                        })
+                    })
+                    .boxed(),
+                (hash(), any::<u8>(), any::<u64>())
+                    .prop_map(|(hash, priority, transfer_id)| {
+                        NetMessage::BulkChunkRequest(BulkChunkRequestMsg {
+                            hash,
+                            priority,
+                            transfer_id,
+                        })
+                    })
+                    .boxed(),
+                (
+                    hash(),
+                    data(),
+                    any::<bool>(),
+                    any::<u32>(),
+                    proptest::option::of(name()),
+                )
+                    .prop_map(|(hash, data, compressed, original_size, error)| {
+                        NetMessage::BulkChunkResponse(BulkChunkResponseMsg {
+                            hash,
+                            data,
+                            compressed,
+                            original_size,
+                            error,
+                        })
+                    })
+                    .boxed(),
+                any::<[u8; 32]>()
+                    .prop_map(|nonce| NetMessage::AuthChallenge(AuthChallengeMessage { nonce }))
+                    .boxed(),
+                any::<[u8; 32]>()
+                    .prop_map(|proof| NetMessage::AuthResponse(AuthResponseMessage { proof }))
+                    .boxed(),
+                (any::<u64>(), token())
+                    .prop_map(|(inode, lock_token)| {
+                        NetMessage::BeginWrite(BeginWriteRequest { inode, lock_token })
+                    })
+                    .boxed(),
+                outcome()
+                    .prop_map(|(success, _, error)| {
+                        NetMessage::BeginWriteResponse(BeginWriteResponse { success, error })
+                    })
+                    .boxed(),
+                (any::<u64>(), token())
+                    .prop_map(|(inode, lock_token)| {
+                        NetMessage::CommitWrite(CommitWriteRequest { inode, lock_token })
+                    })
+                    .boxed(),
+                outcome()
+                    .prop_map(|(success, attr, error)| {
+                        NetMessage::CommitWriteResponse(CommitWriteResponse {
+                            success,
+                            attr,
+                            error,
+                        })
+                    })
+                    .boxed(),
+                (any::<u64>(), token())
+                    .prop_map(|(inode, lock_token)| {
+                        NetMessage::AbortWrite(AbortWriteRequest { inode, lock_token })
+                    })
+                    .boxed(),
+                any::<bool>()
+                    .prop_map(|success| {
+                        NetMessage::AbortWriteResponse(AbortWriteResponse { success })
+                    })
+                    .boxed(),
+                (any::<[u64; 5]>(), token())
+                    .prop_map(|(n, lock_token)| {
+                        NetMessage::CopyRange(CopyRangeRequest {
+                            src_inode: n[0],
+                            src_offset: n[1],
+                            dst_inode: n[2],
+                            dst_offset: n[3],
+                            len: n[4],
+                            lock_token,
+                        })
+                    })
+                    .boxed(),
+                any::<(bool, u64, Option<u64>)>()
+                    .prop_map(|(success, copied, new_size)| {
+                        NetMessage::CopyRangeResponse(CopyRangeResponse {
+                            success,
+                            copied,
+                            new_size,
+                        })
+                    })
+                    .boxed(),
+                (
+                    any::<(u64, u64)>(),
+                    select(vec![SeekWhence::Data, SeekWhence::Hole]),
+                )
+                    .prop_map(|((inode, offset), whence)| {
+                        NetMessage::Seek(SeekRequest {
+                            inode,
+                            offset,
+                            whence,
+                        })
+                    })
+                    .boxed(),
+                any::<Option<u64>>()
+                    .prop_map(|offset| NetMessage::SeekResponse(SeekResponse { offset }))
+                    .boxed(),
+                (
+                    any::<[u64; 3]>(),
+                    select(vec![
+                        FallocateMode::Allocate,
+                        FallocateMode::AllocateKeepSize,
+                        FallocateMode::PunchHole,
+                    ]),
+                    token(),
+                )
+                    .prop_map(|(n, mode, lock_token)| {
+                        NetMessage::Fallocate(FallocateRequest {
+                            inode: n[0],
+                            offset: n[1],
+                            len: n[2],
+                            mode,
+                            lock_token,
+                        })
+                    })
+                    .boxed(),
+                (
+                    any::<bool>(),
+                    proptest::option::of(attr()),
+                    proptest::option::of(name()),
+                )
+                    .prop_map(|(success, attr, error)| {
+                        NetMessage::FallocateResponse(FallocateResponse {
+                            success,
+                            attr,
+                            error,
+                        })
+                    })
+                    .boxed(),
+                (
+                    any::<[u64; 2]>(),
+                    any::<u32>(),
+                    proptest::option::of(mark()),
+                )
+                    .prop_map(|(n, max_depth, since)| {
+                        NetMessage::TreeSnapshot(TreeSnapshotRequest {
+                            root: n[0],
+                            max_depth,
+                            cursor: n[1],
+                            since,
+                        })
+                    })
+                    .boxed(),
+                (
+                    vec(any::<u8>(), 0..64),
+                    vec(any::<u64>(), 0..4),
+                    any::<(bool, u64, bool)>(),
+                    mark(),
+                )
+                    .prop_map(
+                        |(dirs, left_out, (has_more, next_cursor, incremental), mark)| {
+                            NetMessage::TreeSnapshotResponse(TreeSnapshotResponse {
+                                dirs,
+                                left_out,
+                                has_more,
+                                next_cursor,
+                                mark,
+                                incremental,
+                            })
+                        },
+                    )
+                    .boxed(),
+            ];
+            Union::new(variants)
+        }
+
+        #[test]
+        fn test_generator_covers_every_variant() {
+            let mut runner = TestRunner::deterministic();
+            let strategy = message();
+            let mut seen = [false; VARIANTS];
+            for _ in 0..VARIANTS * 50 {
+                let message = strategy.new_tree(&mut runner).unwrap().current();
+                seen[variant_index(&message)] = true;
+            }
+            let missing: Vec<_> = (0..VARIANTS).filter(|&i| !seen[i]).collect();
+            assert!(
+                missing.is_empty(),
+                "variants never generated: {:?}",
+                missing
+            );
+        }
+
+        proptest! {
+            #[test]
+            fn prop_roundtrip_preserves_message(message in message()) {
+                let bytes = serialize_message(&message).unwrap();
+                let len = u32::from_le_bytes(bytes[..4].try_into().unwrap()) as usize;
+                prop_assert_eq!(len, bytes.len() - 4);
+
+                let decoded = deserialize_message(&bytes[4..]).unwrap();
+                prop_assert_eq!(variant_index(&decoded), variant_index(&message));
+                prop_assert_eq!(serialize_message(&decoded).unwrap(), bytes);
+            }
+
+            #[test]
+            fn prop_decoder_rejects_garbage_without_panicking(bytes in vec(any::<u8>(), 0..512)) {
+                let _ = deserialize_message(&bytes);
+            }
+
+            #[test]
+            fn prop_decoder_survives_corrupted_messages(
+                message in message(),
+                position in any::<prop::sample::Index>(),
+                byte in any::<u8>(),
+                truncate in any::<bool>(),
+            ) {
+                let mut bytes = serialize_message(&message).unwrap().split_off(4);
+                if bytes.is_empty() {
+                    return Ok(());
+                }
+                let position = position.index(bytes.len());
+                if truncate {
+                    bytes.truncate(position);
+                } else {
+                    bytes[position] = byte;
+                }
+                let _ = deserialize_message(&bytes);
+            }
+        }
+    }
 }
@@ -4,17 +4,21 @@
 //! - BLAKE3 hashing performance
 //! - Sequential file read throughput
 //! - Buffer pool acquisition overhead
+//! - Concurrent chunk reads through `AsyncIO` (blocking pool vs io_uring)
 //! - Compression performance
 //!
 //! Run with: cargo bench --bench throughput -p teleport-daemon
+//! (add `--features io-uring` to include io_uring on Linux)
 
 use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
 use std::hint::black_box as hint_black_box;
+use std::sync::Arc;
 
 // Import from teleport-core
 use teleport_core::buffer_pool::{BufferPool, BULK_CHUNK_SIZE, RANDOM_CHUNK_SIZE};
 use teleport_core::compression::SmartCompressor;
 use teleport_core::types::ContentHash;
+use teleport_core::AsyncIO;
 
 /// Benchmark BLAKE3 hashing at different sizes
 fn bench_blake3_hashing(c: &mut Criterion) {
@@ -73,6 +77,76 @@ fn bench_buffer_pool(c: &mut Criterion) {
     group.finish();
 }
 
+/// Benchmark concurrent 128KB chunk reads, as a busy host does them
+fn bench_async_io(c: &mut Criterion) {
+    const CONCURRENT_READS: usize = 64;
+    const FILE_CHUNKS: usize = 256; // 32 MB
+
+    let dir = tempfile::tempdir().expect("temp dir");
+    let path = dir.path().join("chunks.bin");
+    std::fs::write(&path, vec![0x5a; RANDOM_CHUNK_SIZE * FILE_CHUNKS]).expect("write test file");
+    let file = Arc::new(std::fs::File::open(&path).expect("open test file"));
+    let pool = BufferPool::new(CONCURRENT_READS, RANDOM_CHUNK_SIZE);
+    let runtime = tokio::runtime::Runtime::new().expect("tokio runtime");
+
+    // Blocking calls on tokio's blocking pool, the default
+    #[cfg(target_os = "linux")]
+    let blocking: (&str, Box<dyn AsyncIO>) =
+        ("pread", Box::new(teleport_core::io::linux::LinuxIO::new()));
+    #[cfg(not(target_os = "linux"))]
+    let blocking: (&str, Box<dyn AsyncIO>) = (
+        "fallback",
+        Box::new(teleport_core::io::fallback::FallbackIO::new()),
+    );
+    let backends: Vec<_> = std::iter::once(blocking)
+        .chain(io_uring(&pool))
+        .collect();
+
+    let mut group = c.benchmark_group("async_io");
+    group.throughput(Throughput::Bytes(
+        (RANDOM_CHUNK_SIZE * CONCURRENT_READS) as u64,
+    ));
+
+    for (name, io) in &backends {
+        group.bench_function(BenchmarkId::new("read_128kb_x64", name), |b| {
+            b.iter(|| {
+                runtime.block_on(async {
+                    let reads = (0..CONCURRENT_READS).map(|i| {
+                        let file = file.clone();
+                        let pool = &pool;
+                        // Spread reads over the file rather than reading it in order
+                        let offset = ((i * 37) % FILE_CHUNKS * RANDOM_CHUNK_SIZE) as u64;
+                        async move {
+                            let buffer = pool.acquire().await;
+                            io.read_at(file, offset, buffer).await.expect("read").1
+                        }
+                    });
+                    hint_black_box(futures_util::future::join_all(reads).await)
+                })
+            })
+        });
+    }
+
+    group.finish();
+}
+
+/// io_uring with `pool` registered, if built with it and the kernel supports it
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+fn io_uring(pool: &Arc<BufferPool>) -> Option<(&'static str, Box<dyn AsyncIO>)> {
+    match teleport_core::io::uring::IoUringIO::with_buffers(pool) {
+        Ok(io) => Some(("io_uring", Box::new(io))),
+        Err(e) => {
+            eprintln!("Skipping io_uring: {}", e);
+            None
+        }
+    }
+}
+
+#[cfg(not(all(target_os = "linux", feature = "io-uring")))]
+fn io_uring(_pool: &Arc<BufferPool>) -> Option<(&'static str, Box<dyn AsyncIO>)> {
+    None
+}
+
 /// Benchmark compression decisions and operations
 fn bench_compression(c: &mut Criterion) {
     let mut group = c.benchmark_group("compression");
@@ -199,6 +273,7 @@ criterion_group!(
     benches,
     bench_blake3_hashing,
     bench_buffer_pool,
+    bench_async_io,
     bench_compression,
     bench_decompression,
     bench_memory_throughput,
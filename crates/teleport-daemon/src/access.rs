@@ -0,0 +1,737 @@
+//! Per-share access control lists
+//!
+//! Each share has its own list of rules, stored in the config directory and
+//! keyed by the share name:
+//!
+//! ```text
+//! ~/.config/wormhole/access/photos.toml
+//!
+//! default = "read"
+//!
+//! [[rule]]
+//! principal = "client:3f2a..."
+//! name = "laptop"
+//! level = "write"
+//!
+//! [[rule]]
+//! principal = "peer:studio-mac"
+//! level = "admin"
+//!
+//! [[rule]]
+//! principal = "ip:192.168.1.0/24"
+//! level = "none"
+//! ```
+//!
+//! A client is matched by the fingerprint of the certificate it presented
+//! (see `identity`), then by its name in the known-peers store, then by the
+//! most specific network containing its address. Clients no rule matches get
+//! the `default` level, or write access if none is set.
+//!
+//! A host reads its share's list when it starts. Edits made while it runs
+//! take effect after a restart; until then the host logs them as needing one.
+
+use std::collections::HashMap;
+use std::fs;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+use directories::ProjectDirs;
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use teleport_core::{ErrorCode, ErrorMessage, LockType, NetMessage};
+use tracing::{debug, warn};
+
+use crate::known_peers::{parse_fingerprint, KnownPeers};
+use crate::net::CertFingerprint;
+
+/// Directory (inside the config directory) holding the per-share files
+const ACCESS_DIR: &str = "access";
+
+/// Access granted to a principal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessLevel {
+    /// No access; the client is refused
+    None,
+    /// Read files and list directories
+    Read,
+    /// Read, create, modify and delete
+    Write,
+    /// Everything, including managing the share
+    Admin,
+}
+
+impl std::fmt::Display for AccessLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AccessLevel::None => write!(f, "none"),
+            AccessLevel::Read => write!(f, "read"),
+            AccessLevel::Write => write!(f, "write"),
+            AccessLevel::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+/// Who a rule applies to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Principal {
+    /// A client identified by its certificate fingerprint
+    Client(CertFingerprint),
+    /// A client whose certificate is stored under this name in known peers
+    Peer(String),
+    /// Any client connecting from this network (a single address is a /32 or /128)
+    Network(IpNet),
+}
+
+impl Principal {
+    /// Principal for a single address
+    pub fn ip(addr: IpAddr) -> Self {
+        Principal::Network(IpNet::from(addr))
+    }
+}
+
+impl std::str::FromStr for Principal {
+    type Err = AccessError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parsed = match s.split_once(':') {
+            Some(("client", fp)) => parse_fingerprint(fp).map(Principal::Client),
+            Some(("peer", name)) if !name.is_empty() => Some(Principal::Peer(name.to_string())),
+            Some(("ip", net)) => parse_network(net).map(Principal::Network),
+            _ => None,
+        };
+        parsed.ok_or_else(|| AccessError::InvalidPrincipal(s.to_string()))
+    }
+}
+
+impl std::fmt::Display for Principal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Principal::Client(fp) => write!(f, "client:{}", hex::encode(fp)),
+            Principal::Peer(name) => write!(f, "peer:{}", name),
+            Principal::Network(net) if net.prefix_len() == net.max_prefix_len() => {
+                write!(f, "ip:{}", net.addr())
+            }
+            Principal::Network(net) => write!(f, "ip:{}", net),
+        }
+    }
+}
+
+/// Parse a network in CIDR notation or a single address
+fn parse_network(s: &str) -> Option<IpNet> {
+    s.parse::<IpNet>()
+        .map(|net| net.trunc())
+        .ok()
+        .or_else(|| s.parse::<IpAddr>().ok().map(IpNet::from))
+}
+
+/// What the host knows about a connecting client
+#[derive(Debug, Clone)]
+pub struct ClientInfo<'a> {
+    /// Fingerprint of the client certificate, if one was presented
+    pub fingerprint: Option<&'a CertFingerprint>,
+    /// Name of the client in the host's known-peers store
+    pub peer_name: Option<&'a str>,
+    /// Remote address
+    pub ip: IpAddr,
+}
+
+impl Serialize for Principal {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Principal {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// A single access rule
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccessRule {
+    pub principal: Principal,
+    /// Friendly name the rule was created with, for display only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub level: AccessLevel,
+}
+
+/// Access control list for one share
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AccessList {
+    /// Level for clients no rule matches (write access when unset)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    default: Option<AccessLevel>,
+    #[serde(default, rename = "rule")]
+    rules: Vec<AccessRule>,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+impl AccessList {
+    /// Default location of the list for `share`
+    pub fn default_path(share: &str) -> Option<PathBuf> {
+        ProjectDirs::from("", "", "wormhole").map(|dirs| {
+            dirs.config_dir()
+                .join(ACCESS_DIR)
+                .join(format!("{}.toml", sanitize_share_name(share)))
+        })
+    }
+
+    /// Load the list for `share` from the default location
+    pub fn load(share: &str) -> Result<Self, AccessError> {
+        let path = Self::default_path(share).ok_or(AccessError::NoConfigDir)?;
+        Self::load_from(&path)
+    }
+
+    /// Load a list from a specific file (missing file = no rules)
+    pub fn load_from(path: &Path) -> Result<Self, AccessError> {
+        let mut list = if path.exists() {
+            let content = fs::read_to_string(path).map_err(|e| AccessError::Io(e.to_string()))?;
+            toml::from_str::<AccessList>(&content).map_err(|e| AccessError::Parse(e.to_string()))?
+        } else {
+            debug!("Access list {:?} not found, no rules", path);
+            AccessList::default()
+        };
+        list.path = Some(path.to_path_buf());
+        Ok(list)
+    }
+
+    /// Write the list back to the file it was loaded from
+    pub fn save(&self) -> Result<(), AccessError> {
+        let path = self.path.as_ref().ok_or(AccessError::NoConfigDir)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| AccessError::Io(e.to_string()))?;
+        }
+        let content =
+            toml::to_string_pretty(self).map_err(|e| AccessError::Serialize(e.to_string()))?;
+
+        let tmp = path.with_extension("toml.tmp");
+        fs::write(&tmp, content).map_err(|e| AccessError::Io(e.to_string()))?;
+        fs::rename(&tmp, path).map_err(|e| AccessError::Io(e.to_string()))?;
+        Ok(())
+    }
+
+    /// All rules
+    pub fn rules(&self) -> &[AccessRule] {
+        &self.rules
+    }
+
+    /// Level for clients no rule matches, if set
+    pub fn default_level(&self) -> Option<AccessLevel> {
+        self.default
+    }
+
+    /// Set the level for clients no rule matches
+    pub fn set_default_level(&mut self, level: AccessLevel) {
+        self.default = Some(level);
+    }
+
+    /// Whether the list has no rules and no default, i.e. allows everything
+    pub fn is_empty(&self) -> bool {
+        self.default.is_none() && self.rules.is_empty()
+    }
+
+    /// Whether any rule names a known peer, needing `PeerNames` to match
+    pub fn uses_peer_names(&self) -> bool {
+        self.rules
+            .iter()
+            .any(|r| matches!(r.principal, Principal::Peer(_)))
+    }
+
+    /// Grant `level` to `principal`, replacing any existing rule for it
+    pub fn grant(&mut self, principal: Principal, name: Option<String>, level: AccessLevel) {
+        match self.rules.iter_mut().find(|r| r.principal == principal) {
+            Some(existing) => {
+                existing.name = name;
+                existing.level = level;
+            }
+            None => self.rules.push(AccessRule {
+                principal,
+                name,
+                level,
+            }),
+        }
+    }
+
+    /// Remove the rule for `principal`; returns whether one existed
+    pub fn revoke(&mut self, principal: &Principal) -> bool {
+        let before = self.rules.len();
+        self.rules.retain(|r| r.principal != *principal);
+        self.rules.len() != before
+    }
+
+    /// Access level of a client, if any rule matches it
+    ///
+    /// A rule for the client's certificate takes precedence over one for its
+    /// peer name, which takes precedence over the most specific network rule.
+    pub fn level_for(&self, client: &ClientInfo<'_>) -> Option<AccessLevel> {
+        let by_identity = client.fingerprint.and_then(|fp| {
+            self.rules
+                .iter()
+                .find(|r| r.principal == Principal::Client(*fp))
+        });
+        let by_name = || {
+            client.peer_name.and_then(|name| {
+                self.rules
+                    .iter()
+                    .find(|r| matches!(&r.principal, Principal::Peer(p) if p == name))
+            })
+        };
+        let by_network = || {
+            self.rules
+                .iter()
+                .filter_map(|r| match &r.principal {
+                    Principal::Network(net) if net.contains(&client.ip) => {
+                        Some((net.prefix_len(), r))
+                    }
+                    _ => None,
+                })
+                .max_by_key(|(prefix_len, _)| *prefix_len)
+                .map(|(_, r)| r)
+        };
+        by_identity
+            .or_else(by_name)
+            .or_else(by_network)
+            .map(|r| r.level)
+    }
+
+    /// Access level of a client, falling back to the default level
+    pub fn effective_level(&self, client: &ClientInfo<'_>) -> AccessLevel {
+        self.level_for(client)
+            .or(self.default)
+            .unwrap_or(AccessLevel::Write)
+    }
+}
+
+/// Level of a connecting client on a share with an optional access list
+///
+/// Without a list every client has full access. The client's peer name comes
+/// from `peers`, by certificate fingerprint.
+pub fn client_level(
+    list: Option<&AccessList>,
+    peers: &PeerNames,
+    fingerprint: Option<&CertFingerprint>,
+    ip: IpAddr,
+) -> AccessLevel {
+    let Some(list) = list else {
+        return AccessLevel::Admin;
+    };
+    list.effective_level(&ClientInfo {
+        fingerprint,
+        peer_name: fingerprint.and_then(|fp| peers.name_of(fp)),
+        ip: ip.to_canonical(),
+    })
+}
+
+/// Names of the host's known peers by certificate fingerprint, for `peer:` rules
+///
+/// Hosts read the store once when they start serving rather than on every
+/// handshake, so peers added while a share is up take effect on restart.
+#[derive(Debug, Clone, Default)]
+pub struct PeerNames {
+    names: HashMap<CertFingerprint, String>,
+}
+
+impl PeerNames {
+    /// Read the known-peers store from the default location
+    ///
+    /// This blocks on the disk; call it off the async runtime. A store that
+    /// can't be read gives no names, so `peer:` rules match no one.
+    pub fn load() -> Self {
+        match KnownPeers::load() {
+            Ok(peers) => Self::from_peers(&peers),
+            Err(e) => {
+                warn!(
+                    "Known peers unavailable, peer: access rules will not match: {}",
+                    e
+                );
+                Self::default()
+            }
+        }
+    }
+
+    pub fn from_peers(peers: &KnownPeers) -> Self {
+        let names = peers
+            .peers()
+            .iter()
+            .filter_map(|p| Some((parse_fingerprint(&p.fingerprint)?, p.name.clone())))
+            .collect();
+        Self { names }
+    }
+
+    /// Name of the peer with this certificate, if known
+    pub fn name_of(&self, fingerprint: &CertFingerprint) -> Option<&str> {
+        self.names.get(fingerprint).map(String::as_str)
+    }
+}
+
+/// Address allow and block lists, checked before a connection is accepted
+///
+/// A blocked network always wins; when an allow list is set, only addresses
+/// inside it are accepted.
+#[derive(Debug, Clone, Default)]
+pub struct IpFilter {
+    allow: Vec<IpNet>,
+    block: Vec<IpNet>,
+}
+
+impl IpFilter {
+    /// Parse allow and block lists of addresses or CIDR networks
+    pub fn parse<S: AsRef<str>>(allow: &[S], block: &[S]) -> Result<Self, AccessError> {
+        let parse_all = |list: &[S]| {
+            list.iter()
+                .map(|s| {
+                    let s = s.as_ref().trim();
+                    parse_network(s).ok_or_else(|| AccessError::InvalidNetwork(s.to_string()))
+                })
+                .collect::<Result<Vec<_>, _>>()
+        };
+        Ok(Self {
+            allow: parse_all(allow)?,
+            block: parse_all(block)?,
+        })
+    }
+
+    /// Whether the filter lets every address through
+    pub fn is_empty(&self) -> bool {
+        self.allow.is_empty() && self.block.is_empty()
+    }
+
+    /// Whether a connection from `ip` may be accepted
+    pub fn permits(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        if self.block.iter().any(|net| net.contains(&ip)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|net| net.contains(&ip))
+    }
+}
+
+/// Level a request needs, or `None` for requests every client may send
+pub fn required_level(request: &NetMessage) -> Option<AccessLevel> {
+    match request {
+        NetMessage::Hello(_) | NetMessage::Ping(_) | NetMessage::Goodbye(_) => None,
+        NetMessage::ListDir(_)
+        | NetMessage::GetAttr(_)
+        | NetMessage::Lookup(_)
+        | NetMessage::ReadChunk(_)
+        | NetMessage::Seek(_)
+        | NetMessage::TreeSnapshot(_)
+        | NetMessage::ReleaseLock(_)
+        | NetMessage::ListShares(_)
+        | NetMessage::ManifestRequest(_)
+        | NetMessage::MissingChunksRequest(_)
+        | NetMessage::BulkChunkRequest(_) => Some(AccessLevel::Read),
+        NetMessage::AcquireLock(req) => match req.lock_type {
+            LockType::Shared => Some(AccessLevel::Read),
+            LockType::Exclusive => Some(AccessLevel::Write),
+        },
+        NetMessage::WriteChunk(_)
+        | NetMessage::CreateFile(_)
+        | NetMessage::DeleteFile(_)
+        | NetMessage::CreateDir(_)
+        | NetMessage::DeleteDir(_)
+        | NetMessage::Rename(_)
+        | NetMessage::Truncate(_)
+        | NetMessage::SetAttr(_)
+        | NetMessage::BeginWrite(_)
+        | NetMessage::CommitWrite(_)
+        | NetMessage::AbortWrite(_)
+        | NetMessage::CopyRange(_)
+        | NetMessage::Fallocate(_) => Some(AccessLevel::Write),
+        // Responses and anything unrecognized are never valid requests
+        _ => Some(AccessLevel::Admin),
+    }
+}
+
+/// Check a request against the client's level
+///
+/// Returns the `PermissionDenied` error to send back when it is not allowed.
+pub fn check_request(request: &NetMessage, level: AccessLevel) -> Result<(), NetMessage> {
+    match required_level(request) {
+        Some(required) if level < required => Err(NetMessage::Error(ErrorMessage {
+            code: ErrorCode::PermissionDenied,
+            message: format!("{} access required", required),
+            related_inode: None,
+        })),
+        _ => Ok(()),
+    }
+}
+
+/// Refuse requests that modify a read-only share
+pub fn check_writable(request: &NetMessage, writable: bool) -> Result<(), NetMessage> {
+    if writable || required_level(request) != Some(AccessLevel::Write) {
+        return Ok(());
+    }
+    Err(NetMessage::Error(ErrorMessage {
+        code: ErrorCode::PermissionDenied,
+        message: "share is read-only".into(),
+        related_inode: None,
+    }))
+}
+
+/// Resolve a user-supplied peer: a client fingerprint, a known peer name, or an IP/CIDR
+///
+/// Known peer names are stored by fingerprint so renaming the peer keeps the
+/// rule; use `peer:<name>` to key a rule by name instead.
+///
+/// Returns the principal and, for known peers, their name.
+pub fn resolve_principal(
+    peer: &str,
+    known_peers: &KnownPeers,
+) -> Result<(Principal, Option<String>), AccessError> {
+    if let Ok(principal) = peer.parse::<Principal>() {
+        return Ok((principal, None));
+    }
+    if let Some(fp) = parse_fingerprint(peer) {
+        return Ok((Principal::Client(fp), None));
+    }
+    if let Some(net) = parse_network(peer) {
+        return Ok((Principal::Network(net), None));
+    }
+    if let Some(known) = known_peers.find(peer) {
+        if let Some(fp) = parse_fingerprint(&known.fingerprint) {
+            return Ok((Principal::Client(fp), Some(known.name.clone())));
+        }
+    }
+    Err(AccessError::InvalidPrincipal(peer.to_string()))
+}
+
+/// Keep share names usable as file names
+pub(crate) fn sanitize_share_name(share: &str) -> String {
+    share
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Access list errors
+#[derive(Debug, Clone)]
+pub enum AccessError {
+    /// I/O error
+    Io(String),
+    /// List file could not be parsed
+    Parse(String),
+    /// List could not be serialized
+    Serialize(String),
+    /// No config directory available
+    NoConfigDir,
+    /// Peer is not a fingerprint, known peer or network
+    InvalidPrincipal(String),
+    /// Not an IP address or CIDR network
+    InvalidNetwork(String),
+}
+
+impl std::fmt::Display for AccessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AccessError::Io(e) => write!(f, "I/O error: {}", e),
+            AccessError::Parse(e) => write!(f, "Invalid access list: {}", e),
+            AccessError::Serialize(e) => write!(f, "Failed to serialize access list: {}", e),
+            AccessError::NoConfigDir => write!(f, "No configuration directory available"),
+            AccessError::InvalidPrincipal(p) => write!(
+                f,
+                "'{}' is not a client fingerprint, known peer or IP/CIDR",
+                p
+            ),
+            AccessError::InvalidNetwork(s) => {
+                write!(f, "'{}' is not an IP address or CIDR network", s)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AccessError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn client<'a>(fingerprint: Option<&'a CertFingerprint>, ip: &str) -> ClientInfo<'a> {
+        ClientInfo {
+            fingerprint,
+            peer_name: None,
+            ip: ip.parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_grant_revoke_roundtrip() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("share.toml");
+        let client = Principal::Client([7u8; 32]);
+
+        let mut list = AccessList::load_from(&path).unwrap();
+        list.grant(client.clone(), Some("laptop".into()), AccessLevel::Read);
+        list.grant(client.clone(), Some("laptop".into()), AccessLevel::Write);
+        list.set_default_level(AccessLevel::Read);
+        list.save().unwrap();
+
+        let mut list = AccessList::load_from(&path).unwrap();
+        assert_eq!(list.rules().len(), 1);
+        assert_eq!(list.rules()[0].level, AccessLevel::Write);
+        assert_eq!(list.default_level(), Some(AccessLevel::Read));
+        assert!(list.revoke(&client));
+        assert!(!list.revoke(&client));
+    }
+
+    #[test]
+    fn test_identity_rule_overrides_ip_rule() {
+        let fp = [1u8; 32];
+        let mut list = AccessList::default();
+        list.grant(
+            Principal::ip("10.0.0.5".parse().unwrap()),
+            None,
+            AccessLevel::None,
+        );
+        list.grant(Principal::Client(fp), None, AccessLevel::Write);
+
+        assert_eq!(
+            list.level_for(&client(Some(&fp), "10.0.0.5")),
+            Some(AccessLevel::Write)
+        );
+        assert_eq!(
+            list.level_for(&client(Some(&[2u8; 32]), "10.0.0.5")),
+            Some(AccessLevel::None)
+        );
+        assert_eq!(list.level_for(&client(None, "10.0.0.6")), None);
+    }
+
+    #[test]
+    fn test_peer_name_and_most_specific_network() {
+        let mut list = AccessList::default();
+        list.grant("ip:10.0.0.0/8".parse().unwrap(), None, AccessLevel::Read);
+        list.grant("ip:10.1.0.0/16".parse().unwrap(), None, AccessLevel::None);
+        list.grant("peer:laptop".parse().unwrap(), None, AccessLevel::Admin);
+
+        assert_eq!(
+            list.effective_level(&client(None, "10.2.3.4")),
+            AccessLevel::Read
+        );
+        assert_eq!(
+            list.effective_level(&client(None, "10.1.3.4")),
+            AccessLevel::None
+        );
+        let named = ClientInfo {
+            peer_name: Some("laptop"),
+            ..client(None, "10.1.3.4")
+        };
+        assert_eq!(list.effective_level(&named), AccessLevel::Admin);
+
+        // Unmatched clients get the default, or write access without one
+        assert_eq!(
+            list.effective_level(&client(None, "192.168.0.1")),
+            AccessLevel::Write
+        );
+        list.set_default_level(AccessLevel::None);
+        assert_eq!(
+            list.effective_level(&client(None, "192.168.0.1")),
+            AccessLevel::None
+        );
+    }
+
+    #[test]
+    fn test_client_level_names_peers_from_cache() {
+        let mut list = AccessList::default();
+        list.grant("peer:laptop".parse().unwrap(), None, AccessLevel::Read);
+        list.set_default_level(AccessLevel::None);
+        assert!(list.uses_peer_names());
+
+        let mut store = KnownPeers::default();
+        store.add("laptop", &[4u8; 32]).unwrap();
+        let peers = PeerNames::from_peers(&store);
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+
+        let level = |fp: Option<&CertFingerprint>, peers: &PeerNames| {
+            client_level(Some(&list), peers, fp, ip)
+        };
+        assert_eq!(level(Some(&[4u8; 32]), &peers), AccessLevel::Read);
+        assert_eq!(level(Some(&[5u8; 32]), &peers), AccessLevel::None);
+        assert_eq!(level(None, &peers), AccessLevel::None);
+        // Without the store, peer rules match no one
+        assert_eq!(
+            level(Some(&[4u8; 32]), &PeerNames::default()),
+            AccessLevel::None
+        );
+        assert_eq!(client_level(None, &peers, None, ip), AccessLevel::Admin);
+    }
+
+    #[test]
+    fn test_check_request() {
+        let read = NetMessage::GetAttr(teleport_core::GetAttrRequest { inode: 1 });
+        let write = NetMessage::DeleteFile(teleport_core::DeleteFileRequest {
+            parent: 1,
+            name: "a".into(),
+            lock_token: None,
+        });
+
+        assert!(check_request(&read, AccessLevel::Read).is_ok());
+        assert!(check_request(&write, AccessLevel::Write).is_ok());
+        match check_request(&write, AccessLevel::Read) {
+            Err(NetMessage::Error(e)) => assert_eq!(e.code, ErrorCode::PermissionDenied),
+            _ => panic!("write with read access must be denied"),
+        }
+        assert!(check_request(&read, AccessLevel::None).is_err());
+    }
+
+    #[test]
+    fn test_resolve_principal() {
+        let dir = TempDir::new().unwrap();
+        let mut peers = KnownPeers::load_from(&dir.path().join("known_peers.toml")).unwrap();
+        peers.add("laptop", &[9u8; 32]).unwrap();
+
+        assert_eq!(
+            resolve_principal("laptop", &peers).unwrap(),
+            (Principal::Client([9u8; 32]), Some("laptop".to_string()))
+        );
+        assert_eq!(
+            resolve_principal(&"ab".repeat(32), &peers).unwrap().0,
+            Principal::Client([0xab; 32])
+        );
+        assert_eq!(
+            resolve_principal("192.168.1.2", &peers).unwrap().0,
+            Principal::ip("192.168.1.2".parse().unwrap())
+        );
+        assert_eq!(
+            resolve_principal("192.168.1.7/24", &peers)
+                .unwrap()
+                .0
+                .to_string(),
+            "ip:192.168.1.0/24"
+        );
+        assert!(resolve_principal("nobody", &peers).is_err());
+    }
+
+    #[test]
+    fn test_ip_filter() {
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+
+        assert!(IpFilter::default().permits(ip("203.0.113.9")));
+
+        let filter = IpFilter::parse(&["10.0.0.0/8", "192.168.1.5"], &["10.0.66.0/24"]).unwrap();
+        assert!(filter.permits(ip("10.1.2.3")));
+        assert!(filter.permits(ip("192.168.1.5")));
+        assert!(!filter.permits(ip("192.168.1.6")));
+        // Block list wins over a broader allow
+        assert!(!filter.permits(ip("10.0.66.1")));
+        // IPv4-mapped addresses from dual-stack sockets match IPv4 rules
+        assert!(filter.permits(ip("::ffff:10.1.2.3")));
+
+        let block_only = IpFilter::parse(&[] as &[&str], &["198.51.100.0/24"]).unwrap();
+        assert!(block_only.permits(ip("203.0.113.9")));
+        assert!(!block_only.permits(ip("198.51.100.7")));
+
+        assert!(IpFilter::parse(&["not-an-ip"], &[]).is_err());
+    }
+}
@@ -0,0 +1,757 @@
+//! Audit log of client activity on hosted shares
+//!
+//! Each share appends one JSON object per line to its own log in the data
+//! directory:
+//!
+//! ```text
+//! ~/.local/share/wormhole/audit/photos.jsonl
+//!
+//! {"time":"2026-03-02T10:15:04.120Z","share":"photos","session":"9c1f03aa","client":"3f2a...","remote":"192.168.1.20:53211","op":"session_open","access":"write"}
+//! {"time":"2026-03-02T10:15:09.981Z",...,"op":"write","path":"/notes.txt","offset":0,"bytes":5120}
+//! {"time":"2026-03-02T10:20:16.004Z",...,"op":"read","path":"/trip/beach.jpg","bytes":4194304,"chunks":32}
+//! {"time":"2026-03-02T10:20:16.004Z",...,"op":"session_close","duration_secs":312,"bytes_read":4194304,"bytes_written":5120}
+//! ```
+//!
+//! Modifications are logged as they succeed. Reads and writes are aggregated
+//! per file, so streaming a video or saving a large file adds one line rather
+//! than thousands. Reads are logged when the session ends, or every few
+//! minutes during long sessions; writes also when the client releases its
+//! lock or commits an atomic write, and before any later change is logged.
+//!
+//! A log that grows past its size limit is renamed to `photos.jsonl.1`
+//! (shifting older ones to `.2`, `.3`, ...) and the oldest is dropped.
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use directories::ProjectDirs;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::access::{sanitize_share_name, AccessLevel};
+
+/// Directory (inside the data directory) holding the per-share logs
+const AUDIT_DIR: &str = "audit";
+
+/// Size at which a log is rotated
+pub const DEFAULT_MAX_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Number of rotated logs kept per share
+pub const DEFAULT_KEEP: usize = 5;
+
+/// How long reads and writes are aggregated before they are logged
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// What happened (paths are relative to the share root, starting with `/`)
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum AuditEvent {
+    /// Client completed the handshake
+    SessionOpen {
+        access: AccessLevel,
+    },
+    /// Client disconnected
+    SessionClose {
+        duration_secs: u64,
+        bytes_read: u64,
+        bytes_written: u64,
+    },
+    CreateFile {
+        path: String,
+    },
+    CreateDir {
+        path: String,
+    },
+    DeleteFile {
+        path: String,
+    },
+    DeleteDir {
+        path: String,
+    },
+    Rename {
+        path: String,
+        to: String,
+    },
+    /// Data written to one file since the previous `Write` entry for it,
+    /// starting at `offset`
+    Write {
+        path: String,
+        offset: u64,
+        bytes: u64,
+    },
+    /// Size, permission or timestamp change (including truncation)
+    SetAttr {
+        path: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        size: Option<u64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        mode: Option<u32>,
+    },
+    /// Data read from one file since the previous `Read` entry for it
+    Read {
+        path: String,
+        bytes: u64,
+        chunks: u64,
+    },
+}
+
+impl AuditEvent {
+    /// Operation name, as written in the `op` field
+    pub fn name(&self) -> &'static str {
+        match self {
+            AuditEvent::SessionOpen { .. } => "session_open",
+            AuditEvent::SessionClose { .. } => "session_close",
+            AuditEvent::CreateFile { .. } => "create_file",
+            AuditEvent::CreateDir { .. } => "create_dir",
+            AuditEvent::DeleteFile { .. } => "delete_file",
+            AuditEvent::DeleteDir { .. } => "delete_dir",
+            AuditEvent::Rename { .. } => "rename",
+            AuditEvent::Write { .. } => "write",
+            AuditEvent::SetAttr { .. } => "set_attr",
+            AuditEvent::Read { .. } => "read",
+        }
+    }
+
+    /// Path the operation applied to, if any
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            AuditEvent::SessionOpen { .. } | AuditEvent::SessionClose { .. } => None,
+            AuditEvent::CreateFile { path }
+            | AuditEvent::CreateDir { path }
+            | AuditEvent::DeleteFile { path }
+            | AuditEvent::DeleteDir { path }
+            | AuditEvent::Rename { path, .. }
+            | AuditEvent::Write { path, .. }
+            | AuditEvent::SetAttr { path, .. }
+            | AuditEvent::Read { path, .. } => Some(path),
+        }
+    }
+}
+
+/// One line of an audit log
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub time: DateTime<Utc>,
+    pub share: String,
+    /// Short session ID, shared by all entries of one connection
+    pub session: String,
+    /// Client certificate fingerprint, or its random ID if it has none
+    pub client: String,
+    pub remote: SocketAddr,
+    #[serde(flatten)]
+    pub event: AuditEvent,
+}
+
+/// Open log file and its current size
+struct LogFile {
+    file: File,
+    size: u64,
+}
+
+/// Append-only audit log of one share
+pub struct AuditLog {
+    share: String,
+    path: PathBuf,
+    max_size: u64,
+    keep: usize,
+    file: Mutex<Option<LogFile>>,
+}
+
+impl AuditLog {
+    /// Default directory holding the logs of all shares
+    pub fn default_dir() -> Option<PathBuf> {
+        ProjectDirs::from("", "", "wormhole").map(|dirs| dirs.data_dir().join(AUDIT_DIR))
+    }
+
+    /// Default location of the log for `share`
+    pub fn default_path(share: &str) -> Option<PathBuf> {
+        Self::default_dir().map(|dir| log_path(&dir, share))
+    }
+
+    /// Open the log for `share` in the default location
+    pub fn open(share: &str) -> Result<Self, AuditError> {
+        let path = Self::default_path(share).ok_or(AuditError::NoDataDir)?;
+        Self::open_at(&path, share)
+    }
+
+    /// Open (creating if needed) a log at a specific path
+    pub fn open_at(path: &Path, share: &str) -> Result<Self, AuditError> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| AuditError::Io(e.to_string()))?;
+        }
+        let log = Self {
+            share: share.to_string(),
+            path: path.to_path_buf(),
+            max_size: DEFAULT_MAX_SIZE,
+            keep: DEFAULT_KEEP,
+            file: Mutex::new(None),
+        };
+        *log.file.lock() = Some(log.open_file()?);
+        Ok(log)
+    }
+
+    /// Rotate once the log reaches `max_size` bytes, keeping `keep` old logs
+    pub fn with_rotation(mut self, max_size: u64, keep: usize) -> Self {
+        self.max_size = max_size;
+        self.keep = keep;
+        self
+    }
+
+    /// Name of the share this log belongs to
+    pub fn share(&self) -> &str {
+        &self.share
+    }
+
+    /// Path of the current log file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append a record, rotating first if the log is full
+    ///
+    /// Failures are logged rather than returned: auditing never fails a request.
+    pub fn append(&self, record: &AuditRecord) {
+        let mut line = match serde_json::to_vec(record) {
+            Ok(line) => line,
+            Err(e) => {
+                warn!("Failed to serialize audit record: {}", e);
+                return;
+            }
+        };
+        line.push(b'\n');
+
+        let mut file = self.file.lock();
+        if let Err(e) = self.write_line(&mut file, &line) {
+            warn!("Failed to write audit log {}: {}", self.path.display(), e);
+            // Reopen on the next record
+            *file = None;
+        }
+    }
+
+    fn write_line(&self, file: &mut Option<LogFile>, line: &[u8]) -> Result<(), AuditError> {
+        let needs_rotation = file
+            .as_ref()
+            .is_some_and(|f| f.size > 0 && f.size + line.len() as u64 > self.max_size);
+        if needs_rotation {
+            *file = None;
+            self.rotate()?;
+        }
+        if file.is_none() {
+            *file = Some(self.open_file()?);
+        }
+        let Some(log) = file.as_mut() else {
+            return Ok(());
+        };
+        log.file
+            .write_all(line)
+            .map_err(|e| AuditError::Io(e.to_string()))?;
+        log.size += line.len() as u64;
+        Ok(())
+    }
+
+    fn open_file(&self) -> Result<LogFile, AuditError> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| AuditError::Io(format!("{}: {}", self.path.display(), e)))?;
+        let size = file.metadata().map(|m| m.len()).unwrap_or(0);
+        Ok(LogFile { file, size })
+    }
+
+    /// Shift `log.N` to `log.N+1` (dropping the oldest) and the current log to `log.1`
+    fn rotate(&self) -> Result<(), AuditError> {
+        debug!("Rotating audit log {}", self.path.display());
+        if self.keep == 0 {
+            return fs::remove_file(&self.path).map_err(|e| AuditError::Io(e.to_string()));
+        }
+        let _ = fs::remove_file(rotated_path(&self.path, self.keep));
+        for n in (1..self.keep).rev() {
+            let from = rotated_path(&self.path, n);
+            if from.exists() {
+                fs::rename(&from, rotated_path(&self.path, n + 1))
+                    .map_err(|e| AuditError::Io(e.to_string()))?;
+            }
+        }
+        fs::rename(&self.path, rotated_path(&self.path, 1))
+            .map_err(|e| AuditError::Io(e.to_string()))
+    }
+}
+
+fn log_path(dir: &Path, share: &str) -> PathBuf {
+    dir.join(format!("{}.jsonl", sanitize_share_name(share)))
+}
+
+fn rotated_path(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+/// Aggregated reads of one file
+#[derive(Default)]
+struct ReadTally {
+    bytes: u64,
+    chunks: u64,
+}
+
+/// Aggregated writes to one file
+struct WriteTally {
+    /// Lowest offset written
+    offset: u64,
+    bytes: u64,
+}
+
+/// Audit context of one client session
+///
+/// Logs `session_open` when created and, once dropped, the remaining writes
+/// and reads and `session_close`.
+pub struct AuditSession {
+    log: Arc<AuditLog>,
+    session: String,
+    client: String,
+    remote: SocketAddr,
+    started: Instant,
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+    reads: Mutex<HashMap<String, ReadTally>>,
+    reads_flushed: Mutex<Instant>,
+    writes: Mutex<HashMap<String, WriteTally>>,
+    writes_flushed: Mutex<Instant>,
+}
+
+impl AuditSession {
+    /// Start auditing a session that was granted `access`
+    pub fn open(
+        log: Arc<AuditLog>,
+        session_id: &[u8],
+        client: impl Into<String>,
+        remote: SocketAddr,
+        access: AccessLevel,
+    ) -> Self {
+        let now = Instant::now();
+        let session = Self {
+            log,
+            session: hex::encode(&session_id[..session_id.len().min(4)]),
+            client: client.into(),
+            remote,
+            started: now,
+            bytes_read: AtomicU64::new(0),
+            bytes_written: AtomicU64::new(0),
+            reads: Mutex::new(HashMap::new()),
+            reads_flushed: Mutex::new(now),
+            writes: Mutex::new(HashMap::new()),
+            writes_flushed: Mutex::new(now),
+        };
+        session.append(AuditEvent::SessionOpen { access });
+        session
+    }
+
+    /// Log an event of this session
+    ///
+    /// Writes are aggregated like `record_write`. Pending writes are logged
+    /// ahead of any other change, which may rename or delete what they wrote.
+    pub fn record(&self, event: AuditEvent) {
+        match event {
+            AuditEvent::Write {
+                path,
+                offset,
+                bytes,
+            } => self.record_write(path, offset, bytes),
+            AuditEvent::Read { .. } => self.append(event),
+            event => {
+                self.flush_writes();
+                self.append(event);
+            }
+        }
+    }
+
+    fn append(&self, event: AuditEvent) {
+        self.log.append(&AuditRecord {
+            time: Utc::now(),
+            share: self.log.share.clone(),
+            session: self.session.clone(),
+            client: self.client.clone(),
+            remote: self.remote,
+            event,
+        });
+    }
+
+    /// Count a chunk read from `path`; reads are logged in aggregate
+    pub fn record_read(&self, path: String, bytes: u64) {
+        self.bytes_read.fetch_add(bytes, Ordering::Relaxed);
+        {
+            let mut reads = self.reads.lock();
+            let tally = reads.entry(path).or_default();
+            tally.bytes += bytes;
+            tally.chunks += 1;
+        }
+        if self.reads_flushed.lock().elapsed() >= FLUSH_INTERVAL {
+            self.flush_reads();
+        }
+    }
+
+    /// Count `bytes` written to `path` at `offset`; writes are logged in aggregate
+    pub fn record_write(&self, path: String, offset: u64, bytes: u64) {
+        self.bytes_written.fetch_add(bytes, Ordering::Relaxed);
+        {
+            let mut writes = self.writes.lock();
+            let tally = writes
+                .entry(path)
+                .or_insert(WriteTally { offset, bytes: 0 });
+            tally.offset = tally.offset.min(offset);
+            tally.bytes += bytes;
+        }
+        if self.writes_flushed.lock().elapsed() >= FLUSH_INTERVAL {
+            self.flush_writes();
+        }
+    }
+
+    /// Log the reads aggregated so far
+    pub fn flush_reads(&self) {
+        *self.reads_flushed.lock() = Instant::now();
+        let reads = std::mem::take(&mut *self.reads.lock());
+        let mut reads: Vec<_> = reads.into_iter().collect();
+        reads.sort_by(|a, b| a.0.cmp(&b.0));
+        for (path, tally) in reads {
+            self.append(AuditEvent::Read {
+                path,
+                bytes: tally.bytes,
+                chunks: tally.chunks,
+            });
+        }
+    }
+
+    /// Log the writes aggregated so far
+    pub fn flush_writes(&self) {
+        *self.writes_flushed.lock() = Instant::now();
+        let writes = std::mem::take(&mut *self.writes.lock());
+        let mut writes: Vec<_> = writes.into_iter().collect();
+        writes.sort_by(|a, b| a.0.cmp(&b.0));
+        for (path, tally) in writes {
+            self.append(AuditEvent::Write {
+                path,
+                offset: tally.offset,
+                bytes: tally.bytes,
+            });
+        }
+    }
+}
+
+impl Drop for AuditSession {
+    fn drop(&mut self) {
+        self.flush_writes();
+        self.flush_reads();
+        self.append(AuditEvent::SessionClose {
+            duration_secs: self.started.elapsed().as_secs(),
+            bytes_read: self.bytes_read.load(Ordering::Relaxed),
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+        });
+    }
+}
+
+/// Read a log and its rotated predecessors, oldest record first
+///
+/// Lines that cannot be parsed (e.g. a partial final line) are skipped.
+pub fn read_log(path: &Path) -> Result<Vec<AuditRecord>, AuditError> {
+    let mut files = Vec::new();
+    let mut n = 1;
+    loop {
+        let rotated = rotated_path(path, n);
+        if !rotated.exists() {
+            break;
+        }
+        files.push(rotated);
+        n += 1;
+    }
+    files.reverse();
+    files.push(path.to_path_buf());
+
+    let mut records = Vec::new();
+    for file in files.iter().filter(|f| f.exists()) {
+        let reader = BufReader::new(File::open(file).map_err(|e| AuditError::Io(e.to_string()))?);
+        for line in reader.lines() {
+            let line = line.map_err(|e| AuditError::Io(e.to_string()))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&line) {
+                Ok(record) => records.push(record),
+                Err(e) => debug!("Skipping audit line in {}: {}", file.display(), e),
+            }
+        }
+    }
+    Ok(records)
+}
+
+/// Filters for `query`
+#[derive(Clone, Debug, Default)]
+pub struct AuditQuery {
+    /// Only this share
+    pub share: Option<String>,
+    /// Operation name; a prefix such as `create` or `session` matches all its variants
+    pub operation: Option<String>,
+    /// Records at or after this time
+    pub since: Option<DateTime<Utc>>,
+    /// Records before this time
+    pub until: Option<DateTime<Utc>>,
+    /// Return only the most recent records (0 = all)
+    pub limit: usize,
+}
+
+impl AuditQuery {
+    /// Whether a record passes the filters
+    pub fn matches(&self, record: &AuditRecord) -> bool {
+        if self.share.as_ref().is_some_and(|s| *s != record.share) {
+            return false;
+        }
+        if let Some(op) = &self.operation {
+            let name = record.event.name();
+            let op = op.replace('-', "_");
+            if name != op && !name.starts_with(&format!("{}_", op)) {
+                return false;
+            }
+        }
+        if self.since.is_some_and(|since| record.time < since) {
+            return false;
+        }
+        if self.until.is_some_and(|until| record.time >= until) {
+            return false;
+        }
+        true
+    }
+}
+
+/// Records in the logs under `dir` matching `query`, oldest first
+pub fn query(dir: &Path, query: &AuditQuery) -> Result<Vec<AuditRecord>, AuditError> {
+    let logs = match &query.share {
+        Some(share) => vec![log_path(dir, share)],
+        None => match fs::read_dir(dir) {
+            Ok(entries) => entries
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| p.extension().is_some_and(|ext| ext == "jsonl"))
+                .collect(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(AuditError::Io(e.to_string())),
+        },
+    };
+
+    let mut records = Vec::new();
+    for log in logs {
+        records.extend(read_log(&log)?.into_iter().filter(|r| query.matches(r)));
+    }
+    records.sort_by_key(|r| r.time);
+    if query.limit > 0 && records.len() > query.limit {
+        records.drain(..records.len() - query.limit);
+    }
+    Ok(records)
+}
+
+/// Audit log errors
+#[derive(Debug, Clone)]
+pub enum AuditError {
+    /// I/O error
+    Io(String),
+    /// No data directory available
+    NoDataDir,
+}
+
+impl std::fmt::Display for AuditError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuditError::Io(e) => write!(f, "I/O error: {}", e),
+            AuditError::NoDataDir => write!(f, "No data directory available"),
+        }
+    }
+}
+
+impl std::error::Error for AuditError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn remote() -> SocketAddr {
+        "192.168.1.20:53211".parse().unwrap()
+    }
+
+    #[test]
+    fn test_session_records_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let log = Arc::new(AuditLog::open_at(&log_path(dir.path(), "photos"), "photos").unwrap());
+
+        let session = AuditSession::open(
+            log.clone(),
+            &[0xab; 16],
+            "3f2a",
+            remote(),
+            AccessLevel::Write,
+        );
+        session.record(AuditEvent::Write {
+            path: "/notes.txt".into(),
+            offset: 0,
+            bytes: 100,
+        });
+        session.record_read("/a.jpg".into(), 64);
+        session.record_read("/a.jpg".into(), 36);
+        drop(session);
+
+        let records = read_log(log.path()).unwrap();
+        let ops: Vec<_> = records.iter().map(|r| r.event.name()).collect();
+        assert_eq!(ops, ["session_open", "write", "read", "session_close"]);
+        assert!(records
+            .iter()
+            .all(|r| r.session == "abababab" && r.share == "photos"));
+        assert_eq!(
+            records[2].event,
+            AuditEvent::Read {
+                path: "/a.jpg".into(),
+                bytes: 100,
+                chunks: 2
+            }
+        );
+        assert!(matches!(
+            records[3].event,
+            AuditEvent::SessionClose {
+                bytes_read: 100,
+                bytes_written: 100,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_writes_are_aggregated_until_flushed() {
+        let dir = TempDir::new().unwrap();
+        let log = Arc::new(AuditLog::open_at(&log_path(dir.path(), "docs"), "docs").unwrap());
+
+        let session = AuditSession::open(
+            log.clone(),
+            &[3; 16],
+            "client",
+            remote(),
+            AccessLevel::Write,
+        );
+        for i in (0..100).rev() {
+            session.record_write("/big.bin".into(), i * 1024, 1024);
+        }
+        session.flush_writes();
+        session.record_write("/notes.txt".into(), 0, 10);
+        // Logged ahead of the rename that moves it
+        session.record(AuditEvent::Rename {
+            path: "/notes.txt".into(),
+            to: "/old.txt".into(),
+        });
+        drop(session);
+
+        let events: Vec<_> = read_log(log.path())
+            .unwrap()
+            .into_iter()
+            .map(|r| r.event)
+            .collect();
+        assert_eq!(events.len(), 5);
+        assert_eq!(
+            events[1],
+            AuditEvent::Write {
+                path: "/big.bin".into(),
+                offset: 0,
+                bytes: 100 * 1024
+            }
+        );
+        assert_eq!(
+            events[2],
+            AuditEvent::Write {
+                path: "/notes.txt".into(),
+                offset: 0,
+                bytes: 10
+            }
+        );
+        assert_eq!(events[3].name(), "rename");
+        assert!(matches!(
+            events[4],
+            AuditEvent::SessionClose {
+                bytes_written: 102410,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_rotation_keeps_old_records_readable() {
+        let dir = TempDir::new().unwrap();
+        let path = log_path(dir.path(), "docs");
+        let log = Arc::new(
+            AuditLog::open_at(&path, "docs")
+                .unwrap()
+                .with_rotation(400, 2),
+        );
+
+        let session = AuditSession::open(log, &[1; 16], "client", remote(), AccessLevel::Read);
+        for i in 0..20 {
+            session.record(AuditEvent::DeleteFile {
+                path: format!("/file{}", i),
+            });
+        }
+        drop(session);
+
+        assert!(rotated_path(&path, 1).exists());
+        assert!(rotated_path(&path, 2).exists());
+        assert!(!rotated_path(&path, 3).exists());
+        let records = read_log(&path).unwrap();
+        // The oldest entries were dropped, the newest survive in order
+        assert!(records.len() < 22);
+        assert_eq!(records.last().unwrap().event.name(), "session_close");
+        assert!(records.windows(2).all(|w| w[0].time <= w[1].time));
+    }
+
+    #[test]
+    fn test_query_filters() {
+        let dir = TempDir::new().unwrap();
+        for share in ["photos", "docs"] {
+            let log = Arc::new(AuditLog::open_at(&log_path(dir.path(), share), share).unwrap());
+            let session = AuditSession::open(log, &[2; 16], "client", remote(), AccessLevel::Write);
+            session.record(AuditEvent::CreateFile {
+                path: "/new.txt".into(),
+            });
+            session.record(AuditEvent::CreateDir {
+                path: "/new".into(),
+            });
+        }
+
+        let all = query(dir.path(), &AuditQuery::default()).unwrap();
+        assert_eq!(all.len(), 8);
+
+        let creates = AuditQuery {
+            share: Some("docs".into()),
+            operation: Some("create".into()),
+            ..Default::default()
+        };
+        let records = query(dir.path(), &creates).unwrap();
+        assert_eq!(records.len(), 2);
+        assert!(records.iter().all(|r| r.share == "docs"));
+
+        let last = AuditQuery {
+            limit: 3,
+            ..Default::default()
+        };
+        assert_eq!(query(dir.path(), &last).unwrap().len(), 3);
+
+        let future = AuditQuery {
+            since: Some(Utc::now() + chrono::Duration::hours(1)),
+            ..Default::default()
+        };
+        assert!(query(dir.path(), &future).unwrap().is_empty());
+        assert!(query(&dir.path().join("missing"), &AuditQuery::default())
+            .unwrap()
+            .is_empty());
+    }
+}
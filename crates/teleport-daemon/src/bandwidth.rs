@@ -0,0 +1,480 @@
+//! Host-side bandwidth limiting
+//!
+//! Share data sent to clients (file chunks, directory listings and snapshot
+//! pages) is metered by token buckets at three levels: all shares together,
+//! each share, and each client. A response is sent once every bucket that
+//! applies to it has room, so the tightest budget wins.
+//!
+//! Transfers an application is waiting on (`PRIORITY_INTERACTIVE` reads and
+//! directory listings) take precedence: while one of them is waiting for
+//! tokens, bulk transfers that draw on the same bucket hold back. Limits can
+//! be changed while serving with `set_limits`.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+use teleport_core::{NetMessage, CHUNK_SIZE, PRIORITY_INTERACTIVE};
+
+/// How long a bulk read backs off while interactive reads are waiting
+const BULK_BACKOFF: Duration = Duration::from_millis(5);
+
+/// Bandwidth budgets in bytes per second (`None` = unlimited)
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BandwidthLimits {
+    /// All shares and clients together
+    pub total: Option<u64>,
+    /// Each share, unless it has its own entry in `shares`
+    pub per_share: Option<u64>,
+    /// Each client, across the shares it uses
+    pub per_client: Option<u64>,
+    /// Budgets for individual shares, by share name
+    pub shares: HashMap<String, u64>,
+}
+
+impl BandwidthLimits {
+    /// Whether no budget is set
+    pub fn is_unlimited(&self) -> bool {
+        self.total.is_none()
+            && self.per_share.is_none()
+            && self.per_client.is_none()
+            && self.shares.is_empty()
+    }
+
+    fn share_rate(&self, share: &str) -> Option<u64> {
+        self.shares.get(share).copied().or(self.per_share)
+    }
+}
+
+/// Scheduling class of a transfer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Priority {
+    /// An application is blocked on the data
+    Interactive,
+    /// Prefetch and bulk transfers
+    Bulk,
+}
+
+impl Priority {
+    /// Class of a `ReadChunkRequest` with the given priority
+    pub fn of_read(priority: u8) -> Self {
+        if priority >= PRIORITY_INTERACTIVE {
+            Priority::Interactive
+        } else {
+            Priority::Bulk
+        }
+    }
+
+    /// Class of a request whose response carries share data
+    ///
+    /// `None` for requests with small, fixed-size answers, which are not metered.
+    pub fn of_request(request: &NetMessage) -> Option<Self> {
+        match request {
+            NetMessage::ReadChunk(req) => Some(Self::of_read(req.priority)),
+            NetMessage::ListDir(_) => Some(Priority::Interactive),
+            NetMessage::TreeSnapshot(_)
+            | NetMessage::ManifestRequest(_)
+            | NetMessage::MissingChunksRequest(_)
+            | NetMessage::BulkChunkRequest(_) => Some(Priority::Bulk),
+            _ => None,
+        }
+    }
+}
+
+/// Size of a response on the wire, as metered against budgets and byte limits
+pub fn metered_size(response: &NetMessage) -> u64 {
+    bincode::serialized_size(response).unwrap_or(0)
+}
+
+/// Token bucket holding up to one second of traffic (at least one chunk)
+#[derive(Debug)]
+struct TokenBucket {
+    rate: u64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u64, now: Instant) -> Self {
+        let mut bucket = Self {
+            rate,
+            tokens: 0.0,
+            updated: now,
+        };
+        bucket.tokens = bucket.capacity();
+        bucket
+    }
+
+    fn capacity(&self) -> f64 {
+        self.rate.max(CHUNK_SIZE as u64) as f64
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate as f64).min(self.capacity());
+        self.updated = now;
+    }
+
+    fn set_rate(&mut self, rate: u64, now: Instant) {
+        self.refill(now);
+        self.rate = rate;
+        self.tokens = self.tokens.min(self.capacity());
+    }
+
+    /// Time until `bytes` may be taken (a full bucket always admits one transfer)
+    fn wait_for(&self, bytes: u64) -> Duration {
+        let needed = (bytes as f64).min(self.capacity());
+        if self.tokens >= needed || self.rate == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64((needed - self.tokens) / self.rate as f64)
+    }
+
+    fn is_full(&self) -> bool {
+        self.tokens >= self.capacity()
+    }
+}
+
+#[derive(Debug, Default)]
+struct Buckets {
+    total: Option<TokenBucket>,
+    shares: HashMap<String, TokenBucket>,
+    clients: HashMap<String, TokenBucket>,
+}
+
+/// Interactive transfers waiting for tokens, by the buckets they draw on
+#[derive(Debug, Default)]
+struct Waiters {
+    total: usize,
+    shares: HashMap<String, usize>,
+    clients: HashMap<String, usize>,
+}
+
+fn add_waiter(map: &mut HashMap<String, usize>, key: &str) {
+    *map.entry(key.to_string()).or_default() += 1;
+}
+
+fn remove_waiter(map: &mut HashMap<String, usize>, key: &str) {
+    if let Some(count) = map.get_mut(key) {
+        *count -= 1;
+        if *count == 0 {
+            map.remove(key);
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct LimiterState {
+    limits: BandwidthLimits,
+    buckets: Buckets,
+    interactive: Waiters,
+}
+
+impl LimiterState {
+    /// Whether an interactive transfer is waiting on a bucket this one would draw on
+    fn interactive_ahead(&self, share: &str, client: &str) -> bool {
+        let waiting = &self.interactive;
+        (self.limits.total.is_some() && waiting.total > 0)
+            || (self.limits.share_rate(share).is_some() && waiting.shares.contains_key(share))
+            || (self.limits.per_client.is_some() && waiting.clients.contains_key(client))
+    }
+
+    /// Take tokens for a transfer, or return how long to wait before retrying
+    fn try_take(&mut self, share: &str, client: &str, bytes: u64) -> Option<Duration> {
+        let now = Instant::now();
+        let Buckets {
+            total,
+            shares,
+            clients,
+        } = &mut self.buckets;
+
+        let share_bucket = self.limits.share_rate(share).map(|rate| {
+            shares
+                .entry(share.to_string())
+                .or_insert_with(|| TokenBucket::new(rate, now))
+        });
+        let client_bucket = self.limits.per_client.map(|rate| {
+            clients
+                .entry(client.to_string())
+                .or_insert_with(|| TokenBucket::new(rate, now))
+        });
+
+        let mut buckets: Vec<&mut TokenBucket> = total
+            .as_mut()
+            .into_iter()
+            .chain(share_bucket)
+            .chain(client_bucket)
+            .collect();
+
+        let mut wait = Duration::ZERO;
+        for bucket in buckets.iter_mut() {
+            bucket.refill(now);
+            wait = wait.max(bucket.wait_for(bytes));
+        }
+        if !wait.is_zero() {
+            return Some(wait);
+        }
+        for bucket in buckets {
+            bucket.tokens -= bytes as f64;
+        }
+        None
+    }
+}
+
+/// Token-bucket bandwidth limiter shared by the hosts in a process
+#[derive(Debug, Default)]
+pub struct BandwidthLimiter {
+    state: Mutex<LimiterState>,
+}
+
+impl BandwidthLimiter {
+    pub fn new(limits: BandwidthLimits) -> Self {
+        let limiter = Self::default();
+        limiter.set_limits(limits);
+        limiter
+    }
+
+    /// Current budgets
+    pub fn limits(&self) -> BandwidthLimits {
+        self.state.lock().limits.clone()
+    }
+
+    /// Change the budgets; transfers in progress pick them up immediately
+    pub fn set_limits(&self, limits: BandwidthLimits) {
+        let now = Instant::now();
+        let mut state = self.state.lock();
+        let buckets = &mut state.buckets;
+
+        buckets.total = match (limits.total, buckets.total.take()) {
+            (Some(rate), Some(mut bucket)) => {
+                bucket.set_rate(rate, now);
+                Some(bucket)
+            }
+            (Some(rate), None) => Some(TokenBucket::new(rate, now)),
+            (None, _) => None,
+        };
+        buckets
+            .shares
+            .retain(|share, bucket| match limits.share_rate(share) {
+                Some(rate) => {
+                    bucket.set_rate(rate, now);
+                    true
+                }
+                None => false,
+            });
+        match limits.per_client {
+            Some(rate) => {
+                for bucket in buckets.clients.values_mut() {
+                    bucket.set_rate(rate, now);
+                }
+            }
+            None => buckets.clients.clear(),
+        }
+
+        state.limits = limits;
+    }
+
+    /// Wait until `bytes` may be sent to `client` from `share`
+    pub async fn acquire(&self, share: &str, client: &str, bytes: u64, priority: Priority) {
+        let _waiting = (priority == Priority::Interactive)
+            .then(|| InteractiveWaiter::new(self, share, client));
+
+        loop {
+            let wait = {
+                let mut state = self.state.lock();
+                if state.limits.is_unlimited() {
+                    return;
+                }
+                if priority == Priority::Bulk && state.interactive_ahead(share, client) {
+                    Some(BULK_BACKOFF)
+                } else {
+                    state.try_take(share, client, bytes)
+                }
+            };
+            match wait {
+                Some(wait) => tokio::time::sleep(wait).await,
+                None => return,
+            }
+        }
+    }
+
+    /// Drop buckets of shares and clients that have been idle long enough to refill
+    pub fn prune_idle(&self) {
+        let now = Instant::now();
+        let mut state = self.state.lock();
+        let buckets = &mut state.buckets;
+        for map in [&mut buckets.shares, &mut buckets.clients] {
+            map.retain(|_, bucket| {
+                bucket.refill(now);
+                !bucket.is_full()
+            });
+        }
+    }
+}
+
+/// Counts an interactive transfer as waiting on its buckets until dropped
+struct InteractiveWaiter<'a> {
+    limiter: &'a BandwidthLimiter,
+    share: &'a str,
+    client: &'a str,
+}
+
+impl<'a> InteractiveWaiter<'a> {
+    fn new(limiter: &'a BandwidthLimiter, share: &'a str, client: &'a str) -> Self {
+        let mut state = limiter.state.lock();
+        let waiting = &mut state.interactive;
+        waiting.total += 1;
+        add_waiter(&mut waiting.shares, share);
+        add_waiter(&mut waiting.clients, client);
+        Self {
+            limiter,
+            share,
+            client,
+        }
+    }
+}
+
+impl Drop for InteractiveWaiter<'_> {
+    fn drop(&mut self) {
+        let mut state = self.limiter.state.lock();
+        let waiting = &mut state.interactive;
+        waiting.total -= 1;
+        remove_waiter(&mut waiting.shares, self.share);
+        remove_waiter(&mut waiting.clients, self.client);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MB: u64 = 1024 * 1024;
+
+    #[test]
+    fn test_read_priority() {
+        assert_eq!(
+            Priority::of_read(PRIORITY_INTERACTIVE),
+            Priority::Interactive
+        );
+        assert_eq!(Priority::of_read(255), Priority::Interactive);
+        assert_eq!(Priority::of_read(0), Priority::Bulk);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_total_budget_paces_transfers() {
+        let limiter = BandwidthLimiter::new(BandwidthLimits {
+            total: Some(MB),
+            ..Default::default()
+        });
+
+        // The first second's worth is a burst, the next one has to wait
+        let start = tokio::time::Instant::now();
+        limiter
+            .acquire("docs", "alice", MB, Priority::Interactive)
+            .await;
+        limiter
+            .acquire("docs", "bob", MB, Priority::Interactive)
+            .await;
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(900), "{:?}", elapsed);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_per_client_budget_is_independent() {
+        let limiter = BandwidthLimiter::new(BandwidthLimits {
+            per_client: Some(MB),
+            ..Default::default()
+        });
+
+        let start = tokio::time::Instant::now();
+        limiter
+            .acquire("docs", "alice", MB, Priority::Interactive)
+            .await;
+        limiter
+            .acquire("docs", "bob", MB, Priority::Interactive)
+            .await;
+        assert!(start.elapsed() < Duration::from_millis(100));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_share_override_and_runtime_change() {
+        let mut limits = BandwidthLimits {
+            per_share: Some(MB),
+            ..Default::default()
+        };
+        limits.shares.insert("footage".into(), 8 * MB);
+        let limiter = BandwidthLimiter::new(limits);
+
+        let start = tokio::time::Instant::now();
+        for _ in 0..8 {
+            limiter
+                .acquire("footage", "alice", MB, Priority::Bulk)
+                .await;
+        }
+        assert!(start.elapsed() < Duration::from_millis(100));
+
+        limiter.set_limits(BandwidthLimits::default());
+        assert!(limiter.limits().is_unlimited());
+        let start = tokio::time::Instant::now();
+        for _ in 0..8 {
+            limiter.acquire("docs", "alice", MB, Priority::Bulk).await;
+        }
+        assert!(start.elapsed() < Duration::from_millis(100));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_interactive_reads_go_first() {
+        let limiter = std::sync::Arc::new(BandwidthLimiter::new(BandwidthLimits {
+            total: Some(MB),
+            ..Default::default()
+        }));
+        // Drain the burst
+        limiter.acquire("docs", "alice", MB, Priority::Bulk).await;
+
+        let bulk = {
+            let limiter = limiter.clone();
+            tokio::spawn(async move {
+                limiter.acquire("docs", "alice", MB, Priority::Bulk).await;
+                tokio::time::Instant::now()
+            })
+        };
+        tokio::task::yield_now().await;
+        limiter
+            .acquire("docs", "bob", MB, Priority::Interactive)
+            .await;
+        let interactive_done = tokio::time::Instant::now();
+
+        assert!(bulk.await.unwrap() > interactive_done);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_interactive_wait_only_holds_back_its_own_buckets() {
+        let limiter = std::sync::Arc::new(BandwidthLimiter::new(BandwidthLimits {
+            per_client: Some(MB),
+            ..Default::default()
+        }));
+        // Drain alice's burst so her next interactive read has to wait
+        limiter.acquire("docs", "alice", MB, Priority::Bulk).await;
+
+        let interactive = {
+            let limiter = limiter.clone();
+            tokio::spawn(async move {
+                limiter
+                    .acquire("docs", "alice", MB, Priority::Interactive)
+                    .await;
+            })
+        };
+        tokio::task::yield_now().await;
+
+        // Bob has budget of his own and does not wait behind alice
+        let start = tokio::time::Instant::now();
+        limiter.acquire("docs", "bob", MB, Priority::Bulk).await;
+        assert!(start.elapsed() < Duration::from_millis(100));
+
+        interactive.await.unwrap();
+    }
+}
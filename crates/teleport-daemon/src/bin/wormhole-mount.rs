@@ -16,27 +16,157 @@
 //! - Linux: Uses FUSE (requires libfuse3)
 //! - macOS: Uses macFUSE (requires macfuse)
 //! - Windows: Uses WinFSP (requires winfsp)
+//!
+//! Cache, TTL and timeout settings come from the config file and
+//! `WORMHOLE_*` environment variables, overridden by the flags below.
+//! Cache TTLs and garbage collection limits follow edits to the config file
+//! (or SIGHUP) while mounted.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use teleport_core::config::{ConfigError, LayeredConfig};
+use teleport_daemon::identity::Identity;
+use teleport_daemon::{ConfigReloader, GarbageCollector, HybridCacheManager};
+
+/// Flags that override settings from the config file and environment
+#[derive(clap::Args)]
+struct SettingsArgs {
+    /// Configuration file path
+    #[arg(long, env = "WORMHOLE_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Attribute cache timeout in seconds
+    #[arg(long)]
+    attr_ttl: Option<u64>,
+
+    /// Directory listing cache timeout in seconds
+    #[arg(long)]
+    dir_ttl: Option<u64>,
+
+    /// Chunks to prefetch ahead of sequential reads
+    #[arg(long)]
+    read_ahead: Option<usize>,
+
+    /// RAM cache size in MB
+    #[arg(long)]
+    ram_cache_mb: Option<u64>,
+
+    /// Disk cache size in GB
+    #[arg(long)]
+    disk_cache_gb: Option<u64>,
+
+    /// Connection timeout in seconds
+    #[arg(long)]
+    connect_timeout: Option<u64>,
+
+    /// Request timeout in seconds
+    #[arg(long)]
+    request_timeout: Option<u64>,
+}
+
+impl SettingsArgs {
+    /// Defaults, then the config file, then the environment, then these flags
+    fn load(&self) -> Result<LayeredConfig, ConfigError> {
+        let mut settings = LayeredConfig::load(self.config.as_deref())?;
+        settings.apply_cli(self.flags())?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// These flags as config keys
+    fn flags(&self) -> Vec<(&'static str, Option<String>)> {
+        vec![
+            ("client.attr_ttl_secs", self.attr_ttl.map(|s| s.to_string())),
+            ("client.dir_ttl_secs", self.dir_ttl.map(|s| s.to_string())),
+            (
+                "client.read_ahead_chunks",
+                self.read_ahead.map(|n| n.to_string()),
+            ),
+            (
+                "cache.max_ram_bytes",
+                self.ram_cache_mb.map(|mb| (mb << 20).to_string()),
+            ),
+            (
+                "cache.max_disk_bytes",
+                self.disk_cache_gb.map(|gb| (gb << 30).to_string()),
+            ),
+            (
+                "network.connect_timeout_secs",
+                self.connect_timeout.map(|s| s.to_string()),
+            ),
+            (
+                "network.request_timeout_secs",
+                self.request_timeout.map(|s| s.to_string()),
+            ),
+        ]
+    }
+
+    /// Reloader that applies config file changes to the running mount
+    fn reloader(
+        &self,
+        settings: &LayeredConfig,
+        cache: Arc<HybridCacheManager>,
+        gc: Option<Arc<GarbageCollector>>,
+    ) -> ConfigReloader {
+        let reloader = ConfigReloader::new(settings)
+            .with_flags(self.flags())
+            .on_change(
+                &["client.attr_ttl_secs", "client.dir_ttl_secs"],
+                move |config| {
+                    cache
+                        .attrs
+                        .set_ttl(Duration::from_secs(config.client.attr_ttl_secs));
+                    cache
+                        .dirs
+                        .set_ttl(Duration::from_secs(config.client.dir_ttl_secs));
+                },
+            );
+        match gc {
+            Some(gc) => reloader.on_change(
+                &["cache.max_disk_bytes", "cache.gc_interval_secs"],
+                move |config| gc.set_config(&config.cache),
+            ),
+            None => reloader,
+        }
+    }
+}
+
+/// This machine's client certificate, presented to the host so it can tell
+/// clients apart; mounts fall back to connecting anonymously without one
+fn load_client_identity() -> Option<Arc<Identity>> {
+    match Identity::default_client() {
+        Ok(identity) => Some(Arc::new(identity)),
+        Err(e) => {
+            tracing::warn!("No client identity, connecting anonymously: {}", e);
+            None
+        }
+    }
+}
 
 // FUSE is only available on Unix platforms (Linux, macOS)
 #[cfg(unix)]
 mod unix_impl {
     use std::net::SocketAddr;
     use std::path::PathBuf;
+    use std::sync::{mpsc, Arc};
     use std::thread;
     use std::time::Duration;
 
     use clap::Parser;
     use fuser::MountOption;
+    use parking_lot::RwLock;
     use tokio::runtime::Runtime;
-    #[cfg(target_os = "macos")]
-    use tracing::warn;
-    use tracing::{error, info, Level};
+    use tracing::{error, info, warn, Level};
     use tracing_subscriber::FmtSubscriber;
 
     use teleport_daemon::bridge::FuseAsyncBridge;
     use teleport_daemon::client::{ClientConfig, WormholeClient};
     use teleport_daemon::fuse::WormholeFS;
-    use teleport_daemon::GarbageCollector;
+    use teleport_daemon::known_peers::parse_fingerprint_arg;
+    use teleport_daemon::net::CertFingerprint;
+    use teleport_daemon::{GarbageCollector, HybridCacheManager, TreeSnapshot};
 
     #[derive(Parser)]
     #[command(name = "wormhole-mount")]
@@ -55,6 +185,21 @@ mod unix_impl {
         /// Use kernel extension backend instead of FSKit (requires kext approval)
         #[arg(long)]
         use_kext: bool,
+
+        /// Expected host certificate fingerprint (hex); pins instead of trust-on-first-use
+        #[arg(long, value_parser = parse_fingerprint_arg)]
+        fingerprint: Option<CertFingerprint>,
+
+        /// Share password, if the host requires one
+        #[arg(long, env = "WORMHOLE_PASSWORD", hide_env_values = true)]
+        password: Option<String>,
+
+        /// Mount read-only even if the host allows writes
+        #[arg(long)]
+        read_only: bool,
+
+        #[command(flatten)]
+        settings: super::SettingsArgs,
     }
 
     pub fn run() -> Result<(), Box<dyn std::error::Error>> {
@@ -72,9 +217,16 @@ mod unix_impl {
             .finish();
         tracing::subscriber::set_global_default(subscriber)?;
 
+        let layered = cli.settings.load()?;
+        let settings = layered.config().clone();
+        let request_timeout = Duration::from_secs(settings.network.request_timeout_secs);
+        let connect_timeout = Duration::from_secs(settings.network.connect_timeout_secs);
+        let sync_interval = Duration::from_secs(settings.client.sync_interval_secs);
+
         // Determine mount point - default to /Volumes/wormhole for FSKit compatibility
         let mount_point = cli
             .mount_point
+            .or_else(|| settings.client.mount_point.clone())
             .unwrap_or_else(|| PathBuf::from("/Volumes/wormhole"));
 
         // On macOS with FSKit, mount point must be in /Volumes
@@ -114,20 +266,56 @@ mod unix_impl {
         info!("Mount point: {:?}", actual_mount_point);
 
         // Create the FUSE ↔ async bridge
-        let (bridge, request_rx) = FuseAsyncBridge::new(Duration::from_secs(30));
+        let (bridge, request_rx) = FuseAsyncBridge::new(request_timeout);
 
         // Create client config
         let config = ClientConfig {
             server_addr: cli.host,
             mount_point: actual_mount_point.clone(),
-            request_timeout: Duration::from_secs(30),
+            request_timeout,
+            expected_fingerprint: cli.fingerprint,
+            known_peers_path: None,
+            client_identity: super::load_client_identity(),
+            password: cli.password,
         };
 
         // Create the WormholeFS first so we can get a reference to its disk cache for GC
-        let fs = WormholeFS::new(bridge);
-
-        // Get the disk cache for the garbage collector
-        let disk_cache = fs.disk_cache();
+        let cache = Arc::new(HybridCacheManager::from_config(&settings));
+        let mut fs = WormholeFS::with_cache(bridge, cache.clone())
+            .with_ttl(Duration::from_secs(settings.client.attr_ttl_secs))
+            .with_read_ahead(settings.client.read_ahead_chunks);
+
+        // The share's tree as of the last mount, to refresh with what changed since
+        let snapshot_depth = settings.client.snapshot_depth;
+        let snapshot_path = settings
+            .cache_dir()
+            .join("snapshots")
+            .join(format!("{}.snap", cli.host));
+        let snapshot = Arc::new(RwLock::new(match TreeSnapshot::load(&snapshot_path) {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                warn!("Ignoring unreadable tree snapshot: {}", e);
+                TreeSnapshot::new()
+            }
+        }));
+        if snapshot_depth > 0 {
+            fs = fs.with_snapshot(snapshot.clone());
+        }
+        let snapshot_cache = cache.clone();
+
+        // Garbage collector for the disk cache, if there is one
+        let gc = fs
+            .disk_cache()
+            .map(|disk_cache| Arc::new(GarbageCollector::from_config(disk_cache, &settings.cache)));
+
+        // The kernel caches attributes as long as we do
+        let kernel_ttl = fs.ttl_handle();
+        let reloader = cli
+            .settings
+            .reloader(&layered, cache, gc.clone())
+            .on_change(&["client.attr_ttl_secs"], move |config| {
+                *kernel_ttl.write() = Duration::from_secs(config.client.attr_ttl_secs);
+            });
 
         // Get the sync engine for background sync (Phase 7)
         let sync_engine = fs.sync_engine();
@@ -138,39 +326,78 @@ mod unix_impl {
         // Clone what we need for the async task
         let request_rx_clone = request_rx;
 
+        // Reports whether the host granted write access once connected
+        let (connected_tx, connected_rx) = mpsc::channel();
+
         // Spawn the client in the runtime
         let client_handle = thread::spawn(move || {
             rt.block_on(async move {
                 let mut client = WormholeClient::new(config);
+                // Locks and unsynced writes are shared with the filesystem
+                client.set_sync_engine(sync_engine.clone());
 
                 // Connect to the host
-                if let Err(e) = client.connect().await {
-                    error!("Failed to connect: {:?}", e);
-                    return;
+                match tokio::time::timeout(connect_timeout, client.connect()).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => {
+                        error!("Failed to connect: {}", e);
+                        return;
+                    }
+                    Err(_) => {
+                        error!("Failed to connect: timed out after {:?}", connect_timeout);
+                        return;
+                    }
                 }
 
                 info!("Connected to host!");
+                let _ = connected_tx.send(client.is_writable());
 
                 // Start the garbage collector if we have a disk cache
-                if let Some(disk_cache) = disk_cache {
+                if let Some(gc) = gc {
                     info!("Starting garbage collector for disk cache");
-                    let gc = GarbageCollector::new(disk_cache);
-                    tokio::spawn(async move {
-                        gc.run_loop().await;
-                    });
+                    tokio::spawn(gc.run_loop());
                 }
+                tokio::spawn(reloader.run());
 
                 // Start background sync for dirty chunks (Phase 7)
                 info!("Starting background sync for dirty chunks");
-                client.start_background_sync(sync_engine);
+                client.start_background_sync_every(sync_engine, sync_interval);
+
+                // Snapshot the tree while serving requests, so browsing
+                // doesn't wait for it
+                let refresh = async {
+                    if snapshot_depth == 0 {
+                        return;
+                    }
+                    let mut fresh = snapshot.read().clone();
+                    match client.refresh_snapshot(&mut fresh, snapshot_depth).await {
+                        Ok(()) => {
+                            info!("Tree snapshot holds {} directories", fresh.len());
+                            fresh.fill(&snapshot_cache);
+                            if let Err(e) = fresh.save(&snapshot_path) {
+                                warn!("Failed to save tree snapshot: {}", e);
+                            }
+                            *snapshot.write() = fresh;
+                        }
+                        Err(e) => warn!("Tree snapshot failed: {}", e),
+                    }
+                };
 
                 // Handle FUSE requests
-                if let Err(e) = client.handle_fuse_requests(request_rx_clone).await {
+                let (result, ()) =
+                    tokio::join!(client.handle_fuse_requests(request_rx_clone), refresh);
+                if let Err(e) = result {
                     error!("Client error: {:?}", e);
                 }
             });
         });
 
+        // The mount options depend on what the host grants, so wait for the handshake
+        let Ok(host_writable) = connected_rx.recv() else {
+            let _ = client_handle.join();
+            return Err("could not connect to host".into());
+        };
+
         // Mount FUSE filesystem (this blocks the main thread)
         info!("Mounting filesystem...");
 
@@ -180,6 +407,11 @@ mod unix_impl {
             MountOption::DefaultPermissions,
         ];
 
+        if cli.read_only || !host_writable {
+            info!("Mounting read-only");
+            mount_options.push(MountOption::RO);
+        }
+
         // On macOS, add FSKit backend option if not using kext
         #[cfg(target_os = "macos")]
         if use_fskit {
@@ -240,6 +472,7 @@ mod unix_impl {
 #[cfg(windows)]
 mod windows_impl {
     use std::net::SocketAddr;
+    use std::sync::Arc;
     use std::thread;
     use std::time::Duration;
 
@@ -250,8 +483,10 @@ mod windows_impl {
 
     use teleport_daemon::bridge::FuseAsyncBridge;
     use teleport_daemon::client::{ClientConfig, WormholeClient};
+    use teleport_daemon::known_peers::parse_fingerprint_arg;
+    use teleport_daemon::net::CertFingerprint;
     use teleport_daemon::winfsp::WormholeWinFS;
-    use teleport_daemon::GarbageCollector;
+    use teleport_daemon::{GarbageCollector, HybridCacheManager};
     use winfsp::host::{FileSystemHost, VolumeParams};
 
     #[derive(Parser)]
@@ -272,6 +507,17 @@ mod windows_impl {
         /// Enable write support (experimental)
         #[arg(long)]
         writable: bool,
+
+        /// Expected host certificate fingerprint (hex); pins instead of trust-on-first-use
+        #[arg(long, value_parser = parse_fingerprint_arg)]
+        fingerprint: Option<CertFingerprint>,
+
+        /// Share password, if the host requires one
+        #[arg(long, env = "WORMHOLE_PASSWORD", hide_env_values = true)]
+        password: Option<String>,
+
+        #[command(flatten)]
+        settings: super::SettingsArgs,
     }
 
     pub fn run() -> Result<(), Box<dyn std::error::Error>> {
@@ -289,6 +535,12 @@ mod windows_impl {
             .finish();
         tracing::subscriber::set_global_default(subscriber)?;
 
+        let layered = cli.settings.load()?;
+        let settings = layered.config().clone();
+        let request_timeout = Duration::from_secs(settings.network.request_timeout_secs);
+        let connect_timeout = Duration::from_secs(settings.network.connect_timeout_secs);
+        let sync_interval = Duration::from_secs(settings.client.sync_interval_secs);
+
         // Check if WinFSP is installed
         if !check_winfsp_installed() {
             error!("WinFSP is not installed or not properly configured.");
@@ -302,24 +554,33 @@ mod windows_impl {
         info!("Mount point: {}", cli.mount_point);
 
         // Create the filesystem ↔ async bridge
-        let (bridge, request_rx) = FuseAsyncBridge::new(Duration::from_secs(30));
+        let (bridge, request_rx) = FuseAsyncBridge::new(request_timeout);
 
         // Create client config (use a dummy path for Windows)
         let config = ClientConfig {
             server_addr: cli.host,
             mount_point: std::path::PathBuf::from(&cli.mount_point),
-            request_timeout: Duration::from_secs(30),
+            request_timeout,
+            expected_fingerprint: cli.fingerprint,
+            known_peers_path: None,
+            client_identity: super::load_client_identity(),
+            password: cli.password,
         };
 
         // Create the WinFSP filesystem
+        let cache = Arc::new(HybridCacheManager::from_config(&settings));
         let fs = if cli.writable {
             WormholeWinFS::new_writable(bridge)
         } else {
             WormholeWinFS::new(bridge)
-        };
+        }
+        .with_cache(cache.clone());
 
         // Get references for background tasks
-        let disk_cache = fs.disk_cache();
+        let gc = fs
+            .disk_cache()
+            .map(|disk_cache| Arc::new(GarbageCollector::from_config(disk_cache, &settings.cache)));
+        let reloader = cli.settings.reloader(&layered, cache, gc.clone());
         let sync_engine = fs.sync_engine();
 
         // Start tokio runtime in a separate thread for async networking
@@ -332,25 +593,30 @@ mod windows_impl {
                 let mut client = WormholeClient::new(config);
 
                 // Connect to the host
-                if let Err(e) = client.connect().await {
-                    error!("Failed to connect: {:?}", e);
-                    return;
+                match tokio::time::timeout(connect_timeout, client.connect()).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => {
+                        error!("Failed to connect: {}", e);
+                        return;
+                    }
+                    Err(_) => {
+                        error!("Failed to connect: timed out after {:?}", connect_timeout);
+                        return;
+                    }
                 }
 
                 info!("Connected to host!");
 
                 // Start the garbage collector if we have a disk cache
-                if let Some(disk_cache) = disk_cache {
+                if let Some(gc) = gc {
                     info!("Starting garbage collector for disk cache");
-                    let gc = GarbageCollector::new(disk_cache);
-                    tokio::spawn(async move {
-                        gc.run_loop().await;
-                    });
+                    tokio::spawn(gc.run_loop());
                 }
+                tokio::spawn(reloader.run());
 
                 // Start background sync for dirty chunks
                 info!("Starting background sync for dirty chunks");
-                client.start_background_sync(sync_engine);
+                client.start_background_sync_every(sync_engine, sync_interval);
 
                 // Handle filesystem requests
                 if let Err(e) = client.handle_fuse_requests(request_rx_clone).await {
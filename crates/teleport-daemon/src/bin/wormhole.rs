@@ -27,7 +27,7 @@
 //! - **Sync**: Control bidirectional synchronization
 //! - **Signal**: Run the rendezvous/signaling server
 
-use std::io::{self, Write as IoWrite};
+use std::io::{self, Read as IoRead, Write as IoWrite};
 use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
@@ -39,12 +39,27 @@ use tokio::signal;
 use tracing::{debug, error, info, warn, Level};
 use tracing_subscriber::EnvFilter;
 
-use teleport_core::crypto::{extract_join_code, make_share_link};
+use teleport_core::config::{Config, ConfigError, ConfigFile, ConfigSource, LayeredConfig};
+use teleport_core::crypto::{extract_fingerprint, extract_join_code, make_pinned_share_link};
 use teleport_core::{CHUNK_SIZE, PROTOCOL_VERSION};
+use teleport_daemon::access::{self, resolve_principal, AccessList, IpFilter};
+use teleport_daemon::audit::{self, AuditEvent, AuditLog, AuditQuery, AuditRecord};
+use teleport_daemon::bandwidth::{BandwidthLimiter, BandwidthLimits};
+use teleport_daemon::discovery::{self, DiscoveredShare, JoinCodeHint, LanAnnouncement};
 use teleport_daemon::host::{HostConfig, WormholeHost};
+use teleport_daemon::identity::{
+    identity_paths, Identity, IdentityError, CLIENT_IDENTITY, HOST_IDENTITY,
+};
+use teleport_daemon::known_peers::{
+    parse_fingerprint, parse_fingerprint_arg, KnownPeer, KnownPeers, TrustLevel,
+};
+use teleport_daemon::net::CertFingerprint;
+use teleport_daemon::rate_limiter::{RateLimiter, RateLimiterConfig};
 use teleport_daemon::rendezvous::{RendezvousClient, RendezvousError};
+use teleport_daemon::share_filter::ShareFilter;
+use teleport_daemon::share_limits::{parse_duration, parse_size, ShareLimits, ShareState};
 use teleport_daemon::updater::{format_update_message, UpdateChannel, UpdateChecker};
-use teleport_daemon::{DiskCache, HybridCacheManager};
+use teleport_daemon::{ConfigReloader, DiskCache, HybridCacheManager};
 
 // ============================================================================
 // CLI Structure
@@ -139,6 +154,9 @@ enum Commands {
     /// Manage trusted peers
     Peers(PeersArgs),
 
+    /// Show or rotate this machine's host and client certificates
+    Identity(IdentityArgs),
+
     /// Synchronization controls
     Sync(SyncArgs),
 
@@ -168,7 +186,7 @@ enum Commands {
     #[command(visible_alias = "ls")]
     List(ListArgs),
 
-    /// Show transfer history and statistics
+    /// Show client activity recorded in the audit logs of hosted shares
     History(HistoryArgs),
 
     /// Manage access control and permissions
@@ -188,19 +206,19 @@ enum Commands {
 // Host Command
 // ============================================================================
 
-#[derive(Args)]
+#[derive(Args, Clone)]
 struct HostArgs {
     /// Directory to share
     #[arg(value_name = "PATH")]
     path: PathBuf,
 
-    /// Port to listen on
-    #[arg(short, long, default_value = "4433", env = "WORMHOLE_PORT")]
-    port: u16,
+    /// Port to listen on [default: 4433]
+    #[arg(short, long)]
+    port: Option<u16>,
 
-    /// Bind address
-    #[arg(short, long, default_value = "0.0.0.0", env = "WORMHOLE_BIND")]
-    bind: String,
+    /// Bind address [default: 0.0.0.0]
+    #[arg(short, long)]
+    bind: Option<String>,
 
     /// Custom name for this share
     #[arg(short, long)]
@@ -230,25 +248,41 @@ struct HostArgs {
     #[arg(long)]
     password: Option<String>,
 
-    /// Only allow specific IP addresses
+    /// Only allow these IP addresses or CIDR networks (e.g. 192.168.1.0/24)
     #[arg(long, value_delimiter = ',')]
     allow_ips: Option<Vec<String>>,
 
-    /// Block specific IP addresses
+    /// Block these IP addresses or CIDR networks
     #[arg(long, value_delimiter = ',')]
     block_ips: Option<Vec<String>>,
 
-    /// Set bandwidth limit in MB/s (0 = unlimited)
-    #[arg(long, default_value = "0")]
-    bandwidth_limit: u64,
+    /// Set bandwidth limit in MB/s (0 = unlimited) [default: 0]
+    #[arg(long)]
+    bandwidth_limit: Option<u64>,
+
+    /// Set bandwidth limit per client in MB/s (0 = unlimited) [default: 0]
+    #[arg(long)]
+    client_bandwidth_limit: Option<u64>,
 
     /// Run in background as daemon
     #[arg(short, long)]
     daemon: bool,
 
     /// Auto-expire the share after duration (e.g., "2h", "30m")
+    #[arg(long, value_parser = parse_duration)]
+    expire_after: Option<Duration>,
+
+    /// Stop admitting new clients after this many have connected
+    #[arg(long)]
+    max_clients: Option<usize>,
+
+    /// Expire the share once this much data has been served (e.g., "500MB", "2G")
+    #[arg(long, value_parser = parse_size)]
+    max_bytes: Option<u64>,
+
+    /// Don't record client activity in the share's audit log (see `wormhole history`)
     #[arg(long)]
-    expire_after: Option<String>,
+    no_audit: bool,
 
     /// Copy join code to clipboard
     #[arg(long)]
@@ -258,11 +292,12 @@ struct HostArgs {
     #[arg(long)]
     qr_code: bool,
 
-    /// Exclude patterns (glob)
+    /// Exclude patterns (gitignore syntax, e.g. .git/,node_modules,.env*);
+    /// a .wormholeignore file in the shared folder adds more and is reloaded on change
     #[arg(long, value_delimiter = ',')]
     exclude: Option<Vec<String>>,
 
-    /// Include only patterns (glob)
+    /// Include only files matching these patterns (gitignore syntax)
     #[arg(long, value_delimiter = ',')]
     include: Option<Vec<String>>,
 
@@ -314,33 +349,41 @@ struct MountArgs {
     #[arg(long)]
     use_kext: bool,
 
+    /// Don't look for the host on the local network before using the signal server
+    #[arg(long)]
+    no_lan: bool,
+
+    /// Expected host certificate fingerprint (taken from the share link if present)
+    #[arg(long, value_parser = parse_fingerprint_arg)]
+    fingerprint: Option<CertFingerprint>,
+
     /// Mount in read-only mode
     #[arg(long)]
     read_only: bool,
 
     /// Password (if host requires one)
-    #[arg(long)]
+    #[arg(long, env = "WORMHOLE_PASSWORD", hide_env_values = true)]
     password: Option<String>,
 
     /// Cache mode
     #[arg(long, value_enum, default_value = "hybrid")]
     cache_mode: CacheMode,
 
-    /// RAM cache size in MB
-    #[arg(long, default_value = "512")]
-    ram_cache_mb: usize,
+    /// RAM cache size in MB [default: 512]
+    #[arg(long)]
+    ram_cache_mb: Option<u64>,
 
-    /// Disk cache size in GB
-    #[arg(long, default_value = "10")]
-    disk_cache_gb: usize,
+    /// Disk cache size in GB [default: 10]
+    #[arg(long)]
+    disk_cache_gb: Option<u64>,
 
     /// Enable prefetching for sequential reads
     #[arg(long)]
     prefetch: bool,
 
-    /// Prefetch lookahead chunks
-    #[arg(long, default_value = "4")]
-    prefetch_lookahead: usize,
+    /// Prefetch lookahead chunks [default: 4]
+    #[arg(long)]
+    prefetch_lookahead: Option<usize>,
 
     /// Auto-reconnect on connection loss
     #[arg(long, default_value = "true")]
@@ -366,9 +409,9 @@ struct MountArgs {
     #[arg(long, default_value = "0")]
     bandwidth_limit: u64,
 
-    /// Connection timeout in seconds
-    #[arg(long, default_value = "30")]
-    timeout: u64,
+    /// Connection timeout in seconds [default: 10]
+    #[arg(long)]
+    timeout: Option<u64>,
 
     /// Mount options (passed to FUSE)
     #[arg(short = 'o', long, value_delimiter = ',')]
@@ -382,13 +425,13 @@ struct MountArgs {
     #[arg(long)]
     write_through: bool,
 
-    /// Attribute cache timeout in seconds
-    #[arg(long, default_value = "1")]
-    attr_timeout: u64,
+    /// Attribute cache timeout in seconds [default: 1]
+    #[arg(long)]
+    attr_timeout: Option<u64>,
 
-    /// Entry cache timeout in seconds
-    #[arg(long, default_value = "1")]
-    entry_timeout: u64,
+    /// Entry cache timeout in seconds [default: 1]
+    #[arg(long)]
+    entry_timeout: Option<u64>,
 
     /// Enable extended attributes
     #[arg(long)]
@@ -667,16 +710,16 @@ struct ConfigShowArgs {
 
 #[derive(Args)]
 struct ConfigSetArgs {
-    /// Configuration key
+    /// Configuration key (e.g. host.port, see `wormhole config list`)
     key: String,
 
-    /// Configuration value
+    /// Configuration value (empty to unset an optional path or URL)
     value: String,
 }
 
 #[derive(Args)]
 struct ConfigGetArgs {
-    /// Configuration key
+    /// Configuration key (e.g. host.port, see `wormhole config list`)
     key: String,
 }
 
@@ -693,7 +736,7 @@ struct ConfigResetArgs {
 
 #[derive(Args)]
 struct ConfigImportArgs {
-    /// Config file to import
+    /// Config file to import (`-` for stdin)
     path: PathBuf,
 
     /// Merge with existing config
@@ -703,7 +746,7 @@ struct ConfigImportArgs {
 
 #[derive(Args)]
 struct ConfigExportArgs {
-    /// Output path
+    /// Output path (`-` for stdout)
     path: PathBuf,
 
     /// Include secrets
@@ -761,7 +804,7 @@ struct PeersListArgs {
 
 #[derive(Args)]
 struct PeersAddArgs {
-    /// Peer identifier or address
+    /// Certificate fingerprint of the peer (hex)
     peer: String,
 
     /// Friendly name for the peer
@@ -816,6 +859,43 @@ struct PeersRenameArgs {
     name: String,
 }
 
+// ============================================================================
+// Identity Command
+// ============================================================================
+
+#[derive(Args)]
+struct IdentityArgs {
+    #[command(subcommand)]
+    command: IdentityCommands,
+}
+
+#[derive(Subcommand)]
+enum IdentityCommands {
+    /// Show the certificate fingerprint
+    Show(IdentityShowArgs),
+
+    /// Replace the certificate with a new one
+    Rotate(IdentityRotateArgs),
+}
+
+#[derive(Args)]
+struct IdentityShowArgs {
+    /// Show the client identity presented when mounting, instead of the host's
+    #[arg(long)]
+    client: bool,
+}
+
+#[derive(Args)]
+struct IdentityRotateArgs {
+    /// Rotate the client identity instead of the host's
+    #[arg(long)]
+    client: bool,
+
+    /// Rotate without confirmation
+    #[arg(short, long)]
+    force: bool,
+}
+
 // ============================================================================
 // Sync Command
 // ============================================================================
@@ -939,13 +1019,13 @@ struct SyncLogArgs {
 
 #[derive(Args)]
 struct SignalArgs {
-    /// Port to listen on
-    #[arg(short, long, default_value = "8080", env = "SIGNAL_PORT")]
-    port: u16,
+    /// Port to listen on [default: 8080]
+    #[arg(short, long)]
+    port: Option<u16>,
 
-    /// Bind address
-    #[arg(short, long, default_value = "0.0.0.0", env = "SIGNAL_BIND")]
-    bind: String,
+    /// Bind address [default: 0.0.0.0]
+    #[arg(short, long)]
+    bind: Option<String>,
 
     /// Maximum concurrent connections
     #[arg(long, default_value = "1000")]
@@ -1124,6 +1204,14 @@ struct ListArgs {
     /// Show detailed information
     #[arg(short, long)]
     detailed: bool,
+
+    /// Browse the local network for announced shares
+    #[arg(long)]
+    lan: bool,
+
+    /// How long to wait for LAN responses in seconds
+    #[arg(long, default_value = "3", requires = "lan")]
+    lan_timeout: u64,
 }
 
 #[derive(ValueEnum, Clone, Copy, Debug, Default)]
@@ -1137,11 +1225,11 @@ enum ListType {
 
 #[derive(Args)]
 struct HistoryArgs {
-    /// Number of entries
+    /// Number of entries (most recent; 0 = all)
     #[arg(short, long, default_value = "50")]
     limit: usize,
 
-    /// Filter by operation type
+    /// Filter by operation (e.g. write, create, delete, rename, read, session)
     #[arg(long)]
     operation: Option<String>,
 
@@ -1190,7 +1278,7 @@ struct AccessGrantArgs {
     /// Share to grant access to
     share: String,
 
-    /// Peer to grant access to
+    /// Peer to grant access to: client fingerprint, known peer name, IP or CIDR
     peer: String,
 
     /// Access level
@@ -1203,7 +1291,7 @@ struct AccessRevokeArgs {
     /// Share
     share: String,
 
-    /// Peer to revoke access from
+    /// Peer to revoke access from: client fingerprint, known peer name, IP or CIDR
     peer: String,
 }
 
@@ -1225,6 +1313,17 @@ enum AccessLevel {
     Admin,
 }
 
+impl From<AccessLevel> for access::AccessLevel {
+    fn from(level: AccessLevel) -> Self {
+        match level {
+            AccessLevel::None => access::AccessLevel::None,
+            AccessLevel::Read => access::AccessLevel::Read,
+            AccessLevel::Write => access::AccessLevel::Write,
+            AccessLevel::Admin => access::AccessLevel::Admin,
+        }
+    }
+}
+
 #[derive(Args)]
 struct WatchArgs {
     /// Share or path to watch
@@ -1361,6 +1460,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         Commands::Cache(args) => run_cache(args, &cli).await,
         Commands::Config(args) => run_config(args, &cli).await,
         Commands::Peers(args) => run_peers(args, &cli).await,
+        Commands::Identity(args) => run_identity(args, &cli),
         Commands::Sync(args) => run_sync(args, &cli).await,
         Commands::Signal(args) => run_signal(args, &cli).await,
         Commands::Completions(args) => run_completions(args),
@@ -1409,6 +1509,18 @@ fn setup_logging(cli: &Cli) {
     }
 }
 
+/// Effective settings: defaults, then the config file, `WORMHOLE_*`
+/// variables and finally the command-line `flags` that were given
+fn load_settings(
+    cli: &Cli,
+    flags: &[(&str, Option<String>)],
+) -> Result<LayeredConfig, ConfigError> {
+    let mut settings = LayeredConfig::load(cli.config.as_deref())?;
+    settings.apply_cli(flags.iter().cloned())?;
+    settings.validate()?;
+    Ok(settings)
+}
+
 // ============================================================================
 // Command Implementations
 // ============================================================================
@@ -1421,7 +1533,29 @@ async fn run_host(args: &HostArgs, cli: &Cli) -> Result<(), Box<dyn std::error::
         return Err("Not a directory".into());
     }
 
-    let bind_addr: SocketAddr = format!("{}:{}", args.bind, args.port).parse()?;
+    let flags = vec![
+        ("host.port", args.port.map(|p| p.to_string())),
+        ("host.bind", args.bind.clone()),
+        ("host.writable", args.allow_write.then(|| "true".into())),
+        (
+            "host.bandwidth_limit_mbps",
+            args.bandwidth_limit.map(|mb| mb.to_string()),
+        ),
+        (
+            "host.client_bandwidth_limit_mbps",
+            args.client_bandwidth_limit.map(|mb| mb.to_string()),
+        ),
+    ];
+    let settings = load_settings(cli, &flags)?;
+    let host_settings = &settings.config().host;
+    let bind_addr = SocketAddr::new(host_settings.bind, host_settings.port);
+    // The banner reports what the config file and environment decided too
+    let args = &HostArgs {
+        allow_write: host_settings.writable,
+        bandwidth_limit: Some(host_settings.bandwidth_limit_mbps),
+        client_bandwidth_limit: Some(host_settings.client_bandwidth_limit_mbps),
+        ..args.clone()
+    };
 
     let host_name = args.name.clone().unwrap_or_else(|| {
         hostname::get()
@@ -1434,6 +1568,7 @@ async fn run_host(args: &HostArgs, cli: &Cli) -> Result<(), Box<dyn std::error::
         shared_path: path.clone(),
         max_connections: args.max_connections,
         host_name: host_name.clone(),
+        writable: args.allow_write,
     };
 
     // Generate or use provided join code
@@ -1443,10 +1578,128 @@ async fn run_host(args: &HostArgs, cli: &Cli) -> Result<(), Box<dyn std::error::
         .map(|c| teleport_core::crypto::normalize_join_code(c))
         .unwrap_or_else(teleport_core::crypto::generate_join_code);
 
+    let ip_filter = IpFilter::parse(
+        args.allow_ips.as_deref().unwrap_or_default(),
+        args.block_ips.as_deref().unwrap_or_default(),
+    )?;
+
+    let filter = ShareFilter::with_patterns(
+        &path,
+        args.include.as_deref().unwrap_or_default(),
+        args.exclude.as_deref().unwrap_or_default(),
+    )?;
+
+    let identity = Arc::new(load_host_identity(args, host_settings.auto_cert)?);
+
     // Display startup info
-    print_host_banner(&path, bind_addr, &join_code, &host_name, args, cli);
+    print_host_banner(
+        &path,
+        bind_addr,
+        &join_code,
+        &identity.fingerprint(),
+        &host_name,
+        args,
+        cli,
+    );
 
-    let host = WormholeHost::new(config);
+    let mut limits = ShareLimits::default();
+    if let Some(lifetime) = args.expire_after {
+        limits = limits.with_lifetime(lifetime);
+    }
+    if let Some(max_clients) = args.max_clients {
+        limits = limits.with_max_clients(max_clients);
+    }
+    if let Some(max_bytes) = args.max_bytes {
+        limits = limits.with_max_bytes(max_bytes);
+    }
+
+    let mut host = WormholeHost::new(config)
+        .with_identity(identity.clone())
+        .with_filter(filter)
+        .with_ip_filter(ip_filter)
+        .with_limits(limits);
+    let usage = host.usage();
+    if let Some(password) = &args.password {
+        host = host.with_password(password.clone());
+    }
+
+    // Name used for the LAN announcement and the share's access list
+    let share_name = args
+        .name
+        .clone()
+        .or_else(|| path.file_name().map(|n| n.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| host_name.clone());
+
+    // Reads an application is waiting on are paced ahead of bulk transfers.
+    // The limiter is installed even when unlimited so a config reload can add limits.
+    let bandwidth = Arc::new(BandwidthLimiter::new(host_bandwidth_limits(
+        settings.config(),
+    )));
+    host = host.with_bandwidth_limiter(bandwidth.clone(), share_name.clone());
+
+    let rate_limiter = Arc::new(RateLimiter::with_config(RateLimiterConfig::from_config(
+        host_settings,
+    )));
+    host = host.with_rate_limiter(rate_limiter.clone());
+
+    // Read once: edits made while hosting are reported as needing a restart
+    let access_list_path = AccessList::default_path(&share_name);
+    let access_list = AccessList::load(&share_name)?;
+    if !access_list.is_empty() {
+        info!(
+            "Loaded {} access rule(s) for share '{}'",
+            access_list.rules().len(),
+            share_name
+        );
+        host = host.with_access_list(Arc::new(access_list));
+    }
+
+    if !args.no_audit {
+        match AuditLog::open(&share_name) {
+            Ok(log) => {
+                info!("Recording client activity in {}", log.path().display());
+                host = host.with_audit_log(Arc::new(log));
+            }
+            Err(e) => warn!("Audit log disabled: {}", e),
+        }
+    }
+
+    if args.announce_local {
+        let machine_name = hostname::get()
+            .map(|h| h.to_string_lossy().into_owned())
+            .unwrap_or_else(|_| host_name.clone());
+
+        host = host.with_lan_announcement(LanAnnouncement {
+            host_name: machine_name,
+            share_name,
+            port: bind_addr.port(),
+            cert_fingerprint: Some(identity.fingerprint()),
+            join_code_hint: Some(JoinCodeHint::new(&join_code)),
+        });
+    }
+
+    // Apply config file edits (or SIGHUP) without dropping sessions
+    let mut reloader = ConfigReloader::new(&settings)
+        .with_flags(flags)
+        .on_change(
+            &[
+                "host.bandwidth_limit_mbps",
+                "host.client_bandwidth_limit_mbps",
+            ],
+            move |config| bandwidth.set_limits(host_bandwidth_limits(config)),
+        )
+        .on_change(
+            &[
+                "host.max_failed_attempts",
+                "host.failed_attempt_window_secs",
+                "host.block_secs",
+            ],
+            move |config| rate_limiter.set_config(RateLimiterConfig::from_config(&config.host)),
+        );
+    if let Some(path) = access_list_path {
+        reloader = reloader.with_restart_file("access list", path);
+    }
+    let reload_task = tokio::spawn(reloader.run());
 
     // Handle Ctrl+C
     let running = Arc::new(AtomicBool::new(true));
@@ -1462,6 +1715,7 @@ async fn run_host(args: &HostArgs, cli: &Cli) -> Result<(), Box<dyn std::error::
         let signal_server = args.signal_server.clone();
         let join_code_clone = join_code.clone();
         let running_clone = running.clone();
+        let usage = usage.clone();
 
         Some(tokio::spawn(async move {
             // Run signal server registration in a loop to handle reconnects
@@ -1469,11 +1723,20 @@ async fn run_host(args: &HostArgs, cli: &Cli) -> Result<(), Box<dyn std::error::
                 if !running_clone.load(Ordering::SeqCst) {
                     break;
                 }
+                // Nobody new can join once the share is full or expired, so leave the room
+                if usage.state() != ShareState::Open {
+                    info!("Share closed to new clients, leaving signal server room");
+                    break;
+                }
 
                 info!("Registering with signal server: {}", signal_server);
                 let rendezvous = RendezvousClient::new(Some(signal_server.clone()));
 
-                match rendezvous.host(&join_code_clone).await {
+                let hosted = tokio::select! {
+                    hosted = rendezvous.host(&join_code_clone) => hosted,
+                    _ = usage.closed() => continue,
+                };
+                match hosted {
                     Ok(result) => {
                         info!("Peer connected via signal server: {:?}", result.peer_addr);
                         // Continue loop to accept more peers
@@ -1498,8 +1761,10 @@ async fn run_host(args: &HostArgs, cli: &Cli) -> Result<(), Box<dyn std::error::
 
     tokio::select! {
         result = host.serve() => {
-            if let Err(e) = result {
-                error!("Host error: {:?}", e);
+            match result {
+                Ok(()) if usage.is_expired() => println!("\nShare expired, stopped sharing."),
+                Ok(()) => {}
+                Err(e) => error!("Host error: {:?}", e),
             }
         }
         _ = async {
@@ -1515,19 +1780,55 @@ async fn run_host(args: &HostArgs, cli: &Cli) -> Result<(), Box<dyn std::error::
     if let Some(task) = signal_task {
         task.abort();
     }
+    reload_task.abort();
 
     Ok(())
 }
 
+/// Bandwidth budgets from the `[host]` section of the config
+fn host_bandwidth_limits(config: &Config) -> BandwidthLimits {
+    let megabytes_per_sec = |mb: u64| (mb > 0).then(|| mb * 1024 * 1024);
+    BandwidthLimits {
+        total: megabytes_per_sec(config.host.bandwidth_limit_mbps),
+        per_client: megabytes_per_sec(config.host.client_bandwidth_limit_mbps),
+        ..Default::default()
+    }
+}
+
+/// Load the host's TLS identity: custom `--tls-cert`/`--tls-key` if given,
+/// otherwise the persistent identity from the config directory, which is
+/// created on first use when `auto_cert` is set
+fn load_host_identity(
+    args: &HostArgs,
+    auto_cert: bool,
+) -> Result<Identity, Box<dyn std::error::Error>> {
+    match (&args.tls_cert, &args.tls_key) {
+        (Some(cert), Some(key)) => Ok(Identity::from_pem_files(cert, key)?),
+        (None, None) => {
+            let dir = Identity::default_dir().ok_or(IdentityError::NoConfigDir)?;
+            if auto_cert {
+                return Ok(Identity::load_or_create(&dir, HOST_IDENTITY)?);
+            }
+            Identity::load(&dir, HOST_IDENTITY)?.ok_or_else(|| {
+                "no host certificate and host.auto_cert is off; \
+                 create one with `wormhole identity rotate`"
+                    .into()
+            })
+        }
+        _ => Err("--tls-cert and --tls-key must be given together".into()),
+    }
+}
+
 fn print_host_banner(
     path: &PathBuf,
     bind_addr: SocketAddr,
     join_code: &str,
+    fingerprint: &CertFingerprint,
     host_name: &str,
     args: &HostArgs,
     cli: &Cli,
 ) {
-    let share_link = make_share_link(join_code);
+    let share_link = make_pinned_share_link(join_code, fingerprint);
 
     if cli.quiet {
         // In quiet mode, just print the share link
@@ -1543,9 +1844,17 @@ fn print_host_banner(
                 "bind_addr": bind_addr.to_string(),
                 "join_code": join_code,
                 "share_link": share_link,
+                "fingerprint": hex::encode(fingerprint),
                 "host_name": host_name,
                 "allow_write": args.allow_write,
+                "password_required": args.password.is_some(),
                 "max_connections": args.max_connections,
+                "expire_after_secs": args.expire_after.map(|d| d.as_secs()),
+                "max_clients": args.max_clients,
+                "max_bytes": args.max_bytes,
+                "bandwidth_limit_mbps": args.bandwidth_limit.unwrap_or_default(),
+                "client_bandwidth_limit_mbps": args.client_bandwidth_limit.unwrap_or_default(),
+                "audit": !args.no_audit,
             });
             println!("{}", serde_json::to_string_pretty(&info).unwrap());
             return;
@@ -1556,6 +1865,7 @@ fn print_host_banner(
             println!("bind_addr: {}", bind_addr);
             println!("join_code: {}", join_code);
             println!("share_link: {}", share_link);
+            println!("fingerprint: {}", hex::encode(fingerprint));
             return;
         }
         OutputFormat::Text => {}
@@ -1587,6 +1897,46 @@ fn print_host_banner(
         }
     );
     println!("║  Max Peers: {:<57} ║", args.max_connections);
+    if args.password.is_some() {
+        println!("║  Password:  {:<57} ║", "required");
+    }
+    let bandwidth_limit = args.bandwidth_limit.unwrap_or_default();
+    let client_bandwidth_limit = args.client_bandwidth_limit.unwrap_or_default();
+    if bandwidth_limit > 0 || client_bandwidth_limit > 0 {
+        let limit = |mb: u64| {
+            if mb > 0 {
+                format!("{} MB/s", mb)
+            } else {
+                "unlimited".into()
+            }
+        };
+        println!(
+            "║  Bandwidth: {:<57} ║",
+            format!(
+                "{} total, {} per client",
+                limit(bandwidth_limit),
+                limit(client_bandwidth_limit)
+            )
+        );
+    }
+    if let Some(lifetime) = args.expire_after {
+        println!(
+            "║  Expires:   {:<57} ║",
+            format!("in {}", format_duration(lifetime))
+        );
+    }
+    if let Some(max_clients) = args.max_clients {
+        println!(
+            "║  Clients:   {:<57} ║",
+            format!("at most {} distinct", max_clients)
+        );
+    }
+    if let Some(max_bytes) = args.max_bytes {
+        println!(
+            "║  Quota:     {:<57} ║",
+            format!("{} served", format_bytes(max_bytes))
+        );
+    }
     println!("║                                                                       ║");
     println!("║  Connect with:                                                        ║");
     println!("║    wormhole mount {}                      ║", share_link);
@@ -1604,15 +1954,37 @@ async fn run_mount(args: &MountArgs, cli: &Cli) -> Result<(), Box<dyn std::error
         }
         code
     } else {
-        args.target.clone()
+        // Direct addresses may carry a pinned fingerprint too
+        args.target
+            .split(['?', '#'])
+            .next()
+            .unwrap_or(&args.target)
+            .to_string()
     };
 
+    // A fingerprint in the link pins the host from the first connection
+    let link_fingerprint = extract_fingerprint(&args.target);
+    if let (Some(given), Some(linked)) = (args.fingerprint, link_fingerprint) {
+        if given != linked {
+            return Err("--fingerprint does not match the fingerprint in the share link".into());
+        }
+    }
+    let fingerprint = args.fingerprint.or(link_fingerprint);
+
     // Determine if this is a direct IP or join code
     let is_direct = is_ip_address(&target);
 
+    // wormhole-mount applies the rest of the settings itself
+    let settings = load_settings(cli, &[])?;
+
     // Create a modified args with the extracted target
     let modified_args = MountArgs {
         target,
+        fingerprint,
+        path: args
+            .path
+            .clone()
+            .or_else(|| settings.config().client.mount_point.clone()),
         ..args.clone()
     };
 
@@ -1663,10 +2035,22 @@ async fn run_mount_direct(args: &MountArgs, cli: &Cli) -> Result<(), Box<dyn std
     let mut cmd = std::process::Command::new(&mount_exe);
     cmd.arg(&args.target);
     cmd.arg(&mount_point);
+    if let Some(fp) = args.fingerprint {
+        cmd.arg("--fingerprint").arg(hex::encode(fp));
+    }
+    // Passed through the environment so it doesn't show up in the process list
+    if let Some(password) = &args.password {
+        cmd.env("WORMHOLE_PASSWORD", password);
+    }
+    #[cfg(unix)]
+    if args.read_only {
+        cmd.arg("--read-only");
+    }
 
     if args.use_kext {
         cmd.arg("--use-kext");
     }
+    forward_mount_settings(&mut cmd, args, cli);
 
     let status = cmd.status()?;
 
@@ -1707,24 +2091,61 @@ async fn run_mount_via_signal(
         println!("Mount point: {:?}", mount_point);
     }
 
-    let rendezvous = teleport_daemon::rendezvous::RendezvousClient::new(Some(args.signal.clone()));
-
-    if !cli.quiet {
-        println!("Connecting to signal server...");
-    }
-
-    let result = rendezvous.connect(&code).await;
+    // A host announcing this code on the LAN saves the signal server round trip
+    let lan_share = if args.no_lan {
+        None
+    } else {
+        find_lan_host(&code).await.filter(|share| {
+            // Another host announcing the same code must not override a pinned link
+            let matches = match (args.fingerprint, share.cert_fingerprint) {
+                (Some(pinned), Some(announced)) => pinned == announced,
+                _ => true,
+            };
+            if !matches {
+                warn!(
+                    "Ignoring LAN host {}: fingerprint does not match the share link",
+                    share.addr
+                );
+            }
+            matches
+        })
+    };
 
-    match result {
-        Ok(rendezvous_result) => {
+    let result = match lan_share {
+        Some(share) => {
             if !cli.quiet {
                 println!(
-                    "Found host at: {} (local: {})",
-                    rendezvous_result.peer_addr, rendezvous_result.is_local
+                    "Found host on local network: {} ({})",
+                    share.addr, share.instance
                 );
             }
+            // Announcements are unauthenticated, so the host is only pinned by
+            // the share link; otherwise it is checked against known peers
+            Ok((share.addr, args.fingerprint))
+        }
+        None => {
+            let rendezvous =
+                teleport_daemon::rendezvous::RendezvousClient::new(Some(args.signal.clone()));
+
+            if !cli.quiet {
+                println!("Connecting to signal server...");
+            }
 
-            let host_addr = rendezvous_result.peer_addr.to_string();
+            rendezvous.connect(&code).await.map(|rendezvous_result| {
+                if !cli.quiet {
+                    println!(
+                        "Found host at: {} (local: {})",
+                        rendezvous_result.peer_addr, rendezvous_result.is_local
+                    );
+                }
+                (rendezvous_result.peer_addr, args.fingerprint)
+            })
+        }
+    };
+
+    match result {
+        Ok((peer_addr, fingerprint)) => {
+            let host_addr = peer_addr.to_string();
 
             // Call mount directly with resolved address
             let _addr: SocketAddr = host_addr.parse()?;
@@ -1750,10 +2171,21 @@ async fn run_mount_via_signal(
             let mut cmd = std::process::Command::new(&mount_exe);
             cmd.arg(host_addr);
             cmd.arg(&actual_mount);
+            if let Some(fp) = fingerprint {
+                cmd.arg("--fingerprint").arg(hex::encode(fp));
+            }
+            if let Some(password) = &args.password {
+                cmd.env("WORMHOLE_PASSWORD", password);
+            }
+            #[cfg(unix)]
+            if args.read_only {
+                cmd.arg("--read-only");
+            }
 
             if args.use_kext {
                 cmd.arg("--use-kext");
             }
+            forward_mount_settings(&mut cmd, args, cli);
 
             let status = cmd.status()?;
 
@@ -1779,6 +2211,40 @@ async fn run_mount_via_signal(
     }
 }
 
+/// Pass the config file and any settings given as flags on to wormhole-mount
+fn forward_mount_settings(cmd: &mut std::process::Command, args: &MountArgs, cli: &Cli) {
+    if let Some(config) = &cli.config {
+        cmd.arg("--config").arg(config);
+    }
+    let flags = [
+        ("--attr-ttl", args.attr_timeout),
+        ("--dir-ttl", args.entry_timeout),
+        ("--read-ahead", args.prefetch_lookahead.map(|n| n as u64)),
+        ("--ram-cache-mb", args.ram_cache_mb),
+        ("--disk-cache-gb", args.disk_cache_gb),
+        ("--connect-timeout", args.timeout),
+    ];
+    for (flag, value) in flags {
+        if let Some(value) = value {
+            cmd.arg(flag).arg(value.to_string());
+        }
+    }
+}
+
+/// How long a mount waits for a LAN announcement before using the signal server
+const LAN_LOOKUP_TIMEOUT: Duration = Duration::from_millis(1500);
+
+/// Look for a host announcing `code` on the local network
+async fn find_lan_host(code: &str) -> Option<DiscoveredShare> {
+    match discovery::find_by_join_code(code, LAN_LOOKUP_TIMEOUT).await {
+        Ok(share) => share,
+        Err(e) => {
+            debug!("LAN lookup failed: {}", e);
+            None
+        }
+    }
+}
+
 async fn run_status(args: &StatusArgs, _cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
     println!("╔═══════════════════════════════════════════════════════════════╗");
     println!("║                    WORMHOLE STATUS                            ║");
@@ -1921,25 +2387,142 @@ async fn run_cache(args: &CacheArgs, _cli: &Cli) -> Result<(), Box<dyn std::erro
     Ok(())
 }
 
-async fn run_config(args: &ConfigArgs, _cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
+async fn run_config(args: &ConfigArgs, cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
     match &args.command {
+        ConfigCommands::Show(_) => {
+            // Show what was loaded even if it doesn't validate, then say why
+            let settings = LayeredConfig::load(cli.config.as_deref())?;
+            print_settings(&settings, cli)?;
+            settings.validate()?;
+        }
+        ConfigCommands::Get(get_args) => {
+            let settings = LayeredConfig::load(cli.config.as_deref())?;
+            let value = settings.config().get(&get_args.key)?;
+            let source = settings.source(&get_args.key);
+            match cli.format {
+                OutputFormat::Json => {
+                    let entry = serde_json::json!({
+                        "key": get_args.key,
+                        "value": value,
+                        "source": source,
+                    });
+                    println!("{}", serde_json::to_string_pretty(&entry)?);
+                }
+                OutputFormat::Yaml => {
+                    println!("{}:", get_args.key);
+                    println!("  value: {}", serde_json::to_string(&value)?);
+                    println!("  source: {}", source);
+                }
+                OutputFormat::Text => {
+                    if let Some(value) = value {
+                        println!("{}", value);
+                    }
+                }
+            }
+        }
+        ConfigCommands::Set(set_args) => {
+            let path = config_path(cli)?;
+            let mut file = ConfigFile::open(&path)?;
+            file.set(&set_args.key, &set_args.value)?;
+            file.save_to(&path)?;
+
+            if !cli.quiet {
+                let value = file.config()?.get(&set_args.key)?;
+                let value = value.unwrap_or_else(|| "unset".into());
+                println!("{} = {} ({})", set_args.key, value, path.display());
+                warn_env_override(&set_args.key);
+            }
+        }
+        ConfigCommands::Reset(reset_args) => {
+            let path = config_path(cli)?;
+            let mut file = ConfigFile::open(&path)?;
+            match &reset_args.key {
+                Some(key) => {
+                    if !file.reset(key)? {
+                        println!("{} is not set in {}", key, path.display());
+                        return Ok(());
+                    }
+                    file.save_to(&path)?;
+                    let default = Config::default()
+                        .get(key)?
+                        .unwrap_or_else(|| "unset".into());
+                    println!("{} reset to its default ({})", key, default);
+                    warn_env_override(key);
+                }
+                None => {
+                    if !reset_args.force {
+                        print!(
+                            "Reset every setting in {} to its default? [y/N] ",
+                            path.display()
+                        );
+                        io::stdout().flush()?;
+                        let mut input = String::new();
+                        io::stdin().read_line(&mut input)?;
+                        if !input.trim().eq_ignore_ascii_case("y") {
+                            println!("Cancelled.");
+                            return Ok(());
+                        }
+                    }
+                    file.reset_all();
+                    file.save_to(&path)?;
+                    println!("Configuration reset to defaults.");
+                }
+            }
+        }
         ConfigCommands::Path => {
-            if let Some(dirs) = directories::ProjectDirs::from("", "", "wormhole") {
-                println!("{}", dirs.config_dir().join("config.toml").display());
+            if let Some(path) = cli.config.clone().or_else(Config::default_path) {
+                println!("{}", path.display());
+            }
+        }
+        ConfigCommands::Import(import_args) => {
+            let content = if import_args.path.as_os_str() == "-" {
+                let mut content = String::new();
+                io::stdin().read_to_string(&mut content)?;
+                content
+            } else {
+                std::fs::read_to_string(&import_args.path)?
+            };
+            let imported: ConfigFile = content.parse()?;
+            imported.config()?.validate()?;
+
+            let path = config_path(cli)?;
+            let count = imported.keys()?.len();
+            if import_args.merge {
+                let mut file = ConfigFile::open(&path)?;
+                file.merge(&imported)?;
+                file.save_to(&path)?;
+            } else {
+                imported.save_to(&path)?;
+            }
+            if !cli.quiet {
+                println!("Imported {} setting(s) into {}", count, path.display());
+            }
+        }
+        ConfigCommands::Export(export_args) => {
+            // The file as written, comments and all; defaults if there isn't one
+            let path = config_path(cli)?;
+            let content = if path.exists() {
+                ConfigFile::open(&path)?.to_string()
+            } else {
+                Config::sample()
+            };
+            if export_args.path.as_os_str() == "-" {
+                print!("{}", content);
+            } else {
+                std::fs::write(&export_args.path, content)?;
+                if !cli.quiet {
+                    println!("Exported configuration to {}", export_args.path.display());
+                }
             }
         }
         ConfigCommands::List => {
-            println!("Available configuration keys:");
+            println!("Available configuration keys (default, environment variable):");
             println!();
-            println!("  cache.ram_size_mb      - RAM cache size (default: 512)");
-            println!("  cache.disk_size_gb     - Disk cache size (default: 10)");
-            println!("  host.default_port      - Default host port (default: 4433)");
-            println!("  host.max_connections   - Max connections (default: 10)");
-            println!("  signal.default_url     - Default signal server URL");
-            println!("  network.bandwidth_limit_mbps - Bandwidth limit");
-            println!("  network.timeout_secs   - Connection timeout");
-            println!("  ui.color               - Enable colors (default: true)");
-            println!("  ui.progress            - Show progress bars (default: true)");
+            let defaults = Config::default();
+            for key in Config::KEYS {
+                let default = defaults.get(key)?.unwrap_or_else(|| "unset".into());
+                println!("  {:<32} {:<14} {}", key, default, Config::env_var(key));
+            }
         }
         _ => {
             println!("Command not yet implemented");
@@ -1949,16 +2532,258 @@ async fn run_config(args: &ConfigArgs, _cli: &Cli) -> Result<(), Box<dyn std::er
     Ok(())
 }
 
-async fn run_peers(args: &PeersArgs, _cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
-    match &args.command {
-        PeersCommands::List(_) => {
-            println!("No known peers.");
+/// Config file that `config set/reset/import` edit
+fn config_path(cli: &Cli) -> Result<PathBuf, ConfigError> {
+    cli.config
+        .clone()
+        .or_else(Config::default_path)
+        .ok_or(ConfigError::NoConfigDir)
+}
+
+/// Point out when an environment variable will override a file setting
+fn warn_env_override(key: &str) {
+    let var = Config::env_var(key);
+    if std::env::var_os(&var).is_some() {
+        eprintln!("Note: {} is set and overrides the config file", var);
+    }
+}
+
+/// Print every setting with its effective value and the layer that set it
+fn print_settings(settings: &LayeredConfig, cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
+    let entries = settings.entries();
+    match cli.format {
+        OutputFormat::Json => {
+            let entries: serde_json::Map<String, serde_json::Value> = entries
+                .into_iter()
+                .map(|(key, value, source)| {
+                    let entry = serde_json::json!({ "value": value, "source": source });
+                    (key.to_string(), entry)
+                })
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&entries)?);
+        }
+        OutputFormat::Yaml => {
+            for (key, value, source) in entries {
+                println!("{}:", key);
+                println!("  value: {}", serde_json::to_string(&value)?);
+                println!("  source: {}", source);
+            }
+        }
+        OutputFormat::Text => {
+            match settings.path() {
+                Some(path) if path.exists() => println!("Config file: {}", path.display()),
+                Some(path) => println!("Config file: {} (not found)", path.display()),
+                None => println!("Config file: none"),
+            }
             println!();
-            println!("Peers are added automatically when you connect to shares.");
-            println!("Or add manually with: wormhole peers add <peer>");
+            println!("{:<32} {:<32} SOURCE", "KEY", "VALUE");
+            for (key, value, source) in entries {
+                let value = value.unwrap_or_else(|| "-".into());
+                println!("{:<32} {:<32} {}", key, value, source);
+            }
         }
-        _ => {
-            println!("Command not yet implemented");
+    }
+    Ok(())
+}
+
+async fn run_peers(args: &PeersArgs, cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
+    let mut peers = KnownPeers::load()?;
+
+    match &args.command {
+        PeersCommands::List(list_args) => {
+            if list_args.online {
+                warn!("Online status is not tracked yet; showing all matching peers");
+            }
+            let shown: Vec<&KnownPeer> = peers
+                .peers()
+                .iter()
+                .filter(|p| list_args.all || !p.blocked)
+                .collect();
+
+            if matches!(cli.format, OutputFormat::Json) {
+                println!("{}", serde_json::to_string_pretty(&shown)?);
+                return Ok(());
+            }
+
+            if shown.is_empty() {
+                println!("No known peers.");
+                println!();
+                println!("Peers are added automatically when you connect to shares.");
+                println!("Or add manually with: wormhole peers add <fingerprint> --name <name>");
+                return Ok(());
+            }
+
+            println!(
+                "{:<24} {:<18} {:<10} {:<20}",
+                "NAME", "FINGERPRINT", "TRUST", "LAST SEEN"
+            );
+            for peer in shown {
+                let trust = if peer.blocked {
+                    "blocked".to_string()
+                } else {
+                    peer.trust.to_string()
+                };
+                println!(
+                    "{:<24} {:<18} {:<10} {:<20}",
+                    peer.name,
+                    &peer.fingerprint[..16.min(peer.fingerprint.len())],
+                    trust,
+                    format_timestamp(peer.last_seen)
+                );
+            }
+        }
+
+        PeersCommands::Add(add_args) => {
+            let fingerprint = parse_fingerprint(&add_args.peer)
+                .ok_or("expected a 64-character hex certificate fingerprint")?;
+            let name = add_args
+                .name
+                .clone()
+                .unwrap_or_else(|| format!("peer-{}", &hex::encode(fingerprint)[..8]));
+            peers.add(&name, &fingerprint)?;
+            peers.save()?;
+            println!("Added peer '{}' ({})", name, hex::encode(fingerprint));
+        }
+
+        PeersCommands::Remove(remove_args) => {
+            let name = peers
+                .find(&remove_args.peer)
+                .map(|p| p.name.clone())
+                .ok_or_else(|| format!("Unknown peer: {}", remove_args.peer))?;
+            if !remove_args.force {
+                print!("Remove peer '{}'? [y/N] ", name);
+                io::stdout().flush()?;
+                let mut input = String::new();
+                io::stdin().read_line(&mut input)?;
+                if !input.trim().eq_ignore_ascii_case("y") {
+                    println!("Cancelled.");
+                    return Ok(());
+                }
+            }
+            peers.remove(&name)?;
+            peers.save()?;
+            println!("Removed peer '{}'", name);
+        }
+
+        PeersCommands::Show(show_args) => {
+            let peer = peers
+                .find(&show_args.peer)
+                .ok_or_else(|| format!("Unknown peer: {}", show_args.peer))?;
+
+            if matches!(cli.format, OutputFormat::Json) {
+                println!("{}", serde_json::to_string_pretty(peer)?);
+                return Ok(());
+            }
+
+            println!("Name:        {}", peer.name);
+            println!("Fingerprint: {}", peer.fingerprint);
+            println!("Trust:       {}", peer.trust);
+            println!("Blocked:     {}", if peer.blocked { "yes" } else { "no" });
+            println!("First seen:  {}", format_timestamp(peer.first_seen));
+            println!("Last seen:   {}", format_timestamp(peer.last_seen));
+            if !peer.addresses.is_empty() {
+                println!("Addresses:   {}", peer.addresses.join(", "));
+            }
+            if let Some(pending) = &peer.pending_fingerprint {
+                println!();
+                println!("⚠ Peer presented a different certificate: {}", pending);
+                println!("  Accept it with: wormhole peers trust {}", peer.name);
+            }
+        }
+
+        PeersCommands::Block(block_args) => {
+            peers.set_blocked(&block_args.peer, true)?;
+            peers.save()?;
+            println!("Blocked peer '{}'", block_args.peer);
+        }
+
+        PeersCommands::Unblock(unblock_args) => {
+            peers.set_blocked(&unblock_args.peer, false)?;
+            peers.save()?;
+            println!("Unblocked peer '{}'", unblock_args.peer);
+        }
+
+        PeersCommands::Trust(trust_args) => {
+            let level: TrustLevel = trust_args.level.parse()?;
+            let replaced = peers.trust(&trust_args.peer, level)?;
+            peers.save()?;
+            match replaced {
+                Some(old) => println!(
+                    "Accepted new certificate for '{}' (replaced {}), trust level {}",
+                    trust_args.peer, old, level
+                ),
+                None => println!("Set trust level of '{}' to {}", trust_args.peer, level),
+            }
+        }
+
+        PeersCommands::Rename(rename_args) => {
+            peers.rename(&rename_args.peer, &rename_args.name)?;
+            peers.save()?;
+            println!("Renamed '{}' to '{}'", rename_args.peer, rename_args.name);
+        }
+    }
+
+    Ok(())
+}
+
+fn run_identity(args: &IdentityArgs, cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
+    let dir = Identity::default_dir().ok_or(IdentityError::NoConfigDir)?;
+    let client = match &args.command {
+        IdentityCommands::Show(show_args) => show_args.client,
+        IdentityCommands::Rotate(rotate_args) => rotate_args.client,
+    };
+    let (kind, name) = if client {
+        ("client", CLIENT_IDENTITY)
+    } else {
+        ("host", HOST_IDENTITY)
+    };
+    let (cert_path, _) = identity_paths(&dir, name);
+
+    let identity = match &args.command {
+        IdentityCommands::Show(_) => Identity::load_or_create(&dir, name)?,
+        IdentityCommands::Rotate(rotate_args) => {
+            if !rotate_args.force {
+                if client {
+                    println!("Hosts that granted access to the current certificate will no");
+                    println!("longer recognize this machine (wormhole access grant).");
+                } else {
+                    println!("Clients that pinned the current certificate will refuse to connect");
+                    println!("until they trust the new one (wormhole peers trust <host>).");
+                }
+                print!("Rotate {} identity? [y/N] ", kind);
+                io::stdout().flush()?;
+                let mut input = String::new();
+                io::stdin().read_line(&mut input)?;
+                if !input.trim().eq_ignore_ascii_case("y") {
+                    println!("Cancelled.");
+                    return Ok(());
+                }
+            }
+            Identity::rotate(&dir, name)?
+        }
+    };
+
+    let fingerprint = hex::encode(identity.fingerprint());
+    match cli.format {
+        OutputFormat::Json => {
+            let info = serde_json::json!({
+                "identity": kind,
+                "fingerprint": fingerprint,
+                "certificate": cert_path,
+            });
+            println!("{}", serde_json::to_string_pretty(&info)?);
+        }
+        OutputFormat::Yaml => {
+            println!("identity: {}", kind);
+            println!("fingerprint: {}", fingerprint);
+            println!("certificate: {:?}", cert_path);
+        }
+        OutputFormat::Text => {
+            if matches!(args.command, IdentityCommands::Rotate(_)) {
+                println!("Generated new {} identity.", kind);
+            }
+            println!("Fingerprint: {}", fingerprint);
+            println!("Certificate: {}", cert_path.display());
         }
     }
 
@@ -1991,7 +2816,15 @@ async fn run_sync(args: &SyncArgs, _cli: &Cli) -> Result<(), Box<dyn std::error:
 }
 
 async fn run_signal(args: &SignalArgs, cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
-    let bind_addr: SocketAddr = format!("{}:{}", args.bind, args.port).parse()?;
+    let settings = load_settings(
+        cli,
+        &[
+            ("signal.port", args.port.map(|p| p.to_string())),
+            ("signal.bind", args.bind.clone()),
+        ],
+    )?;
+    let signal_settings = &settings.config().signal;
+    let bind_addr = SocketAddr::new(signal_settings.bind, signal_settings.port);
 
     if !cli.quiet {
         println!();
@@ -2014,7 +2847,9 @@ async fn run_signal(args: &SignalArgs, cli: &Cli) -> Result<(), Box<dyn std::err
         println!();
     }
 
-    let server = teleport_signal::SignalServer::new();
+    let server = teleport_signal::SignalServer::new()
+        .with_room_idle_timeout(signal_settings.room_idle_timeout_secs)
+        .with_max_peers_per_room(signal_settings.max_peers_per_room);
 
     tokio::select! {
         result = server.serve(bind_addr) => {
@@ -2311,7 +3146,11 @@ async fn run_unmount(args: &UnmountArgs, _cli: &Cli) -> Result<(), Box<dyn std::
     Ok(())
 }
 
-async fn run_list(args: &ListArgs, _cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
+async fn run_list(args: &ListArgs, cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
+    if args.lan {
+        return run_list_lan(args, cli).await;
+    }
+
     println!("╔═══════════════════════════════════════════════════════════════╗");
     println!("║                    ACTIVE CONNECTIONS                         ║");
     println!("╠═══════════════════════════════════════════════════════════════╣");
@@ -2349,26 +3188,288 @@ async fn run_list(args: &ListArgs, _cli: &Cli) -> Result<(), Box<dyn std::error:
     Ok(())
 }
 
-fn run_history(args: &HistoryArgs, _cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
-    println!("Transfer history (last {} entries):", args.limit);
+async fn run_list_lan(args: &ListArgs, cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
+    if !cli.quiet && matches!(cli.format, OutputFormat::Text) {
+        println!("Searching the local network for {}s...", args.lan_timeout);
+    }
+
+    let shares = discovery::browse(Duration::from_secs(args.lan_timeout)).await?;
+
+    match cli.format {
+        OutputFormat::Json => {
+            let items: Vec<_> = shares
+                .iter()
+                .map(|s| {
+                    serde_json::json!({
+                        "share": s.share_name,
+                        "host": s.host_name,
+                        "address": s.addr.to_string(),
+                        "cert_fingerprint": s.cert_fingerprint.map(hex::encode),
+                    })
+                })
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&items)?);
+            return Ok(());
+        }
+        OutputFormat::Yaml => {
+            for s in &shares {
+                println!("- share: {}", s.share_name);
+                println!("  host: {}", s.host_name);
+                println!("  address: {}", s.addr);
+            }
+            return Ok(());
+        }
+        OutputFormat::Text => {}
+    }
+
+    if shares.is_empty() {
+        println!("No shares found on the local network.");
+        println!("Hosts must be started with --announce-local to be listed here.");
+        return Ok(());
+    }
+
     println!();
-    println!("No transfer history available.");
+    println!("{:<24} {:<20} {:<22}", "SHARE", "HOST", "ADDRESS");
+    for s in &shares {
+        println!("{:<24} {:<20} {:<22}", s.share_name, s.host_name, s.addr);
+        if args.detailed {
+            if let Some(fp) = s.cert_fingerprint {
+                println!("    fingerprint: {}", hex::encode(fp));
+            }
+        }
+    }
     println!();
-    println!("History is recorded when you host or mount shares.");
+    println!("Connect with: wormhole mount <address>");
+
+    Ok(())
+}
+
+fn run_history(args: &HistoryArgs, cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
+    let query = AuditQuery {
+        share: args.share.clone(),
+        operation: args.operation.clone(),
+        since: args
+            .since
+            .as_deref()
+            .map(|d| parse_local_date(d, 0))
+            .transpose()?,
+        until: args
+            .until
+            .as_deref()
+            .map(|d| parse_local_date(d, 1))
+            .transpose()?,
+        limit: args.limit,
+    };
+    let dir = AuditLog::default_dir().ok_or(audit::AuditError::NoDataDir)?;
+    let records = audit::query(&dir, &query)?;
+
+    match cli.format {
+        OutputFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(&records)?);
+            return Ok(());
+        }
+        OutputFormat::Yaml => {
+            for r in &records {
+                println!("- time: {}", r.time.to_rfc3339());
+                println!("  share: {}", r.share);
+                println!("  client: {}", r.client);
+                println!("  op: {}", r.event.name());
+                if let Some(path) = r.event.path() {
+                    println!("  path: {}", path);
+                }
+            }
+            return Ok(());
+        }
+        OutputFormat::Text => {}
+    }
+
+    if records.is_empty() {
+        println!("No matching activity recorded.");
+        println!();
+        println!("Client activity is recorded while you host shares (unless --no-audit is given).");
+        return Ok(());
+    }
+
+    println!(
+        "{:<19}  {:<16} {:<12} {:<13} DETAILS",
+        "TIME", "SHARE", "CLIENT", "OPERATION"
+    );
+    for r in &records {
+        println!(
+            "{:<19}  {:<16} {:<12} {:<13} {}",
+            r.time
+                .with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M:%S"),
+            r.share,
+            &r.client[..r.client.len().min(12)],
+            r.event.name(),
+            describe_audit_record(r)
+        );
+    }
 
     Ok(())
 }
 
-async fn run_access(args: &AccessArgs, _cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
+/// Start of a local `YYYY-MM-DD` date, `days_after` days later, in UTC
+fn parse_local_date(
+    date: &str,
+    days_after: u64,
+) -> Result<chrono::DateTime<chrono::Utc>, Box<dyn std::error::Error>> {
+    let day = chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|_| format!("invalid date '{}' (expected YYYY-MM-DD)", date))?
+        + chrono::Days::new(days_after);
+    day.and_hms_opt(0, 0, 0)
+        .and_then(|t| t.and_local_timezone(chrono::Local).earliest())
+        .map(|t| t.with_timezone(&chrono::Utc))
+        .ok_or_else(|| format!("invalid date '{}'", date).into())
+}
+
+fn describe_audit_record(record: &AuditRecord) -> String {
+    match &record.event {
+        AuditEvent::SessionOpen { access } => {
+            format!("from {} ({} access)", record.remote, access)
+        }
+        AuditEvent::SessionClose {
+            duration_secs,
+            bytes_read,
+            bytes_written,
+        } => format!(
+            "after {}, {} read, {} written",
+            format_duration(Duration::from_secs(*duration_secs)),
+            format_bytes(*bytes_read),
+            format_bytes(*bytes_written)
+        ),
+        AuditEvent::Rename { path, to } => format!("{} -> {}", path, to),
+        AuditEvent::Write {
+            path,
+            offset,
+            bytes,
+        } => format!("{} ({} at offset {})", path, format_bytes(*bytes), offset),
+        AuditEvent::SetAttr { path, size, mode } => {
+            let mut changes = Vec::new();
+            if let Some(size) = size {
+                changes.push(format!("size {}", format_bytes(*size)));
+            }
+            if let Some(mode) = mode {
+                changes.push(format!("mode {:o}", mode));
+            }
+            if changes.is_empty() {
+                path.clone()
+            } else {
+                format!("{} ({})", path, changes.join(", "))
+            }
+        }
+        AuditEvent::Read {
+            path,
+            bytes,
+            chunks,
+        } => format!("{} ({} in {} chunk(s))", path, format_bytes(*bytes), chunks),
+        event => event.path().unwrap_or_default().to_string(),
+    }
+}
+
+/// Access lists are read when a host starts, so edits apply from its next start
+const ACCESS_RESTART_NOTE: &str = "Restart hosts serving this share to apply the change.";
+
+async fn run_access(args: &AccessArgs, cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
     match &args.command {
-        AccessCommands::Show(_) => {
-            println!("No access rules configured.");
+        AccessCommands::Show(show_args) => {
+            let Some(share) = &show_args.share else {
+                println!("Usage: wormhole access show <share>");
+                println!();
+                println!("Access rules control who can connect to your shares.");
+                println!("Use 'wormhole access grant <share> <peer>' to add rules.");
+                return Ok(());
+            };
+            let list = AccessList::load(share)?;
+
+            if matches!(cli.format, OutputFormat::Json) {
+                let rules: Vec<_> = list
+                    .rules()
+                    .iter()
+                    .map(|r| {
+                        serde_json::json!({
+                            "principal": r.principal.to_string(),
+                            "name": r.name,
+                            "level": r.level.to_string(),
+                        })
+                    })
+                    .collect();
+                let info = serde_json::json!({
+                    "share": share,
+                    "default": list.default_level().map(|l| l.to_string()),
+                    "rules": rules,
+                });
+                println!("{}", serde_json::to_string_pretty(&info)?);
+                return Ok(());
+            }
+
+            if list.is_empty() {
+                println!("No access rules configured for '{}'.", share);
+                println!();
+                println!("Use 'wormhole access grant {} <peer>' to add rules.", share);
+                return Ok(());
+            }
+
+            println!(
+                "Default: {}",
+                list.default_level()
+                    .map(|l| l.to_string())
+                    .unwrap_or_else(|| "write (not set)".into())
+            );
             println!();
-            println!("Access rules control who can connect to your shares.");
-            println!("Use 'wormhole access grant <share> <peer>' to add rules.");
+
+            println!("{:<8} {:<20} PRINCIPAL", "LEVEL", "NAME");
+            for rule in list.rules() {
+                println!(
+                    "{:<8} {:<20} {}",
+                    rule.level.to_string(),
+                    rule.name.as_deref().unwrap_or("-"),
+                    rule.principal
+                );
+            }
         }
-        _ => {
-            println!("Command not yet implemented");
+        AccessCommands::Grant(grant_args) => {
+            let peers = KnownPeers::load()?;
+            let (principal, name) = resolve_principal(&grant_args.peer, &peers)?;
+            let mut list = AccessList::load(&grant_args.share)?;
+            let level = grant_args.level.into();
+            list.grant(principal.clone(), name, level);
+            list.save()?;
+            println!(
+                "Granted {} access on '{}' to {}",
+                level, grant_args.share, principal
+            );
+            println!("{}", ACCESS_RESTART_NOTE);
+        }
+        AccessCommands::Revoke(revoke_args) => {
+            let peers = KnownPeers::load()?;
+            let (principal, _) = resolve_principal(&revoke_args.peer, &peers)?;
+            let mut list = AccessList::load(&revoke_args.share)?;
+            if !list.revoke(&principal) {
+                return Err(format!(
+                    "No access rule for {} on '{}'",
+                    principal, revoke_args.share
+                )
+                .into());
+            }
+            list.save()?;
+            println!(
+                "Revoked access on '{}' from {}",
+                revoke_args.share, principal
+            );
+            println!("{}", ACCESS_RESTART_NOTE);
+        }
+        AccessCommands::Set(set_args) => {
+            let mut list = AccessList::load(&set_args.share)?;
+            let level = set_args.default_level.into();
+            list.set_default_level(level);
+            list.save()?;
+            println!(
+                "Clients without a matching rule now get {} access on '{}'",
+                level, set_args.share
+            );
+            println!("{}", ACCESS_RESTART_NOTE);
         }
     }
 
@@ -2422,10 +3523,14 @@ async fn run_update_check(
         println!();
     }
 
+    let settings = LayeredConfig::load(cli.config.as_deref())?;
+    let hours = settings.config().update.check_interval_hours;
+    let interval = Duration::from_secs(hours.saturating_mul(3600));
+    let checker = UpdateChecker::default_repo(channel).with_interval(interval);
     let checker = if args.force {
-        UpdateChecker::default_repo(channel).skip_cache()
+        checker.skip_cache()
     } else {
-        UpdateChecker::default_repo(channel)
+        checker
     };
 
     match checker.check_for_update().await {
@@ -2485,22 +3590,44 @@ async fn run_update_changelog(
     Ok(())
 }
 
-fn run_update_config(
-    args: &UpdateConfigArgs,
-    _cli: &Cli,
-) -> Result<(), Box<dyn std::error::Error>> {
+fn run_update_config(args: &UpdateConfigArgs, cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
     if args.auto_check.is_none() && args.channel.is_none() && args.check_interval_hours.is_none() {
         // Show current config
+        let settings = LayeredConfig::load(cli.config.as_deref())?;
+        let update = &settings.config().update;
+        let source = |key| match settings.source(key) {
+            ConfigSource::Default => " (default)".to_string(),
+            source => format!(" ({})", source),
+        };
         println!("Update configuration:");
         println!();
-        println!("  auto_check: true (default)");
-        println!("  channel: stable");
-        println!("  check_interval: 24 hours");
+        println!(
+            "  auto_check: {}{}",
+            update.auto_check,
+            source("update.auto_check")
+        );
+        println!("  channel: {}{}", update.channel, source("update.channel"));
+        println!(
+            "  check_interval: {} hours{}",
+            update.check_interval_hours,
+            source("update.check_interval_hours")
+        );
         println!();
         println!("Use --auto-check, --channel, or --check-interval-hours to modify.");
     } else {
-        println!("Update configuration saved.");
-        // TODO: Actually persist the configuration
+        let path = config_path(cli)?;
+        let mut file = ConfigFile::open(&path)?;
+        if let Some(auto_check) = args.auto_check {
+            file.set("update.auto_check", &auto_check.to_string())?;
+        }
+        if let Some(channel) = args.channel {
+            file.set("update.channel", &UpdateChannel::from(channel).to_string())?;
+        }
+        if let Some(hours) = args.check_interval_hours {
+            file.set("update.check_interval_hours", &hours.to_string())?;
+        }
+        file.save_to(&path)?;
+        println!("Update configuration saved to {}", path.display());
     }
 
     Ok(())
@@ -2694,6 +3821,35 @@ async fn run_doctor(args: &DoctorArgs, _cli: &Cli) -> Result<(), Box<dyn std::er
 // Utility Functions
 // ============================================================================
 
+fn format_timestamp(unix_secs: u64) -> String {
+    chrono::DateTime::from_timestamp(unix_secs as i64, 0)
+        .map(|t| {
+            t.with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        })
+        .unwrap_or_else(|| "unknown".into())
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let parts: Vec<String> = [
+        (secs / 86400, "d"),
+        (secs / 3600 % 24, "h"),
+        (secs / 60 % 60, "m"),
+        (secs % 60, "s"),
+    ]
+    .iter()
+    .filter(|(n, _)| *n > 0)
+    .map(|(n, unit)| format!("{}{}", n, unit))
+    .collect();
+    if parts.is_empty() {
+        "0s".into()
+    } else {
+        parts.join(" ")
+    }
+}
+
 fn format_bytes(bytes: u64) -> String {
     const KB: u64 = 1024;
     const MB: u64 = KB * 1024;
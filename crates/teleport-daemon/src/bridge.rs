@@ -37,10 +37,14 @@ use std::time::Duration;
 use tokio::sync::oneshot;
 use tracing::{debug, error, warn};
 
-use teleport_core::{DirEntry, FileAttr, Inode, ProtocolError};
+use teleport_core::{DirEntry, FallocateMode, FileAttr, Inode, ProtocolError, SeekWhence};
 
 use crate::MAX_INFLIGHT_REQUESTS;
 
+/// A directory's entries with their attributes, `None` where the host
+/// couldn't stat an entry
+pub type DirListing = Vec<(DirEntry, Option<FileAttr>)>;
+
 /// Request from FUSE to async runtime
 #[derive(Debug)]
 pub enum FuseRequest {
@@ -64,6 +68,12 @@ pub enum FuseRequest {
         reply: oneshot::Sender<Result<Vec<DirEntry>, FuseError>>,
     },
 
+    /// Read a whole directory with the attributes of its entries
+    ReadDirPlus {
+        inode: Inode,
+        reply: oneshot::Sender<Result<DirListing, FuseError>>,
+    },
+
     /// Read file data
     Read {
         inode: Inode,
@@ -99,6 +109,25 @@ pub enum FuseRequest {
         reply: oneshot::Sender<Result<(), FuseError>>,
     },
 
+    /// Start rewriting a file in a write session, which leaves the file as it
+    /// was until committed
+    BeginWrite {
+        inode: Inode,
+        reply: oneshot::Sender<Result<(), FuseError>>,
+    },
+
+    /// Replace the file with the session's writes; `None` if none was open
+    CommitWrite {
+        inode: Inode,
+        reply: oneshot::Sender<Result<Option<FileAttr>, FuseError>>,
+    },
+
+    /// Discard the session's writes
+    AbortWrite {
+        inode: Inode,
+        reply: oneshot::Sender<Result<(), FuseError>>,
+    },
+
     /// Create a new file (Phase 7)
     CreateFile {
         parent: Inode,
@@ -148,6 +177,33 @@ pub enum FuseRequest {
         reply: oneshot::Sender<Result<FileAttr, FuseError>>,
     },
 
+    /// Copy a byte range between two files on the host
+    CopyRange {
+        src: Inode,
+        src_offset: u64,
+        dst: Inode,
+        dst_offset: u64,
+        len: u64,
+        reply: oneshot::Sender<Result<u64, FuseError>>,
+    },
+
+    /// Find data or a hole in a file, `None` if there is none past the offset
+    Seek {
+        inode: Inode,
+        offset: u64,
+        whence: SeekWhence,
+        reply: oneshot::Sender<Result<Option<u64>, FuseError>>,
+    },
+
+    /// Allocate or deallocate part of a file
+    Fallocate {
+        inode: Inode,
+        offset: u64,
+        len: u64,
+        mode: FallocateMode,
+        reply: oneshot::Sender<Result<FileAttr, FuseError>>,
+    },
+
     /// Shutdown the bridge
     Shutdown,
 }
@@ -173,6 +229,8 @@ pub enum FuseError {
     LockRequired,
     /// Read-only filesystem
     ReadOnly,
+    /// Operation not supported by the host
+    Unsupported,
 }
 
 impl FuseError {
@@ -189,6 +247,7 @@ impl FuseError {
             FuseError::LockConflict(_) => libc::EAGAIN,
             FuseError::LockRequired => libc::ENOLCK,
             FuseError::ReadOnly => libc::EROFS,
+            FuseError::Unsupported => libc::EOPNOTSUPP,
         }
     }
 
@@ -207,6 +266,7 @@ impl FuseError {
         const STATUS_FILE_LOCK_CONFLICT: i32 = 0xC0000054_u32 as i32;
         const STATUS_LOCK_NOT_GRANTED: i32 = 0xC0000055_u32 as i32;
         const STATUS_MEDIA_WRITE_PROTECTED: i32 = 0xC00000A2_u32 as i32;
+        const STATUS_NOT_SUPPORTED: i32 = 0xC00000BB_u32 as i32;
 
         match self {
             FuseError::NotFound => STATUS_OBJECT_NAME_NOT_FOUND,
@@ -218,6 +278,7 @@ impl FuseError {
             FuseError::LockConflict(_) => STATUS_FILE_LOCK_CONFLICT,
             FuseError::LockRequired => STATUS_LOCK_NOT_GRANTED,
             FuseError::ReadOnly => STATUS_MEDIA_WRITE_PROTECTED,
+            FuseError::Unsupported => STATUS_NOT_SUPPORTED,
         }
     }
 }
@@ -293,6 +354,18 @@ impl FuseAsyncBridge {
         self.recv_response(reply_rx, &format!("readdir {} @ {}", inode, offset))
     }
 
+    /// Read a whole directory with the attributes of its entries (blocking)
+    pub fn readdirplus(&self, inode: Inode) -> Result<DirListing, FuseError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+
+        self.send_request(FuseRequest::ReadDirPlus {
+            inode,
+            reply: reply_tx,
+        })?;
+
+        self.recv_response(reply_rx, &format!("readdirplus {}", inode))
+    }
+
     /// Read file data (blocking)
     pub fn read(&self, inode: Inode, offset: u64, size: u32) -> Result<Vec<u8>, FuseError> {
         let (reply_tx, reply_rx) = oneshot::channel();
@@ -368,6 +441,42 @@ impl FuseAsyncBridge {
         self.recv_response(reply_rx, &format!("flush {}", inode))
     }
 
+    /// Start a write session for a file (blocking)
+    pub fn begin_write(&self, inode: Inode) -> Result<(), FuseError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+
+        self.send_request(FuseRequest::BeginWrite {
+            inode,
+            reply: reply_tx,
+        })?;
+
+        self.recv_response(reply_rx, &format!("begin_write {}", inode))
+    }
+
+    /// Commit a file's write session (blocking)
+    pub fn commit_write(&self, inode: Inode) -> Result<Option<FileAttr>, FuseError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+
+        self.send_request(FuseRequest::CommitWrite {
+            inode,
+            reply: reply_tx,
+        })?;
+
+        self.recv_response(reply_rx, &format!("commit_write {}", inode))
+    }
+
+    /// Abort a file's write session (blocking)
+    pub fn abort_write(&self, inode: Inode) -> Result<(), FuseError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+
+        self.send_request(FuseRequest::AbortWrite {
+            inode,
+            reply: reply_tx,
+        })?;
+
+        self.recv_response(reply_rx, &format!("abort_write {}", inode))
+    }
+
     /// Create a new file (blocking) - Phase 7
     pub fn create_file(
         &self,
@@ -482,6 +591,80 @@ impl FuseAsyncBridge {
         self.recv_response(reply_rx, &format!("setattr {}", inode))
     }
 
+    /// Copy bytes from one file to another on the host (blocking)
+    ///
+    /// Returns how many bytes were copied, which may be fewer than `len`.
+    pub fn copy_range(
+        &self,
+        src: Inode,
+        src_offset: u64,
+        dst: Inode,
+        dst_offset: u64,
+        len: u64,
+    ) -> Result<u64, FuseError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+
+        self.send_request(FuseRequest::CopyRange {
+            src,
+            src_offset,
+            dst,
+            dst_offset,
+            len,
+            reply: reply_tx,
+        })?;
+
+        self.recv_response(
+            reply_rx,
+            &format!(
+                "copy_range {}@{} -> {}@{}",
+                src, src_offset, dst, dst_offset
+            ),
+        )
+    }
+
+    /// Find the next data or hole at or after `offset` (blocking)
+    pub fn seek(
+        &self,
+        inode: Inode,
+        offset: u64,
+        whence: SeekWhence,
+    ) -> Result<Option<u64>, FuseError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+
+        self.send_request(FuseRequest::Seek {
+            inode,
+            offset,
+            whence,
+            reply: reply_tx,
+        })?;
+
+        self.recv_response(reply_rx, &format!("seek {}@{} {:?}", inode, offset, whence))
+    }
+
+    /// Allocate or deallocate part of a file (blocking)
+    pub fn fallocate(
+        &self,
+        inode: Inode,
+        offset: u64,
+        len: u64,
+        mode: FallocateMode,
+    ) -> Result<FileAttr, FuseError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+
+        self.send_request(FuseRequest::Fallocate {
+            inode,
+            offset,
+            len,
+            mode,
+            reply: reply_tx,
+        })?;
+
+        self.recv_response(
+            reply_rx,
+            &format!("fallocate {}@{}+{} {:?}", inode, offset, len, mode),
+        )
+    }
+
     /// Request shutdown
     pub fn shutdown(&self) {
         let _ = self.request_tx.try_send(FuseRequest::Shutdown);
@@ -629,6 +812,7 @@ mod tests {
         assert_eq!(FuseError::NotFound.to_errno(), libc::ENOENT);
         assert_eq!(FuseError::PermissionDenied.to_errno(), libc::EACCES);
         assert_eq!(FuseError::Timeout.to_errno(), libc::ETIMEDOUT);
+        assert_eq!(FuseError::Unsupported.to_errno(), libc::EOPNOTSUPP);
     }
 
     #[cfg(windows)]
@@ -214,6 +214,7 @@ pub struct BulkTransferCoordinator {
     /// Dedup index for content-addressed chunks
     dedup_index: Arc<DedupIndex>,
     /// Buffer pool for zero-copy I/O
+    #[allow(dead_code)]
     buffer_pool: Arc<BufferPool>,
     /// Compressor for smart compression
     compressor: SmartCompressor,
@@ -505,7 +506,7 @@ mod tests {
 
         let manifest = coordinator.build_manifest(temp.path(), 123).unwrap();
 
-        assert!(manifest.chunks.len() >= 1);
+        assert!(!manifest.chunks.is_empty());
         assert_eq!(manifest.total_size, data.len() as u64);
         assert_eq!(manifest.inode, 123);
 
@@ -15,7 +15,7 @@ use lru::LruCache;
 use parking_lot::RwLock;
 use tracing::{debug, trace, warn};
 
-use teleport_core::{ChunkId, DirEntry, FileAttr, Inode, CHUNK_SIZE};
+use teleport_core::{ChunkId, Config, DirEntry, FileAttr, Inode, CHUNK_SIZE};
 
 use crate::disk_cache::DiskCache;
 
@@ -51,7 +51,7 @@ impl<T> CacheEntry<T> {
 /// Attribute cache (inode → FileAttr)
 pub struct AttrCache {
     entries: RwLock<HashMap<Inode, CacheEntry<FileAttr>>>,
-    ttl: Duration,
+    ttl: RwLock<Duration>,
     max_entries: usize,
 }
 
@@ -59,11 +59,16 @@ impl AttrCache {
     pub fn new(ttl: Duration, max_entries: usize) -> Self {
         Self {
             entries: RwLock::new(HashMap::new()),
-            ttl,
+            ttl: RwLock::new(ttl),
             max_entries,
         }
     }
 
+    /// Change how long new entries live; cached ones keep their expiry
+    pub fn set_ttl(&self, ttl: Duration) {
+        *self.ttl.write() = ttl;
+    }
+
     pub fn get(&self, inode: Inode) -> Option<FileAttr> {
         let entries = self.entries.read();
         entries.get(&inode).and_then(|entry| {
@@ -90,7 +95,7 @@ impl AttrCache {
             }
         }
 
-        entries.insert(inode, CacheEntry::new(attr, self.ttl));
+        entries.insert(inode, CacheEntry::new(attr, *self.ttl.read()));
     }
 
     pub fn invalidate(&self, inode: Inode) {
@@ -105,7 +110,7 @@ impl AttrCache {
 /// Directory entry cache (parent inode → entries)
 pub struct DirCache {
     entries: RwLock<HashMap<Inode, CacheEntry<Vec<DirEntry>>>>,
-    ttl: Duration,
+    ttl: RwLock<Duration>,
     max_entries: usize,
 }
 
@@ -113,11 +118,16 @@ impl DirCache {
     pub fn new(ttl: Duration, max_entries: usize) -> Self {
         Self {
             entries: RwLock::new(HashMap::new()),
-            ttl,
+            ttl: RwLock::new(ttl),
             max_entries,
         }
     }
 
+    /// Change how long new entries live; cached ones keep their expiry
+    pub fn set_ttl(&self, ttl: Duration) {
+        *self.ttl.write() = ttl;
+    }
+
     pub fn get(&self, parent: Inode) -> Option<Vec<DirEntry>> {
         let entries = self.entries.read();
         entries.get(&parent).and_then(|entry| {
@@ -142,7 +152,7 @@ impl DirCache {
             }
         }
 
-        entries.insert(parent, CacheEntry::new(dir_entries, self.ttl));
+        entries.insert(parent, CacheEntry::new(dir_entries, *self.ttl.read()));
     }
 
     pub fn invalidate(&self, parent: Inode) {
@@ -619,6 +629,34 @@ impl HybridCacheManager {
         }
     }
 
+    /// Create with the TTLs, RAM budget and cache directory from `config`
+    ///
+    /// Chunks go under `chunks/` in `cache.cache_dir` when it is set, and in
+    /// the platform cache directory otherwise.
+    pub fn from_config(config: &Config) -> Self {
+        let ram_entries = (config.cache.max_ram_bytes / CHUNK_SIZE as u64).max(1) as usize;
+        let chunks = match &config.cache.cache_dir {
+            Some(dir) => match DiskCache::with_dir(dir.join("chunks")) {
+                Ok(disk_cache) => {
+                    HybridChunkCache::with_disk_cache(ram_entries, Arc::new(disk_cache))
+                }
+                Err(e) => {
+                    warn!(
+                        "Failed to open disk cache in {:?}: {} - running RAM-only",
+                        dir, e
+                    );
+                    HybridChunkCache::ram_only(ram_entries)
+                }
+            },
+            None => HybridChunkCache::new(ram_entries),
+        };
+        Self {
+            attrs: AttrCache::new(Duration::from_secs(config.client.attr_ttl_secs), 10_000),
+            dirs: DirCache::new(Duration::from_secs(config.client.dir_ttl_secs), 1_000),
+            chunks,
+        }
+    }
+
     /// Get disk cache for GC
     pub fn disk_cache(&self) -> Option<Arc<DiskCache>> {
         self.chunks.disk_cache()
@@ -1,31 +1,52 @@
 //! Wormhole client - connects to remote host and serves FUSE requests
 
+use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::Duration;
 
-use tracing::info;
+use tracing::{debug, info, warn};
 
 use teleport_core::{
-    ChunkId, CreateDirRequest, CreateDirResponse, CreateFileRequest, CreateFileResponse,
-    DeleteDirRequest, DeleteDirResponse, DeleteFileRequest, DeleteFileResponse, DirEntry, FileAttr,
-    GetAttrRequest, GetAttrResponse, HelloMessage, Inode, ListDirRequest, ListDirResponse,
-    LockRequest, LockResponse, LockType, LookupRequest, LookupResponse, NetMessage,
-    ReadChunkRequest, ReadChunkResponse, ReleaseRequest, ReleaseResponse, RenameRequest,
-    RenameResponse, SetAttrRequest, SetAttrResponse, WriteChunkRequest, WriteChunkResponse,
-    PROTOCOL_VERSION, ROOT_INODE,
+    crypto::password_proof, AbortWriteRequest, AbortWriteResponse, AuthResponseMessage,
+    BeginWriteRequest, BeginWriteResponse, ChunkId, CommitWriteRequest, CommitWriteResponse,
+    CopyRangeRequest, CopyRangeResponse, CreateDirRequest, CreateDirResponse, CreateFileRequest,
+    CreateFileResponse, DeleteDirRequest, DeleteDirResponse, DeleteFileRequest, DeleteFileResponse,
+    DirEntry, DisconnectReason, ErrorCode, ErrorMessage, FallocateMode, FallocateRequest,
+    FallocateResponse, FileAttr, GetAttrRequest, GetAttrResponse, HelloMessage, Inode,
+    ListDirRequest, ListDirResponse, LockRequest, LockResponse, LockToken, LockType, LookupRequest,
+    LookupResponse, NetMessage, ReadChunkRequest, ReleaseRequest, ReleaseResponse, RenameRequest,
+    RenameResponse, SeekRequest, SeekResponse, SeekWhence, SetAttrRequest, SetAttrResponse,
+    TreeSnapshotRequest, WriteChunkRequest, WriteChunkResponse, MAX_COPY_RANGE, MAX_LIST_DIR_ATTRS,
+    PRIORITY_INTERACTIVE, PROTOCOL_VERSION, ROOT_INODE,
 };
 
-use crate::bridge::{BridgeHandler, FuseError, FuseRequest};
-#[allow(deprecated)] // create_client_endpoint is deprecated but used for dev/LAN mode
-use crate::net::{connect, create_client_endpoint, recv_message, send_message, QuicConnection};
+use crate::bridge::{BridgeHandler, DirListing, FuseError, FuseRequest};
+use crate::identity::Identity;
+use crate::known_peers::{check_peer, verify_peer, PeerCheck};
+use crate::net::{
+    connect, create_client_endpoint_tofu, create_client_endpoint_with_identity,
+    create_client_endpoint_with_pinned_cert, recv_message, send_message, CertFingerprint,
+    QuicConnection,
+};
 use crate::sync_engine::SyncEngine;
+use crate::tree_snapshot::TreeSnapshot;
 
 /// Wormhole client configuration
 pub struct ClientConfig {
     pub server_addr: SocketAddr,
     pub mount_point: PathBuf,
     pub request_timeout: Duration,
+    /// Certificate fingerprint obtained out of band (share link, LAN announcement).
+    /// When unset, the host is trusted on first use.
+    pub expected_fingerprint: Option<CertFingerprint>,
+    /// Known-peers store to verify against (None = default location)
+    pub known_peers_path: Option<PathBuf>,
+    /// Certificate presented to the host; without one the client is anonymous
+    pub client_identity: Option<Arc<Identity>>,
+    /// Share password, for hosts that require one
+    pub password: Option<String>,
 }
 
 impl Default for ClientConfig {
@@ -34,6 +55,10 @@ impl Default for ClientConfig {
             server_addr: "127.0.0.1:4433".parse().unwrap(),
             mount_point: PathBuf::from("/tmp/wormhole"),
             request_timeout: Duration::from_secs(30),
+            expected_fingerprint: None,
+            known_peers_path: None,
+            client_identity: None,
+            password: None,
         }
     }
 }
@@ -44,6 +69,15 @@ pub struct WormholeClient {
     connection: Option<QuicConnection>,
     session_id: Option<[u8; 16]>,
     root_inode: Inode,
+    /// Whether the host granted write access
+    writable: bool,
+    /// Whether the host can stage rewrites of a file until they are committed
+    atomic_writes: bool,
+    /// Files being rewritten in a write session, and whether the session took
+    /// the file's lock itself
+    write_sessions: parking_lot::Mutex<HashMap<Inode, bool>>,
+    /// Why the host ended the session, once it has
+    goodbye: Arc<parking_lot::Mutex<Option<DisconnectReason>>>,
     /// Sync engine for tracking dirty chunks and locks (Phase 7)
     sync_engine: std::sync::Arc<SyncEngine>,
 }
@@ -55,10 +89,31 @@ impl WormholeClient {
             connection: None,
             session_id: None,
             root_inode: ROOT_INODE,
+            writable: false,
+            atomic_writes: false,
+            write_sessions: parking_lot::Mutex::new(HashMap::new()),
+            goodbye: Arc::new(parking_lot::Mutex::new(None)),
             sync_engine: std::sync::Arc::new(SyncEngine::default()),
         }
     }
 
+    /// Whether the host allows this client to modify the share (known once connected)
+    pub fn is_writable(&self) -> bool {
+        self.writable
+    }
+
+    /// Reason the host gave for ending the session, if it has
+    pub fn disconnect_reason(&self) -> Option<DisconnectReason> {
+        *self.goodbye.lock()
+    }
+
+    /// Close the connection to the host
+    pub fn disconnect(&mut self) {
+        if let Some(conn) = self.connection.take() {
+            conn.close(0, "disconnect");
+        }
+    }
+
     /// Get the sync engine (for sharing with FUSE)
     pub fn sync_engine(&self) -> std::sync::Arc<SyncEngine> {
         self.sync_engine.clone()
@@ -72,6 +127,15 @@ impl WormholeClient {
     /// Start background sync task that periodically uploads dirty chunks
     /// Call this after connect() and before handle_fuse_requests()
     pub fn start_background_sync(&self, sync_engine: std::sync::Arc<SyncEngine>) {
+        self.start_background_sync_every(sync_engine, Duration::from_secs(1));
+    }
+
+    /// Like `start_background_sync`, uploading every `interval`
+    pub fn start_background_sync_every(
+        &self,
+        sync_engine: std::sync::Arc<SyncEngine>,
+        interval: Duration,
+    ) {
         use crate::sync_engine::SyncRunner;
         use tracing::{debug, warn};
 
@@ -83,7 +147,7 @@ impl WormholeClient {
             }
         };
 
-        let runner = SyncRunner::new(sync_engine, std::time::Duration::from_secs(1));
+        let runner = SyncRunner::new(sync_engine, interval);
 
         tokio::spawn(async move {
             runner
@@ -138,10 +202,22 @@ impl WormholeClient {
     }
 
     /// Connect to the server and perform handshake
-    #[allow(deprecated)] // Using insecure endpoint for LAN/dev connections
+    ///
+    /// The host's certificate is pinned when `expected_fingerprint` is set and
+    /// is always checked against the known-peers store, before answering a
+    /// password challenge and again once the host has identified itself.
     pub async fn connect(&mut self) -> Result<(), ClientError> {
-        let endpoint =
-            create_client_endpoint().map_err(|e| ClientError::Connection(format!("{:?}", e)))?;
+        let endpoint = match (
+            &self.config.client_identity,
+            self.config.expected_fingerprint,
+        ) {
+            (Some(identity), expected) => {
+                create_client_endpoint_with_identity(0, expected, identity)
+            }
+            (None, Some(fingerprint)) => create_client_endpoint_with_pinned_cert(0, fingerprint),
+            (None, None) => create_client_endpoint_tofu(0),
+        }
+        .map_err(|e| ClientError::Connection(format!("{:?}", e)))?;
 
         let conn = connect(&endpoint, self.config.server_addr, "localhost")
             .await
@@ -171,13 +247,42 @@ impl WormholeClient {
             .map_err(|e| ClientError::Connection(format!("{:?}", e)))?;
 
         // Receive HelloAck with timeout
-        let response = tokio::time::timeout(
-            self.config.request_timeout,
-            recv_message(&mut recv),
-        )
-        .await
-        .map_err(|_| ClientError::Connection("timeout waiting for HelloAck".into()))?
-        .map_err(|e| ClientError::Connection(format!("{:?}", e)))?;
+        let mut response =
+            tokio::time::timeout(self.config.request_timeout, recv_message(&mut recv))
+                .await
+                .map_err(|_| ClientError::Connection("timeout waiting for HelloAck".into()))?
+                .map_err(|e| ClientError::Connection(format!("{:?}", e)))?;
+
+        // Password-protected shares challenge us before acknowledging
+        if let NetMessage::AuthChallenge(challenge) = &response {
+            let Some(password) = &self.config.password else {
+                conn.close(0, "password required");
+                return Err(ClientError::PasswordRequired);
+            };
+            // Only answer a host we would trust, on this TLS session only
+            if let Err(e) = self.check_known_peer(&conn) {
+                conn.close(1, "untrusted certificate");
+                return Err(e);
+            }
+            let channel = conn.channel_binding().ok_or_else(|| {
+                ClientError::Connection("TLS session exports no keying material".into())
+            })?;
+            let answer = NetMessage::AuthResponse(AuthResponseMessage {
+                proof: password_proof(password, &challenge.nonce, &channel),
+            });
+            tokio::time::timeout(
+                self.config.request_timeout,
+                send_message(&mut send, &answer),
+            )
+            .await
+            .map_err(|_| ClientError::Connection("timeout sending AuthResponse".into()))?
+            .map_err(|e| ClientError::Connection(format!("{:?}", e)))?;
+
+            response = tokio::time::timeout(self.config.request_timeout, recv_message(&mut recv))
+                .await
+                .map_err(|_| ClientError::Connection("timeout waiting for HelloAck".into()))?
+                .map_err(|e| ClientError::Connection(format!("{:?}", e)))?;
+        }
 
         match response {
             NetMessage::HelloAck(ack) => {
@@ -187,10 +292,25 @@ impl WormholeClient {
                         actual: ack.protocol_version,
                     });
                 }
+                if let Err(e) = self.verify_known_peer(&conn, &ack.host_name) {
+                    conn.close(1, "untrusted certificate");
+                    return Err(e);
+                }
                 self.session_id = Some(ack.session_id);
                 self.root_inode = ack.root_inode;
+                self.writable = ack.capabilities.iter().any(|c| c == "write");
+                self.atomic_writes = ack.capabilities.iter().any(|c| c == "atomic-write");
                 info!("Connected to host: {}", ack.host_name);
             }
+            NetMessage::Error(e) if e.code == ErrorCode::AuthFailed => {
+                return Err(ClientError::AuthFailed(e.message));
+            }
+            NetMessage::Goodbye(goodbye) => {
+                return Err(match goodbye.reason {
+                    DisconnectReason::ShareExpired => ClientError::ShareExpired,
+                    reason => ClientError::Connection(format!("host refused: {:?}", reason)),
+                });
+            }
             NetMessage::Error(e) => {
                 return Err(ClientError::ServerError(e.message));
             }
@@ -199,10 +319,119 @@ impl WormholeClient {
             }
         }
 
+        // The host announces the end of a session (e.g. share expiry) on a stream of its own
+        let watcher = conn.clone();
+        let reason = self.goodbye.clone();
+        tokio::spawn(async move {
+            while let Ok(mut recv) = watcher.accept_uni_stream().await {
+                if let Ok(NetMessage::Goodbye(goodbye)) = recv_message(&mut recv).await {
+                    warn!("Host ended the session: {:?}", goodbye.reason);
+                    *reason.lock() = Some(goodbye.reason);
+                }
+            }
+        });
+
         self.connection = Some(conn);
         Ok(())
     }
 
+    /// Check the host's certificate against the known-peers store (TOFU)
+    fn verify_known_peer(&self, conn: &QuicConnection, host_name: &str) -> Result<(), ClientError> {
+        let fingerprint = conn
+            .peer_fingerprint()
+            .ok_or_else(|| ClientError::Connection("host presented no certificate".into()))?;
+
+        let address = self.config.server_addr.to_string();
+        verify_peer(
+            self.config.known_peers_path.as_deref(),
+            host_name,
+            &fingerprint,
+            &address,
+        )
+        .map_err(|check| peer_error(check, host_name))
+    }
+
+    /// Check the host's certificate against the store before it has identified itself
+    fn check_known_peer(&self, conn: &QuicConnection) -> Result<(), ClientError> {
+        let fingerprint = conn
+            .peer_fingerprint()
+            .ok_or_else(|| ClientError::Connection("host presented no certificate".into()))?;
+
+        let address = self.config.server_addr.to_string();
+        check_peer(
+            self.config.known_peers_path.as_deref(),
+            &fingerprint,
+            &address,
+        )
+        .map_err(|check| peer_error(check, &address))
+    }
+
+    /// Bring `snapshot` up to date with the share, `max_depth` levels deep
+    ///
+    /// Only directories that changed since the last refresh are fetched,
+    /// unless the host restarted since, which takes a full snapshot.
+    pub async fn refresh_snapshot(
+        &self,
+        snapshot: &mut TreeSnapshot,
+        max_depth: u32,
+    ) -> Result<(), ClientError> {
+        let conn = self.connection.as_ref().ok_or(ClientError::NotConnected)?;
+        let since = snapshot.mark();
+        let mut cursor = 0;
+        let mut walk = None;
+        let mut dirs = Vec::new();
+        let mut left_out = Vec::new();
+
+        loop {
+            let (mut send, mut recv) = conn
+                .open_stream()
+                .await
+                .map_err(|e| ClientError::Connection(format!("{:?}", e)))?;
+
+            let request = NetMessage::TreeSnapshot(TreeSnapshotRequest {
+                root: self.root_inode,
+                max_depth,
+                cursor,
+                since,
+            });
+
+            send_message(&mut send, &request)
+                .await
+                .map_err(|e| ClientError::Connection(format!("{:?}", e)))?;
+
+            let response = recv_message(&mut recv)
+                .await
+                .map_err(|e| ClientError::Connection(format!("{:?}", e)))?;
+
+            let page = match response {
+                NetMessage::TreeSnapshotResponse(page) => page,
+                NetMessage::Error(e) => return Err(ClientError::ServerError(e.message)),
+                _ => return Err(ClientError::Protocol("unexpected response".into())),
+            };
+            dirs.extend(
+                page.dirs()
+                    .map_err(|e| ClientError::Protocol(e.to_string()))?,
+            );
+            left_out.extend_from_slice(&page.left_out);
+            // The walk as a whole dates from its first page
+            let (mark, incremental) = *walk.get_or_insert((page.mark, page.incremental));
+
+            if !page.has_more {
+                debug!(
+                    "Tree snapshot: {} directories ({})",
+                    dirs.len(),
+                    if incremental { "changes" } else { "full" }
+                );
+                snapshot.update(self.root_inode, mark, incremental, dirs, &left_out);
+                return Ok(());
+            }
+            if page.next_cursor == 0 {
+                return Err(ClientError::Protocol("tree snapshot has no cursor".into()));
+            }
+            cursor = page.next_cursor;
+        }
+    }
+
     /// Handle FUSE requests from the bridge
     pub async fn handle_fuse_requests(
         &self,
@@ -236,6 +465,10 @@ impl WormholeClient {
                         let result = self.readdir(inode, offset).await;
                         let _ = reply.send(result);
                     }
+                    FuseRequest::ReadDirPlus { inode, reply } => {
+                        let result = self.readdirplus(inode).await;
+                        let _ = reply.send(result);
+                    }
                     FuseRequest::Read {
                         inode,
                         offset,
@@ -271,6 +504,18 @@ impl WormholeClient {
                         let result = self.flush(inode).await;
                         let _ = reply.send(result);
                     }
+                    FuseRequest::BeginWrite { inode, reply } => {
+                        let result = self.begin_write(inode).await;
+                        let _ = reply.send(result);
+                    }
+                    FuseRequest::CommitWrite { inode, reply } => {
+                        let result = self.commit_write(inode).await;
+                        let _ = reply.send(result);
+                    }
+                    FuseRequest::AbortWrite { inode, reply } => {
+                        let result = self.abort_write(inode).await;
+                        let _ = reply.send(result);
+                    }
                     // Phase 7: File operations
                     FuseRequest::CreateFile {
                         parent,
@@ -329,6 +574,36 @@ impl WormholeClient {
                         let result = self.setattr(inode, size, mode, mtime, atime).await;
                         let _ = reply.send(result);
                     }
+                    FuseRequest::CopyRange {
+                        src,
+                        src_offset,
+                        dst,
+                        dst_offset,
+                        len,
+                        reply,
+                    } => {
+                        let result = self.copy_range(src, src_offset, dst, dst_offset, len).await;
+                        let _ = reply.send(result);
+                    }
+                    FuseRequest::Seek {
+                        inode,
+                        offset,
+                        whence,
+                        reply,
+                    } => {
+                        let result = self.seek(inode, offset, whence).await;
+                        let _ = reply.send(result);
+                    }
+                    FuseRequest::Fallocate {
+                        inode,
+                        offset,
+                        len,
+                        mode,
+                        reply,
+                    } => {
+                        let result = self.fallocate(inode, offset, len, mode).await;
+                        let _ = reply.send(result);
+                    }
                     FuseRequest::Shutdown => {
                         // Handler will exit
                     }
@@ -364,7 +639,7 @@ impl WormholeClient {
         match response {
             NetMessage::LookupResponse(LookupResponse { attr: Some(attr) }) => Ok(attr),
             NetMessage::LookupResponse(LookupResponse { attr: None }) => Err(FuseError::NotFound),
-            NetMessage::Error(e) => Err(FuseError::IoError(format!("{:?}: {}", e.code, e.message))),
+            NetMessage::Error(e) => Err(error_from_host(&e)),
             _ => Err(FuseError::Internal("unexpected response".into())),
         }
     }
@@ -391,7 +666,7 @@ impl WormholeClient {
         match response {
             NetMessage::GetAttrResponse(GetAttrResponse { attr: Some(attr) }) => Ok(attr),
             NetMessage::GetAttrResponse(GetAttrResponse { attr: None }) => Err(FuseError::NotFound),
-            NetMessage::Error(e) => Err(FuseError::IoError(format!("{:?}: {}", e.code, e.message))),
+            NetMessage::Error(e) => Err(error_from_host(&e)),
             _ => Err(FuseError::Internal("unexpected response".into())),
         }
     }
@@ -409,6 +684,7 @@ impl WormholeClient {
             inode,
             offset,
             limit: 1000,
+            with_attrs: false,
         });
 
         send_message(&mut send, &request)
@@ -421,11 +697,59 @@ impl WormholeClient {
 
         match response {
             NetMessage::ListDirResponse(ListDirResponse { entries, .. }) => Ok(entries),
-            NetMessage::Error(e) => Err(FuseError::IoError(format!("{:?}: {}", e.code, e.message))),
+            NetMessage::Error(e) => Err(error_from_host(&e)),
             _ => Err(FuseError::Internal("unexpected response".into())),
         }
     }
 
+    /// Read a whole directory with the attributes of its entries, a page of
+    /// up to `MAX_LIST_DIR_ATTRS` entries per round trip
+    async fn readdirplus(&self, inode: Inode) -> Result<DirListing, FuseError> {
+        let conn = self.connection.as_ref().ok_or(FuseError::Shutdown)?;
+        let mut listing = Vec::new();
+        let mut offset = 0;
+
+        loop {
+            let (mut send, mut recv) = conn
+                .open_stream()
+                .await
+                .map_err(|e| FuseError::IoError(format!("{:?}", e)))?;
+
+            let request = NetMessage::ListDir(ListDirRequest {
+                inode,
+                offset,
+                limit: MAX_LIST_DIR_ATTRS,
+                with_attrs: true,
+            });
+
+            send_message(&mut send, &request)
+                .await
+                .map_err(|e| FuseError::IoError(format!("{:?}", e)))?;
+
+            let response = recv_message(&mut recv)
+                .await
+                .map_err(|e| FuseError::IoError(format!("{:?}", e)))?;
+
+            match response {
+                NetMessage::ListDirResponse(page) => {
+                    let mut attrs = page.attrs.into_iter();
+                    listing.extend(
+                        page.entries
+                            .into_iter()
+                            .map(|entry| (entry, attrs.next().flatten())),
+                    );
+                    // A page without entries ends the listing even if the host says more follow
+                    if !page.has_more || page.next_offset == offset {
+                        return Ok(listing);
+                    }
+                    offset = page.next_offset;
+                }
+                NetMessage::Error(e) => return Err(error_from_host(&e)),
+                _ => return Err(FuseError::Internal("unexpected response".into())),
+            }
+        }
+    }
+
     /// Read file data
     async fn read(&self, inode: Inode, offset: u64, size: u32) -> Result<Vec<u8>, FuseError> {
         let conn = self.connection.as_ref().ok_or(FuseError::Shutdown)?;
@@ -439,7 +763,7 @@ impl WormholeClient {
 
         let request = NetMessage::ReadChunk(ReadChunkRequest {
             chunk_id,
-            priority: 0,
+            priority: PRIORITY_INTERACTIVE,
         });
 
         send_message(&mut send, &request)
@@ -451,7 +775,10 @@ impl WormholeClient {
             .map_err(|e| FuseError::IoError(format!("{:?}", e)))?;
 
         match response {
-            NetMessage::ReadChunkResponse(ReadChunkResponse { data, checksum, .. }) => {
+            NetMessage::ReadChunkResponse(chunk) => {
+                let checksum = chunk.checksum;
+                let data = chunk.into_data();
+
                 // Verify checksum
                 let computed = teleport_core::crypto::checksum(&data);
                 if computed != checksum {
@@ -465,7 +792,7 @@ impl WormholeClient {
 
                 Ok(data[chunk_offset..chunk_offset + to_read].to_vec())
             }
-            NetMessage::Error(e) => Err(FuseError::IoError(format!("{:?}: {}", e.code, e.message))),
+            NetMessage::Error(e) => Err(error_from_host(&e)),
             _ => Err(FuseError::Internal("unexpected response".into())),
         }
     }
@@ -539,7 +866,7 @@ impl WormholeClient {
                     return Err(FuseError::IoError("write failed on host".into()));
                 }
                 NetMessage::Error(e) => {
-                    return Err(FuseError::IoError(format!("{:?}: {}", e.code, e.message)));
+                    return Err(error_from_host(&e));
                 }
                 _ => return Err(FuseError::Internal("unexpected response".into())),
             }
@@ -604,7 +931,7 @@ impl WormholeClient {
                 );
                 Err(FuseError::LockConflict(msg))
             }
-            NetMessage::Error(e) => Err(FuseError::IoError(format!("{:?}: {}", e.code, e.message))),
+            NetMessage::Error(e) => Err(error_from_host(&e)),
             _ => Err(FuseError::Internal("unexpected response".into())),
         }
     }
@@ -645,7 +972,7 @@ impl WormholeClient {
                 self.sync_engine.remove_lock(inode);
                 Ok(())
             }
-            NetMessage::Error(e) => Err(FuseError::IoError(format!("{:?}: {}", e.code, e.message))),
+            NetMessage::Error(e) => Err(error_from_host(&e)),
             _ => Err(FuseError::Internal("unexpected response".into())),
         }
     }
@@ -702,7 +1029,7 @@ impl WormholeClient {
                 NetMessage::Error(e) => {
                     self.sync_engine
                         .mark_sync_failed(&chunk_id, format!("{:?}", e.code));
-                    return Err(FuseError::IoError(format!("{:?}: {}", e.code, e.message)));
+                    return Err(error_from_host(&e));
                 }
                 _ => return Err(FuseError::Internal("unexpected response".into())),
             }
@@ -753,7 +1080,7 @@ impl WormholeClient {
                 error: Some(err),
                 ..
             }) => Err(FuseError::IoError(err)),
-            NetMessage::Error(e) => Err(FuseError::IoError(format!("{:?}: {}", e.code, e.message))),
+            NetMessage::Error(e) => Err(error_from_host(&e)),
             _ => Err(FuseError::Internal("unexpected response".into())),
         }
     }
@@ -789,7 +1116,7 @@ impl WormholeClient {
                 success: false,
                 error: Some(err),
             }) => Err(FuseError::IoError(err)),
-            NetMessage::Error(e) => Err(FuseError::IoError(format!("{:?}: {}", e.code, e.message))),
+            NetMessage::Error(e) => Err(error_from_host(&e)),
             _ => Err(FuseError::Internal("unexpected response".into())),
         }
     }
@@ -833,7 +1160,7 @@ impl WormholeClient {
                 error: Some(err),
                 ..
             }) => Err(FuseError::IoError(err)),
-            NetMessage::Error(e) => Err(FuseError::IoError(format!("{:?}: {}", e.code, e.message))),
+            NetMessage::Error(e) => Err(error_from_host(&e)),
             _ => Err(FuseError::Internal("unexpected response".into())),
         }
     }
@@ -866,7 +1193,7 @@ impl WormholeClient {
                 success: false,
                 error: Some(err),
             }) => Err(FuseError::IoError(err)),
-            NetMessage::Error(e) => Err(FuseError::IoError(format!("{:?}: {}", e.code, e.message))),
+            NetMessage::Error(e) => Err(error_from_host(&e)),
             _ => Err(FuseError::Internal("unexpected response".into())),
         }
     }
@@ -910,7 +1237,7 @@ impl WormholeClient {
                 success: false,
                 error: Some(err),
             }) => Err(FuseError::IoError(err)),
-            NetMessage::Error(e) => Err(FuseError::IoError(format!("{:?}: {}", e.code, e.message))),
+            NetMessage::Error(e) => Err(error_from_host(&e)),
             _ => Err(FuseError::Internal("unexpected response".into())),
         }
     }
@@ -961,10 +1288,387 @@ impl WormholeClient {
                 error: Some(err),
                 ..
             }) => Err(FuseError::IoError(err)),
-            NetMessage::Error(e) => Err(FuseError::IoError(format!("{:?}: {}", e.code, e.message))),
+            NetMessage::Error(e) => Err(error_from_host(&e)),
+            _ => Err(FuseError::Internal("unexpected response".into())),
+        }
+    }
+
+    /// Run `op` with an exclusive lock on `inode`: the one held, or one taken for it
+    async fn with_lock<T, F, Fut>(&self, inode: Inode, op: F) -> Result<T, FuseError>
+    where
+        F: FnOnce(LockToken) -> Fut,
+        Fut: std::future::Future<Output = Result<T, FuseError>>,
+    {
+        if let Some(lock_token) = self.sync_engine.get_lock_token(inode) {
+            return op(lock_token).await;
+        }
+
+        self.acquire_lock(inode, true).await?;
+        let lock_token = self
+            .sync_engine
+            .get_lock_token(inode)
+            .ok_or(FuseError::LockRequired)?;
+        let result = op(lock_token).await;
+        if let Err(e) = self.release_lock(inode).await {
+            warn!("Failed to release lock on {}: {:?}", inode, e);
+        }
+        result
+    }
+
+    /// Start rewriting `inode` in a write session, taking its lock if it isn't held
+    ///
+    /// Writes made with the lock then go to a staged file on the host, which
+    /// replaces the file on commit; until then readers see the file as it was.
+    async fn begin_write(&self, inode: Inode) -> Result<(), FuseError> {
+        if !self.atomic_writes {
+            return Err(FuseError::Unsupported);
+        }
+        if self.write_sessions.lock().contains_key(&inode) {
+            return Ok(());
+        }
+
+        let owns_lock = !self.sync_engine.has_lock(inode, LockType::Exclusive);
+        if owns_lock {
+            self.acquire_lock(inode, true).await?;
+        }
+        // Writes made before the session belong to the file, not the rewrite
+        let result = match self.flush(inode).await {
+            Ok(()) => self.request_begin_write(inode).await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(()) => {
+                self.write_sessions.lock().insert(inode, owns_lock);
+                Ok(())
+            }
+            Err(e) => {
+                if owns_lock {
+                    if let Err(e) = self.release_lock(inode).await {
+                        warn!("Failed to release lock on {}: {:?}", inode, e);
+                    }
+                }
+                Err(e)
+            }
+        }
+    }
+
+    async fn request_begin_write(&self, inode: Inode) -> Result<(), FuseError> {
+        let conn = self.connection.as_ref().ok_or(FuseError::Shutdown)?;
+        let lock_token = self
+            .sync_engine
+            .get_lock_token(inode)
+            .ok_or(FuseError::LockRequired)?;
+
+        let (mut send, mut recv) = conn
+            .open_stream()
+            .await
+            .map_err(|e| FuseError::IoError(format!("{:?}", e)))?;
+
+        let request = NetMessage::BeginWrite(BeginWriteRequest { inode, lock_token });
+
+        send_message(&mut send, &request)
+            .await
+            .map_err(|e| FuseError::IoError(format!("{:?}", e)))?;
+
+        let response = recv_message(&mut recv)
+            .await
+            .map_err(|e| FuseError::IoError(format!("{:?}", e)))?;
+
+        match response {
+            NetMessage::BeginWriteResponse(BeginWriteResponse { success: true, .. }) => Ok(()),
+            NetMessage::BeginWriteResponse(BeginWriteResponse { error, .. }) => Err(
+                FuseError::IoError(error.unwrap_or_else(|| "write session refused".into())),
+            ),
+            NetMessage::Error(e) => Err(error_from_host(&e)),
+            _ => Err(FuseError::Internal("unexpected response".into())),
+        }
+    }
+
+    /// Upload the rest of `inode`'s rewrite and replace the file with it
+    ///
+    /// Returns the new attributes, or `None` if no session was open. On
+    /// failure the session is kept for `abort_write` to discard.
+    async fn commit_write(&self, inode: Inode) -> Result<Option<FileAttr>, FuseError> {
+        let Some(owns_lock) = self.write_sessions.lock().get(&inode).copied() else {
+            return Ok(None);
+        };
+
+        self.flush(inode).await?;
+        let attr = self.request_commit_write(inode).await?;
+
+        self.write_sessions.lock().remove(&inode);
+        if owns_lock {
+            if let Err(e) = self.release_lock(inode).await {
+                warn!("Failed to release lock on {}: {:?}", inode, e);
+            }
+        }
+        Ok(Some(attr))
+    }
+
+    async fn request_commit_write(&self, inode: Inode) -> Result<FileAttr, FuseError> {
+        let conn = self.connection.as_ref().ok_or(FuseError::Shutdown)?;
+        let lock_token = self
+            .sync_engine
+            .get_lock_token(inode)
+            .ok_or(FuseError::LockRequired)?;
+
+        let (mut send, mut recv) = conn
+            .open_stream()
+            .await
+            .map_err(|e| FuseError::IoError(format!("{:?}", e)))?;
+
+        let request = NetMessage::CommitWrite(CommitWriteRequest { inode, lock_token });
+
+        send_message(&mut send, &request)
+            .await
+            .map_err(|e| FuseError::IoError(format!("{:?}", e)))?;
+
+        let response = recv_message(&mut recv)
+            .await
+            .map_err(|e| FuseError::IoError(format!("{:?}", e)))?;
+
+        match response {
+            NetMessage::CommitWriteResponse(CommitWriteResponse {
+                success: true,
+                attr: Some(attr),
+                ..
+            }) => Ok(attr),
+            NetMessage::CommitWriteResponse(CommitWriteResponse { error, .. }) => Err(
+                FuseError::IoError(error.unwrap_or_else(|| "commit failed on host".into())),
+            ),
+            NetMessage::Error(e) => Err(error_from_host(&e)),
+            _ => Err(FuseError::Internal("unexpected response".into())),
+        }
+    }
+
+    /// Discard `inode`'s rewrite, leaving the file as it was
+    async fn abort_write(&self, inode: Inode) -> Result<(), FuseError> {
+        let Some(owns_lock) = self.write_sessions.lock().remove(&inode) else {
+            return Ok(());
+        };
+
+        // Unsent writes were for the staged file
+        self.sync_engine.discard_dirty(inode);
+        let result = self.request_abort_write(inode).await;
+
+        if owns_lock {
+            if let Err(e) = self.release_lock(inode).await {
+                warn!("Failed to release lock on {}: {:?}", inode, e);
+            }
+        }
+        result
+    }
+
+    async fn request_abort_write(&self, inode: Inode) -> Result<(), FuseError> {
+        let conn = self.connection.as_ref().ok_or(FuseError::Shutdown)?;
+        // Without the lock the host has dropped the session already
+        let Some(lock_token) = self.sync_engine.get_lock_token(inode) else {
+            return Ok(());
+        };
+
+        let (mut send, mut recv) = conn
+            .open_stream()
+            .await
+            .map_err(|e| FuseError::IoError(format!("{:?}", e)))?;
+
+        let request = NetMessage::AbortWrite(AbortWriteRequest { inode, lock_token });
+
+        send_message(&mut send, &request)
+            .await
+            .map_err(|e| FuseError::IoError(format!("{:?}", e)))?;
+
+        let response = recv_message(&mut recv)
+            .await
+            .map_err(|e| FuseError::IoError(format!("{:?}", e)))?;
+
+        match response {
+            NetMessage::AbortWriteResponse(AbortWriteResponse { .. }) => Ok(()),
+            NetMessage::Error(e) => Err(error_from_host(&e)),
+            _ => Err(FuseError::Internal("unexpected response".into())),
+        }
+    }
+
+    /// Copy bytes between files on the host, without moving them over the network
+    async fn copy_range(
+        &self,
+        src: Inode,
+        src_offset: u64,
+        dst: Inode,
+        dst_offset: u64,
+        len: u64,
+    ) -> Result<u64, FuseError> {
+        self.with_lock(dst, |lock_token| {
+            self.request_copy(src, src_offset, dst, dst_offset, len, lock_token)
+        })
+        .await
+    }
+
+    async fn request_copy(
+        &self,
+        src_inode: Inode,
+        src_offset: u64,
+        dst_inode: Inode,
+        dst_offset: u64,
+        len: u64,
+        lock_token: LockToken,
+    ) -> Result<u64, FuseError> {
+        let conn = self.connection.as_ref().ok_or(FuseError::Shutdown)?;
+
+        let (mut send, mut recv) = conn
+            .open_stream()
+            .await
+            .map_err(|e| FuseError::IoError(format!("{:?}", e)))?;
+
+        let request = NetMessage::CopyRange(CopyRangeRequest {
+            src_inode,
+            src_offset,
+            dst_inode,
+            dst_offset,
+            len: len.min(MAX_COPY_RANGE),
+            lock_token,
+        });
+
+        send_message(&mut send, &request)
+            .await
+            .map_err(|e| FuseError::IoError(format!("{:?}", e)))?;
+
+        let response = recv_message(&mut recv)
+            .await
+            .map_err(|e| FuseError::IoError(format!("{:?}", e)))?;
+
+        match response {
+            NetMessage::CopyRangeResponse(CopyRangeResponse {
+                success: true,
+                copied,
+                ..
+            }) => Ok(copied),
+            NetMessage::CopyRangeResponse(CopyRangeResponse { success: false, .. }) => {
+                Err(FuseError::IoError("copy failed on host".into()))
+            }
+            NetMessage::Error(e) => Err(error_from_host(&e)),
+            _ => Err(FuseError::Internal("unexpected response".into())),
+        }
+    }
+
+    /// Find the next data or hole at or after `offset`
+    async fn seek(
+        &self,
+        inode: Inode,
+        offset: u64,
+        whence: SeekWhence,
+    ) -> Result<Option<u64>, FuseError> {
+        let conn = self.connection.as_ref().ok_or(FuseError::Shutdown)?;
+
+        let (mut send, mut recv) = conn
+            .open_stream()
+            .await
+            .map_err(|e| FuseError::IoError(format!("{:?}", e)))?;
+
+        let request = NetMessage::Seek(SeekRequest {
+            inode,
+            offset,
+            whence,
+        });
+
+        send_message(&mut send, &request)
+            .await
+            .map_err(|e| FuseError::IoError(format!("{:?}", e)))?;
+
+        let response = recv_message(&mut recv)
+            .await
+            .map_err(|e| FuseError::IoError(format!("{:?}", e)))?;
+
+        match response {
+            NetMessage::SeekResponse(SeekResponse { offset }) => Ok(offset),
+            NetMessage::Error(e) => Err(error_from_host(&e)),
             _ => Err(FuseError::Internal("unexpected response".into())),
         }
     }
+
+    /// Allocate or deallocate part of a file
+    async fn fallocate(
+        &self,
+        inode: Inode,
+        offset: u64,
+        len: u64,
+        mode: FallocateMode,
+    ) -> Result<FileAttr, FuseError> {
+        self.with_lock(inode, |lock_token| async move {
+            let conn = self.connection.as_ref().ok_or(FuseError::Shutdown)?;
+
+            let (mut send, mut recv) = conn
+                .open_stream()
+                .await
+                .map_err(|e| FuseError::IoError(format!("{:?}", e)))?;
+
+            let request = NetMessage::Fallocate(FallocateRequest {
+                inode,
+                offset,
+                len,
+                mode,
+                lock_token,
+            });
+
+            send_message(&mut send, &request)
+                .await
+                .map_err(|e| FuseError::IoError(format!("{:?}", e)))?;
+
+            let response = recv_message(&mut recv)
+                .await
+                .map_err(|e| FuseError::IoError(format!("{:?}", e)))?;
+
+            match response {
+                NetMessage::FallocateResponse(FallocateResponse {
+                    success: true,
+                    attr: Some(attr),
+                    ..
+                }) => Ok(attr),
+                NetMessage::FallocateResponse(FallocateResponse { error, .. }) => Err(
+                    FuseError::IoError(error.unwrap_or_else(|| "fallocate failed".into())),
+                ),
+                NetMessage::Error(e) => Err(error_from_host(&e)),
+                _ => Err(FuseError::Internal("unexpected response".into())),
+            }
+        })
+        .await
+    }
+}
+
+impl Drop for WormholeClient {
+    /// Background tasks hold the connection too, so close it explicitly
+    fn drop(&mut self) {
+        self.disconnect();
+    }
+}
+
+/// Map an error reply from the host to the error surfaced through FUSE
+fn error_from_host(e: &ErrorMessage) -> FuseError {
+    match e.code {
+        ErrorCode::FileNotFound => FuseError::NotFound,
+        ErrorCode::PermissionDenied => FuseError::PermissionDenied,
+        ErrorCode::NotImplemented => FuseError::Unsupported,
+        _ => FuseError::IoError(format!("{:?}: {}", e.code, e.message)),
+    }
+}
+
+/// Map a failed known-peers check to a client error; `peer` names the host
+fn peer_error(check: PeerCheck, peer: &str) -> ClientError {
+    match check {
+        PeerCheck::Changed {
+            peer,
+            expected,
+            actual,
+        } => ClientError::CertificateChanged {
+            peer,
+            expected,
+            actual,
+        },
+        PeerCheck::Unavailable(e) => ClientError::Connection(format!(
+            "cannot verify host, known peers store unavailable: {}",
+            e
+        )),
+        _ => ClientError::PeerBlocked(peer.to_string()),
+    }
 }
 
 /// Client errors
@@ -974,9 +1678,63 @@ pub enum ClientError {
     Connection(String),
     Protocol(String),
     ServerError(String),
-    VersionMismatch { expected: u32, actual: u32 },
+    VersionMismatch {
+        expected: u32,
+        actual: u32,
+    },
+    /// The host presented a different certificate than the one on record
+    CertificateChanged {
+        peer: String,
+        expected: String,
+        actual: String,
+    },
+    /// The host is blocked in the known-peers store
+    PeerBlocked(String),
+    /// The host requires a password and none was configured
+    PasswordRequired,
+    /// The host rejected the password
+    AuthFailed(String),
+    /// The share expired or reached its usage limits
+    ShareExpired,
+}
+
+impl std::fmt::Display for ClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientError::NotConnected => write!(f, "Not connected"),
+            ClientError::Connection(msg) => write!(f, "Connection error: {}", msg),
+            ClientError::Protocol(msg) => write!(f, "Protocol error: {}", msg),
+            ClientError::ServerError(msg) => write!(f, "Server error: {}", msg),
+            ClientError::VersionMismatch { expected, actual } => {
+                write!(f, "Version mismatch: expected {}, got {}", expected, actual)
+            }
+            ClientError::CertificateChanged {
+                peer,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "WARNING: the certificate of peer '{}' has changed!\n\
+                 Someone could be intercepting this connection, or the host rotated its identity.\n\
+                 Expected fingerprint: {}\n\
+                 Received fingerprint: {}\n\
+                 If you trust the new certificate, run: wormhole peers trust {}",
+                peer, expected, actual, peer
+            ),
+            ClientError::PeerBlocked(peer) => write!(f, "Peer '{}' is blocked", peer),
+            ClientError::PasswordRequired => {
+                write!(f, "Host requires a password (use --password)")
+            }
+            ClientError::AuthFailed(msg) => write!(f, "Authentication failed: {}", msg),
+            ClientError::ShareExpired => {
+                write!(f, "Share has expired or reached its usage limits")
+            }
+        }
+    }
 }
 
+impl std::error::Error for ClientError {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -986,4 +1744,486 @@ mod tests {
         let config = ClientConfig::default();
         assert_eq!(config.server_addr.port(), 4433);
     }
+
+    #[tokio::test]
+    async fn test_connect_trusts_on_first_use() {
+        use crate::host::{HostConfig, WormholeHost};
+
+        let share = tempfile::tempdir().unwrap();
+        let state = tempfile::tempdir().unwrap();
+        let known_peers = state.path().join("known_peers.toml");
+
+        // Reserve a free port for the host
+        let port = std::net::UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let server_addr: SocketAddr = format!("127.0.0.1:{}", port).parse().unwrap();
+
+        let host = WormholeHost::new(HostConfig {
+            bind_addr: server_addr,
+            shared_path: share.path().to_path_buf(),
+            max_connections: 4,
+            host_name: "tofu-test".into(),
+            writable: true,
+        });
+        tokio::spawn(async move { host.serve().await });
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let config = || ClientConfig {
+            server_addr,
+            known_peers_path: Some(known_peers.clone()),
+            ..ClientConfig::default()
+        };
+
+        WormholeClient::new(config()).connect().await.unwrap();
+        let peers = crate::known_peers::KnownPeers::load_from(&known_peers).unwrap();
+        assert!(peers.find("tofu-test").is_some());
+
+        // Same host, same certificate: accepted
+        WormholeClient::new(config()).connect().await.unwrap();
+
+        // Pinning a different fingerprint must fail the TLS handshake
+        let mut pinned = WormholeClient::new(ClientConfig {
+            expected_fingerprint: Some([0u8; 32]),
+            ..config()
+        });
+        assert!(pinned.connect().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_host_denies_client_identity() {
+        use crate::access::{AccessLevel, AccessList, Principal};
+        use crate::host::{HostConfig, WormholeHost};
+        use crate::identity::Identity;
+
+        let share = tempfile::tempdir().unwrap();
+        let state = tempfile::tempdir().unwrap();
+
+        let allowed = Arc::new(Identity::generate().unwrap());
+        let denied = Arc::new(Identity::generate().unwrap());
+        let mut access = AccessList::default();
+        access.grant(
+            Principal::Client(denied.fingerprint()),
+            None,
+            AccessLevel::None,
+        );
+
+        let port = std::net::UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let server_addr: SocketAddr = format!("127.0.0.1:{}", port).parse().unwrap();
+
+        let host = WormholeHost::new(HostConfig {
+            bind_addr: server_addr,
+            shared_path: share.path().to_path_buf(),
+            max_connections: 4,
+            host_name: "mtls-test".into(),
+            writable: true,
+        })
+        .with_access_list(Arc::new(access));
+        tokio::spawn(async move { host.serve().await });
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let config = |identity: &Arc<Identity>| ClientConfig {
+            server_addr,
+            known_peers_path: Some(state.path().join("known_peers.toml")),
+            client_identity: Some(identity.clone()),
+            ..ClientConfig::default()
+        };
+
+        WormholeClient::new(config(&allowed))
+            .connect()
+            .await
+            .unwrap();
+        assert!(WormholeClient::new(config(&denied))
+            .connect()
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_host_refuses_other_protocol_versions() {
+        use crate::host::{HostConfig, WormholeHost};
+        use crate::net::recv_message;
+
+        let share = tempfile::tempdir().unwrap();
+        let port = std::net::UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let server_addr: SocketAddr = format!("127.0.0.1:{}", port).parse().unwrap();
+
+        let host = WormholeHost::new(HostConfig {
+            bind_addr: server_addr,
+            shared_path: share.path().to_path_buf(),
+            max_connections: 4,
+            host_name: "version-test".into(),
+            writable: true,
+        });
+        tokio::spawn(async move { host.serve().await });
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        // A peer from before the last layout change must not get as far as a chunk
+        let endpoint = create_client_endpoint_tofu(0).unwrap();
+        let conn = connect(&endpoint, server_addr, "localhost").await.unwrap();
+        let (mut send, mut recv) = conn.open_stream().await.unwrap();
+        let hello = NetMessage::Hello(HelloMessage {
+            protocol_version: PROTOCOL_VERSION - 1,
+            client_id: [0; 16],
+            capabilities: vec!["read".into()],
+        });
+        send_message(&mut send, &hello).await.unwrap();
+        match recv_message(&mut recv).await.unwrap() {
+            NetMessage::Error(e) => assert_eq!(e.code, ErrorCode::ProtocolError),
+            other => panic!("Expected a version error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_host_password_challenge() {
+        use crate::host::{HostConfig, WormholeHost};
+
+        let share = tempfile::tempdir().unwrap();
+        let state = tempfile::tempdir().unwrap();
+
+        let port = std::net::UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let server_addr: SocketAddr = format!("127.0.0.1:{}", port).parse().unwrap();
+
+        let host = WormholeHost::new(HostConfig {
+            bind_addr: server_addr,
+            shared_path: share.path().to_path_buf(),
+            max_connections: 4,
+            host_name: "password-test".into(),
+            writable: true,
+        })
+        .with_password("open sesame");
+        tokio::spawn(async move { host.serve().await });
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let config = |password: Option<&str>| ClientConfig {
+            server_addr,
+            known_peers_path: Some(state.path().join("known_peers.toml")),
+            password: password.map(String::from),
+            ..ClientConfig::default()
+        };
+
+        assert!(matches!(
+            WormholeClient::new(config(None)).connect().await,
+            Err(ClientError::PasswordRequired)
+        ));
+        assert!(matches!(
+            WormholeClient::new(config(Some("wrong"))).connect().await,
+            Err(ClientError::AuthFailed(_))
+        ));
+        WormholeClient::new(config(Some("open sesame")))
+            .connect()
+            .await
+            .unwrap();
+
+        // A host whose certificate changed is not answered at all
+        let path = state.path().join("known_peers.toml");
+        let mut peers = crate::known_peers::KnownPeers::load_from(&path).unwrap();
+        peers.remove("password-test").unwrap();
+        peers.record_seen("password-test", &[9u8; 32], &server_addr.to_string());
+        peers.save().unwrap();
+        assert!(matches!(
+            WormholeClient::new(config(Some("open sesame")))
+                .connect()
+                .await,
+            Err(ClientError::CertificateChanged { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_relayed_password_proof_is_rejected() {
+        use crate::host::{HostConfig, WormholeHost};
+        use crate::net::{create_server_endpoint, recv_message};
+
+        let share = tempfile::tempdir().unwrap();
+        let state = tempfile::tempdir().unwrap();
+
+        let port = std::net::UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let server_addr: SocketAddr = format!("127.0.0.1:{}", port).parse().unwrap();
+
+        let host = WormholeHost::new(HostConfig {
+            bind_addr: server_addr,
+            shared_path: share.path().to_path_buf(),
+            max_connections: 4,
+            host_name: "relay-test".into(),
+            writable: true,
+        })
+        .with_password("open sesame");
+        tokio::spawn(async move { host.serve().await });
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        // A man-in-the-middle terminates TLS on both sides and forwards the handshake
+        let (relay, _) = create_server_endpoint("127.0.0.1:0".parse().unwrap()).unwrap();
+        let relay_addr = relay.local_addr().unwrap();
+        let forwarded = tokio::spawn(async move {
+            let incoming = relay.accept().await.unwrap().await.unwrap();
+            let (mut client_send, mut client_recv) = incoming.accept_bi().await.unwrap();
+            let upstream = create_client_endpoint_tofu(0).unwrap();
+            let upstream = connect(&upstream, server_addr, "localhost").await.unwrap();
+            let (mut host_send, mut host_recv) = upstream.open_stream().await.unwrap();
+
+            // Hello out, AuthChallenge back, then the client's proof out
+            let mut reply = None;
+            for _ in 0..2 {
+                let message = recv_message(&mut client_recv).await.unwrap();
+                send_message(&mut host_send, &message).await.unwrap();
+                let message = recv_message(&mut host_recv).await.unwrap();
+                send_message(&mut client_send, &message).await.unwrap();
+                reply = Some(message);
+            }
+            reply.unwrap()
+        });
+
+        let mut client = WormholeClient::new(ClientConfig {
+            server_addr: relay_addr,
+            known_peers_path: Some(state.path().join("known_peers.toml")),
+            password: Some("open sesame".into()),
+            request_timeout: Duration::from_secs(5),
+            ..ClientConfig::default()
+        });
+        assert!(client.connect().await.is_err());
+
+        // The proof was made for the client's session with the relay, not the host's
+        match forwarded.await.unwrap() {
+            NetMessage::Error(e) => assert_eq!(e.code, ErrorCode::AuthFailed),
+            other => panic!("host accepted a relayed proof: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_only_host_denies_writes() {
+        use crate::host::{HostConfig, WormholeHost};
+
+        let share = tempfile::tempdir().unwrap();
+        let state = tempfile::tempdir().unwrap();
+
+        let port = std::net::UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let server_addr: SocketAddr = format!("127.0.0.1:{}", port).parse().unwrap();
+
+        let host = WormholeHost::new(HostConfig {
+            bind_addr: server_addr,
+            shared_path: share.path().to_path_buf(),
+            max_connections: 4,
+            host_name: "read-only-test".into(),
+            writable: false,
+        });
+        tokio::spawn(async move { host.serve().await });
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let mut client = WormholeClient::new(ClientConfig {
+            server_addr,
+            known_peers_path: Some(state.path().join("known_peers.toml")),
+            ..ClientConfig::default()
+        });
+        client.connect().await.unwrap();
+        assert!(!client.is_writable());
+
+        assert!(matches!(
+            client.create_file(ROOT_INODE, "new.txt", 0o644).await,
+            Err(FuseError::PermissionDenied)
+        ));
+        assert!(matches!(
+            client.create_dir(ROOT_INODE, "new", 0o755).await,
+            Err(FuseError::PermissionDenied)
+        ));
+        assert!(!share.path().join("new.txt").exists());
+        assert!(client.readdir(ROOT_INODE, 0).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_share_limits_end_sessions() {
+        use crate::host::{HostConfig, WormholeHost};
+        use crate::share_limits::ShareLimits;
+
+        let share = tempfile::tempdir().unwrap();
+        let state = tempfile::tempdir().unwrap();
+
+        let port = std::net::UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let server_addr: SocketAddr = format!("127.0.0.1:{}", port).parse().unwrap();
+
+        let host = WormholeHost::new(HostConfig {
+            bind_addr: server_addr,
+            shared_path: share.path().to_path_buf(),
+            max_connections: 4,
+            host_name: "limits-test".into(),
+            writable: true,
+        })
+        .with_limits(
+            ShareLimits::default()
+                .with_max_clients(1)
+                .with_lifetime(Duration::from_secs(2)),
+        );
+        let usage = host.usage();
+        let serving = tokio::spawn(async move { host.serve().await });
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let config = || ClientConfig {
+            server_addr,
+            known_peers_path: Some(state.path().join("known_peers.toml")),
+            ..ClientConfig::default()
+        };
+
+        let mut first = WormholeClient::new(config());
+        first.connect().await.unwrap();
+        assert!(matches!(
+            WormholeClient::new(config()).connect().await,
+            Err(ClientError::ShareExpired)
+        ));
+        assert_eq!(usage.client_count(), 1);
+
+        // Expiry ends the session and stops the host
+        tokio::time::timeout(Duration::from_secs(10), serving)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert!(usage.is_expired());
+        assert!(matches!(
+            first.disconnect_reason(),
+            Some(DisconnectReason::ShareExpired)
+        ));
+        assert!(first.readdir(ROOT_INODE, 0).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_host_audits_modifications() {
+        use crate::audit::{read_log, AuditEvent, AuditLog};
+        use crate::host::{HostConfig, WormholeHost};
+
+        let share = tempfile::tempdir().unwrap();
+        let state = tempfile::tempdir().unwrap();
+        let log_path = state.path().join("audit.jsonl");
+
+        let port = std::net::UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let server_addr: SocketAddr = format!("127.0.0.1:{}", port).parse().unwrap();
+
+        let host = WormholeHost::new(HostConfig {
+            bind_addr: server_addr,
+            shared_path: share.path().to_path_buf(),
+            max_connections: 4,
+            host_name: "audit-test".into(),
+            writable: true,
+        })
+        .with_audit_log(Arc::new(AuditLog::open_at(&log_path, "docs").unwrap()));
+        tokio::spawn(async move { host.serve().await });
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let mut client = WormholeClient::new(ClientConfig {
+            server_addr,
+            known_peers_path: Some(state.path().join("known_peers.toml")),
+            ..ClientConfig::default()
+        });
+        client.connect().await.unwrap();
+
+        client
+            .create_file(ROOT_INODE, "draft.txt", 0o644)
+            .await
+            .unwrap();
+        client
+            .rename(ROOT_INODE, "draft.txt", ROOT_INODE, "final.txt")
+            .await
+            .unwrap();
+        // Failed operations are not recorded
+        assert!(client.delete_file(ROOT_INODE, "missing.txt").await.is_err());
+
+        let events: Vec<_> = read_log(&log_path)
+            .unwrap()
+            .into_iter()
+            .map(|r| r.event)
+            .collect();
+        assert_eq!(events.len(), 3, "{:?}", events);
+        assert_eq!(events[0].name(), "session_open");
+        assert_eq!(
+            events[1],
+            AuditEvent::CreateFile {
+                path: "/draft.txt".into()
+            }
+        );
+        assert_eq!(
+            events[2],
+            AuditEvent::Rename {
+                path: "/draft.txt".into(),
+                to: "/final.txt".into()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_copy_range_runs_on_host() {
+        use crate::host::{HostConfig, WormholeHost};
+
+        let share = tempfile::tempdir().unwrap();
+        let state = tempfile::tempdir().unwrap();
+        std::fs::write(share.path().join("src.bin"), b"0123456789").unwrap();
+        std::fs::write(share.path().join("dst.bin"), b"abcdefghij").unwrap();
+
+        let port = std::net::UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let server_addr: SocketAddr = format!("127.0.0.1:{}", port).parse().unwrap();
+
+        let host = WormholeHost::new(HostConfig {
+            bind_addr: server_addr,
+            shared_path: share.path().to_path_buf(),
+            max_connections: 4,
+            host_name: "copy-test".into(),
+            writable: true,
+        });
+        tokio::spawn(async move { host.serve().await });
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let mut client = WormholeClient::new(ClientConfig {
+            server_addr,
+            known_peers_path: Some(state.path().join("known_peers.toml")),
+            ..ClientConfig::default()
+        });
+        client.connect().await.unwrap();
+
+        let src = client.lookup(ROOT_INODE, "src.bin").await.unwrap().inode;
+        let dst = client.lookup(ROOT_INODE, "dst.bin").await.unwrap().inode;
+        // Copies stop where the source ends
+        assert_eq!(client.copy_range(src, 2, dst, 4, 100).await.unwrap(), 8);
+        assert_eq!(
+            std::fs::read(share.path().join("dst.bin")).unwrap(),
+            b"abcd23456789"
+        );
+        // The lock taken for the copy is given back
+        assert!(client.sync_engine.get_lock_token(dst).is_none());
+
+        assert!(matches!(
+            client.copy_range(src, 0, 9999, 0, 4).await,
+            Err(FuseError::NotFound)
+        ));
+    }
 }
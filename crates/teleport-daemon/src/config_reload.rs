@@ -0,0 +1,319 @@
+//! Config hot-reload for long-running host and mount processes
+//!
+//! `ConfigReloader` re-reads the config file when it changes on disk or the
+//! process receives SIGHUP, diffs the result against the settings in effect
+//! and hands the changed settings to the handlers registered for them.
+//! Changed settings no handler covers are logged as needing a restart.
+//!
+//! Settings kept in files of their own, such as share access lists, are not
+//! part of `Config` and are not applied live. A reloader can watch such files
+//! so that edits to them are logged as needing a restart too.
+//!
+//! A reload that fails to parse or validate is logged and ignored; the
+//! running settings stay as they were.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use tracing::{debug, info, warn};
+
+use teleport_core::config::{Config, ConfigError, LayeredConfig};
+
+/// How often `run` checks the config file for changes
+pub const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+type Apply = Box<dyn Fn(&Config) + Send + Sync>;
+
+/// Settings a reload changed
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ReloadReport {
+    /// Now in effect
+    pub applied: Vec<&'static str>,
+    /// Take effect only after a restart
+    pub needs_restart: Vec<&'static str>,
+}
+
+impl ReloadReport {
+    /// Whether nothing changed
+    pub fn is_empty(&self) -> bool {
+        self.applied.is_empty() && self.needs_restart.is_empty()
+    }
+}
+
+/// Watches the config file and applies changes to a running process
+pub struct ConfigReloader {
+    path: Option<PathBuf>,
+    /// Command-line flags, re-applied on every reload so they keep precedence
+    flags: Vec<(&'static str, Option<String>)>,
+    current: Config,
+    modified: Option<SystemTime>,
+    handlers: Vec<(&'static [&'static str], Apply)>,
+    /// Files outside the config that need a restart when they change
+    restart_files: Vec<RestartFile>,
+}
+
+/// A watched file outside the config, and when it was last seen modified
+struct RestartFile {
+    key: &'static str,
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl ConfigReloader {
+    /// Reloader for the process started with `settings`
+    pub fn new(settings: &LayeredConfig) -> Self {
+        let path = settings.path().map(PathBuf::from);
+        Self {
+            modified: path.as_deref().and_then(modified),
+            path,
+            flags: Vec::new(),
+            current: settings.config().clone(),
+            handlers: Vec::new(),
+            restart_files: Vec::new(),
+        }
+    }
+
+    /// Command-line flags the process was started with, as given to `LayeredConfig::apply_cli`
+    pub fn with_flags(mut self, flags: Vec<(&'static str, Option<String>)>) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Call `apply` with the new settings whenever any of `keys` changes
+    pub fn on_change(
+        mut self,
+        keys: &'static [&'static str],
+        apply: impl Fn(&Config) + Send + Sync + 'static,
+    ) -> Self {
+        self.handlers.push((keys, Box::new(apply)));
+        self
+    }
+
+    /// Report `key` as needing a restart whenever the file at `path` changes
+    pub fn with_restart_file(mut self, key: &'static str, path: PathBuf) -> Self {
+        self.restart_files.push(RestartFile {
+            key,
+            modified: modified(&path),
+            path,
+        });
+        self
+    }
+
+    /// Settings in effect
+    pub fn config(&self) -> &Config {
+        &self.current
+    }
+
+    /// Re-read the config file and apply what changed
+    pub fn reload(&mut self) -> Result<ReloadReport, ConfigError> {
+        self.modified = self.path.as_deref().and_then(modified);
+        let mut changed_files = Vec::new();
+        for file in &mut self.restart_files {
+            let modified = modified(&file.path);
+            if modified != file.modified {
+                file.modified = modified;
+                changed_files.push(file.key);
+            }
+        }
+
+        let mut settings = LayeredConfig::load(self.path.as_deref())?;
+        settings.apply_cli(self.flags.iter().cloned())?;
+        settings.validate()?;
+        let mut report = self.apply(settings.config().clone());
+        report.needs_restart.extend(changed_files);
+        Ok(report)
+    }
+
+    /// Reload if the config file, or a restart file, was created, modified or
+    /// removed since the last look
+    pub fn reload_if_changed(&mut self) -> Option<Result<ReloadReport, ConfigError>> {
+        let config_changed = self.path.as_deref().and_then(modified) != self.modified;
+        let files_changed = self
+            .restart_files
+            .iter()
+            .any(|file| modified(&file.path) != file.modified);
+        if !config_changed && !files_changed {
+            return None;
+        }
+        Some(self.reload())
+    }
+
+    /// Make `config` the settings in effect, calling the handlers for what changed
+    pub fn apply(&mut self, config: Config) -> ReloadReport {
+        let changed = self.current.diff(&config);
+        let mut report = ReloadReport::default();
+
+        for (keys, apply) in &self.handlers {
+            if keys.iter().any(|key| changed.contains(key)) {
+                apply(&config);
+            }
+        }
+        for key in changed {
+            if self.handlers.iter().any(|(keys, _)| keys.contains(&key)) {
+                report.applied.push(key);
+            } else {
+                report.needs_restart.push(key);
+            }
+        }
+
+        self.current = config;
+        report
+    }
+
+    /// Reload whenever the config file changes or on SIGHUP, forever
+    pub async fn run(mut self) {
+        let mut poll = tokio::time::interval(POLL_INTERVAL);
+        let mut hangup = Hangup::new();
+
+        loop {
+            let result = tokio::select! {
+                _ = poll.tick() => match self.reload_if_changed() {
+                    Some(result) => result,
+                    None => continue,
+                },
+                _ = hangup.recv() => {
+                    info!("Received SIGHUP, reloading configuration");
+                    self.reload()
+                }
+            };
+
+            match result {
+                Ok(report) => log_report(&report),
+                Err(e) => warn!("Ignoring configuration change: {}", e),
+            }
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn log_report(report: &ReloadReport) {
+    if report.is_empty() {
+        debug!("Configuration reloaded, nothing changed");
+    }
+    if !report.applied.is_empty() {
+        info!(
+            "Applied configuration change: {}",
+            report.applied.join(", ")
+        );
+    }
+    if !report.needs_restart.is_empty() {
+        warn!(
+            "Restart to apply configuration change: {}",
+            report.needs_restart.join(", ")
+        );
+    }
+}
+
+/// SIGHUP stream; never fires where there is no SIGHUP
+struct Hangup {
+    #[cfg(unix)]
+    signal: Option<tokio::signal::unix::Signal>,
+}
+
+impl Hangup {
+    fn new() -> Self {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+            let signal = signal(SignalKind::hangup())
+                .map_err(|e| warn!("SIGHUP reload unavailable: {}", e))
+                .ok();
+            Self { signal }
+        }
+        #[cfg(not(unix))]
+        Self {}
+    }
+
+    async fn recv(&mut self) {
+        #[cfg(unix)]
+        if let Some(signal) = &mut self.signal {
+            if signal.recv().await.is_some() {
+                return;
+            }
+        }
+        std::future::pending::<()>().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+
+    fn reloader(dir: &tempfile::TempDir, content: &str) -> ConfigReloader {
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, content).unwrap();
+        ConfigReloader::new(&LayeredConfig::load(Some(&path)).unwrap())
+    }
+
+    #[test]
+    fn test_applies_live_changes_and_reports_the_rest() {
+        let dir = tempfile::tempdir().unwrap();
+        let ttl = Arc::new(AtomicU64::new(0));
+        let seen = ttl.clone();
+        let mut reloader = reloader(&dir, "[client]\nattr_ttl_secs = 1\n").on_change(
+            &["client.attr_ttl_secs", "client.dir_ttl_secs"],
+            move |config| seen.store(config.client.attr_ttl_secs, Ordering::SeqCst),
+        );
+
+        std::fs::write(
+            dir.path().join("config.toml"),
+            "[client]\nattr_ttl_secs = 7\n[host]\nport = 5000\n",
+        )
+        .unwrap();
+        let report = reloader.reload().unwrap();
+        assert_eq!(report.applied, ["client.attr_ttl_secs"]);
+        assert_eq!(report.needs_restart, ["host.port"]);
+        assert_eq!(ttl.load(Ordering::SeqCst), 7);
+        assert_eq!(reloader.config().host.port, 5000);
+
+        assert!(reloader.reload().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_restart_files_are_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let acl = dir.path().join("photos.toml");
+        let mut reloader = reloader(&dir, "").with_restart_file("access list", acl.clone());
+        assert!(reloader.reload_if_changed().is_none());
+
+        std::fs::write(&acl, "default = \"read\"\n").unwrap();
+        let report = reloader.reload_if_changed().unwrap().unwrap();
+        assert!(report.applied.is_empty());
+        assert_eq!(report.needs_restart, ["access list"]);
+        assert!(reloader.reload_if_changed().is_none());
+    }
+
+    #[test]
+    fn test_bad_reload_keeps_running_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut reloader = reloader(&dir, "[cache]\ngc_interval_secs = 30\n");
+
+        std::fs::write(
+            dir.path().join("config.toml"),
+            "[cache]\ngc_interval_secs = 0\n",
+        )
+        .unwrap();
+        assert!(reloader.reload().is_err());
+        assert_eq!(reloader.config().cache.gc_interval_secs, 30);
+    }
+
+    #[test]
+    fn test_flags_keep_precedence() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "[host]\nblock_secs = 10\n").unwrap();
+        let flags = vec![("host.block_secs", Some("20".to_string()))];
+        let mut settings = LayeredConfig::load(Some(&path)).unwrap();
+        settings.apply_cli(flags.clone()).unwrap();
+        let mut reloader = ConfigReloader::new(&settings).with_flags(flags);
+
+        std::fs::write(&path, "[host]\nblock_secs = 30\n").unwrap();
+        assert!(reloader.reload().unwrap().is_empty());
+        assert_eq!(reloader.config().host.block_secs, 20);
+    }
+}
@@ -8,6 +8,7 @@
 
 use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
@@ -22,8 +23,21 @@ use teleport_core::{
 };
 
 use crate::bridge::FuseError;
-#[allow(deprecated)] // create_client_endpoint is deprecated but used for dev/LAN mode
-use crate::net::{connect, create_client_endpoint, recv_message, send_message, QuicConnection};
+use crate::discovery::{self, DiscoveredShare};
+use crate::fault_injection::FaultInjector;
+use crate::identity::Identity;
+use crate::known_peers::{verify_peer, PeerCheck};
+use crate::net::{
+    connect, create_client_endpoint_tofu, create_client_endpoint_with_identity,
+    create_client_endpoint_with_pinned_cert, recv_message, send_message, CertFingerprint,
+    QuicConnection,
+};
+
+/// How many times `read` sends a request before giving up on the host
+const READ_ATTEMPTS: u32 = 3;
+
+/// How often a request waiting for a reconnect checks on it
+const RECONNECT_POLL_INTERVAL: Duration = Duration::from_millis(50);
 
 /// Configuration for connecting to a host
 #[derive(Clone, Debug)]
@@ -34,6 +48,8 @@ pub struct HostConnectionConfig {
     pub join_code: Option<String>,
     /// Display name for the host
     pub display_name: Option<String>,
+    /// Expected certificate fingerprint (None = trust on first use)
+    pub expected_fingerprint: Option<CertFingerprint>,
     /// Reconnection settings
     pub reconnect: ReconnectConfig,
 }
@@ -93,6 +109,8 @@ pub enum ConnectionEvent {
     ShareRemoved { share_id: ShareId },
     /// Connection health changed
     HealthChanged { host_id: String, healthy: bool },
+    /// A share was found on the local network (not yet connected)
+    LanShareDiscovered { share: DiscoveredShare },
 }
 
 /// Managed host connection state
@@ -151,10 +169,15 @@ pub struct ConnectionManager {
     /// Event broadcaster
     event_tx: broadcast::Sender<ConnectionEvent>,
     /// Request timeout
-    #[allow(dead_code)]
     request_timeout: Duration,
     /// Health check interval
     health_check_interval: Duration,
+    /// Certificate presented to hosts; without one connections are anonymous
+    client_identity: Option<Arc<Identity>>,
+    /// Known-peers store to verify hosts against (None = default location)
+    known_peers_path: Option<PathBuf>,
+    /// Faults applied to every connection (testing only)
+    fault_injector: Option<FaultInjector>,
 }
 
 impl ConnectionManager {
@@ -170,9 +193,42 @@ impl ConnectionManager {
             event_tx,
             request_timeout: Duration::from_secs(30),
             health_check_interval: Duration::from_secs(30),
+            client_identity: None,
+            known_peers_path: None,
+            fault_injector: None,
         }
     }
 
+    /// Present this identity to hosts (mutual TLS)
+    pub fn with_client_identity(mut self, identity: Arc<Identity>) -> Self {
+        self.client_identity = Some(identity);
+        self
+    }
+
+    /// Time limit for connecting, handshakes and each request
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+
+    /// How often `start_health_checks` pings connected hosts
+    pub fn with_health_check_interval(mut self, interval: Duration) -> Self {
+        self.health_check_interval = interval;
+        self
+    }
+
+    /// Verify hosts against this known-peers store instead of the default one
+    pub fn with_known_peers_path(mut self, path: PathBuf) -> Self {
+        self.known_peers_path = Some(path);
+        self
+    }
+
+    /// Route every connection through `injector`, for network fault tests
+    pub fn with_fault_injector(mut self, injector: FaultInjector) -> Self {
+        self.fault_injector = Some(injector);
+        self
+    }
+
     /// Subscribe to connection events
     pub fn subscribe(&self) -> broadcast::Receiver<ConnectionEvent> {
         self.event_tx.subscribe()
@@ -194,11 +250,17 @@ impl ConnectionManager {
         self.hosts.insert(host_id.clone(), host);
 
         // Attempt connection
-        self.connect_host(&host_id).await
+        let result = self.connect_host(&host_id).await;
+        if result.is_err() {
+            self.update_host_status(&host_id, ConnectionStatus::Failed);
+        }
+        result
     }
 
     /// Connect to a specific host
-    #[allow(deprecated)] // Using insecure endpoint for LAN/dev connections
+    ///
+    /// Leaves the host's status alone when the host can't be reached, so a
+    /// reconnect in progress stays `Reconnecting` between attempts.
     async fn connect_host(&self, host_id: &str) -> Result<(), ConnectionError> {
         let config = {
             let host = self
@@ -209,20 +271,66 @@ impl ConnectionManager {
         };
 
         // Create QUIC endpoint
-        let endpoint = create_client_endpoint()
-            .map_err(|e| ConnectionError::Connection(format!("{:?}", e)))?;
+        let endpoint = match (&self.client_identity, config.expected_fingerprint) {
+            (Some(identity), expected) => {
+                create_client_endpoint_with_identity(0, expected, identity)
+            }
+            (None, Some(fingerprint)) => create_client_endpoint_with_pinned_cert(0, fingerprint),
+            (None, None) => create_client_endpoint_tofu(0),
+        }
+        .map_err(|e| ConnectionError::Connection(format!("{:?}", e)))?;
+        if let Some(injector) = &self.fault_injector {
+            injector
+                .wrap(&endpoint)
+                .map_err(|e| ConnectionError::Io(e.to_string()))?;
+        }
 
         // Connect to host
-        let conn = match connect(&endpoint, config.address, "localhost").await {
-            Ok(c) => c,
-            Err(e) => {
-                self.update_host_status(host_id, ConnectionStatus::Failed);
-                return Err(ConnectionError::Connection(format!("{:?}", e)));
-            }
+        let connecting = tokio::time::timeout(
+            self.request_timeout,
+            connect(&endpoint, config.address, "localhost"),
+        );
+        let conn = match connecting.await {
+            Ok(Ok(c)) => c,
+            Ok(Err(e)) => return Err(ConnectionError::Connection(format!("{:?}", e))),
+            Err(_) => return Err(ConnectionError::Connection("connect timed out".into())),
         };
 
         // Perform handshake
-        let (session_id, host_name, shares) = self.handshake(&conn).await?;
+        let (session_id, host_name, shares) =
+            tokio::time::timeout(self.request_timeout, self.handshake(&conn))
+                .await
+                .map_err(|_| ConnectionError::Connection("handshake timed out".into()))??;
+
+        // Check the certificate against the known-peers store (TOFU)
+        let fingerprint = conn
+            .peer_fingerprint()
+            .ok_or_else(|| ConnectionError::Connection("host presented no certificate".into()))?;
+        if let Err(check) = verify_peer(
+            self.known_peers_path.as_deref(),
+            &host_name,
+            &fingerprint,
+            &config.address.to_string(),
+        ) {
+            conn.close(1, "untrusted certificate");
+            self.update_host_status(host_id, ConnectionStatus::Failed);
+            return Err(match check {
+                PeerCheck::Changed {
+                    peer,
+                    expected,
+                    actual,
+                } => ConnectionError::Connection(format!(
+                    "certificate of peer '{}' has changed (expected {}, got {}); \
+                     run `wormhole peers trust {}` if this is expected",
+                    peer, expected, actual, peer
+                )),
+                PeerCheck::Unavailable(e) => ConnectionError::Connection(format!(
+                    "cannot verify peer, known peers store unavailable: {}",
+                    e
+                )),
+                _ => ConnectionError::Connection(format!("peer '{}' is blocked", host_name)),
+            });
+        }
 
         // Update host state
         if let Some(mut host) = self.hosts.get_mut(host_id) {
@@ -239,8 +347,11 @@ impl ConnectionManager {
             host.reconnect_attempts = 0;
         }
 
-        // Register shares from this host
+        // Register shares from this host; after a reconnect they keep their indices
         for share in shares {
+            if self.refresh_share(host_id, &share) {
+                continue;
+            }
             if self.register_share(host_id, share).await.is_none() {
                 warn!("Failed to register share - index space exhausted");
             }
@@ -310,6 +421,13 @@ impl ConnectionManager {
                 Ok((ack.session_id, ack.host_name, shares))
             }
             NetMessage::Error(e) => Err(ConnectionError::Protocol(e.message)),
+            NetMessage::AuthChallenge(_) => {
+                Err(ConnectionError::Protocol("host requires a password".into()))
+            }
+            NetMessage::Goodbye(goodbye) => Err(ConnectionError::Protocol(format!(
+                "host refused the session: {:?}",
+                goodbye.reason
+            ))),
             _ => Err(ConnectionError::Protocol(
                 "unexpected response to Hello".into(),
             )),
@@ -391,6 +509,26 @@ impl ConnectionManager {
         Some(index)
     }
 
+    /// Update a share this host registered before, keeping its ID and index
+    ///
+    /// Returns false if the host has no share with that name yet.
+    fn refresh_share(&self, host_id: &str, share: &ShareInfo) -> bool {
+        let mut existing = match self
+            .shares
+            .iter_mut()
+            .find(|s| s.host_id == host_id && s.info.name == share.name)
+        {
+            Some(existing) => existing,
+            None => return false,
+        };
+        existing.info = ShareInfo {
+            id: existing.info.id,
+            ..share.clone()
+        };
+        existing.last_accessed = Instant::now();
+        true
+    }
+
     /// Remove a host and its shares
     pub async fn remove_host(&self, host_id: &str) {
         if let Some((_, _host)) = self.hosts.remove(host_id) {
@@ -418,6 +556,50 @@ impl ConnectionManager {
         }
     }
 
+    /// Browse the local network for announced shares
+    ///
+    /// Emits a `LanShareDiscovered` event for every share found.
+    pub async fn discover_lan(
+        &self,
+        timeout: Duration,
+    ) -> Result<Vec<DiscoveredShare>, ConnectionError> {
+        let shares = discovery::browse(timeout)
+            .await
+            .map_err(|e| ConnectionError::Io(e.to_string()))?;
+
+        for share in &shares {
+            let _ = self.event_tx.send(ConnectionEvent::LanShareDiscovered {
+                share: share.clone(),
+            });
+        }
+
+        Ok(shares)
+    }
+
+    /// Add a host found on the local network
+    ///
+    /// The host is registered under its DNS-SD instance name, which is
+    /// returned as the host ID. Its announced fingerprint is not pinned, since
+    /// anyone on the network could have sent it; the host is checked against
+    /// known peers instead.
+    pub async fn add_lan_host(
+        &self,
+        share: &DiscoveredShare,
+        join_code: Option<String>,
+    ) -> Result<String, ConnectionError> {
+        let host_id = share.instance.clone();
+        let config = HostConnectionConfig {
+            address: share.addr,
+            join_code,
+            display_name: Some(share.host_name.clone()),
+            expected_fingerprint: None,
+            reconnect: ReconnectConfig::default(),
+        };
+
+        self.add_host(host_id.clone(), config).await?;
+        Ok(host_id)
+    }
+
     /// Update host connection status
     fn update_host_status(&self, host_id: &str, status: ConnectionStatus) {
         if let Some(mut host) = self.hosts.get_mut(host_id) {
@@ -590,6 +772,7 @@ impl ConnectionManager {
             inode: local_inode,
             offset,
             limit: 1000,
+            with_attrs: false,
         });
 
         send_message(&mut send, &request)
@@ -615,42 +798,57 @@ impl ConnectionManager {
     }
 
     /// Read file data
+    ///
+    /// A request that fails in transit is sent again once the host has been
+    /// reconnected, up to `READ_ATTEMPTS` times in all.
     pub async fn read(
         &self,
         global: GlobalInode,
         offset: u64,
         size: u32,
     ) -> Result<Vec<u8>, FuseError> {
-        use teleport_core::{ChunkId, NetMessage, ReadChunkRequest, ReadChunkResponse};
+        use teleport_core::{ChunkId, NetMessage, ReadChunkRequest, PRIORITY_INTERACTIVE};
 
         let (share, local_inode) = self.resolve_inode(global).ok_or(FuseError::NotFound)?;
 
-        let conn = self
-            .get_connection_for_share(&share.info.id)
-            .ok_or(FuseError::Shutdown)?;
-
-        let (mut send, mut recv) = conn
-            .open_stream()
-            .await
-            .map_err(|e| FuseError::IoError(format!("{:?}", e)))?;
-
         let chunk_id = ChunkId::from_offset(local_inode, offset);
 
         let request = NetMessage::ReadChunk(ReadChunkRequest {
             chunk_id,
-            priority: 0,
+            priority: PRIORITY_INTERACTIVE,
         });
 
-        send_message(&mut send, &request)
-            .await
-            .map_err(|e| FuseError::IoError(format!("{:?}", e)))?;
+        let mut attempt = 1;
+        let response = loop {
+            let conn = self.get_connection_for_share(&share.info.id);
+            let error = match &conn {
+                Some(conn) => {
+                    let exchange = exchange(conn, &request);
+                    match tokio::time::timeout(self.request_timeout, exchange).await {
+                        Ok(Ok(response)) => break response,
+                        Ok(Err(e)) => e,
+                        Err(_) => FuseError::Timeout,
+                    }
+                }
+                None => FuseError::Shutdown,
+            };
 
-        let response = recv_message(&mut recv)
-            .await
-            .map_err(|e| FuseError::IoError(format!("{:?}", e)))?;
+            if attempt == READ_ATTEMPTS {
+                return Err(error);
+            }
+            warn!(
+                "Read from {} failed (attempt {}): {:?}",
+                share.host_id, attempt, error
+            );
+            attempt += 1;
+            self.recover_host(&share.host_id, conn.as_ref()).await;
+        };
 
         match response {
-            NetMessage::ReadChunkResponse(ReadChunkResponse { data, checksum, .. }) => {
+            NetMessage::ReadChunkResponse(chunk) => {
+                let checksum = chunk.checksum;
+                let data = chunk.into_data();
+
                 // Verify checksum
                 let computed = teleport_core::crypto::checksum(&data);
                 if computed != checksum {
@@ -675,6 +873,27 @@ impl ConnectionManager {
         }
     }
 
+    /// Get a host back after a request over `failed` went wrong
+    ///
+    /// Starts reconnecting if the host is still on that connection. If
+    /// another task is already reconnecting, waits up to the request
+    /// timeout for it to finish.
+    async fn recover_host(&self, host_id: &str, failed: Option<&QuicConnection>) {
+        if let Some(failed) = failed {
+            self.handle_connection_failure(host_id, failed).await;
+        }
+
+        let deadline = Instant::now() + self.request_timeout;
+        while Instant::now() < deadline {
+            match self.hosts.get(host_id).map(|h| h.status) {
+                Some(ConnectionStatus::Connecting | ConnectionStatus::Reconnecting) => {
+                    tokio::time::sleep(RECONNECT_POLL_INTERVAL).await
+                }
+                _ => return,
+            }
+        }
+    }
+
     /// Start health check task
     pub fn start_health_checks(self: &Arc<Self>) -> tokio::task::JoinHandle<()> {
         let manager = Arc::clone(self);
@@ -685,33 +904,40 @@ impl ConnectionManager {
             loop {
                 interval.tick().await;
 
-                for host in manager.hosts.iter() {
-                    let host_id = host.key().clone();
-                    if host.status == ConnectionStatus::Connected {
-                        if let Err(e) = manager.ping_host(&host_id).await {
-                            warn!("Health check failed for {}: {:?}", host_id, e);
-                            manager.handle_connection_failure(&host_id).await;
-                        }
-                    }
+                // Collected first: the map can't stay borrowed across reconnects
+                let connected: Vec<(String, QuicConnection)> = manager
+                    .hosts
+                    .iter()
+                    .filter(|host| host.status == ConnectionStatus::Connected)
+                    .filter_map(|host| Some((host.key().clone(), host.connection.clone()?)))
+                    .collect();
+
+                for (host_id, conn) in connected {
+                    let ping = manager.ping_host(&host_id, &conn);
+                    let error = match tokio::time::timeout(manager.request_timeout, ping).await {
+                        Ok(Ok(_)) => continue,
+                        Ok(Err(e)) => e,
+                        Err(_) => ConnectionError::Connection("ping timed out".into()),
+                    };
+                    warn!("Health check failed for {}: {:?}", host_id, error);
+                    let _ = manager.event_tx.send(ConnectionEvent::HealthChanged {
+                        host_id: host_id.clone(),
+                        healthy: false,
+                    });
+                    manager.handle_connection_failure(&host_id, &conn).await;
                 }
             }
         })
     }
 
     /// Ping a host for health check
-    async fn ping_host(&self, host_id: &str) -> Result<Duration, ConnectionError> {
+    async fn ping_host(
+        &self,
+        host_id: &str,
+        conn: &QuicConnection,
+    ) -> Result<Duration, ConnectionError> {
         use teleport_core::{NetMessage, PingMessage};
 
-        let conn = {
-            let host = self
-                .hosts
-                .get(host_id)
-                .ok_or_else(|| ConnectionError::HostNotFound(host_id.to_string()))?;
-            host.connection
-                .clone()
-                .ok_or(ConnectionError::NotConnected)?
-        };
-
         let (mut send, mut recv) = conn
             .open_stream()
             .await
@@ -760,13 +986,21 @@ impl ConnectionManager {
     }
 
     /// Handle a connection failure
-    async fn handle_connection_failure(&self, host_id: &str) {
+    ///
+    /// Does nothing unless `failed` is still the host's current connection,
+    /// so concurrent failures on one connection cause a single reconnect.
+    async fn handle_connection_failure(&self, host_id: &str, failed: &QuicConnection) {
         let should_reconnect = {
             let mut host = match self.hosts.get_mut(host_id) {
                 Some(h) => h,
                 None => return,
             };
+            match &host.connection {
+                Some(current) if current.stable_id() == failed.stable_id() => {}
+                _ => return,
+            }
 
+            failed.close(0, "connection lost");
             host.status = ConnectionStatus::Reconnecting;
             host.info.status = ConnectionStatus::Reconnecting;
             host.connection = None;
@@ -830,6 +1064,10 @@ impl ConnectionManager {
             match self.connect_host(host_id).await {
                 Ok(()) => {
                     info!("Reconnected to {}", host_id);
+                    let _ = self.event_tx.send(ConnectionEvent::HealthChanged {
+                        host_id: host_id.to_string(),
+                        healthy: true,
+                    });
                     return;
                 }
                 Err(e) => {
@@ -993,6 +1231,7 @@ impl ConnectionManager {
                 inode,
                 offset,
                 limit: 1000,
+                with_attrs: false,
             });
 
             send_message(&mut send, &request)
@@ -1020,7 +1259,7 @@ impl ConnectionManager {
         share_index: u16,
         chunk_id: teleport_core::ChunkId,
     ) -> Result<Vec<u8>, ConnectionError> {
-        use teleport_core::{NetMessage, ReadChunkRequest, ReadChunkResponse};
+        use teleport_core::{NetMessage, ReadChunkRequest, PRIORITY_INTERACTIVE};
 
         let share = self
             .get_share_by_index(share_index)
@@ -1041,7 +1280,7 @@ impl ConnectionManager {
 
             let request = NetMessage::ReadChunk(ReadChunkRequest {
                 chunk_id,
-                priority: 0,
+                priority: PRIORITY_INTERACTIVE,
             });
 
             send_message(&mut send, &request)
@@ -1053,7 +1292,10 @@ impl ConnectionManager {
                 .map_err(|e| ConnectionError::Connection(format!("{:?}", e)))?;
 
             match response {
-                NetMessage::ReadChunkResponse(ReadChunkResponse { data, checksum, .. }) => {
+                NetMessage::ReadChunkResponse(chunk) => {
+                    let checksum = chunk.checksum;
+                    let data = chunk.into_data();
+
                     // Verify checksum
                     let computed = teleport_core::crypto::checksum(&data);
                     if computed != checksum {
@@ -1071,6 +1313,25 @@ impl ConnectionManager {
     }
 }
 
+/// Send `request` on a new stream of `conn` and wait for the response
+async fn exchange(
+    conn: &QuicConnection,
+    request: &teleport_core::NetMessage,
+) -> Result<teleport_core::NetMessage, FuseError> {
+    let (mut send, mut recv) = conn
+        .open_stream()
+        .await
+        .map_err(|e| FuseError::IoError(format!("{:?}", e)))?;
+
+    send_message(&mut send, request)
+        .await
+        .map_err(|e| FuseError::IoError(format!("{:?}", e)))?;
+
+    recv_message(&mut recv)
+        .await
+        .map_err(|e| FuseError::IoError(format!("{:?}", e)))
+}
+
 /// Errors from the connection manager
 #[derive(Debug)]
 pub enum ConnectionError {
@@ -1111,6 +1372,185 @@ impl std::error::Error for ConnectionError {}
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::fault_injection::{FaultConfig, FaultInjector};
+    use crate::loopback;
+    use crate::memory_backend::MemoryBackend;
+
+    const HOST: &str = "faulty";
+
+    /// A manager connected through a fault injector to an in-memory host
+    struct Scenario {
+        manager: Arc<ConnectionManager>,
+        faults: FaultInjector,
+        share_index: u16,
+        host: tokio::task::JoinHandle<Result<(), crate::host::HostError>>,
+        _state: tempfile::TempDir,
+    }
+
+    impl Scenario {
+        async fn start(reconnect: ReconnectConfig) -> Self {
+            let backend = Arc::new(MemoryBackend::new().with_file("data.bin", test_data()));
+            let (addr, host) = loopback::serve(backend, |host| host);
+            let state = tempfile::tempdir().unwrap();
+            let faults = FaultInjector::new(42);
+            let manager = Arc::new(
+                ConnectionManager::new()
+                    .with_request_timeout(Duration::from_millis(300))
+                    .with_health_check_interval(Duration::from_millis(100))
+                    .with_known_peers_path(state.path().join("known_peers.toml"))
+                    .with_fault_injector(faults.clone()),
+            );
+
+            let config = HostConnectionConfig {
+                address: addr,
+                join_code: None,
+                display_name: None,
+                expected_fingerprint: None,
+                reconnect,
+            };
+            // The host may still be binding its endpoint
+            let mut attempts = 0;
+            while let Err(e) = manager.add_host(HOST.into(), config.clone()).await {
+                attempts += 1;
+                assert!(attempts < 50, "could not reach host: {}", e);
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+
+            let share_index = manager.get_shares()[0].index;
+            Self {
+                manager,
+                faults,
+                share_index,
+                host,
+                _state: state,
+            }
+        }
+
+        async fn data_inode(&self) -> GlobalInode {
+            let root = GlobalInode::new(self.share_index, ROOT_INODE);
+            let attr = self.manager.lookup(root, "data.bin").await.unwrap();
+            GlobalInode::from_packed(attr.inode)
+        }
+
+        fn status(&self) -> ConnectionStatus {
+            self.manager.get_host(HOST).unwrap().status
+        }
+    }
+
+    impl Drop for Scenario {
+        fn drop(&mut self) {
+            self.host.abort();
+        }
+    }
+
+    fn test_data() -> Vec<u8> {
+        (0..64 * 1024).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn fast_reconnect() -> ReconnectConfig {
+        ReconnectConfig {
+            enabled: true,
+            initial_delay: Duration::from_millis(50),
+            max_delay: Duration::from_millis(200),
+            max_attempts: 0,
+        }
+    }
+
+    async fn next_event(events: &mut broadcast::Receiver<ConnectionEvent>) -> ConnectionEvent {
+        tokio::time::timeout(Duration::from_secs(10), events.recv())
+            .await
+            .expect("no connection event")
+            .unwrap()
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_read_through_lossy_link() {
+        let scenario = Scenario::start(fast_reconnect()).await;
+        let inode = scenario.data_inode().await;
+
+        scenario.faults.set(FaultConfig {
+            drop_rate: 0.1,
+            delay: Duration::from_millis(5),
+            reorder: true,
+            partitioned: false,
+        });
+        let data = scenario.manager.read(inode, 0, 64 * 1024).await.unwrap();
+        assert_eq!(data, test_data());
+        assert!(scenario.faults.dropped() > 0);
+        assert_eq!(scenario.status(), ConnectionStatus::Connected);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_partition_reconnects_with_events() {
+        let scenario = Scenario::start(fast_reconnect()).await;
+        let inode = scenario.data_inode().await;
+        let mut events = scenario.manager.subscribe();
+        let _health = scenario.manager.start_health_checks();
+
+        let _schedule = scenario.faults.run_schedule(vec![
+            (Duration::ZERO, FaultConfig::partition()),
+            (Duration::from_secs(1), FaultConfig::default()),
+        ]);
+
+        assert!(matches!(
+            next_event(&mut events).await,
+            ConnectionEvent::HealthChanged { healthy: false, .. }
+        ));
+        assert!(matches!(
+            next_event(&mut events).await,
+            ConnectionEvent::HostDisconnected { .. }
+        ));
+        assert!(matches!(
+            next_event(&mut events).await,
+            ConnectionEvent::HostReconnecting { attempt: 1, .. }
+        ));
+        let connected = loop {
+            match next_event(&mut events).await {
+                ConnectionEvent::HostReconnecting { .. } => continue,
+                event => break event,
+            }
+        };
+        assert!(matches!(connected, ConnectionEvent::HostConnected { .. }));
+        assert!(matches!(
+            next_event(&mut events).await,
+            ConnectionEvent::HealthChanged { healthy: true, .. }
+        ));
+
+        // The share keeps its index, so inodes from before still work
+        assert_eq!(scenario.manager.get_shares().len(), 1);
+        let data = scenario.manager.read(inode, 0, 16).await.unwrap();
+        assert_eq!(data, test_data()[..16]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_read_retries_across_partition() {
+        let scenario = Scenario::start(fast_reconnect()).await;
+        let inode = scenario.data_inode().await;
+
+        // Longer than the request timeout, so the first attempt fails
+        scenario.faults.partition();
+        let _schedule = scenario
+            .faults
+            .run_schedule(vec![(Duration::from_millis(500), FaultConfig::default())]);
+
+        let data = scenario.manager.read(inode, 100, 16).await.unwrap();
+        assert_eq!(data, test_data()[100..116]);
+        assert_eq!(scenario.status(), ConnectionStatus::Connected);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_reconnect_gives_up_after_max_attempts() {
+        let scenario = Scenario::start(ReconnectConfig {
+            max_attempts: 2,
+            ..fast_reconnect()
+        })
+        .await;
+        let inode = scenario.data_inode().await;
+
+        scenario.faults.partition();
+        assert!(scenario.manager.read(inode, 0, 16).await.is_err());
+        assert_eq!(scenario.status(), ConnectionStatus::Failed);
+    }
 
     #[test]
     fn test_reconnect_config_default() {
@@ -0,0 +1,761 @@
+//! LAN discovery via mDNS / DNS-SD
+//!
+//! Hosts started with `--announce-local` advertise a `_wormhole._udp.local`
+//! service on the mDNS multicast group. Each announcement carries:
+//! - PTR/SRV records pointing at the QUIC port
+//! - A records for the host's local IPv4 addresses
+//! - TXT keys: `share`, `host`, `fp` (cert fingerprint) and `jc` (salted join
+//!   code hash)
+//!
+//! Clients browse with a one-shot query from an ephemeral port, so responders
+//! reply unicast and nothing needs to bind 5353 on the client side. A client
+//! that already knows a join code can match it against `jc` and connect to the
+//! LAN address directly without asking the signal server. Nothing in an
+//! announcement is authenticated, so `fp` is for display: clients verify the
+//! host they find as they would any other (a pinned link or known peers).
+//!
+//! Only the small subset of DNS needed for this is implemented here.
+
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket as StdUdpSocket};
+use std::time::Duration;
+
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::UdpSocket;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tracing::{debug, info, warn};
+
+use teleport_core::crypto::{join_code_lan_hash, LAN_HASH_SIZE, LAN_SALT_SIZE};
+
+use crate::net::CertFingerprint;
+
+/// DNS-SD service type advertised by hosts
+pub const SERVICE_TYPE: &str = "_wormhole._udp.local";
+
+/// mDNS multicast group
+pub const MDNS_ADDR: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+
+/// mDNS port
+pub const MDNS_PORT: u16 = 5353;
+
+/// Default time to wait for responses when browsing
+pub const DEFAULT_BROWSE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// TTL for announced records (seconds)
+const RECORD_TTL: u32 = 120;
+
+/// Interval between unsolicited announcements
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Maximum DNS label length
+const MAX_LABEL_LEN: usize = 63;
+
+/// Maximum mDNS packet we will read
+const MAX_PACKET_SIZE: usize = 9000;
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+const TYPE_ANY: u16 = 255;
+
+const CLASS_IN: u16 = 1;
+/// Cache-flush bit, set on records unique to this host (RFC 6762 §10.2)
+const CLASS_CACHE_FLUSH: u16 = 0x8000;
+
+const FLAGS_RESPONSE: u16 = 0x8400;
+
+/// What a host advertises on the local network
+#[derive(Debug, Clone)]
+pub struct LanAnnouncement {
+    /// Machine host name
+    pub host_name: String,
+    /// Name of the shared folder
+    pub share_name: String,
+    /// QUIC port the host listens on
+    pub port: u16,
+    /// Fingerprint of the host's TLS certificate
+    pub cert_fingerprint: Option<CertFingerprint>,
+    /// Salted hash of the join code
+    pub join_code_hint: Option<JoinCodeHint>,
+}
+
+impl LanAnnouncement {
+    /// DNS-SD instance label for this announcement
+    pub fn instance_label(&self) -> String {
+        sanitize_label(&format!("{} on {}", self.share_name, self.host_name))
+    }
+
+    fn target_name(&self) -> String {
+        format!(
+            "{}.local",
+            sanitize_label(&self.host_name).replace(' ', "-")
+        )
+    }
+}
+
+/// A share found while browsing the local network
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveredShare {
+    /// DNS-SD instance label
+    pub instance: String,
+    /// Machine host name
+    pub host_name: String,
+    /// Name of the shared folder
+    pub share_name: String,
+    /// Address to connect to
+    pub addr: SocketAddr,
+    /// Fingerprint the host claims for its TLS certificate, if advertised
+    ///
+    /// Unauthenticated: anyone on the network can announce any fingerprint.
+    pub cert_fingerprint: Option<CertFingerprint>,
+    /// Salted hash of the host's join code, if advertised
+    pub join_code_hint: Option<JoinCodeHint>,
+}
+
+impl DiscoveredShare {
+    /// Check whether this share may have been announced with the given join code
+    pub fn matches_join_code(&self, join_code: &str) -> bool {
+        self.join_code_hint
+            .is_some_and(|hint| hint.matches(join_code))
+    }
+}
+
+/// Join code hash advertised as `jc` (see `join_code_lan_hash`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JoinCodeHint {
+    pub salt: [u8; LAN_SALT_SIZE],
+    pub hash: [u8; LAN_HASH_SIZE],
+}
+
+impl JoinCodeHint {
+    /// Hash `join_code` with a fresh random salt
+    pub fn new(join_code: &str) -> Self {
+        let mut salt = [0u8; LAN_SALT_SIZE];
+        getrandom::getrandom(&mut salt).expect("RNG failed - system entropy source unavailable");
+        Self {
+            salt,
+            hash: join_code_lan_hash(join_code, &salt),
+        }
+    }
+
+    /// Whether `join_code` hashes to this hint; other codes can too
+    pub fn matches(&self, join_code: &str) -> bool {
+        join_code_lan_hash(join_code, &self.salt) == self.hash
+    }
+
+    fn encode(&self) -> String {
+        format!("{}{}", hex::encode(self.salt), hex::encode(self.hash))
+    }
+
+    fn decode(value: &str) -> Option<Self> {
+        let bytes: [u8; LAN_SALT_SIZE + LAN_HASH_SIZE] = decode_hex_array(value)?;
+        let (salt, hash) = bytes.split_at(LAN_SALT_SIZE);
+        Some(Self {
+            salt: salt.try_into().ok()?,
+            hash: hash.try_into().ok()?,
+        })
+    }
+}
+
+/// Advertises a share on the local network
+pub struct LanAnnouncer {
+    announcement: LanAnnouncement,
+    addrs: Vec<Ipv4Addr>,
+}
+
+impl LanAnnouncer {
+    /// Create an announcer using the detected local addresses
+    pub fn new(announcement: LanAnnouncement) -> Self {
+        let addrs = crate::rendezvous::detect_local_addresses()
+            .into_iter()
+            .filter_map(|addr| match addr.ip() {
+                IpAddr::V4(ip) if !ip.is_unspecified() && !ip.is_loopback() => Some(ip),
+                _ => None,
+            })
+            .collect();
+
+        Self {
+            announcement,
+            addrs,
+        }
+    }
+
+    /// Bind the mDNS socket and start answering queries
+    ///
+    /// The returned handle stops the announcer and sends a goodbye packet
+    /// when dropped.
+    pub fn spawn(self) -> io::Result<LanAnnouncerHandle> {
+        let socket = UdpSocket::from_std(bind_mdns_socket()?)?;
+        let response = build_response(&self.announcement, &self.addrs, RECORD_TTL);
+        let goodbye = build_response(&self.announcement, &self.addrs, 0);
+
+        info!(
+            "Announcing '{}' on the local network ({} port {})",
+            self.announcement.instance_label(),
+            SERVICE_TYPE,
+            self.announcement.port
+        );
+
+        let task = tokio::spawn(async move {
+            let group = SocketAddr::V4(SocketAddrV4::new(MDNS_ADDR, MDNS_PORT));
+            let mut announce = tokio::time::interval(ANNOUNCE_INTERVAL);
+            let mut buf = vec![0u8; MAX_PACKET_SIZE];
+
+            loop {
+                tokio::select! {
+                    _ = announce.tick() => {
+                        if let Err(e) = socket.send_to(&response, group).await {
+                            debug!("mDNS announcement failed: {}", e);
+                        }
+                    }
+                    received = socket.recv_from(&mut buf) => {
+                        let (len, src) = match received {
+                            Ok(r) => r,
+                            Err(e) => {
+                                debug!("mDNS receive error: {}", e);
+                                continue;
+                            }
+                        };
+                        if !is_service_query(&buf[..len]) {
+                            continue;
+                        }
+                        // One-shot queries from ephemeral ports get a unicast reply
+                        let dest = if src.port() == MDNS_PORT { group } else { src };
+                        debug!("Answering mDNS query from {}", src);
+                        if let Err(e) = socket.send_to(&response, dest).await {
+                            debug!("mDNS response to {} failed: {}", dest, e);
+                        }
+                    }
+                }
+            }
+        });
+
+        Ok(LanAnnouncerHandle { task, goodbye })
+    }
+}
+
+/// Running announcer; stops announcing when dropped
+pub struct LanAnnouncerHandle {
+    task: JoinHandle<()>,
+    goodbye: Vec<u8>,
+}
+
+impl Drop for LanAnnouncerHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+        // Best effort: tell caches to forget us (TTL 0 records)
+        if let Ok(socket) = StdUdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)) {
+            let _ = socket.send_to(&self.goodbye, (MDNS_ADDR, MDNS_PORT));
+        }
+    }
+}
+
+/// Browse the local network for announced shares
+///
+/// Sends a single query and collects responses until `timeout` elapses.
+pub async fn browse(timeout: Duration) -> io::Result<Vec<DiscoveredShare>> {
+    let mut found: HashMap<String, DiscoveredShare> = HashMap::new();
+    collect_responses(timeout, |share| {
+        found.insert(share.instance.clone(), share);
+        false
+    })
+    .await?;
+
+    let mut shares: Vec<DiscoveredShare> = found.into_values().collect();
+    shares.sort_by(|a, b| a.instance.cmp(&b.instance));
+    Ok(shares)
+}
+
+/// Find a LAN host announcing the given join code
+///
+/// Returns as soon as a matching announcement arrives.
+pub async fn find_by_join_code(
+    join_code: &str,
+    timeout: Duration,
+) -> io::Result<Option<DiscoveredShare>> {
+    let mut matched = None;
+    collect_responses(timeout, |share| {
+        if share.matches_join_code(join_code) {
+            matched = Some(share);
+            true
+        } else {
+            false
+        }
+    })
+    .await?;
+    Ok(matched)
+}
+
+/// Send a query and feed parsed responses to `on_share` until it returns
+/// true or the timeout elapses
+async fn collect_responses(
+    timeout: Duration,
+    mut on_share: impl FnMut(DiscoveredShare) -> bool,
+) -> io::Result<()> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    socket.set_multicast_loop_v4(true)?;
+    socket
+        .send_to(&build_query(), (MDNS_ADDR, MDNS_PORT))
+        .await?;
+
+    let deadline = Instant::now() + timeout;
+    let mut buf = vec![0u8; MAX_PACKET_SIZE];
+
+    loop {
+        let received = match tokio::time::timeout_at(deadline, socket.recv_from(&mut buf)).await {
+            Ok(received) => received,
+            Err(_) => return Ok(()),
+        };
+        let (len, src) = match received {
+            Ok(r) => r,
+            Err(e) => {
+                warn!("mDNS browse receive error: {}", e);
+                continue;
+            }
+        };
+        if let Some(share) = parse_announcement(&buf[..len], src) {
+            debug!(
+                "Discovered LAN share '{}' at {}",
+                share.instance, share.addr
+            );
+            if on_share(share) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Bind a socket to the mDNS port, shared with any other responders
+fn bind_mdns_socket() -> io::Result<StdUdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+    socket.set_reuse_port(true)?;
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, MDNS_PORT)).into())?;
+    socket.join_multicast_v4(&MDNS_ADDR, &Ipv4Addr::UNSPECIFIED)?;
+    socket.set_multicast_loop_v4(true)?;
+    socket.set_nonblocking(true)?;
+    Ok(socket.into())
+}
+
+/// Make a string usable as a single DNS label
+fn sanitize_label(s: &str) -> String {
+    let mut label: String = s
+        .chars()
+        .map(|c| if c == '.' || c.is_control() { '-' } else { c })
+        .collect();
+    if label.len() > MAX_LABEL_LEN {
+        let mut end = MAX_LABEL_LEN;
+        while !label.is_char_boundary(end) {
+            end -= 1;
+        }
+        label.truncate(end);
+    }
+    if label.is_empty() {
+        label.push_str("wormhole");
+    }
+    label
+}
+
+// ============================================================================
+// Packet encoding
+// ============================================================================
+
+fn write_header(buf: &mut Vec<u8>, flags: u16, questions: u16, answers: u16) {
+    buf.extend_from_slice(&0u16.to_be_bytes()); // id
+    buf.extend_from_slice(&flags.to_be_bytes());
+    buf.extend_from_slice(&questions.to_be_bytes());
+    buf.extend_from_slice(&answers.to_be_bytes());
+    buf.extend_from_slice(&0u16.to_be_bytes()); // authority
+    buf.extend_from_slice(&0u16.to_be_bytes()); // additional
+}
+
+/// Write a dotted name as uncompressed labels
+fn write_name(buf: &mut Vec<u8>, name: &str) {
+    write_labels(buf, name.split('.'));
+}
+
+fn write_labels<'a>(buf: &mut Vec<u8>, labels: impl IntoIterator<Item = &'a str>) {
+    for label in labels {
+        let bytes = label.as_bytes();
+        let len = bytes.len().min(MAX_LABEL_LEN);
+        buf.push(len as u8);
+        buf.extend_from_slice(&bytes[..len]);
+    }
+    buf.push(0);
+}
+
+fn write_instance_name(buf: &mut Vec<u8>, announcement: &LanAnnouncement) {
+    let label = announcement.instance_label();
+    write_labels(
+        buf,
+        std::iter::once(label.as_str()).chain(SERVICE_TYPE.split('.')),
+    );
+}
+
+fn write_record(buf: &mut Vec<u8>, name: &[u8], rtype: u16, class: u16, ttl: u32, rdata: &[u8]) {
+    buf.extend_from_slice(name);
+    buf.extend_from_slice(&rtype.to_be_bytes());
+    buf.extend_from_slice(&class.to_be_bytes());
+    buf.extend_from_slice(&ttl.to_be_bytes());
+    buf.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+    buf.extend_from_slice(rdata);
+}
+
+/// Build a PTR query for the wormhole service
+fn build_query() -> Vec<u8> {
+    let mut buf = Vec::with_capacity(64);
+    write_header(&mut buf, 0, 1, 0);
+    write_name(&mut buf, SERVICE_TYPE);
+    buf.extend_from_slice(&TYPE_PTR.to_be_bytes());
+    buf.extend_from_slice(&CLASS_IN.to_be_bytes());
+    buf
+}
+
+/// Build a response advertising `announcement`
+fn build_response(announcement: &LanAnnouncement, addrs: &[Ipv4Addr], ttl: u32) -> Vec<u8> {
+    let mut service = Vec::new();
+    write_name(&mut service, SERVICE_TYPE);
+    let mut instance = Vec::new();
+    write_instance_name(&mut instance, announcement);
+    let mut target = Vec::new();
+    write_name(&mut target, &announcement.target_name());
+
+    let answers = 3 + addrs.len();
+    let mut buf = Vec::with_capacity(512);
+    write_header(&mut buf, FLAGS_RESPONSE, 0, answers as u16);
+
+    // PTR: service -> instance
+    write_record(&mut buf, &service, TYPE_PTR, CLASS_IN, ttl, &instance);
+
+    // SRV: priority, weight, port, target
+    let mut srv = Vec::with_capacity(6 + target.len());
+    srv.extend_from_slice(&0u16.to_be_bytes());
+    srv.extend_from_slice(&0u16.to_be_bytes());
+    srv.extend_from_slice(&announcement.port.to_be_bytes());
+    srv.extend_from_slice(&target);
+    let unique = CLASS_IN | CLASS_CACHE_FLUSH;
+    write_record(&mut buf, &instance, TYPE_SRV, unique, ttl, &srv);
+
+    // TXT: key=value strings
+    let mut txt = Vec::new();
+    let mut entries = vec![
+        "v=1".to_string(),
+        format!("share={}", announcement.share_name),
+        format!("host={}", announcement.host_name),
+    ];
+    if let Some(fp) = announcement.cert_fingerprint {
+        entries.push(format!("fp={}", hex::encode(fp)));
+    }
+    if let Some(hint) = announcement.join_code_hint {
+        entries.push(format!("jc={}", hint.encode()));
+    }
+    for entry in entries {
+        let bytes = entry.as_bytes();
+        let len = bytes.len().min(255);
+        txt.push(len as u8);
+        txt.extend_from_slice(&bytes[..len]);
+    }
+    write_record(&mut buf, &instance, TYPE_TXT, unique, ttl, &txt);
+
+    for addr in addrs {
+        write_record(&mut buf, &target, TYPE_A, unique, ttl, &addr.octets());
+    }
+
+    buf
+}
+
+// ============================================================================
+// Packet decoding
+// ============================================================================
+
+struct Record {
+    name: String,
+    rtype: u16,
+    ttl: u32,
+    /// Offset of rdata in the packet (names inside rdata may use compression)
+    rdata_offset: usize,
+    rdata_len: usize,
+}
+
+fn read_u16(packet: &[u8], offset: usize) -> Option<u16> {
+    let bytes = packet.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(packet: &[u8], offset: usize) -> Option<u32> {
+    let bytes = packet.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Read a (possibly compressed) name, returning it and the offset after it
+fn read_name(packet: &[u8], mut offset: usize) -> Option<(String, usize)> {
+    let mut labels: Vec<String> = Vec::new();
+    let mut end = None;
+    // Bound pointer chasing so malicious loops terminate
+    for _ in 0..128 {
+        let len = *packet.get(offset)? as usize;
+        if len == 0 {
+            return Some((labels.join("."), end.unwrap_or(offset + 1)));
+        }
+        if len & 0xC0 == 0xC0 {
+            let pointer = (read_u16(packet, offset)? & 0x3FFF) as usize;
+            end.get_or_insert(offset + 2);
+            offset = pointer;
+            continue;
+        }
+        let label = packet.get(offset + 1..offset + 1 + len)?;
+        labels.push(String::from_utf8_lossy(label).into_owned());
+        offset += 1 + len;
+    }
+    None
+}
+
+fn read_records(packet: &[u8], mut offset: usize, count: usize) -> Option<(Vec<Record>, usize)> {
+    let mut records = Vec::with_capacity(count);
+    for _ in 0..count {
+        let (name, next) = read_name(packet, offset)?;
+        let rtype = read_u16(packet, next)?;
+        let ttl = read_u32(packet, next + 4)?;
+        let rdata_len = read_u16(packet, next + 8)? as usize;
+        let rdata_offset = next + 10;
+        packet.get(rdata_offset..rdata_offset + rdata_len)?;
+        records.push(Record {
+            name,
+            rtype,
+            ttl,
+            rdata_offset,
+            rdata_len,
+        });
+        offset = rdata_offset + rdata_len;
+    }
+    Some((records, offset))
+}
+
+/// Check whether a packet is a query for the wormhole service
+fn is_service_query(packet: &[u8]) -> bool {
+    let Some(flags) = read_u16(packet, 2) else {
+        return false;
+    };
+    if flags & 0x8000 != 0 {
+        return false;
+    }
+    let questions = read_u16(packet, 4).unwrap_or(0);
+    let mut offset = 12;
+    for _ in 0..questions {
+        let Some((name, next)) = read_name(packet, offset) else {
+            return false;
+        };
+        let Some(qtype) = read_u16(packet, next) else {
+            return false;
+        };
+        if name.eq_ignore_ascii_case(SERVICE_TYPE) && (qtype == TYPE_PTR || qtype == TYPE_ANY) {
+            return true;
+        }
+        offset = next + 4;
+    }
+    false
+}
+
+/// Parse a response packet into a discovered share
+///
+/// `src` is used as the address when the response has no A record.
+fn parse_announcement(packet: &[u8], src: SocketAddr) -> Option<DiscoveredShare> {
+    let flags = read_u16(packet, 2)?;
+    if flags & 0x8000 == 0 {
+        return None;
+    }
+    let questions = read_u16(packet, 4)? as usize;
+    let total = read_u16(packet, 6)? as usize
+        + read_u16(packet, 8)? as usize
+        + read_u16(packet, 10)? as usize;
+
+    let mut offset = 12;
+    for _ in 0..questions {
+        let (_, next) = read_name(packet, offset)?;
+        offset = next + 4;
+    }
+    let (records, _) = read_records(packet, offset, total)?;
+
+    let instance = records.iter().find_map(|r| {
+        if r.rtype == TYPE_PTR && r.name.eq_ignore_ascii_case(SERVICE_TYPE) && r.ttl > 0 {
+            read_name(packet, r.rdata_offset).map(|(name, _)| name)
+        } else {
+            None
+        }
+    })?;
+
+    let (port, target) = records.iter().find_map(|r| {
+        if r.rtype == TYPE_SRV && r.name.eq_ignore_ascii_case(&instance) {
+            let port = read_u16(packet, r.rdata_offset + 4)?;
+            let (target, _) = read_name(packet, r.rdata_offset + 6)?;
+            Some((port, target))
+        } else {
+            None
+        }
+    })?;
+
+    let ip = records
+        .iter()
+        .find_map(|r| {
+            if r.rtype == TYPE_A && r.rdata_len == 4 && r.name.eq_ignore_ascii_case(&target) {
+                let b = packet.get(r.rdata_offset..r.rdata_offset + 4)?;
+                Some(IpAddr::V4(Ipv4Addr::new(b[0], b[1], b[2], b[3])))
+            } else {
+                None
+            }
+        })
+        .unwrap_or_else(|| src.ip());
+
+    let mut txt: HashMap<String, String> = HashMap::new();
+    if let Some(r) = records
+        .iter()
+        .find(|r| r.rtype == TYPE_TXT && r.name.eq_ignore_ascii_case(&instance))
+    {
+        let data = &packet[r.rdata_offset..r.rdata_offset + r.rdata_len];
+        let mut pos = 0;
+        while pos < data.len() {
+            let len = data[pos] as usize;
+            let Some(entry) = data.get(pos + 1..pos + 1 + len) else {
+                break;
+            };
+            let entry = String::from_utf8_lossy(entry);
+            if let Some((key, value)) = entry.split_once('=') {
+                txt.insert(key.to_string(), value.to_string());
+            }
+            pos += 1 + len;
+        }
+    }
+
+    let instance_label = instance
+        .strip_suffix(SERVICE_TYPE)
+        .and_then(|s| s.strip_suffix('.'))
+        .unwrap_or(&instance)
+        .to_string();
+
+    Some(DiscoveredShare {
+        share_name: txt
+            .get("share")
+            .cloned()
+            .unwrap_or_else(|| instance_label.clone()),
+        host_name: txt.get("host").cloned().unwrap_or_default(),
+        instance: instance_label,
+        addr: SocketAddr::new(ip, port),
+        cert_fingerprint: txt.get("fp").and_then(|v| decode_hex_array(v)),
+        join_code_hint: txt.get("jc").and_then(|v| JoinCodeHint::decode(v)),
+    })
+}
+
+fn decode_hex_array<const N: usize>(value: &str) -> Option<[u8; N]> {
+    hex::decode(value).ok()?.try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_announcement() -> LanAnnouncement {
+        LanAnnouncement {
+            host_name: "studio-mac".into(),
+            share_name: "Projects".into(),
+            port: 4433,
+            cert_fingerprint: Some([7u8; 32]),
+            join_code_hint: Some(JoinCodeHint {
+                salt: [9u8; LAN_SALT_SIZE],
+                hash: join_code_lan_hash("ABC-XYZ", &[9u8; LAN_SALT_SIZE]),
+            }),
+        }
+    }
+
+    fn src() -> SocketAddr {
+        "10.0.0.9:5353".parse().unwrap()
+    }
+
+    #[test]
+    fn test_response_roundtrip() {
+        let ann = sample_announcement();
+        let packet = build_response(&ann, &[Ipv4Addr::new(192, 168, 1, 20)], RECORD_TTL);
+
+        let share = parse_announcement(&packet, src()).unwrap();
+        assert_eq!(share.instance, "Projects on studio-mac");
+        assert_eq!(share.share_name, "Projects");
+        assert_eq!(share.host_name, "studio-mac");
+        assert_eq!(share.addr, "192.168.1.20:4433".parse().unwrap());
+        assert_eq!(share.cert_fingerprint, Some([7u8; 32]));
+        assert!(share.matches_join_code("abcxyz"));
+        assert!(!share.matches_join_code("ABC-XYQ"));
+        assert_eq!(share.join_code_hint, ann.join_code_hint);
+    }
+
+    #[test]
+    fn test_join_code_hints_are_salted() {
+        let a = JoinCodeHint::new("ABC-XYZ");
+        let b = JoinCodeHint::new("ABC-XYZ");
+        assert_ne!(a.salt, b.salt);
+        assert!(a.matches("abcxyz") && b.matches("abcxyz"));
+        assert_eq!(JoinCodeHint::decode(&a.encode()), Some(a));
+        assert_eq!(JoinCodeHint::decode("00ff"), None);
+    }
+
+    #[test]
+    fn test_response_without_address_uses_source() {
+        let ann = sample_announcement();
+        let packet = build_response(&ann, &[], RECORD_TTL);
+
+        let share = parse_announcement(&packet, src()).unwrap();
+        assert_eq!(share.addr, "10.0.0.9:4433".parse().unwrap());
+    }
+
+    #[test]
+    fn test_goodbye_is_ignored() {
+        let packet = build_response(&sample_announcement(), &[], 0);
+        assert!(parse_announcement(&packet, src()).is_none());
+    }
+
+    #[test]
+    fn test_query_detection() {
+        let query = build_query();
+        assert!(is_service_query(&query));
+        assert!(parse_announcement(&query, src()).is_none());
+
+        let response = build_response(&sample_announcement(), &[], RECORD_TTL);
+        assert!(!is_service_query(&response));
+        assert!(!is_service_query(&[0u8; 3]));
+    }
+
+    #[test]
+    fn test_compressed_names() {
+        // "a.local" at offset 12, then a pointer back to it
+        let mut packet = vec![0u8; 12];
+        packet.extend_from_slice(&[1, b'a', 5, b'l', b'o', b'c', b'a', b'l', 0]);
+        let pointer_at = packet.len();
+        packet.extend_from_slice(&[1, b'b', 0xC0, 12]);
+
+        let (name, next) = read_name(&packet, pointer_at).unwrap();
+        assert_eq!(name, "b.a.local");
+        assert_eq!(next, packet.len());
+
+        // Self-referencing pointer must not loop forever
+        let looped = [0xC0, 0x00];
+        assert!(read_name(&looped, 0).is_none());
+    }
+
+    #[test]
+    fn test_truncated_packets() {
+        let packet = build_response(&sample_announcement(), &[], RECORD_TTL);
+        for len in 0..packet.len() {
+            // Must never panic on partial input
+            let _ = parse_announcement(&packet[..len], src());
+        }
+    }
+
+    #[test]
+    fn test_sanitize_label() {
+        assert_eq!(sanitize_label("my.share"), "my-share");
+        assert_eq!(sanitize_label(""), "wormhole");
+        assert!(sanitize_label(&"é".repeat(100)).len() <= MAX_LABEL_LEN);
+    }
+}
@@ -0,0 +1,343 @@
+//! Fault injection for QUIC endpoints
+//!
+//! `FaultInjector::wrap` moves an endpoint onto a UDP socket that drops,
+//! delays, reorders or blackholes datagrams according to the injector's
+//! current `FaultConfig`. The config can be changed at any time, or driven
+//! from a schedule with `run_schedule`, so tests can take a link down and
+//! bring it back while requests are in flight.
+//!
+//! Drops and delays apply to outgoing datagrams; a partition also discards
+//! incoming ones. Random drops come from a seeded generator, so a scenario
+//! sees the same drop pattern for the same sequence of datagrams.
+
+use std::fmt;
+use std::io::{self, IoSliceMut};
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use quinn::udp::{EcnCodepoint, RecvMeta, Transmit};
+use quinn::{AsyncUdpSocket, Endpoint, Runtime, TokioRuntime, UdpPoller};
+
+/// How long a held-back datagram waits for a successor before it is sent anyway
+const REORDER_WINDOW: Duration = Duration::from_millis(10);
+
+/// What the link does to datagrams
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FaultConfig {
+    /// Fraction of outgoing datagrams to drop (0.0 - 1.0)
+    pub drop_rate: f64,
+    /// Extra latency added to every outgoing datagram
+    pub delay: Duration,
+    /// Swap each pair of consecutive outgoing datagrams
+    pub reorder: bool,
+    /// Drop everything in both directions
+    pub partitioned: bool,
+}
+
+impl FaultConfig {
+    /// A link that passes nothing
+    pub fn partition() -> Self {
+        Self {
+            partitioned: true,
+            ..Self::default()
+        }
+    }
+}
+
+/// A datagram copied out of a `Transmit` so it can be sent later
+#[derive(Debug)]
+struct Datagram {
+    destination: SocketAddr,
+    ecn: Option<EcnCodepoint>,
+    src_ip: Option<IpAddr>,
+    contents: Vec<u8>,
+}
+
+impl Datagram {
+    fn new(transmit: &Transmit<'_>) -> Self {
+        Self {
+            destination: transmit.destination,
+            ecn: transmit.ecn,
+            src_ip: transmit.src_ip,
+            contents: transmit.contents.to_vec(),
+        }
+    }
+
+    fn send(&self, socket: &dyn AsyncUdpSocket) {
+        // Lost sends look like lost packets, which QUIC recovers from
+        let _ = socket.try_send(&Transmit {
+            destination: self.destination,
+            ecn: self.ecn,
+            contents: &self.contents,
+            segment_size: None,
+            src_ip: self.src_ip,
+        });
+    }
+}
+
+struct FaultState {
+    config: FaultConfig,
+    rng: u64,
+}
+
+impl FaultState {
+    /// Next value of the xorshift generator, in [0, 1)
+    fn next_random(&mut self) -> f64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Handle controlling the faults on every endpoint it has wrapped
+#[derive(Clone)]
+pub struct FaultInjector {
+    state: Arc<Mutex<FaultState>>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl FaultInjector {
+    /// Create an injector with a clean link; `seed` fixes the drop pattern
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(FaultState {
+                config: FaultConfig::default(),
+                // xorshift gets stuck at zero
+                rng: seed | 1,
+            })),
+            dropped: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Replace the current faults
+    pub fn set(&self, config: FaultConfig) {
+        self.state.lock().config = config;
+    }
+
+    /// Current faults
+    pub fn config(&self) -> FaultConfig {
+        self.state.lock().config.clone()
+    }
+
+    /// Cut the link
+    pub fn partition(&self) {
+        self.set(FaultConfig::partition());
+    }
+
+    /// Remove all faults
+    pub fn heal(&self) {
+        self.set(FaultConfig::default());
+    }
+
+    /// Number of datagrams dropped so far
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Apply each config once its offset from now has passed
+    ///
+    /// Offsets are measured from the call, not from the previous step.
+    pub fn run_schedule(
+        &self,
+        schedule: Vec<(Duration, FaultConfig)>,
+    ) -> tokio::task::JoinHandle<()> {
+        let injector = self.clone();
+        let start = tokio::time::Instant::now();
+        tokio::spawn(async move {
+            for (offset, config) in schedule {
+                tokio::time::sleep_until(start + offset).await;
+                injector.set(config);
+            }
+        })
+    }
+
+    /// Move `endpoint` onto a fresh socket subject to this injector's faults
+    ///
+    /// The new socket is bound to an ephemeral port on the unspecified
+    /// address of the endpoint's current family.
+    pub fn wrap(&self, endpoint: &Endpoint) -> io::Result<()> {
+        let bind_addr: SocketAddr = match endpoint.local_addr()? {
+            SocketAddr::V4(_) => "0.0.0.0:0".parse().unwrap(),
+            SocketAddr::V6(_) => "[::]:0".parse().unwrap(),
+        };
+        let socket = std::net::UdpSocket::bind(bind_addr)?;
+        let inner = TokioRuntime.wrap_udp_socket(socket)?;
+        endpoint.rebind_abstract(Arc::new(FaultySocket {
+            inner,
+            faults: self.clone(),
+            held: Arc::new(Mutex::new(None)),
+        }))
+    }
+
+    fn drop_datagram(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl fmt::Debug for FaultInjector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FaultInjector")
+            .field("config", &self.config())
+            .field("dropped", &self.dropped())
+            .finish()
+    }
+}
+
+/// UDP socket that passes datagrams through a `FaultInjector`
+#[derive(Debug)]
+struct FaultySocket {
+    inner: Arc<dyn AsyncUdpSocket>,
+    faults: FaultInjector,
+    /// Datagram held back for reordering, with the hold's sequence number
+    held: Arc<Mutex<Option<(u64, Datagram)>>>,
+}
+
+impl FaultySocket {
+    /// Hold `datagram` back, sending it on its own if nothing follows soon
+    fn hold(&self, slot: &mut Option<(u64, Datagram)>, datagram: Datagram) {
+        static HOLDS: AtomicU64 = AtomicU64::new(0);
+        let sequence = HOLDS.fetch_add(1, Ordering::Relaxed);
+        *slot = Some((sequence, datagram));
+
+        let held = self.held.clone();
+        let inner = self.inner.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(REORDER_WINDOW).await;
+            let datagram = {
+                let mut held = held.lock();
+                match &*held {
+                    Some((held_sequence, _)) if *held_sequence == sequence => held.take(),
+                    _ => None,
+                }
+            };
+            if let Some((_, datagram)) = datagram {
+                datagram.send(inner.as_ref());
+            }
+        });
+    }
+}
+
+impl AsyncUdpSocket for FaultySocket {
+    fn create_io_poller(self: Arc<Self>) -> Pin<Box<dyn UdpPoller>> {
+        self.inner.clone().create_io_poller()
+    }
+
+    fn try_send(&self, transmit: &Transmit) -> io::Result<()> {
+        let config = {
+            let mut state = self.faults.state.lock();
+            let config = state.config.clone();
+            if config.partitioned
+                || (config.drop_rate > 0.0 && state.next_random() < config.drop_rate)
+            {
+                drop(state);
+                self.faults.drop_datagram();
+                return Ok(());
+            }
+            config
+        };
+        let mut held = self.held.lock();
+
+        // A datagram that overtakes the held one is sent before it
+        let mut outgoing = Vec::with_capacity(2);
+        if config.reorder {
+            match held.take() {
+                Some((_, held)) => {
+                    outgoing.push(Datagram::new(transmit));
+                    outgoing.push(held);
+                }
+                None => {
+                    self.hold(&mut held, Datagram::new(transmit));
+                    return Ok(());
+                }
+            }
+        } else {
+            outgoing.extend(held.take().map(|(_, datagram)| datagram));
+            outgoing.push(Datagram::new(transmit));
+        }
+        drop(held);
+
+        if config.delay.is_zero() {
+            for datagram in &outgoing {
+                datagram.send(self.inner.as_ref());
+            }
+        } else {
+            let inner = self.inner.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(config.delay).await;
+                for datagram in &outgoing {
+                    datagram.send(inner.as_ref());
+                }
+            });
+        }
+        Ok(())
+    }
+
+    fn poll_recv(
+        &self,
+        cx: &mut Context,
+        bufs: &mut [IoSliceMut<'_>],
+        meta: &mut [RecvMeta],
+    ) -> Poll<io::Result<usize>> {
+        loop {
+            match self.inner.poll_recv(cx, bufs, meta) {
+                Poll::Ready(Ok(count)) if self.faults.state.lock().config.partitioned => {
+                    for _ in 0..count {
+                        self.faults.drop_datagram();
+                    }
+                }
+                other => return other,
+            }
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    fn max_receive_segments(&self) -> usize {
+        self.inner.max_receive_segments()
+    }
+
+    fn may_fragment(&self) -> bool {
+        self.inner.may_fragment()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drop_pattern_is_seeded() {
+        let sample = |seed| {
+            let injector = FaultInjector::new(seed);
+            let mut state = injector.state.lock();
+            (0..64)
+                .map(|_| state.next_random() < 0.5)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(sample(7), sample(7));
+        assert_ne!(sample(7), sample(8));
+        assert!(sample(7).iter().any(|&dropped| dropped));
+        assert!(sample(7).iter().any(|&dropped| !dropped));
+    }
+
+    #[tokio::test]
+    async fn test_schedule_applies_configs() {
+        let injector = FaultInjector::new(1);
+        let schedule = injector.run_schedule(vec![
+            (Duration::ZERO, FaultConfig::partition()),
+            (Duration::from_millis(20), FaultConfig::default()),
+        ]);
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        assert!(injector.config().partitioned);
+        schedule.await.unwrap();
+        assert_eq!(injector.config(), FaultConfig::default());
+    }
+}
@@ -0,0 +1,854 @@
+//! Chunk reads and writes straight to files on the host's disk
+//!
+//! `dispatch` calls the backend on the async runtime, which suits metadata
+//! requests but not file contents: every chunk would open the file again and
+//! block a runtime thread on disk I/O. For backends whose files are on the
+//! local disk, `FileHandles` keeps a session's files open between requests and
+//! reads and writes them through the platform's `AsyncIO`, into buffers from a
+//! pool shared by the whole host.
+//!
+//! An open file is used for as long as the backend maps its inode to the path
+//! it was opened at. Before every write, at most a second apart for reads, and
+//! whenever that mapping changes, it is checked to still be the file at that
+//! path, so files replaced on the host (e.g. by an editor saving through a
+//! rename, or another session committing a write) are reopened and checked
+//! again. Writes never land in a file that is no longer linked.
+//!
+//! Writes made in a write session (see `write_session`) go to the session's
+//! staged file instead, and are only synced once, when it is committed.
+//! Copies between files run on blocking threads, as they may take a while.
+
+use std::fs::{self, File};
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use lru::LruCache;
+use parking_lot::Mutex;
+use tracing::{info, warn};
+
+use teleport_core::buffer_pool::DEFAULT_MAX_RANDOM_BUFFERS;
+use teleport_core::io::copy_range;
+use teleport_core::{
+    platform_io_for, AbortWriteRequest, AbortWriteResponse, AsyncIO, BeginWriteRequest,
+    BeginWriteResponse, BufferPool, ChunkId, CommitWriteRequest, CommitWriteResponse,
+    CopyRangeRequest, ErrorCode, FileAttr, Inode, LockToken, LockType, NetMessage,
+    TreeSnapshotRequest, WriteChunkRequest, CHUNK_SIZE,
+};
+
+use crate::lock_manager::LockManager;
+use crate::share_backend::{
+    backend_error, check_copy, check_write, copy_response, dispatch, io_error, read_response,
+    write_response, BackendResult, ChunkData, ShareBackend,
+};
+use crate::tree_snapshot::TreeWalks;
+use crate::write_session::{WriteSession, WriteSessions};
+
+/// Files each session keeps open
+pub const MAX_OPEN_FILES: usize = 64;
+
+/// How long an open file is read before checking it is still the one at its path
+const REVALIDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Platform I/O and read buffers shared by all sessions of a host
+#[derive(Clone)]
+pub struct ChunkIo {
+    io: Arc<dyn AsyncIO>,
+    buffers: Arc<BufferPool>,
+}
+
+impl ChunkIo {
+    /// The platform's best I/O, with up to `DEFAULT_MAX_RANDOM_BUFFERS` reads in flight
+    pub fn new() -> Self {
+        Self::with_buffers(BufferPool::new(DEFAULT_MAX_RANDOM_BUFFERS, CHUNK_SIZE))
+    }
+
+    /// Read into buffers from `buffers`, which must hold at least a chunk each
+    pub fn with_buffers(buffers: Arc<BufferPool>) -> Self {
+        Self {
+            io: Arc::from(platform_io_for(&buffers)),
+            buffers,
+        }
+    }
+
+    /// Pool reads are buffered in
+    pub fn buffers(&self) -> &Arc<BufferPool> {
+        &self.buffers
+    }
+}
+
+impl Default for ChunkIo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A file kept open between requests
+#[derive(Clone)]
+struct OpenFile {
+    file: Arc<File>,
+    /// Where it was opened
+    path: PathBuf,
+    writable: bool,
+    /// Device and inode number, where the platform has them
+    identity: Option<(u64, u64)>,
+    /// When it was last known to be the file at `path`
+    checked: Instant,
+}
+
+/// A session's open files, serving its chunk reads and writes
+pub struct FileHandles {
+    backend: Arc<dyn ShareBackend>,
+    chunk_io: ChunkIo,
+    files: Mutex<LruCache<Inode, OpenFile>>,
+    /// Discarded with the session unless committed
+    writes: WriteSessions,
+    /// Unfinished tree snapshots, continued by cursor
+    walks: TreeWalks,
+}
+
+impl FileHandles {
+    pub fn new(backend: Arc<dyn ShareBackend>, chunk_io: ChunkIo) -> Self {
+        let capacity = NonZeroUsize::new(MAX_OPEN_FILES).expect("MAX_OPEN_FILES is not zero");
+        Self {
+            backend,
+            chunk_io,
+            files: Mutex::new(LruCache::new(capacity)),
+            writes: WriteSessions::new(),
+            walks: TreeWalks::new(),
+        }
+    }
+
+    /// Number of files held open
+    pub fn open_files(&self) -> usize {
+        self.files.lock().len()
+    }
+
+    /// Number of write sessions in progress
+    pub fn write_sessions(&self) -> usize {
+        self.writes.len()
+    }
+
+    /// Serve a request like `dispatch`, reading and writing files on disk directly
+    pub async fn dispatch(
+        &self,
+        request: NetMessage,
+        lock_manager: &LockManager,
+        holder_id: &str,
+    ) -> NetMessage {
+        self.writes.expire(lock_manager);
+
+        let result = match request {
+            NetMessage::WriteChunk(req)
+                if self
+                    .writes
+                    .file(&req.lock_token, req.chunk_id.inode)
+                    .is_some() =>
+            {
+                let (chunk_id, len) = (req.chunk_id, req.data.len());
+                self.write_staged(req, lock_manager)
+                    .await
+                    .map(|new_size| write_response(chunk_id, len, new_size))
+            }
+            NetMessage::BeginWrite(req) => self.begin_write(req, lock_manager).await.map(|()| {
+                NetMessage::BeginWriteResponse(BeginWriteResponse {
+                    success: true,
+                    error: None,
+                })
+            }),
+            NetMessage::CommitWrite(req) => {
+                self.commit_write(req, lock_manager).await.map(|attr| {
+                    NetMessage::CommitWriteResponse(CommitWriteResponse {
+                        success: true,
+                        attr: Some(attr),
+                        error: None,
+                    })
+                })
+            }
+            NetMessage::AbortWrite(req) => Ok(NetMessage::AbortWriteResponse(AbortWriteResponse {
+                success: self.abort_write(req),
+            })),
+            NetMessage::TreeSnapshot(req) => self.snapshot_page(req).await,
+            NetMessage::CopyRange(req) => self
+                .copy_range(&req, lock_manager)
+                .await
+                .map(|(copied, new_size)| copy_response(&req, copied, new_size)),
+            NetMessage::ReadChunk(req) => match self.backend.local_path(req.chunk_id.inode) {
+                Some(path) => self
+                    .read_chunk(req.chunk_id, path)
+                    .await
+                    .map(|chunk| read_response(req.chunk_id, chunk)),
+                None => {
+                    return self.dispatch_to_backend(
+                        NetMessage::ReadChunk(req),
+                        lock_manager,
+                        holder_id,
+                    )
+                }
+            },
+            NetMessage::WriteChunk(req) => match self.backend.local_path(req.chunk_id.inode) {
+                Some(path) => {
+                    let (chunk_id, len) = (req.chunk_id, req.data.len());
+                    self.write_chunk(req, path, lock_manager)
+                        .await
+                        .map(|new_size| write_response(chunk_id, len, new_size))
+                }
+                None => {
+                    return self.dispatch_to_backend(
+                        NetMessage::WriteChunk(req),
+                        lock_manager,
+                        holder_id,
+                    )
+                }
+            },
+            request => {
+                let unlinks = matches!(request, NetMessage::DeleteFile(_) | NetMessage::Rename(_));
+                let releases = matches!(request, NetMessage::ReleaseLock(_));
+                let response = self.dispatch_to_backend(request, lock_manager, holder_id);
+                // Don't hold deleted or replaced files open
+                if unlinks {
+                    self.close_stale();
+                }
+                // Nor writes staged under a released lock
+                if releases {
+                    self.writes.expire(lock_manager);
+                }
+                return response;
+            }
+        };
+
+        result.unwrap_or_else(NetMessage::Error)
+    }
+
+    /// Serve the next page of a tree walk, listing directories on a blocking thread
+    async fn snapshot_page(&self, req: TreeSnapshotRequest) -> BackendResult<NetMessage> {
+        let mut walk = self.walks.take(&req, self.backend.as_ref())?;
+        let backend = self.backend.clone();
+        let (walk, page) = blocking(req.root, move || {
+            let page = walk.next_page(backend.as_ref());
+            (walk, page)
+        })
+        .await?;
+        let mut page = page?;
+        if page.has_more {
+            page.next_cursor = self.walks.put(walk);
+        }
+        Ok(NetMessage::TreeSnapshotResponse(page))
+    }
+
+    fn dispatch_to_backend(
+        &self,
+        request: NetMessage,
+        lock_manager: &LockManager,
+        holder_id: &str,
+    ) -> NetMessage {
+        dispatch(request, self.backend.as_ref(), lock_manager, holder_id)
+    }
+
+    async fn read_chunk(&self, chunk_id: ChunkId, path: PathBuf) -> BackendResult<ChunkData> {
+        let inode = chunk_id.inode;
+        let file = self.file(inode, path, false).await?;
+        let offset = chunk_id.byte_offset();
+
+        let buffer = self.chunk_io.buffers.acquire().await;
+        let (buffer, bytes_read) = self
+            .chunk_io
+            .io
+            .read_at(file.clone(), offset, buffer)
+            .await
+            .map_err(|e| io_error(&e, inode))?;
+        let data = buffer[..bytes_read.min(CHUNK_SIZE)].to_vec();
+        drop(buffer);
+
+        // A short read stops at the end of the file; a full chunk may end there too
+        let is_final = data.len() < CHUNK_SIZE || {
+            let file_size = blocking(inode, move || file.metadata())
+                .await?
+                .map(|m| m.len())
+                .unwrap_or(0);
+            offset.saturating_add(data.len() as u64) >= file_size
+        };
+
+        Ok(ChunkData { data, is_final })
+    }
+
+    async fn write_chunk(
+        &self,
+        req: WriteChunkRequest,
+        path: PathBuf,
+        lock_manager: &LockManager,
+    ) -> BackendResult<Option<u64>> {
+        check_write(&req, lock_manager)?;
+
+        let inode = req.chunk_id.inode;
+        let file = self.file(inode, path, true).await?;
+        self.chunk_io
+            .io
+            .write_at(file.clone(), req.chunk_id.byte_offset(), req.data)
+            .await
+            .map_err(|e| {
+                backend_error(ErrorCode::IoError, format!("Write failed: {}", e), inode)
+            })?;
+
+        blocking(inode, move || {
+            // Sync to disk
+            if let Err(e) = file.sync_data() {
+                warn!("Failed to sync file data: {}", e);
+            }
+            file.metadata().map(|m| m.len()).ok()
+        })
+        .await
+    }
+
+    /// Copy between files, into the staged file if the token has a write session
+    async fn copy_range(
+        &self,
+        req: &CopyRangeRequest,
+        lock_manager: &LockManager,
+    ) -> BackendResult<(u64, Option<u64>)> {
+        let len = check_copy(req, lock_manager)?;
+        let staged = self.writes.file(&req.lock_token, req.dst_inode);
+        let backend = self.backend.clone();
+        let req = req.clone();
+        blocking(req.dst_inode, move || match staged {
+            Some(staged) => {
+                let src = backend.open_file(req.src_inode, false)?;
+                let copied = copy_range(&src, req.src_offset, &staged, req.dst_offset, len)
+                    .map_err(|e| io_error(&e, req.dst_inode))?;
+                Ok((copied, staged.metadata().map(|m| m.len()).ok()))
+            }
+            None => backend.copy_range(
+                req.src_inode,
+                req.src_offset,
+                req.dst_inode,
+                req.dst_offset,
+                len,
+            ),
+        })
+        .await?
+    }
+
+    /// Stage the writes `req.lock_token` makes to `req.inode` from now on
+    async fn begin_write(
+        &self,
+        req: BeginWriteRequest,
+        lock_manager: &LockManager,
+    ) -> BackendResult<()> {
+        let inode = req.inode;
+        check_lock(lock_manager, inode, &req.lock_token)?;
+
+        let backend = self.backend.clone();
+        let (staged, file) = blocking(inode, move || backend.stage_file(inode)).await??;
+        info!("Write session started for inode {}", inode);
+        self.writes
+            .insert(req.lock_token, WriteSession::new(inode, staged, file));
+        Ok(())
+    }
+
+    async fn write_staged(
+        &self,
+        req: WriteChunkRequest,
+        lock_manager: &LockManager,
+    ) -> BackendResult<Option<u64>> {
+        check_write(&req, lock_manager)?;
+
+        let inode = req.chunk_id.inode;
+        let Some(file) = self.writes.file(&req.lock_token, inode) else {
+            return Err(no_write_session(inode));
+        };
+        self.chunk_io
+            .io
+            .write_at(file.clone(), req.chunk_id.byte_offset(), req.data)
+            .await
+            .map_err(|e| {
+                backend_error(ErrorCode::IoError, format!("Write failed: {}", e), inode)
+            })?;
+
+        // Synced once, on commit
+        blocking(inode, move || file.metadata().map(|m| m.len()).ok()).await
+    }
+
+    /// Replace the file with its staged writes
+    async fn commit_write(
+        &self,
+        req: CommitWriteRequest,
+        lock_manager: &LockManager,
+    ) -> BackendResult<FileAttr> {
+        let inode = req.inode;
+        check_lock(lock_manager, inode, &req.lock_token)?;
+        let Some(mut session) = self.writes.take(&req.lock_token, inode) else {
+            return Err(no_write_session(inode));
+        };
+
+        let backend = self.backend.clone();
+        let attr = blocking(inode, move || {
+            session.file().sync_all().map_err(|e| io_error(&e, inode))?;
+            let attr = backend.commit_staged(inode, session.staged())?;
+            session.mark_committed();
+            Ok::<_, teleport_core::ErrorMessage>(attr)
+        })
+        .await??;
+
+        // The open file is the one that was replaced
+        self.files.lock().pop(&inode);
+        info!("Write session committed for inode {}", inode);
+        Ok(attr)
+    }
+
+    /// Discard staged writes, returning whether a session was open
+    fn abort_write(&self, req: AbortWriteRequest) -> bool {
+        self.writes.take(&req.lock_token, req.inode).is_some()
+    }
+
+    /// Open file for `inode`, opening it if it isn't open (or not for writing)
+    async fn file(&self, inode: Inode, path: PathBuf, write: bool) -> BackendResult<Arc<File>> {
+        let cached = self.files.lock().get(&inode).cloned();
+        if let Some(mut open) = cached.filter(|open| open.path == path && (open.writable || !write))
+        {
+            // Writes are synced anyway, so an extra stat costs them little
+            if !write && open.checked.elapsed() < REVALIDATE_INTERVAL {
+                return Ok(open.file);
+            }
+            let identity = open.identity;
+            let check = open.path.clone();
+            let unchanged = blocking(inode, move || {
+                identity.is_some()
+                    && fs::metadata(&check).ok().and_then(|m| file_identity(&m)) == identity
+            })
+            .await?;
+            if unchanged {
+                open.checked = Instant::now();
+                let file = open.file.clone();
+                self.files.lock().put(inode, open);
+                return Ok(file);
+            }
+        }
+
+        self.close_stale();
+        let backend = self.backend.clone();
+        let (file, identity) = blocking(inode, move || {
+            let file = backend.open_file(inode, write)?;
+            let identity = file.metadata().ok().and_then(|m| file_identity(&m));
+            Ok::<_, teleport_core::ErrorMessage>((Arc::new(file), identity))
+        })
+        .await??;
+
+        self.files.lock().put(
+            inode,
+            OpenFile {
+                file: file.clone(),
+                path,
+                writable: write,
+                identity,
+                checked: Instant::now(),
+            },
+        );
+        Ok(file)
+    }
+
+    /// Close files whose inode no longer maps to the path they were opened at
+    fn close_stale(&self) {
+        let mut files = self.files.lock();
+        let stale: Vec<Inode> = files
+            .iter()
+            .filter(|(inode, open)| self.backend.local_path(**inode).as_ref() != Some(&open.path))
+            .map(|(inode, _)| *inode)
+            .collect();
+        for inode in stale {
+            files.pop(&inode);
+        }
+    }
+}
+
+/// SECURITY: Write sessions are opened and committed by the file's lock holder
+fn check_lock(lock_manager: &LockManager, inode: Inode, token: &LockToken) -> BackendResult<()> {
+    if lock_manager.validate(inode, token, LockType::Exclusive) {
+        return Ok(());
+    }
+    Err(backend_error(
+        ErrorCode::LockRequired,
+        "Invalid or expired lock token",
+        inode,
+    ))
+}
+
+fn no_write_session(inode: Inode) -> teleport_core::ErrorMessage {
+    backend_error(
+        ErrorCode::ProtocolError,
+        "no write session is open for this lock",
+        inode,
+    )
+}
+
+/// Run blocking file system calls off the async runtime
+async fn blocking<T: Send + 'static>(
+    inode: Inode,
+    f: impl FnOnce() -> T + Send + 'static,
+) -> BackendResult<T> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| backend_error(ErrorCode::IoError, e.to_string(), inode))
+}
+
+#[cfg(unix)]
+fn file_identity(meta: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((meta.dev(), meta.ino()))
+}
+
+/// Without a stable file ID, open files are reopened every `REVALIDATE_INTERVAL`
+#[cfg(not(unix))]
+fn file_identity(_meta: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::local_backend::LocalBackend;
+    use teleport_core::{
+        crypto::checksum, DeleteFileRequest, LockType, ReadChunkRequest, ROOT_INODE,
+    };
+    use tempfile::TempDir;
+
+    fn read(inode: Inode, index: u64) -> NetMessage {
+        NetMessage::ReadChunk(ReadChunkRequest {
+            chunk_id: ChunkId::new(inode, index),
+            priority: 0,
+        })
+    }
+
+    fn data(response: NetMessage) -> (Vec<u8>, bool) {
+        match response {
+            NetMessage::ReadChunkResponse(r) => {
+                assert_eq!(r.checksum, checksum(&r.data));
+                (r.data, r.is_final)
+            }
+            other => panic!("Expected ReadChunkResponse, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reads_reuse_open_files_and_pooled_buffers() {
+        let temp_dir = TempDir::new().unwrap();
+        let content: Vec<u8> = (0..CHUNK_SIZE + 10).map(|i| (i % 251) as u8).collect();
+        std::fs::write(temp_dir.path().join("big.bin"), &content).unwrap();
+        let backend = Arc::new(LocalBackend::new(temp_dir.path()));
+        let inode = backend
+            .lookup(ROOT_INODE, "big.bin")
+            .unwrap()
+            .unwrap()
+            .inode;
+        let chunk_io = ChunkIo::with_buffers(BufferPool::new(2, CHUNK_SIZE));
+        let files = FileHandles::new(backend, chunk_io.clone());
+        let locks = LockManager::default();
+
+        let (first, is_final) = data(files.dispatch(read(inode, 0), &locks, "alice").await);
+        assert_eq!(first, content[..CHUNK_SIZE]);
+        assert!(!is_final);
+        let (second, is_final) = data(files.dispatch(read(inode, 1), &locks, "alice").await);
+        assert_eq!(second, content[CHUNK_SIZE..]);
+        assert!(is_final);
+
+        assert_eq!(files.open_files(), 1);
+        assert!(chunk_io.buffers().stats().cache_hits >= 1);
+        assert_eq!(chunk_io.buffers().in_use(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_writes_check_locks_and_deleted_files_are_closed() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("notes.txt"), b"hello").unwrap();
+        let backend = Arc::new(LocalBackend::new(temp_dir.path()));
+        let inode = backend
+            .lookup(ROOT_INODE, "notes.txt")
+            .unwrap()
+            .unwrap()
+            .inode;
+        let files = FileHandles::new(backend, ChunkIo::new());
+        let locks = LockManager::default();
+        let write = |data: &[u8], lock_token| {
+            NetMessage::WriteChunk(WriteChunkRequest {
+                chunk_id: ChunkId::new(inode, 0),
+                checksum: checksum(data),
+                data: data.to_vec(),
+                lock_token,
+            })
+        };
+
+        let token = locks
+            .acquire(inode, LockType::Exclusive, "alice", None)
+            .unwrap();
+        let response = files.dispatch(write(b"HE", token), &locks, "alice").await;
+        match response {
+            NetMessage::WriteChunkResponse(r) => assert_eq!(r.new_size, Some(5)),
+            other => panic!("Expected WriteChunkResponse, got {:?}", other),
+        }
+        assert_eq!(
+            data(files.dispatch(read(inode, 0), &locks, "alice").await).0,
+            b"HEllo"
+        );
+
+        // A token that isn't held is refused before the file is touched
+        let stale = locks
+            .acquire(ROOT_INODE, LockType::Exclusive, "alice", None)
+            .unwrap();
+        let response = files.dispatch(write(b"no", stale), &locks, "alice").await;
+        assert!(matches!(response, NetMessage::Error(e) if e.code == ErrorCode::LockRequired));
+
+        let delete = NetMessage::DeleteFile(DeleteFileRequest {
+            parent: ROOT_INODE,
+            name: "notes.txt".into(),
+            lock_token: None,
+        });
+        files.dispatch(delete, &locks, "alice").await;
+        assert_eq!(files.open_files(), 0);
+        let response = files.dispatch(read(inode, 0), &locks, "alice").await;
+        assert!(matches!(response, NetMessage::Error(e) if e.code == ErrorCode::FileNotFound));
+    }
+
+    fn write(inode: Inode, index: u64, data: &[u8], lock_token: &LockToken) -> NetMessage {
+        NetMessage::WriteChunk(WriteChunkRequest {
+            chunk_id: ChunkId::new(inode, index),
+            checksum: checksum(data),
+            data: data.to_vec(),
+            lock_token: lock_token.clone(),
+        })
+    }
+
+    fn entries(dir: &std::path::Path) -> Vec<String> {
+        let mut names: Vec<String> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[tokio::test]
+    async fn test_write_sessions_replace_files_on_commit() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("photo.psd");
+        std::fs::write(&path, b"old layers, old pixels").unwrap();
+        let backend = Arc::new(LocalBackend::new(temp_dir.path()));
+        let inode = backend
+            .lookup(ROOT_INODE, "photo.psd")
+            .unwrap()
+            .unwrap()
+            .inode;
+        let files = FileHandles::new(backend.clone(), ChunkIo::new());
+        let locks = LockManager::default();
+        let token = locks
+            .acquire(inode, LockType::Exclusive, "alice", None)
+            .unwrap();
+        let begin = |lock_token: &LockToken| {
+            NetMessage::BeginWrite(BeginWriteRequest {
+                inode,
+                lock_token: lock_token.clone(),
+            })
+        };
+
+        // Only the lock holder can stage writes
+        let response = files
+            .dispatch(begin(&LockToken::generate()), &locks, "alice")
+            .await;
+        assert!(matches!(response, NetMessage::Error(e) if e.code == ErrorCode::LockRequired));
+
+        let response = files.dispatch(begin(&token), &locks, "alice").await;
+        assert!(matches!(response, NetMessage::BeginWriteResponse(r) if r.success));
+        let response = files
+            .dispatch(write(inode, 0, b"new", &token), &locks, "alice")
+            .await;
+        match response {
+            NetMessage::WriteChunkResponse(r) => assert_eq!(r.new_size, Some(3)),
+            other => panic!("Expected WriteChunkResponse, got {:?}", other),
+        }
+
+        // Until the commit, the file is untouched and the staged file hidden
+        assert_eq!(std::fs::read(&path).unwrap(), b"old layers, old pixels");
+        assert_eq!(
+            data(files.dispatch(read(inode, 0), &locks, "alice").await).0,
+            b"old layers, old pixels"
+        );
+        assert_eq!(entries(temp_dir.path()).len(), 2);
+        let page = backend.list_dir(ROOT_INODE, 0, 10).unwrap();
+        assert_eq!(page.entries.len(), 1);
+
+        let commit = NetMessage::CommitWrite(CommitWriteRequest {
+            inode,
+            lock_token: token.clone(),
+        });
+        match files.dispatch(commit.clone(), &locks, "alice").await {
+            NetMessage::CommitWriteResponse(r) => {
+                assert!(r.success);
+                assert_eq!(r.attr.unwrap().size, 3);
+            }
+            other => panic!("Expected CommitWriteResponse, got {:?}", other),
+        }
+        assert_eq!(std::fs::read(&path).unwrap(), b"new");
+        assert_eq!(entries(temp_dir.path()), vec!["photo.psd".to_string()]);
+        assert_eq!(
+            data(files.dispatch(read(inode, 0), &locks, "alice").await).0,
+            b"new"
+        );
+
+        // The session is over: committing again fails, writes go in place
+        let response = files.dispatch(commit, &locks, "alice").await;
+        assert!(matches!(response, NetMessage::Error(e) if e.code == ErrorCode::ProtocolError));
+        files
+            .dispatch(write(inode, 0, b"N", &token), &locks, "alice")
+            .await;
+        assert_eq!(std::fs::read(&path).unwrap(), b"New");
+    }
+
+    #[tokio::test]
+    async fn test_writes_follow_a_file_replaced_by_another_session() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("notes.txt");
+        std::fs::write(&path, b"hello").unwrap();
+        let backend = Arc::new(LocalBackend::new(temp_dir.path()));
+        let inode = backend
+            .lookup(ROOT_INODE, "notes.txt")
+            .unwrap()
+            .unwrap()
+            .inode;
+        let alice = FileHandles::new(backend.clone(), ChunkIo::new());
+        let bob = FileHandles::new(backend, ChunkIo::new());
+        let locks = LockManager::default();
+
+        let token = locks
+            .acquire(inode, LockType::Exclusive, "alice", None)
+            .unwrap();
+        alice
+            .dispatch(write(inode, 0, b"H", &token), &locks, "alice")
+            .await;
+        locks.release(&token).unwrap();
+
+        // Bob replaces the file while alice still has it open
+        let token = locks
+            .acquire(inode, LockType::Exclusive, "bob", None)
+            .unwrap();
+        let requests = [
+            NetMessage::BeginWrite(BeginWriteRequest {
+                inode,
+                lock_token: token.clone(),
+            }),
+            write(inode, 0, b"fresh", &token),
+            NetMessage::CommitWrite(CommitWriteRequest {
+                inode,
+                lock_token: token.clone(),
+            }),
+        ];
+        for request in requests {
+            bob.dispatch(request, &locks, "bob").await;
+        }
+        locks.release(&token).unwrap();
+
+        // Alice's next write, right away, goes to the file now at the path
+        let token = locks
+            .acquire(inode, LockType::Exclusive, "alice", None)
+            .unwrap();
+        match alice
+            .dispatch(write(inode, 0, b"F", &token), &locks, "alice")
+            .await
+        {
+            NetMessage::WriteChunkResponse(r) => assert_eq!(r.new_size, Some(5)),
+            other => panic!("Expected WriteChunkResponse, got {:?}", other),
+        }
+        assert_eq!(std::fs::read(&path).unwrap(), b"Fresh");
+    }
+
+    #[tokio::test]
+    async fn test_write_sessions_are_discarded_without_commit() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("notes.txt");
+        std::fs::write(&path, b"hello").unwrap();
+        let backend = Arc::new(LocalBackend::new(temp_dir.path()));
+        let inode = backend
+            .lookup(ROOT_INODE, "notes.txt")
+            .unwrap()
+            .unwrap()
+            .inode;
+        let files = FileHandles::new(backend, ChunkIo::new());
+        let locks = LockManager::default();
+        let token = locks
+            .acquire(inode, LockType::Exclusive, "alice", None)
+            .unwrap();
+        let begin = NetMessage::BeginWrite(BeginWriteRequest {
+            inode,
+            lock_token: token.clone(),
+        });
+
+        // Aborted
+        files.dispatch(begin.clone(), &locks, "alice").await;
+        files
+            .dispatch(write(inode, 0, b"bye", &token), &locks, "alice")
+            .await;
+        let abort = NetMessage::AbortWrite(AbortWriteRequest {
+            inode,
+            lock_token: token.clone(),
+        });
+        let response = files.dispatch(abort, &locks, "alice").await;
+        assert!(matches!(response, NetMessage::AbortWriteResponse(r) if r.success));
+        assert_eq!(entries(temp_dir.path()), vec!["notes.txt".to_string()]);
+
+        // The lock is released
+        files.dispatch(begin.clone(), &locks, "alice").await;
+        assert_eq!(files.write_sessions(), 1);
+        let release = NetMessage::ReleaseLock(teleport_core::ReleaseRequest {
+            token: token.clone(),
+        });
+        files.dispatch(release, &locks, "alice").await;
+        assert_eq!(files.write_sessions(), 0);
+        assert_eq!(entries(temp_dir.path()), vec!["notes.txt".to_string()]);
+
+        // The client disconnects
+        let token = locks
+            .acquire(inode, LockType::Exclusive, "alice", None)
+            .unwrap();
+        let begin = NetMessage::BeginWrite(BeginWriteRequest {
+            inode,
+            lock_token: token.clone(),
+        });
+        files.dispatch(begin, &locks, "alice").await;
+        files
+            .dispatch(write(inode, 0, b"bye", &token), &locks, "alice")
+            .await;
+        drop(files);
+        assert_eq!(entries(temp_dir.path()), vec!["notes.txt".to_string()]);
+        assert_eq!(std::fs::read(&path).unwrap(), b"hello");
+    }
+
+    #[tokio::test]
+    async fn test_replaced_files_are_reopened() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("doc.txt");
+        std::fs::write(&path, b"old").unwrap();
+        let backend = Arc::new(LocalBackend::new(temp_dir.path()));
+        let inode = backend
+            .lookup(ROOT_INODE, "doc.txt")
+            .unwrap()
+            .unwrap()
+            .inode;
+        let files = FileHandles::new(backend, ChunkIo::new());
+        let locks = LockManager::default();
+        assert_eq!(
+            data(files.dispatch(read(inode, 0), &locks, "alice").await).0,
+            b"old"
+        );
+
+        // Saved on the host by writing a new file over the old one
+        let saved = temp_dir.path().join("doc.txt.tmp");
+        std::fs::write(&saved, b"new").unwrap();
+        std::fs::rename(&saved, &path).unwrap();
+        if let Some(open) = files.files.lock().peek_mut(&inode) {
+            open.checked = Instant::now() - REVALIDATE_INTERVAL;
+        }
+
+        assert_eq!(
+            data(files.dispatch(read(inode, 0), &locks, "alice").await).0,
+            b"new"
+        );
+    }
+}
@@ -17,27 +17,36 @@
 //! - Write operations (write, setattr)
 //! - SyncEngine for dirty chunk tracking
 //! - Lock management for write coordination
+//! - copy_file_range served by a copy on the host
+//! - lseek(SEEK_DATA/SEEK_HOLE) and fallocate for sparse files
+//! - Files truncated on open are rewritten in a write session, so the host
+//!   keeps the old contents until the new ones are flushed
 
+use std::collections::HashMap;
 use std::ffi::OsStr;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, UNIX_EPOCH};
 
 use fuser::{
-    FileAttr as FuserAttr, FileType as FuserFileType, Filesystem, ReplyAttr, ReplyData,
-    ReplyDirectory, ReplyEntry, ReplyWrite, Request,
+    consts::{FUSE_ATOMIC_O_TRUNC, FUSE_DO_READDIRPLUS, FUSE_READDIRPLUS_AUTO},
+    FileAttr as FuserAttr, FileType as FuserFileType, Filesystem, KernelConfig, ReplyAttr,
+    ReplyData, ReplyDirectory, ReplyDirectoryPlus, ReplyEntry, ReplyWrite, Request,
 };
-use parking_lot::Mutex;
+use parking_lot::{Mutex, RwLock};
 use tracing::{debug, error, info, trace, warn};
 
-use teleport_core::{ChunkId, FileAttr, FileType, Inode};
+use teleport_core::{
+    ChunkId, DirEntry, FallocateMode, FileAttr, FileType, Inode, LockType, SeekWhence,
+};
 
 use crate::bridge::{FuseAsyncBridge, FuseError};
 use crate::cache::HybridCacheManager;
-use crate::governor::{Governor, MAX_PREFETCH_CONCURRENT};
+use crate::governor::{Governor, MAX_PREFETCH_CONCURRENT, SEQUENTIAL_THRESHOLD};
 use crate::sync_engine::SyncEngine;
+use crate::tree_snapshot::TreeSnapshot;
 
-/// TTL for FUSE kernel cache
+/// Default TTL for FUSE kernel cache
 const TTL: Duration = Duration::from_secs(1);
 
 /// Wormhole FUSE filesystem with prefetch support and hybrid caching
@@ -51,6 +60,12 @@ pub struct WormholeFS {
     writable: bool,
     /// SECURITY: Counter for in-flight prefetch threads to prevent DoS
     prefetch_inflight: Arc<AtomicUsize>,
+    /// How long the kernel may cache entries and attributes
+    ttl: Arc<RwLock<Duration>>,
+    /// Metadata to answer from while the host can't be reached
+    snapshot: Option<Arc<RwLock<TreeSnapshot>>>,
+    /// Files being rewritten in a write session, with their size so far
+    rewrites: Mutex<HashMap<Inode, u64>>,
 }
 
 impl WormholeFS {
@@ -66,6 +81,9 @@ impl WormholeFS {
             sync_engine: Arc::new(SyncEngine::default()),
             writable: false, // Read-only by default
             prefetch_inflight: Arc::new(AtomicUsize::new(0)),
+            ttl: Arc::new(RwLock::new(TTL)),
+            snapshot: None,
+            rewrites: Mutex::new(HashMap::new()),
         }
     }
 
@@ -82,6 +100,9 @@ impl WormholeFS {
             sync_engine: Arc::new(SyncEngine::default()),
             writable: true,
             prefetch_inflight: Arc::new(AtomicUsize::new(0)),
+            ttl: Arc::new(RwLock::new(TTL)),
+            snapshot: None,
+            rewrites: Mutex::new(HashMap::new()),
         }
     }
 
@@ -94,6 +115,9 @@ impl WormholeFS {
             sync_engine: Arc::new(SyncEngine::default()),
             writable: false,
             prefetch_inflight: Arc::new(AtomicUsize::new(0)),
+            ttl: Arc::new(RwLock::new(TTL)),
+            snapshot: None,
+            rewrites: Mutex::new(HashMap::new()),
         }
     }
 
@@ -106,9 +130,40 @@ impl WormholeFS {
             sync_engine: Arc::new(SyncEngine::default()),
             writable: true,
             prefetch_inflight: Arc::new(AtomicUsize::new(0)),
+            ttl: Arc::new(RwLock::new(TTL)),
+            snapshot: None,
+            rewrites: Mutex::new(HashMap::new()),
         }
     }
 
+    /// Set how long the kernel may cache entries and attributes
+    pub fn with_ttl(self, ttl: Duration) -> Self {
+        *self.ttl.write() = ttl;
+        self
+    }
+
+    /// Answer lookups, attributes and listings from `snapshot` when the host
+    /// can't be reached
+    pub fn with_snapshot(mut self, snapshot: Arc<RwLock<TreeSnapshot>>) -> Self {
+        self.snapshot = Some(snapshot);
+        self
+    }
+
+    /// Handle for changing the kernel cache TTL once the filesystem is mounted
+    pub fn ttl_handle(&self) -> Arc<RwLock<Duration>> {
+        self.ttl.clone()
+    }
+
+    /// Prefetch up to `chunks` chunks ahead of sequential reads
+    pub fn with_read_ahead(mut self, chunks: usize) -> Self {
+        self.governor = Mutex::new(Governor::with_config(chunks as u64, SEQUENTIAL_THRESHOLD));
+        self
+    }
+
+    fn ttl(&self) -> Duration {
+        *self.ttl.read()
+    }
+
     /// Get the sync engine (for external sync operations)
     pub fn sync_engine(&self) -> Arc<SyncEngine> {
         self.sync_engine.clone()
@@ -295,9 +350,170 @@ impl WormholeFS {
 
         Ok(result)
     }
+
+    /// Answer from the snapshot if `error` means the host couldn't be reached
+    fn offline<T>(
+        &self,
+        error: FuseError,
+        answer: impl FnOnce(&TreeSnapshot) -> Option<T>,
+    ) -> Result<T, FuseError> {
+        let unreachable = matches!(
+            error,
+            FuseError::Timeout | FuseError::Shutdown | FuseError::IoError(_)
+        );
+        match &self.snapshot {
+            Some(snapshot) if unreachable => {
+                let answer = answer(&snapshot.read()).ok_or(error)?;
+                debug!("host unreachable, answering from the tree snapshot");
+                Ok(answer)
+            }
+            _ => Err(error),
+        }
+    }
+
+    /// Attributes of `ino`, from the cache if they are there
+    fn cached_attr(&self, ino: Inode) -> Result<FileAttr, FuseError> {
+        if let Some(attr) = self.cache.attrs.get(ino) {
+            return Ok(self.rewritten(attr));
+        }
+        let attr = self
+            .bridge
+            .getattr(ino)
+            .or_else(|e| self.offline(e, |snapshot| snapshot.attr(ino).cloned()))?;
+        self.cache.attrs.insert(ino, attr.clone());
+        Ok(self.rewritten(attr))
+    }
+
+    /// Entries of the directory `ino`, listing it with their attributes if it
+    /// isn't cached so that stat-ing them afterwards needs no round trips
+    fn dir_entries_plus(&self, ino: Inode) -> Result<Vec<DirEntry>, FuseError> {
+        if let Some(entries) = self.cache.dirs.get(ino) {
+            return Ok(entries);
+        }
+        let listing = self.bridge.readdirplus(ino).or_else(|e| {
+            self.offline(e, |snapshot| {
+                let entries = snapshot.entries(ino)?;
+                let attr = |entry: &DirEntry| snapshot.attr(entry.inode).cloned();
+                Some(entries.iter().map(|e| (e.clone(), attr(e))).collect())
+            })
+        })?;
+        let entries: Vec<DirEntry> = listing
+            .into_iter()
+            .map(|(entry, attr)| {
+                if let Some(attr) = attr {
+                    self.cache.attrs.insert(entry.inode, attr);
+                }
+                entry
+            })
+            .collect();
+        self.cache.dirs.insert(ino, entries.clone());
+        Ok(entries)
+    }
+
+    /// `attr` with the size of the rewrite in progress, if there is one
+    fn rewritten(&self, mut attr: FileAttr) -> FileAttr {
+        if let Some(&size) = self.rewrites.lock().get(&attr.inode) {
+            attr.size = size;
+        }
+        attr
+    }
+
+    /// Start rewriting `ino` from empty, returning false if the host can't
+    /// stage rewrites and the file must be truncated in place instead
+    fn begin_rewrite(&self, ino: Inode) -> Result<bool, FuseError> {
+        if self.rewrites.lock().contains_key(&ino) {
+            return Ok(true);
+        }
+        self.sync_engine.discard_dirty(ino);
+        self.cache.chunks.invalidate_inode(ino);
+        match self.bridge.begin_write(ino) {
+            Ok(()) => {
+                debug!("rewriting {} in a write session", ino);
+                self.rewrites.lock().insert(ino, 0);
+                self.cache.attrs.invalidate(ino);
+                Ok(true)
+            }
+            Err(FuseError::Unsupported) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Commit the rewrite of `ino`, if one is in progress, aborting it if
+    /// that fails so the file keeps its old contents
+    fn end_rewrite(&self, ino: Inode) -> Result<(), FuseError> {
+        if self.rewrites.lock().remove(&ino).is_none() {
+            return Ok(());
+        }
+        match self.bridge.commit_write(ino) {
+            Ok(Some(attr)) => {
+                self.cache.attrs.insert(ino, attr);
+                Ok(())
+            }
+            Ok(None) => {
+                self.cache.attrs.invalidate(ino);
+                Ok(())
+            }
+            Err(e) => {
+                error!("committing rewrite of {} failed: {:?}", ino, e);
+                if let Err(e) = self.bridge.abort_write(ino) {
+                    warn!("aborting rewrite of {} failed: {:?}", ino, e);
+                }
+                self.cache.chunks.invalidate_inode(ino);
+                self.cache.attrs.invalidate(ino);
+                Err(e)
+            }
+        }
+    }
+
+    /// Drop cached chunks overlapping `start..end` after the host changed them
+    fn invalidate_chunks(&self, ino: Inode, start: u64, end: u64) {
+        if start >= end {
+            return;
+        }
+        let chunk_size = teleport_core::CHUNK_SIZE as u64;
+        for chunk_idx in start / chunk_size..=(end - 1) / chunk_size {
+            self.cache.chunks.invalidate(&ChunkId::new(ino, chunk_idx));
+        }
+    }
+}
+
+/// Protocol mode for a fallocate(2) mode, `None` for modes hosts can't apply
+fn fallocate_mode(mode: i32) -> Option<FallocateMode> {
+    #[cfg(target_os = "linux")]
+    {
+        const PUNCH_HOLE: i32 = libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE;
+        match mode {
+            0 => Some(FallocateMode::Allocate),
+            libc::FALLOC_FL_KEEP_SIZE => Some(FallocateMode::AllocateKeepSize),
+            PUNCH_HOLE => Some(FallocateMode::PunchHole),
+            _ => None,
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        (mode == 0).then_some(FallocateMode::Allocate)
+    }
 }
 
 impl Filesystem for WormholeFS {
+    fn init(&mut self, _req: &Request<'_>, config: &mut KernelConfig) -> Result<(), libc::c_int> {
+        // List directories with their entries' attributes (readdirplus), so
+        // `ls -l` doesn't look every entry up
+        if let Err(missing) = config.add_capabilities(FUSE_DO_READDIRPLUS | FUSE_READDIRPLUS_AUTO) {
+            debug!("kernel doesn't support readdirplus (flags {:#x})", missing);
+        }
+        // Pass O_TRUNC to open, so truncating opens can start a rewrite
+        // instead of emptying the file first
+        if let Err(missing) = config.add_capabilities(FUSE_ATOMIC_O_TRUNC) {
+            debug!(
+                "kernel doesn't support atomic O_TRUNC (flags {:#x})",
+                missing
+            );
+        }
+        Ok(())
+    }
+
     fn lookup(&mut self, _req: &Request<'_>, parent: Inode, name: &OsStr, reply: ReplyEntry) {
         let name = match name.to_str() {
             Some(n) => n.to_string(),
@@ -312,10 +528,14 @@ impl Filesystem for WormholeFS {
         // Check cache first
         // (In a full implementation, we'd cache name→inode mappings)
 
-        match self.bridge.lookup(parent, name.clone()) {
+        let attr = self
+            .bridge
+            .lookup(parent, name.clone())
+            .or_else(|e| self.offline(e, |snapshot| snapshot.lookup(parent, &name).cloned()));
+        match attr {
             Ok(attr) => {
                 self.cache.attrs.insert(attr.inode, attr.clone());
-                reply.entry(&TTL, &Self::to_fuser_attr(&attr), 0);
+                reply.entry(&self.ttl(), &Self::to_fuser_attr(&attr), 0);
             }
             Err(FuseError::NotFound) => {
                 trace!("lookup not found: {}", name);
@@ -333,14 +553,18 @@ impl Filesystem for WormholeFS {
 
         // Check cache first
         if let Some(attr) = self.cache.attrs.get(ino) {
-            reply.attr(&TTL, &Self::to_fuser_attr(&attr));
+            reply.attr(&self.ttl(), &Self::to_fuser_attr(&self.rewritten(attr)));
             return;
         }
 
-        match self.bridge.getattr(ino) {
+        let attr = self
+            .bridge
+            .getattr(ino)
+            .or_else(|e| self.offline(e, |snapshot| snapshot.attr(ino).cloned()));
+        match attr {
             Ok(attr) => {
                 self.cache.attrs.insert(ino, attr.clone());
-                reply.attr(&TTL, &Self::to_fuser_attr(&attr));
+                reply.attr(&self.ttl(), &Self::to_fuser_attr(&self.rewritten(attr)));
             }
             Err(FuseError::NotFound) => {
                 reply.error(libc::ENOENT);
@@ -451,7 +675,11 @@ impl Filesystem for WormholeFS {
         }
 
         // Fetch from network
-        match self.bridge.readdir(ino, offset as u64) {
+        let entries = self
+            .bridge
+            .readdir(ino, offset as u64)
+            .or_else(|e| self.offline(e, |snapshot| snapshot.entries(ino).map(<[_]>::to_vec)));
+        match entries {
             Ok(entries) => {
                 // Cache entries
                 self.cache.dirs.insert(ino, entries.clone());
@@ -503,8 +731,91 @@ impl Filesystem for WormholeFS {
         }
     }
 
-    fn open(&mut self, _req: &Request<'_>, ino: Inode, _flags: i32, reply: fuser::ReplyOpen) {
+    fn readdirplus(
+        &mut self,
+        _req: &Request<'_>,
+        ino: Inode,
+        _fh: u64,
+        offset: i64,
+        mut reply: ReplyDirectoryPlus,
+    ) {
+        debug!("readdirplus: ino={}, offset={}", ino, offset);
+
+        let result = self
+            .dir_entries_plus(ino)
+            .and_then(|entries| Ok((self.cached_attr(ino)?, entries)));
+        let (dir_attr, entries) = match result {
+            Ok(listing) => listing,
+            Err(FuseError::NotFound) => {
+                reply.error(libc::ENOENT);
+                return;
+            }
+            Err(e) => {
+                error!("readdirplus error: {:?}", e);
+                reply.error(e.to_errno());
+                return;
+            }
+        };
+
+        let ttl = self.ttl();
+        let dir_attr = Self::to_fuser_attr(&dir_attr);
+
+        // Same offsets as readdir: "." is 1, ".." is 2 and entry i is i + 3.
+        // The kernel doesn't cache the attributes of "." and "..".
+        let dots = [(1, "."), (2, "..")];
+        for (entry_offset, name) in dots {
+            if entry_offset > offset && reply.add(ino, entry_offset, name, &ttl, &dir_attr, 0) {
+                reply.ok();
+                return;
+            }
+        }
+
+        for (i, entry) in entries.iter().enumerate() {
+            let entry_offset = i as i64 + 3;
+            if entry_offset <= offset {
+                continue;
+            }
+            let attr = match self.cached_attr(entry.inode) {
+                Ok(attr) => attr,
+                // Removed since the directory was listed
+                Err(FuseError::NotFound) => continue,
+                Err(e) => {
+                    error!("readdirplus error: {:?}", e);
+                    reply.error(e.to_errno());
+                    return;
+                }
+            };
+            let attr = Self::to_fuser_attr(&attr);
+            if reply.add(entry.inode, entry_offset, &entry.name, &ttl, &attr, 0) {
+                break;
+            }
+        }
+
+        reply.ok();
+    }
+
+    fn open(&mut self, _req: &Request<'_>, ino: Inode, flags: i32, reply: fuser::ReplyOpen) {
         trace!("open: ino={}", ino);
+
+        // A truncating open starts a save: write the new contents beside the
+        // file and swap them in on flush, so an interrupted save loses nothing
+        let truncate = flags & libc::O_TRUNC != 0 && flags & libc::O_ACCMODE != libc::O_RDONLY;
+        if truncate && self.writable {
+            let result = self.begin_rewrite(ino).and_then(|staged| {
+                if staged {
+                    return Ok(());
+                }
+                self.bridge
+                    .setattr(ino, Some(0), None, None, None)
+                    .map(|attr| self.cache.attrs.insert(ino, attr))
+            });
+            if let Err(e) = result {
+                error!("open: truncating {} failed: {:?}", ino, e);
+                reply.error(e.to_errno());
+                return;
+            }
+        }
+
         // We don't track file handles - stateless
         reply.opened(0, 0);
     }
@@ -522,6 +833,10 @@ impl Filesystem for WormholeFS {
         trace!("release: ino={}", ino);
         // Clear governor state for this file
         self.governor.lock().clear_inode(ino);
+        if let Err(e) = self.end_rewrite(ino) {
+            reply.error(e.to_errno());
+            return;
+        }
         reply.ok();
     }
 
@@ -581,6 +896,9 @@ impl Filesystem for WormholeFS {
         // 2. Write locally
         // 3. Mark dirty for background sync
 
+        // A rewrite starts from an empty file, not the one on the host
+        let rewriting = self.rewrites.lock().contains_key(&ino);
+
         // Handle writes that span multiple chunks
         let mut written = 0usize;
         let mut current_offset = offset;
@@ -600,6 +918,8 @@ impl Filesystem for WormholeFS {
                 (*cached).clone()
             } else if let Some(dirty) = self.sync_engine.get_dirty_chunk(&chunk_id) {
                 dirty
+            } else if rewriting {
+                Vec::new()
             } else {
                 // Fetch from network
                 match self.fetch_chunk(chunk_id) {
@@ -638,6 +958,9 @@ impl Filesystem for WormholeFS {
 
         // Invalidate attr cache since size may have changed
         self.cache.attrs.invalidate(ino);
+        if let Some(size) = self.rewrites.lock().get_mut(&ino) {
+            *size = (*size).max(end_offset);
+        }
 
         debug!("write: wrote {} bytes", written);
         reply.written(written as u32);
@@ -654,6 +977,12 @@ impl Filesystem for WormholeFS {
     ) {
         debug!("flush: ino={}", ino);
 
+        // Closing a file being rewritten completes the save
+        if let Err(e) = self.end_rewrite(ino) {
+            reply.error(e.to_errno());
+            return;
+        }
+
         // For now, we rely on background sync
         // A full implementation would immediately sync dirty chunks for this inode
         if self.sync_engine.has_dirty_chunks(ino) {
@@ -686,6 +1015,177 @@ impl Filesystem for WormholeFS {
         reply.ok();
     }
 
+    /// Copy between files on the host instead of through the client
+    ///
+    /// Every inode here lives on the one share, so the host can always do the
+    /// copy itself. Unflushed writes are pushed first so it copies what the
+    /// application wrote; if that fails the kernel falls back to read/write.
+    fn copy_file_range(
+        &mut self,
+        _req: &Request<'_>,
+        ino_in: Inode,
+        _fh_in: u64,
+        offset_in: i64,
+        ino_out: Inode,
+        _fh_out: u64,
+        offset_out: i64,
+        len: u64,
+        _flags: u32,
+        reply: ReplyWrite,
+    ) {
+        debug!(
+            "copy_file_range: ino={}, offset={} -> ino={}, offset={}, len={}",
+            ino_in, offset_in, ino_out, offset_out, len
+        );
+
+        if !self.writable {
+            warn!("copy_file_range rejected: filesystem is read-only");
+            reply.error(libc::EROFS);
+            return;
+        }
+
+        for ino in [ino_in, ino_out] {
+            if self.sync_engine.has_dirty_chunks(ino) {
+                if let Err(e) = self.bridge.flush(ino) {
+                    debug!("copy_file_range: flush of {} failed: {:?}", ino, e);
+                    reply.error(libc::EOPNOTSUPP);
+                    return;
+                }
+            }
+        }
+
+        let len = len.min(u32::MAX as u64);
+        let dst_offset = offset_out as u64;
+        match self
+            .bridge
+            .copy_range(ino_in, offset_in as u64, ino_out, dst_offset, len)
+        {
+            Ok(copied) => {
+                // The copy changed the destination behind the chunk cache's back
+                self.invalidate_chunks(ino_out, dst_offset, dst_offset + copied);
+                self.cache.attrs.invalidate(ino_out);
+                reply.written(copied as u32);
+            }
+            Err(e) => {
+                error!("copy_file_range error: {:?}", e);
+                reply.error(e.to_errno());
+            }
+        }
+    }
+
+    /// Find data or holes in sparse files (`SEEK_DATA`/`SEEK_HOLE`)
+    ///
+    /// The kernel handles the other whences itself.
+    fn lseek(
+        &mut self,
+        _req: &Request<'_>,
+        ino: Inode,
+        _fh: u64,
+        offset: i64,
+        whence: i32,
+        reply: fuser::ReplyLseek,
+    ) {
+        debug!("lseek: ino={}, offset={}, whence={}", ino, offset, whence);
+
+        let whence = match whence {
+            libc::SEEK_DATA => SeekWhence::Data,
+            libc::SEEK_HOLE => SeekWhence::Hole,
+            _ => {
+                reply.error(libc::EINVAL);
+                return;
+            }
+        };
+        if offset < 0 {
+            reply.error(libc::ENXIO);
+            return;
+        }
+        let offset = offset as u64;
+
+        // Unsynced writes may have filled holes the host still has; if they
+        // can't be pushed, report the file as all data, which is always allowed
+        let result = if self.sync_engine.has_dirty_chunks(ino) && self.bridge.flush(ino).is_err() {
+            self.bridge.getattr(ino).map(|attr| {
+                (offset < attr.size).then_some(match whence {
+                    SeekWhence::Data => offset,
+                    SeekWhence::Hole => attr.size,
+                })
+            })
+        } else {
+            self.bridge.seek(ino, offset, whence)
+        };
+
+        match result {
+            Ok(Some(pos)) => reply.offset(pos as i64),
+            Ok(None) => reply.error(libc::ENXIO),
+            Err(e) => {
+                error!("lseek error: {:?}", e);
+                reply.error(e.to_errno());
+            }
+        }
+    }
+
+    /// Preallocate space or punch holes on the host
+    fn fallocate(
+        &mut self,
+        _req: &Request<'_>,
+        ino: Inode,
+        _fh: u64,
+        offset: i64,
+        length: i64,
+        mode: i32,
+        reply: fuser::ReplyEmpty,
+    ) {
+        debug!(
+            "fallocate: ino={}, offset={}, len={}, mode={:#x}",
+            ino, offset, length, mode
+        );
+
+        if !self.writable {
+            warn!("fallocate rejected: filesystem is read-only");
+            reply.error(libc::EROFS);
+            return;
+        }
+        let Some(mode) = fallocate_mode(mode) else {
+            reply.error(libc::EOPNOTSUPP);
+            return;
+        };
+        if offset < 0 || length <= 0 {
+            reply.error(libc::EINVAL);
+            return;
+        }
+        let (offset, len) = (offset as u64, length as u64);
+
+        // Unsynced writes would undo a punched hole when they are synced
+        if self.sync_engine.has_dirty_chunks(ino) {
+            if let Err(e) = self.bridge.flush(ino) {
+                error!("fallocate: flush of {} failed: {:?}", ino, e);
+                reply.error(e.to_errno());
+                return;
+            }
+        }
+
+        let old_size = self.cache.attrs.get(ino).map_or(0, |attr| attr.size);
+        match self.bridge.fallocate(ino, offset, len, mode) {
+            Ok(attr) => {
+                let end = offset.saturating_add(len).min(attr.size);
+                match mode {
+                    FallocateMode::PunchHole => self.invalidate_chunks(ino, offset, end),
+                    // The chunk that was last may have grown
+                    FallocateMode::Allocate => {
+                        self.invalidate_chunks(ino, offset.min(old_size), end)
+                    }
+                    FallocateMode::AllocateKeepSize => {}
+                }
+                self.cache.attrs.insert(ino, attr);
+                reply.ok();
+            }
+            Err(e) => {
+                error!("fallocate error: {:?}", e);
+                reply.error(e.to_errno());
+            }
+        }
+    }
+
     /// Create a regular file (Phase 7)
     fn create(
         &mut self,
@@ -721,7 +1221,7 @@ impl Filesystem for WormholeFS {
                 // Invalidate parent directory cache
                 self.cache.dirs.invalidate(parent);
 
-                reply.created(&TTL, &Self::to_fuser_attr(&attr), 0, 0, 0);
+                reply.created(&self.ttl(), &Self::to_fuser_attr(&attr), 0, 0, 0);
             }
             Err(e) => {
                 error!("create error: {:?}", e);
@@ -772,7 +1272,7 @@ impl Filesystem for WormholeFS {
             Ok(attr) => {
                 self.cache.attrs.insert(attr.inode, attr.clone());
                 self.cache.dirs.invalidate(parent);
-                reply.entry(&TTL, &Self::to_fuser_attr(&attr), 0);
+                reply.entry(&self.ttl(), &Self::to_fuser_attr(&attr), 0);
             }
             Err(e) => {
                 error!("mknod error: {:?}", e);
@@ -850,7 +1350,7 @@ impl Filesystem for WormholeFS {
             Ok(attr) => {
                 self.cache.attrs.insert(attr.inode, attr.clone());
                 self.cache.dirs.invalidate(parent);
-                reply.entry(&TTL, &Self::to_fuser_attr(&attr), 0);
+                reply.entry(&self.ttl(), &Self::to_fuser_attr(&attr), 0);
             }
             Err(e) => {
                 error!("mkdir error: {:?}", e);
@@ -997,10 +1497,37 @@ impl Filesystem for WormholeFS {
                 .unwrap_or(0),
         });
 
-        match self.bridge.setattr(ino, size, mode, mtime_secs, atime_secs) {
+        // Resizing a file being rewritten ends the rewrite first
+        if size.is_some() {
+            if let Err(e) = self.end_rewrite(ino) {
+                reply.error(e.to_errno());
+                return;
+            }
+        }
+
+        // Emptying a file while holding its lock starts a rewrite of it
+        let mut size = size;
+        if size == Some(0) && self.sync_engine.has_lock(ino, LockType::Exclusive) {
+            match self.begin_rewrite(ino) {
+                Ok(true) => size = None,
+                Ok(false) => {}
+                Err(e) => {
+                    error!("setattr: rewriting {} failed: {:?}", ino, e);
+                    reply.error(e.to_errno());
+                    return;
+                }
+            }
+        }
+
+        let result = if size.is_none() && mode.is_none() && mtime.is_none() && atime.is_none() {
+            self.cached_attr(ino)
+        } else {
+            self.bridge.setattr(ino, size, mode, mtime_secs, atime_secs)
+        };
+        match result {
             Ok(attr) => {
                 self.cache.attrs.insert(ino, attr.clone());
-                reply.attr(&TTL, &Self::to_fuser_attr(&attr));
+                reply.attr(&self.ttl(), &Self::to_fuser_attr(&self.rewritten(attr)));
             }
             Err(e) => {
                 error!("setattr error: {:?}", e);
@@ -1024,6 +1551,23 @@ mod tests {
         assert!(matches!(fuser_attr.kind, FuserFileType::RegularFile));
     }
 
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_fallocate_modes() {
+        assert_eq!(fallocate_mode(0), Some(FallocateMode::Allocate));
+        assert_eq!(
+            fallocate_mode(libc::FALLOC_FL_KEEP_SIZE),
+            Some(FallocateMode::AllocateKeepSize)
+        );
+        assert_eq!(
+            fallocate_mode(libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE),
+            Some(FallocateMode::PunchHole)
+        );
+        // Punching a hole must keep the size, as on Linux
+        assert_eq!(fallocate_mode(libc::FALLOC_FL_PUNCH_HOLE), None);
+        assert_eq!(fallocate_mode(libc::FALLOC_FL_ZERO_RANGE), None);
+    }
+
     #[test]
     fn test_dir_attr_conversion() {
         let attr = FileAttr::directory(1);
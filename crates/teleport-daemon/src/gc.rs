@@ -9,13 +9,20 @@
 //! - HIGH_WATERMARK: 90% - start GC when cache reaches this
 //! - LOW_WATERMARK: 70% - GC until cache reaches this
 //! - GC_INTERVAL: 60 seconds between checks
+//!
+//! `GarbageCollector::from_config` takes the size limit and interval from
+//! the `[cache]` section of the config instead, and `set_config` changes
+//! them while the loop runs.
 
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
-use tokio::time::interval;
+use parking_lot::RwLock;
 use tracing::{debug, error, info, warn};
 
+use teleport_core::config::CacheConfig;
+
 use crate::disk_cache::DiskCache;
 
 /// Maximum disk cache size in bytes (10GB)
@@ -33,9 +40,10 @@ pub const LOW_WATERMARK: f64 = 0.7;
 /// Garbage collector for disk cache
 pub struct GarbageCollector {
     disk_cache: Arc<DiskCache>,
-    max_bytes: u64,
+    max_bytes: AtomicU64,
     high_watermark: f64,
     low_watermark: f64,
+    interval: RwLock<Duration>,
 }
 
 impl GarbageCollector {
@@ -43,12 +51,24 @@ impl GarbageCollector {
     pub fn new(disk_cache: Arc<DiskCache>) -> Self {
         Self {
             disk_cache,
-            max_bytes: MAX_CACHE_BYTES,
+            max_bytes: AtomicU64::new(MAX_CACHE_BYTES),
             high_watermark: HIGH_WATERMARK,
             low_watermark: LOW_WATERMARK,
+            interval: RwLock::new(Duration::from_secs(GC_INTERVAL_SECS)),
         }
     }
 
+    /// Create with the size limit and interval from `config`
+    pub fn from_config(disk_cache: Arc<DiskCache>, config: &CacheConfig) -> Self {
+        Self::with_config(
+            disk_cache,
+            config.max_disk_bytes,
+            HIGH_WATERMARK,
+            LOW_WATERMARK,
+        )
+        .with_interval(Duration::from_secs(config.gc_interval_secs))
+    }
+
     /// Create with custom configuration
     pub fn with_config(
         disk_cache: Arc<DiskCache>,
@@ -58,42 +78,56 @@ impl GarbageCollector {
     ) -> Self {
         Self {
             disk_cache,
-            max_bytes,
+            max_bytes: AtomicU64::new(max_bytes),
             high_watermark,
             low_watermark,
+            interval: RwLock::new(Duration::from_secs(GC_INTERVAL_SECS)),
         }
     }
 
-    /// Run the garbage collection loop (call from a tokio task)
-    pub async fn run_loop(self) {
-        let mut tick = interval(Duration::from_secs(GC_INTERVAL_SECS));
+    /// Set the time between checks
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        *self.interval.get_mut() = interval;
+        self
+    }
 
-        loop {
-            tick.tick().await;
+    /// Apply a changed `[cache]` section; the next check uses the new values
+    pub fn set_config(&self, config: &CacheConfig) {
+        self.max_bytes
+            .store(config.max_disk_bytes, Ordering::Relaxed);
+        *self.interval.write() = Duration::from_secs(config.gc_interval_secs);
+    }
 
+    /// Run the garbage collection loop (call from a tokio task)
+    pub async fn run_loop(self: Arc<Self>) {
+        loop {
             if let Err(e) = self.maybe_gc() {
                 error!("GC error: {}", e);
             }
+
+            let interval = *self.interval.read();
+            tokio::time::sleep(interval).await;
         }
     }
 
     /// Check if GC is needed and run if so
     pub fn maybe_gc(&self) -> Result<(), GcError> {
+        let max_bytes = self.max_bytes.load(Ordering::Relaxed);
         let current_size = self.disk_cache.total_size();
-        let threshold = (self.max_bytes as f64 * self.high_watermark) as u64;
+        let threshold = (max_bytes as f64 * self.high_watermark) as u64;
 
         if current_size > threshold {
-            let percent = (current_size as f64 / self.max_bytes as f64 * 100.0) as u32;
+            let percent = (current_size as f64 / max_bytes as f64 * 100.0) as u32;
             info!(
                 "Starting GC: {} bytes / {} max ({}%)",
-                current_size, self.max_bytes, percent
+                current_size, max_bytes, percent
             );
 
             self.gc_to_target()?;
         } else {
             debug!(
                 "GC check: {} bytes / {} max - below threshold",
-                current_size, self.max_bytes
+                current_size, max_bytes
             );
         }
 
@@ -102,7 +136,8 @@ impl GarbageCollector {
 
     /// Run GC until cache size is below low watermark
     fn gc_to_target(&self) -> Result<(), GcError> {
-        let target_size = (self.max_bytes as f64 * self.low_watermark) as u64;
+        let max_bytes = self.max_bytes.load(Ordering::Relaxed);
+        let target_size = (max_bytes as f64 * self.low_watermark) as u64;
         let mut current_size = self.disk_cache.total_size();
 
         // Get all entries sorted by last access time (oldest first)
@@ -153,14 +188,15 @@ impl GarbageCollector {
 
     /// Get current cache statistics
     pub fn stats(&self) -> CacheStats {
+        let max_bytes = self.max_bytes.load(Ordering::Relaxed);
         let current_size = self.disk_cache.total_size();
         let entry_count = self.disk_cache.entry_count();
 
         CacheStats {
             current_bytes: current_size,
-            max_bytes: self.max_bytes,
+            max_bytes,
             entry_count,
-            usage_percent: (current_size as f64 / self.max_bytes as f64 * 100.0) as u32,
+            usage_percent: (current_size as f64 / max_bytes as f64 * 100.0) as u32,
         }
     }
 }
@@ -283,4 +319,24 @@ mod tests {
         assert!(stats.bytes_freed > 0);
         assert!(stats.bytes_remaining <= 300);
     }
+
+    #[test]
+    fn test_set_config_changes_limit() {
+        let temp_dir = TempDir::new().unwrap();
+        let disk_cache = Arc::new(DiskCache::with_dir(temp_dir.path().to_path_buf()).unwrap());
+        let gc = GarbageCollector::with_config(disk_cache.clone(), 1000, 0.9, 0.7);
+
+        disk_cache.write(ChunkId::new(1, 0), &[0; 50]).unwrap();
+        disk_cache.write(ChunkId::new(1, 1), &[0; 50]).unwrap();
+        gc.maybe_gc().unwrap();
+        assert_eq!(disk_cache.total_size(), 100);
+
+        gc.set_config(&CacheConfig {
+            max_disk_bytes: 100,
+            ..CacheConfig::default()
+        });
+        gc.maybe_gc().unwrap();
+        assert!(disk_cache.total_size() <= 70);
+        assert_eq!(gc.stats().max_bytes, 100);
+    }
 }
@@ -163,6 +163,7 @@ where
         host_name: hostname::get()
             .map(|h| h.to_string_lossy().into_owned())
             .unwrap_or_else(|_| "wormhole-host".into()),
+        writable: true,
     };
 
     // Emit host ready event
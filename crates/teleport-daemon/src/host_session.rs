@@ -0,0 +1,514 @@
+//! Client sessions on a host, shared by `WormholeHost` and `MultiShareHost`
+//!
+//! Once a client is through the handshake, both hosts pass each of its
+//! requests to `serve_request` with the `ShareSession` it is for. That checks
+//! the client's access, dispatches to the share through the session's open
+//! files, meters the response against the share's bandwidth and usage limits,
+//! and records it in the share's audit log.
+//!
+//! `Housekeeping` runs the periodic upkeep both hosts need while serving.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+use teleport_core::{ErrorCode, ErrorMessage, NetMessage, PROTOCOL_VERSION};
+
+use crate::access::{check_request, check_writable, AccessLevel};
+use crate::audit::{AuditEvent, AuditSession};
+use crate::bandwidth::{metered_size, BandwidthLimiter, Priority};
+use crate::file_handles::FileHandles;
+use crate::lock_manager::LockManager;
+use crate::net::{recv_message, send_message, CertFingerprint, ConnectionError};
+use crate::rate_limiter::RateLimiter;
+use crate::share_backend::{succeeded, ShareBackend};
+use crate::share_limits::ShareUsage;
+
+/// SECURITY: Handshake timeout - prevent clients from holding connections without completing handshake
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often share backends do their housekeeping (e.g. checking `.wormholeignore` for changes)
+const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How often expired rate limiter entries and idle bandwidth budgets are dropped
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// One client's view of one share
+pub struct ShareSession {
+    /// Access level granted by the share's access list
+    pub level: AccessLevel,
+    /// Whether the share accepts modifications
+    pub writable: bool,
+    /// Where the share's files come from
+    pub backend: Arc<dyn ShareBackend>,
+    /// Files kept open for the client's reads and writes
+    pub files: FileHandles,
+    /// Expiry and usage limits, with the usage so far
+    pub usage: Arc<ShareUsage>,
+    /// Bandwidth budgets, and the share's name in them
+    pub bandwidth: Arc<BandwidthLimiter>,
+    pub bandwidth_share: String,
+    /// Audit context; the session is logged as closed once this is dropped
+    pub audit: Option<AuditSession>,
+}
+
+impl ShareSession {
+    /// The client's access level, which is none once the share has expired
+    pub fn level(&self) -> AccessLevel {
+        if self.usage.is_expired() {
+            AccessLevel::None
+        } else {
+            self.level
+        }
+    }
+
+    pub fn can_write(&self) -> bool {
+        self.writable && self.level() >= AccessLevel::Write
+    }
+}
+
+/// Capabilities to advertise in `HelloAck` for a client served from `share`
+///
+/// Write is only advertised when this client may actually write, so clients
+/// that can't mount read-only.
+pub fn capabilities(share: Option<&ShareSession>) -> Vec<String> {
+    let mut capabilities = vec!["read".into(), "lock".into()];
+    if share.is_some_and(ShareSession::can_write) {
+        capabilities.push("write".into());
+        capabilities.push("atomic-write".into());
+    }
+    capabilities
+}
+
+/// Serve one request of the client `holder_id` from `share`
+pub async fn serve_request(
+    request: NetMessage,
+    share: &ShareSession,
+    holder_id: &str,
+    lock_manager: &LockManager,
+) -> NetMessage {
+    let level = share.level();
+    if let Err(denied) =
+        check_request(&request, level).and_then(|()| check_writable(&request, share.writable))
+    {
+        debug!(
+            "Denied request from client {} ({} access{})",
+            holder_id,
+            level,
+            if share.writable {
+                ""
+            } else {
+                ", read-only share"
+            }
+        );
+        return denied;
+    }
+
+    let backend = share.backend.as_ref();
+    let priority = Priority::of_request(&request);
+    // Resolve paths before the request moves or renames them
+    let audit_event = share
+        .audit
+        .as_ref()
+        .and_then(|_| audit_event(&request, backend));
+    // Writes are logged in aggregate until the client is done with the file
+    let ends_writes = matches!(
+        request,
+        NetMessage::ReleaseLock(_) | NetMessage::CommitWrite(_)
+    );
+
+    let response = share.files.dispatch(request, lock_manager, holder_id).await;
+
+    if let Some(priority) = priority {
+        let bytes = metered_size(&response);
+        share
+            .bandwidth
+            .acquire(&share.bandwidth_share, holder_id, bytes, priority)
+            .await;
+        share.usage.record_bytes(bytes);
+    }
+
+    if let Some(audit) = &share.audit {
+        match (&response, audit_event) {
+            (NetMessage::ReadChunkResponse(chunk), _) => {
+                if let Some(path) = backend.display_path(chunk.chunk_id.inode, None) {
+                    audit.record_read(path, chunk.chunk_len() as u64);
+                }
+            }
+            // Logged with what was actually copied, which may be less than requested
+            (NetMessage::CopyRangeResponse(copy), Some(AuditEvent::Write { path, offset, .. }))
+                if copy.success && copy.copied > 0 =>
+            {
+                audit.record_write(path, offset, copy.copied);
+            }
+            (NetMessage::CopyRangeResponse(_), _) => {}
+            (response, Some(event)) if succeeded(response) => audit.record(event),
+            _ => {}
+        }
+        if ends_writes {
+            audit.flush_writes();
+        }
+    }
+
+    response
+}
+
+/// Audit entry for a modifying request, with paths relative to the share root
+fn audit_event(request: &NetMessage, backend: &dyn ShareBackend) -> Option<AuditEvent> {
+    let path = |inode, name: Option<&str>| backend.display_path(inode, name);
+    let event = match request {
+        NetMessage::CreateFile(req) => AuditEvent::CreateFile {
+            path: path(req.parent, Some(&req.name))?,
+        },
+        NetMessage::CreateDir(req) => AuditEvent::CreateDir {
+            path: path(req.parent, Some(&req.name))?,
+        },
+        NetMessage::DeleteFile(req) => AuditEvent::DeleteFile {
+            path: path(req.parent, Some(&req.name))?,
+        },
+        NetMessage::DeleteDir(req) => AuditEvent::DeleteDir {
+            path: path(req.parent, Some(&req.name))?,
+        },
+        NetMessage::Rename(req) => AuditEvent::Rename {
+            path: path(req.old_parent, Some(&req.old_name))?,
+            to: path(req.new_parent, Some(&req.new_name))?,
+        },
+        NetMessage::WriteChunk(req) => AuditEvent::Write {
+            path: path(req.chunk_id.inode, None)?,
+            offset: req.chunk_id.byte_offset(),
+            bytes: req.data.len() as u64,
+        },
+        // Bytes are filled in from the response
+        NetMessage::CopyRange(req) => AuditEvent::Write {
+            path: path(req.dst_inode, None)?,
+            offset: req.dst_offset,
+            bytes: 0,
+        },
+        NetMessage::Fallocate(req) => AuditEvent::Write {
+            path: path(req.inode, None)?,
+            offset: req.offset,
+            bytes: req.len,
+        },
+        NetMessage::Truncate(req) => AuditEvent::SetAttr {
+            path: path(req.inode, None)?,
+            size: Some(req.size),
+            mode: None,
+        },
+        NetMessage::SetAttr(req) => AuditEvent::SetAttr {
+            path: path(req.inode, None)?,
+            size: req.size,
+            mode: req.mode,
+        },
+        _ => return None,
+    };
+    Some(event)
+}
+
+/// Background upkeep of a serving host; stops when dropped
+pub struct Housekeeping {
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl Housekeeping {
+    /// Start pruning `rate_limiter` and `bandwidth`, and maintaining `backends`
+    pub fn spawn(
+        rate_limiter: Arc<RateLimiter>,
+        bandwidth: Arc<BandwidthLimiter>,
+        backends: Vec<Arc<dyn ShareBackend>>,
+    ) -> Self {
+        let cleanup = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+            loop {
+                interval.tick().await;
+                rate_limiter.cleanup_expired();
+                bandwidth.prune_idle();
+            }
+        });
+
+        // Filter reloads, stale inode cleanup and the like touch the disk
+        let backends = Arc::new(backends);
+        let maintenance = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(MAINTENANCE_INTERVAL);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                let backends = backends.clone();
+                let maintained = tokio::task::spawn_blocking(move || {
+                    for backend in backends.iter() {
+                        backend.maintain();
+                    }
+                })
+                .await;
+                if let Err(e) = maintained {
+                    warn!("Share maintenance failed: {}", e);
+                }
+            }
+        });
+
+        Self {
+            tasks: vec![cleanup, maintenance],
+        }
+    }
+}
+
+impl Drop for Housekeeping {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+/// Send a handshake error and give the client a moment to receive it
+///
+/// The connection is dropped as soon as the handshake fails, which would
+/// otherwise discard the error before it reaches the client.
+pub async fn reject_handshake(
+    send: &mut quinn::SendStream,
+    code: ErrorCode,
+    message: String,
+) -> Result<(), ConnectionError> {
+    let error = NetMessage::Error(ErrorMessage {
+        code,
+        message,
+        related_inode: None,
+    });
+    send_final(send, &error).await
+}
+
+/// Send the last message of a stream and wait briefly for the client to read it
+pub async fn send_final(
+    send: &mut quinn::SendStream,
+    message: &NetMessage,
+) -> Result<(), ConnectionError> {
+    send_message(send, message).await?;
+    let _ = send.finish();
+    let _ = tokio::time::timeout(Duration::from_secs(1), send.stopped()).await;
+    Ok(())
+}
+
+/// Accept the handshake stream and the client's Hello
+///
+/// Clients speaking another protocol version are told so and refused.
+/// Returns the stream, to finish the handshake on, and the client's random ID.
+pub async fn accept_hello(
+    connection: &quinn::Connection,
+) -> Result<(quinn::SendStream, quinn::RecvStream, [u8; 16]), ConnectionError> {
+    let (mut send, mut recv) = tokio::time::timeout(HANDSHAKE_TIMEOUT, connection.accept_bi())
+        .await
+        .map_err(|_| ConnectionError::StreamAccept("handshake timeout waiting for stream".into()))?
+        .map_err(|e| ConnectionError::StreamAccept(e.to_string()))?;
+
+    let hello = tokio::time::timeout(HANDSHAKE_TIMEOUT, recv_message(&mut recv))
+        .await
+        .map_err(|_| ConnectionError::Receive("handshake timeout waiting for Hello".into()))??;
+
+    match hello {
+        NetMessage::Hello(h) if h.protocol_version != PROTOCOL_VERSION => {
+            reject_handshake(
+                &mut send,
+                ErrorCode::ProtocolError,
+                format!(
+                    "protocol version mismatch: expected {}, got {}",
+                    PROTOCOL_VERSION, h.protocol_version
+                ),
+            )
+            .await?;
+            Err(ConnectionError::Protocol(
+                teleport_core::ProtocolError::VersionMismatch {
+                    expected: PROTOCOL_VERSION,
+                    actual: h.protocol_version,
+                },
+            ))
+        }
+        NetMessage::Hello(h) => Ok((send, recv, h.client_id)),
+        _ => Err(ConnectionError::Receive("expected Hello".into())),
+    }
+}
+
+/// Lock holder and audit identity of a client
+///
+/// The certificate fingerprint if the client has an identity, its random
+/// Hello ID otherwise.
+pub fn holder_id(fingerprint: Option<&CertFingerprint>, client_id: &[u8; 16]) -> String {
+    match fingerprint {
+        Some(fp) => hex::encode(fp),
+        None => format!(
+            "{:02x}{:02x}{:02x}{:02x}",
+            client_id[0], client_id[1], client_id[2], client_id[3]
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::{read_log, AuditLog};
+    use crate::file_handles::ChunkIo;
+    use crate::local_backend::LocalBackend;
+    use crate::memory_backend::MemoryBackend;
+    use crate::share_limits::ShareLimits;
+    use teleport_core::{
+        ChunkId, CopyRangeRequest, CreateFileRequest, LockRequest, LockType, ReadChunkRequest,
+        ReleaseRequest, WriteChunkRequest, ROOT_INODE,
+    };
+
+    fn open_audit(log: &Arc<AuditLog>, level: AccessLevel) -> AuditSession {
+        AuditSession::open(
+            log.clone(),
+            &[1; 16],
+            "client",
+            "10.0.0.1:5000".parse().unwrap(),
+            level,
+        )
+    }
+
+    fn share_session(
+        backend: Arc<dyn ShareBackend>,
+        level: AccessLevel,
+        audit: Option<AuditSession>,
+    ) -> ShareSession {
+        ShareSession {
+            level,
+            writable: true,
+            files: FileHandles::new(backend.clone(), ChunkIo::new()),
+            backend,
+            usage: Arc::new(ShareUsage::new(ShareLimits::default())),
+            bandwidth: Arc::new(BandwidthLimiter::default()),
+            bandwidth_share: "docs".into(),
+            audit,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_requests_are_checked_metered_and_audited() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = Arc::new(AuditLog::open_at(&dir.path().join("docs.jsonl"), "docs").unwrap());
+        let audit = open_audit(&log, AccessLevel::Read);
+        let backend = Arc::new(MemoryBackend::new().with_file("a.txt", "hello"));
+        let share = share_session(backend.clone(), AccessLevel::Read, Some(audit));
+        let lock_manager = LockManager::default();
+        assert_eq!(capabilities(Some(&share)), ["read", "lock"]);
+
+        let create = NetMessage::CreateFile(CreateFileRequest {
+            parent: ROOT_INODE,
+            name: "b.txt".into(),
+            mode: 0o644,
+            lock_token: None,
+        });
+        let response = serve_request(create, &share, "client", &lock_manager).await;
+        assert!(matches!(response, NetMessage::Error(e) if e.code == ErrorCode::PermissionDenied));
+        assert!(!backend.exists("b.txt"));
+
+        let inode = backend.lookup(ROOT_INODE, "a.txt").unwrap().unwrap().inode;
+        let read = NetMessage::ReadChunk(ReadChunkRequest {
+            chunk_id: ChunkId::new(inode, 0),
+            priority: 0,
+        });
+        let response = serve_request(read, &share, "client", &lock_manager).await;
+        assert!(matches!(response, NetMessage::ReadChunkResponse(_)));
+        assert_eq!(share.usage.bytes_served(), metered_size(&response));
+
+        drop(share);
+        let ops: Vec<_> = read_log(log.path())
+            .unwrap()
+            .into_iter()
+            .map(|r| r.event.name())
+            .collect();
+        assert_eq!(ops, ["session_open", "read", "session_close"]);
+    }
+
+    #[tokio::test]
+    async fn test_writes_are_audited_once_per_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = Arc::new(AuditLog::open_at(&dir.path().join("docs.jsonl"), "docs").unwrap());
+        let audit = open_audit(&log, AccessLevel::Write);
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(root.path().join("a.txt"), "hello").unwrap();
+        std::fs::write(root.path().join("b.txt"), "").unwrap();
+        let backend = Arc::new(LocalBackend::new(root.path()));
+        let share = share_session(backend.clone(), AccessLevel::Write, Some(audit));
+        let lock_manager = LockManager::default();
+        let serve = |request| serve_request(request, &share, "client", &lock_manager);
+
+        let a = backend.lookup(ROOT_INODE, "a.txt").unwrap().unwrap().inode;
+        let b = backend.lookup(ROOT_INODE, "b.txt").unwrap().unwrap().inode;
+        let lock = serve(NetMessage::AcquireLock(LockRequest {
+            inode: b,
+            lock_type: LockType::Exclusive,
+            timeout_ms: 0,
+        }))
+        .await;
+        let NetMessage::AcquireLockResponse(lock) = lock else {
+            panic!("lock not granted: {:?}", lock);
+        };
+        let token = lock.token.unwrap();
+
+        for chunk in 0..3 {
+            let data = vec![b'x'; 100];
+            let write = NetMessage::WriteChunk(WriteChunkRequest {
+                chunk_id: ChunkId::new(b, chunk),
+                checksum: teleport_core::crypto::checksum(&data),
+                data,
+                lock_token: token.clone(),
+            });
+            assert!(succeeded(&serve(write).await));
+        }
+        // Only the five bytes of a.txt are there to copy
+        let copy = NetMessage::CopyRange(CopyRangeRequest {
+            src_inode: a,
+            src_offset: 0,
+            dst_inode: b,
+            dst_offset: 0,
+            len: 1 << 20,
+            lock_token: token.clone(),
+        });
+        assert!(succeeded(&serve(copy).await));
+        assert_eq!(read_log(log.path()).unwrap().len(), 1);
+
+        serve(NetMessage::ReleaseLock(ReleaseRequest { token })).await;
+        let records = read_log(log.path()).unwrap();
+        assert_eq!(records.len(), 2);
+        assert!(matches!(
+            &records[1].event,
+            AuditEvent::Write { path, offset: 0, bytes } if path == "/b.txt" && *bytes == 3 * 100 + 5
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_housekeeping_stops_when_dropped() {
+        let backend: Arc<dyn ShareBackend> = Arc::new(MemoryBackend::new());
+        let housekeeping = Housekeeping::spawn(
+            Arc::new(RateLimiter::new()),
+            Arc::new(BandwidthLimiter::default()),
+            vec![backend.clone()],
+        );
+        tokio::task::yield_now().await;
+        assert!(Arc::strong_count(&backend) > 1);
+
+        // The tasks and their hold on the backend are gone once dropped
+        drop(housekeeping);
+        let started = std::time::Instant::now();
+        while Arc::strong_count(&backend) > 1 {
+            assert!(started.elapsed() < Duration::from_secs(5));
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_expired_share_refuses_requests() {
+        let backend = Arc::new(MemoryBackend::new().with_file("a.txt", "hello"));
+        let share = share_session(backend, AccessLevel::Read, None);
+        share.usage.expire();
+        assert_eq!(share.level(), AccessLevel::None);
+
+        let lookup = NetMessage::Lookup(teleport_core::LookupRequest {
+            parent: ROOT_INODE,
+            name: "a.txt".into(),
+        });
+        let response = serve_request(lookup, &share, "client", &LockManager::default()).await;
+        assert!(matches!(response, NetMessage::Error(e) if e.code == ErrorCode::PermissionDenied));
+    }
+}
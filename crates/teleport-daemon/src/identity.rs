@@ -0,0 +1,267 @@
+//! Persistent TLS identity
+//!
+//! A host keeps the same self-signed certificate across restarts so that
+//! clients can pin its fingerprint (see `known_peers`). The certificate and
+//! key are stored as PEM in the config directory:
+//!
+//! ```text
+//! ~/.config/wormhole/identity/
+//! ├── host.crt
+//! ├── host.key   # mode 0600
+//! ├── client.crt
+//! └── client.key # mode 0600
+//! ```
+//!
+//! The client identity is presented to hosts during the TLS handshake (mutual
+//! TLS) so they can tell clients apart. Users may supply their own certificate/key pair instead (`--tls-cert`,
+//! `--tls-key`); the fingerprint is always computed over the leaf certificate.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use directories::ProjectDirs;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tracing::info;
+
+use crate::net::{compute_cert_fingerprint, CertFingerprint};
+
+/// Name of the host identity files
+pub const HOST_IDENTITY: &str = "host";
+
+/// Name of the client identity files
+pub const CLIENT_IDENTITY: &str = "client";
+
+/// A certificate chain, its private key and the leaf fingerprint
+pub struct Identity {
+    certs: Vec<CertificateDer<'static>>,
+    key: PrivateKeyDer<'static>,
+    fingerprint: CertFingerprint,
+}
+
+impl Identity {
+    /// Generate a fresh self-signed identity (not persisted)
+    pub fn generate() -> Result<Self, IdentityError> {
+        let (cert_pem, key_pem) = generate_pem()?;
+        Self::from_pem(cert_pem.as_bytes(), key_pem.as_bytes())
+    }
+
+    /// Parse an identity from PEM-encoded certificate chain and private key
+    pub fn from_pem(cert_pem: &[u8], key_pem: &[u8]) -> Result<Self, IdentityError> {
+        let certs = CertificateDer::pem_slice_iter(cert_pem)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| IdentityError::InvalidCertificate(e.to_string()))?;
+        let leaf = certs
+            .first()
+            .ok_or_else(|| IdentityError::InvalidCertificate("no certificate found".into()))?;
+        let fingerprint = compute_cert_fingerprint(leaf);
+        let key = PrivateKeyDer::from_pem_slice(key_pem)
+            .map_err(|e| IdentityError::InvalidKey(e.to_string()))?;
+
+        Ok(Self {
+            certs,
+            key,
+            fingerprint,
+        })
+    }
+
+    /// Load an identity from user-supplied PEM files
+    pub fn from_pem_files(cert_path: &Path, key_path: &Path) -> Result<Self, IdentityError> {
+        let cert_pem = fs::read(cert_path)
+            .map_err(|e| IdentityError::Io(format!("{}: {}", cert_path.display(), e)))?;
+        let key_pem = fs::read(key_path)
+            .map_err(|e| IdentityError::Io(format!("{}: {}", key_path.display(), e)))?;
+        Self::from_pem(&cert_pem, &key_pem)
+    }
+
+    /// Default directory for stored identities
+    pub fn default_dir() -> Option<PathBuf> {
+        ProjectDirs::from("", "", "wormhole").map(|dirs| dirs.config_dir().join("identity"))
+    }
+
+    /// Load the named identity from `dir`, creating it on first use
+    pub fn load_or_create(dir: &Path, name: &str) -> Result<Self, IdentityError> {
+        let (cert_path, key_path) = identity_paths(dir, name);
+        if cert_path.exists() && key_path.exists() {
+            return Self::from_pem_files(&cert_path, &key_path);
+        }
+
+        let identity = Self::create(dir, name)?;
+        info!(
+            "Created new {} identity with fingerprint {}",
+            name,
+            hex::encode(identity.fingerprint)
+        );
+        Ok(identity)
+    }
+
+    /// Load this machine's client identity from the default directory, creating it on first use
+    pub fn default_client() -> Result<Self, IdentityError> {
+        let dir = Self::default_dir().ok_or(IdentityError::NoConfigDir)?;
+        Self::load_or_create(&dir, CLIENT_IDENTITY)
+    }
+
+    /// Load the named identity from `dir` if it exists
+    pub fn load(dir: &Path, name: &str) -> Result<Option<Self>, IdentityError> {
+        let (cert_path, key_path) = identity_paths(dir, name);
+        if cert_path.exists() && key_path.exists() {
+            Self::from_pem_files(&cert_path, &key_path).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Replace the named identity with a freshly generated one
+    ///
+    /// The previous files are kept with an `.old` suffix.
+    pub fn rotate(dir: &Path, name: &str) -> Result<Self, IdentityError> {
+        let (cert_path, key_path) = identity_paths(dir, name);
+        for path in [&cert_path, &key_path] {
+            if path.exists() {
+                let backup = path.with_extension(format!(
+                    "{}.old",
+                    path.extension()
+                        .and_then(|e| e.to_str())
+                        .unwrap_or_default()
+                ));
+                fs::rename(path, backup).map_err(|e| IdentityError::Io(e.to_string()))?;
+            }
+        }
+        Self::create(dir, name)
+    }
+
+    fn create(dir: &Path, name: &str) -> Result<Self, IdentityError> {
+        let (cert_pem, key_pem) = generate_pem()?;
+        let identity = Self::from_pem(cert_pem.as_bytes(), key_pem.as_bytes())?;
+
+        let (cert_path, key_path) = identity_paths(dir, name);
+        fs::create_dir_all(dir).map_err(|e| IdentityError::Io(e.to_string()))?;
+        write_private(&key_path, key_pem.as_bytes())?;
+        fs::write(&cert_path, cert_pem).map_err(|e| IdentityError::Io(e.to_string()))?;
+
+        Ok(identity)
+    }
+
+    /// Certificate chain (leaf first)
+    pub fn certs(&self) -> Vec<CertificateDer<'static>> {
+        self.certs.clone()
+    }
+
+    /// Private key
+    pub fn key(&self) -> PrivateKeyDer<'static> {
+        self.key.clone_key()
+    }
+
+    /// BLAKE3 fingerprint of the leaf certificate
+    pub fn fingerprint(&self) -> CertFingerprint {
+        self.fingerprint
+    }
+}
+
+/// Paths of the certificate and key files for a named identity
+pub fn identity_paths(dir: &Path, name: &str) -> (PathBuf, PathBuf) {
+    (
+        dir.join(format!("{}.crt", name)),
+        dir.join(format!("{}.key", name)),
+    )
+}
+
+fn generate_pem() -> Result<(String, String), IdentityError> {
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()])
+        .map_err(|e| IdentityError::Generate(e.to_string()))?;
+    let cert_pem = cert
+        .serialize_pem()
+        .map_err(|e| IdentityError::Generate(e.to_string()))?;
+    Ok((cert_pem, cert.serialize_private_key_pem()))
+}
+
+/// Write a file readable only by the current user
+fn write_private(path: &Path, contents: &[u8]) -> Result<(), IdentityError> {
+    use std::io::Write;
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .map_err(|e| IdentityError::Io(e.to_string()))?;
+    file.write_all(contents)
+        .map_err(|e| IdentityError::Io(e.to_string()))
+}
+
+/// Identity errors
+#[derive(Debug, Clone)]
+pub enum IdentityError {
+    /// I/O error
+    Io(String),
+    /// Certificate could not be parsed
+    InvalidCertificate(String),
+    /// Private key could not be parsed
+    InvalidKey(String),
+    /// Certificate generation failed
+    Generate(String),
+    /// No config directory available
+    NoConfigDir,
+}
+
+impl std::fmt::Display for IdentityError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IdentityError::Io(e) => write!(f, "I/O error: {}", e),
+            IdentityError::InvalidCertificate(e) => write!(f, "Invalid certificate: {}", e),
+            IdentityError::InvalidKey(e) => write!(f, "Invalid private key: {}", e),
+            IdentityError::Generate(e) => write!(f, "Certificate generation failed: {}", e),
+            IdentityError::NoConfigDir => write!(f, "No configuration directory available"),
+        }
+    }
+}
+
+impl std::error::Error for IdentityError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_identity_is_stable_across_loads() {
+        let dir = TempDir::new().unwrap();
+        let first = Identity::load_or_create(dir.path(), HOST_IDENTITY).unwrap();
+        let second = Identity::load_or_create(dir.path(), HOST_IDENTITY).unwrap();
+        assert_eq!(first.fingerprint(), second.fingerprint());
+        assert_eq!(first.certs(), second.certs());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_key_file_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new().unwrap();
+        Identity::load_or_create(dir.path(), HOST_IDENTITY).unwrap();
+        let (_, key_path) = identity_paths(dir.path(), HOST_IDENTITY);
+        let mode = fs::metadata(key_path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn test_rotate_changes_fingerprint() {
+        let dir = TempDir::new().unwrap();
+        let old = Identity::load_or_create(dir.path(), HOST_IDENTITY).unwrap();
+        let new = Identity::rotate(dir.path(), HOST_IDENTITY).unwrap();
+        assert_ne!(old.fingerprint(), new.fingerprint());
+
+        let reloaded = Identity::load(dir.path(), HOST_IDENTITY).unwrap().unwrap();
+        assert_eq!(reloaded.fingerprint(), new.fingerprint());
+        assert!(dir.path().join("host.crt.old").exists());
+    }
+
+    #[test]
+    fn test_from_pem_rejects_garbage() {
+        assert!(Identity::from_pem(b"not a cert", b"not a key").is_err());
+    }
+}
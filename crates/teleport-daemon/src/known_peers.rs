@@ -0,0 +1,639 @@
+//! Known peers - trust-on-first-use certificate store
+//!
+//! Remembers the certificate fingerprint of every host we have connected to,
+//! keyed by the address we dialed. The first connection to a new address
+//! records its fingerprint; later connections to that address must present
+//! the same certificate or they are refused until the user explicitly accepts
+//! the new one. The name a host reports about itself is only a label.
+//!
+//! # File Layout
+//! ```text
+//! ~/.config/wormhole/known_peers.toml
+//!
+//! [[peer]]
+//! name = "studio-mac"
+//! fingerprint = "3f2a..."
+//! first_seen = 1718000000
+//! last_seen = 1718003600
+//! trust = "standard"
+//! blocked = false
+//! addresses = ["192.168.1.20:4433"]
+//! ```
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error, info, warn};
+
+use crate::net::CertFingerprint;
+
+/// File name of the store inside the config directory
+const KNOWN_PEERS_FILE: &str = "known_peers.toml";
+
+/// Minimum fingerprint prefix length accepted when looking up a peer
+const MIN_FINGERPRINT_PREFIX: usize = 8;
+
+/// How much a peer is trusted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrustLevel {
+    /// Recorded but not explicitly vetted
+    Limited,
+    /// Default for peers trusted on first use
+    #[default]
+    Standard,
+    /// Explicitly vetted by the user
+    Full,
+}
+
+impl std::str::FromStr for TrustLevel {
+    type Err = KnownPeersError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "limited" => Ok(TrustLevel::Limited),
+            "standard" => Ok(TrustLevel::Standard),
+            "full" => Ok(TrustLevel::Full),
+            other => Err(KnownPeersError::InvalidTrustLevel(other.to_string())),
+        }
+    }
+}
+
+impl std::fmt::Display for TrustLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TrustLevel::Limited => write!(f, "limited"),
+            TrustLevel::Standard => write!(f, "standard"),
+            TrustLevel::Full => write!(f, "full"),
+        }
+    }
+}
+
+/// A remembered peer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KnownPeer {
+    /// Peer name (host name reported on first use, or set by the user)
+    pub name: String,
+    /// Hex-encoded BLAKE3 fingerprint of the peer's certificate
+    pub fingerprint: String,
+    /// Unix timestamp of the first connection
+    pub first_seen: u64,
+    /// Unix timestamp of the most recent connection
+    pub last_seen: u64,
+    /// Trust level
+    #[serde(default)]
+    pub trust: TrustLevel,
+    /// Refuse connections to this peer
+    #[serde(default)]
+    pub blocked: bool,
+    /// Addresses this peer's certificate is trusted at, most recent first
+    #[serde(default)]
+    pub addresses: Vec<String>,
+    /// A different fingerprint the peer presented, awaiting `peers trust`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pending_fingerprint: Option<String>,
+}
+
+impl KnownPeer {
+    fn new(name: &str, fingerprint: &CertFingerprint) -> Self {
+        let now = unix_now();
+        Self {
+            name: name.to_string(),
+            fingerprint: hex::encode(fingerprint),
+            first_seen: now,
+            last_seen: now,
+            trust: TrustLevel::default(),
+            blocked: false,
+            addresses: Vec::new(),
+            pending_fingerprint: None,
+        }
+    }
+
+    fn note_address(&mut self, address: &str) {
+        self.addresses.retain(|a| a != address);
+        self.addresses.insert(0, address.to_string());
+    }
+}
+
+/// Outcome of checking a peer certificate against the store
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeerCheck {
+    /// Never seen this address or certificate before
+    New,
+    /// Fingerprint matches the stored one
+    Known,
+    /// The address presented a different certificate than last time
+    Changed {
+        peer: String,
+        expected: String,
+        actual: String,
+    },
+    /// The peer is blocked
+    Blocked,
+    /// The store could not be read, so the peer cannot be verified
+    Unavailable(String),
+}
+
+/// Persistent known-peers store
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct KnownPeers {
+    #[serde(default, rename = "peer")]
+    peers: Vec<KnownPeer>,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+impl KnownPeers {
+    /// Default location of the store
+    pub fn default_path() -> Option<PathBuf> {
+        ProjectDirs::from("", "", "wormhole").map(|dirs| dirs.config_dir().join(KNOWN_PEERS_FILE))
+    }
+
+    /// Load the store from the default location
+    pub fn load() -> Result<Self, KnownPeersError> {
+        let path = Self::default_path().ok_or(KnownPeersError::NoConfigDir)?;
+        Self::load_from(&path)
+    }
+
+    /// Load the store from a specific file (missing file = empty store)
+    pub fn load_from(path: &Path) -> Result<Self, KnownPeersError> {
+        let mut store = if path.exists() {
+            let content =
+                fs::read_to_string(path).map_err(|e| KnownPeersError::Io(e.to_string()))?;
+            toml::from_str::<KnownPeers>(&content)
+                .map_err(|e| KnownPeersError::Parse(e.to_string()))?
+        } else {
+            debug!("Known peers file {:?} not found, starting empty", path);
+            KnownPeers::default()
+        };
+        store.path = Some(path.to_path_buf());
+        Ok(store)
+    }
+
+    /// Write the store back to the file it was loaded from
+    pub fn save(&self) -> Result<(), KnownPeersError> {
+        let path = self.path.as_ref().ok_or(KnownPeersError::NoConfigDir)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| KnownPeersError::Io(e.to_string()))?;
+        }
+        let content =
+            toml::to_string_pretty(self).map_err(|e| KnownPeersError::Serialize(e.to_string()))?;
+
+        // Write-then-rename so a crash never leaves a truncated store
+        let tmp = path.with_extension("toml.tmp");
+        fs::write(&tmp, content).map_err(|e| KnownPeersError::Io(e.to_string()))?;
+        fs::rename(&tmp, path).map_err(|e| KnownPeersError::Io(e.to_string()))?;
+        Ok(())
+    }
+
+    /// All known peers
+    pub fn peers(&self) -> &[KnownPeer] {
+        &self.peers
+    }
+
+    /// Find a peer by name, fingerprint (or unique prefix), or address
+    pub fn find(&self, id: &str) -> Option<&KnownPeer> {
+        self.position(id).map(|i| &self.peers[i])
+    }
+
+    fn position(&self, id: &str) -> Option<usize> {
+        if let Some(i) = self.peers.iter().position(|p| p.name == id) {
+            return Some(i);
+        }
+
+        let lower = id.to_ascii_lowercase();
+        if lower.len() >= MIN_FINGERPRINT_PREFIX && lower.chars().all(|c| c.is_ascii_hexdigit()) {
+            let mut matches = self
+                .peers
+                .iter()
+                .enumerate()
+                .filter(|(_, p)| p.fingerprint.starts_with(&lower));
+            if let (Some((i, _)), None) = (matches.next(), matches.next()) {
+                return Some(i);
+            }
+        }
+
+        self.peers
+            .iter()
+            .position(|p| p.addresses.iter().any(|a| a == id))
+    }
+
+    fn get_mut(&mut self, id: &str) -> Result<&mut KnownPeer, KnownPeersError> {
+        let i = self
+            .position(id)
+            .ok_or_else(|| KnownPeersError::NotFound(id.to_string()))?;
+        Ok(&mut self.peers[i])
+    }
+
+    /// Check a certificate presented at `address` against the store
+    ///
+    /// A certificate already trusted elsewhere is the same peer at a new
+    /// address; anything else at a remembered address must match it.
+    pub fn check(&self, address: &str, fingerprint: &CertFingerprint) -> PeerCheck {
+        let actual = hex::encode(fingerprint);
+
+        if self
+            .peers
+            .iter()
+            .any(|p| p.blocked && p.fingerprint == actual)
+        {
+            return PeerCheck::Blocked;
+        }
+
+        match self
+            .peers
+            .iter()
+            .find(|p| p.addresses.iter().any(|a| a == address))
+        {
+            Some(peer) if peer.blocked => PeerCheck::Blocked,
+            Some(peer) if peer.fingerprint == actual => PeerCheck::Known,
+            Some(peer) => PeerCheck::Changed {
+                peer: peer.name.clone(),
+                expected: peer.fingerprint.clone(),
+                actual,
+            },
+            None if self.peers.iter().any(|p| p.fingerprint == actual) => PeerCheck::Known,
+            None => PeerCheck::New,
+        }
+    }
+
+    /// Record a successful connection to `address`, adding the peer if it is new
+    ///
+    /// `name` only labels a new peer. Does not overwrite a stored
+    /// fingerprint; call `check` first.
+    pub fn record_seen(&mut self, name: &str, fingerprint: &CertFingerprint, address: &str) {
+        let encoded = hex::encode(fingerprint);
+        if let Some(peer) = self.peers.iter_mut().find(|p| p.fingerprint == encoded) {
+            peer.last_seen = unix_now();
+            peer.note_address(address);
+            return;
+        }
+
+        let name = if self.peers.iter().any(|p| p.name == name) {
+            format!("{}@{}", name, address)
+        } else {
+            name.to_string()
+        };
+        info!("Trusting new peer '{}' on first use ({})", name, encoded);
+        let mut peer = KnownPeer::new(&name, fingerprint);
+        peer.note_address(address);
+        self.peers.push(peer);
+    }
+
+    /// Remember a fingerprint that did not match, so `trust` can accept it
+    pub fn record_mismatch(&mut self, address: &str, fingerprint: &CertFingerprint) {
+        if let Some(peer) = self
+            .peers
+            .iter_mut()
+            .find(|p| p.addresses.iter().any(|a| a == address))
+        {
+            peer.pending_fingerprint = Some(hex::encode(fingerprint));
+        }
+    }
+
+    /// Add a peer with a known fingerprint before ever connecting to it
+    pub fn add(
+        &mut self,
+        name: &str,
+        fingerprint: &CertFingerprint,
+    ) -> Result<(), KnownPeersError> {
+        if self.peers.iter().any(|p| p.name == name) {
+            return Err(KnownPeersError::AlreadyExists(name.to_string()));
+        }
+        let mut peer = KnownPeer::new(name, fingerprint);
+        peer.trust = TrustLevel::Full;
+        self.peers.push(peer);
+        Ok(())
+    }
+
+    /// Remove a peer
+    pub fn remove(&mut self, id: &str) -> Result<KnownPeer, KnownPeersError> {
+        let i = self
+            .position(id)
+            .ok_or_else(|| KnownPeersError::NotFound(id.to_string()))?;
+        Ok(self.peers.remove(i))
+    }
+
+    /// Block or unblock a peer
+    pub fn set_blocked(&mut self, id: &str, blocked: bool) -> Result<(), KnownPeersError> {
+        self.get_mut(id)?.blocked = blocked;
+        Ok(())
+    }
+
+    /// Set a peer's trust level, accepting any pending certificate change
+    ///
+    /// Returns the replaced fingerprint if a pending one was accepted.
+    pub fn trust(
+        &mut self,
+        id: &str,
+        level: TrustLevel,
+    ) -> Result<Option<String>, KnownPeersError> {
+        let peer = self.get_mut(id)?;
+        peer.trust = level;
+        Ok(peer
+            .pending_fingerprint
+            .take()
+            .map(|pending| std::mem::replace(&mut peer.fingerprint, pending)))
+    }
+
+    /// Rename a peer
+    pub fn rename(&mut self, id: &str, new_name: &str) -> Result<(), KnownPeersError> {
+        if self.peers.iter().any(|p| p.name == new_name) {
+            return Err(KnownPeersError::AlreadyExists(new_name.to_string()));
+        }
+        self.get_mut(id)?.name = new_name.to_string();
+        Ok(())
+    }
+}
+
+/// Check a freshly connected peer against the store and record it (TOFU)
+///
+/// `address` is what the client dialed and keys the trust; `name` is what
+/// the host calls itself and only labels a new peer. `store_path` of `None`
+/// uses the default location. Returns the failed check when the store cannot
+/// be loaded, or the peer is blocked or its certificate changed.
+pub fn verify_peer(
+    store_path: Option<&Path>,
+    name: &str,
+    fingerprint: &CertFingerprint,
+    address: &str,
+) -> Result<(), PeerCheck> {
+    let mut peers = load_store(store_path)?;
+
+    let check = peers.check(address, fingerprint);
+    match &check {
+        PeerCheck::Blocked | PeerCheck::Unavailable(_) => return Err(check),
+        PeerCheck::Changed {
+            peer,
+            expected,
+            actual,
+        } => {
+            error!(
+                "CERTIFICATE FOR PEER '{}' AT {} HAS CHANGED (expected {}, got {})",
+                peer, address, expected, actual
+            );
+            peers.record_mismatch(address, fingerprint);
+        }
+        PeerCheck::New | PeerCheck::Known => peers.record_seen(name, fingerprint, address),
+    }
+
+    if let Err(e) = peers.save() {
+        // Trust that was never written down would not be checked next time
+        if check == PeerCheck::New {
+            error!("Failed to record new peer: {}", e);
+            return Err(PeerCheck::Unavailable(e.to_string()));
+        }
+        warn!("Failed to save known peers: {}", e);
+    }
+
+    match check {
+        PeerCheck::Changed { .. } => Err(check),
+        _ => Ok(()),
+    }
+}
+
+/// Check a peer against the store without recording anything
+///
+/// Lets a client vet the host before answering its password challenge; the
+/// connection is recorded with `verify_peer` once the host has identified
+/// itself. New peers pass.
+pub fn check_peer(
+    store_path: Option<&Path>,
+    fingerprint: &CertFingerprint,
+    address: &str,
+) -> Result<(), PeerCheck> {
+    match load_store(store_path)?.check(address, fingerprint) {
+        PeerCheck::New | PeerCheck::Known => Ok(()),
+        check => Err(check),
+    }
+}
+
+fn load_store(store_path: Option<&Path>) -> Result<KnownPeers, PeerCheck> {
+    let loaded = match store_path {
+        Some(path) => KnownPeers::load_from(path),
+        None => KnownPeers::load(),
+    };
+    loaded.map_err(|e| {
+        error!("Known peers store unavailable: {}", e);
+        PeerCheck::Unavailable(e.to_string())
+    })
+}
+
+/// Parse a hex-encoded certificate fingerprint
+pub fn parse_fingerprint(s: &str) -> Option<CertFingerprint> {
+    let cleaned: String = s.chars().filter(|c| *c != ':').collect();
+    hex::decode(cleaned).ok()?.try_into().ok()
+}
+
+/// Parse a fingerprint argument, for use as a clap value parser
+pub fn parse_fingerprint_arg(s: &str) -> Result<CertFingerprint, String> {
+    parse_fingerprint(s)
+        .ok_or_else(|| "expected a 64-character hex certificate fingerprint".to_string())
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Known peers errors
+#[derive(Debug, Clone)]
+pub enum KnownPeersError {
+    /// I/O error
+    Io(String),
+    /// Store file could not be parsed
+    Parse(String),
+    /// Store could not be serialized
+    Serialize(String),
+    /// No config directory available
+    NoConfigDir,
+    /// No peer matches the identifier
+    NotFound(String),
+    /// A peer with this name already exists
+    AlreadyExists(String),
+    /// Unknown trust level
+    InvalidTrustLevel(String),
+}
+
+impl std::fmt::Display for KnownPeersError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KnownPeersError::Io(e) => write!(f, "I/O error: {}", e),
+            KnownPeersError::Parse(e) => write!(f, "Parse error: {}", e),
+            KnownPeersError::Serialize(e) => write!(f, "Serialization error: {}", e),
+            KnownPeersError::NoConfigDir => write!(f, "No configuration directory available"),
+            KnownPeersError::NotFound(id) => write!(f, "Unknown peer: {}", id),
+            KnownPeersError::AlreadyExists(name) => write!(f, "Peer already exists: {}", name),
+            KnownPeersError::InvalidTrustLevel(level) => write!(
+                f,
+                "Invalid trust level '{}' (expected limited, standard or full)",
+                level
+            ),
+        }
+    }
+}
+
+impl std::error::Error for KnownPeersError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn store(dir: &TempDir) -> KnownPeers {
+        KnownPeers::load_from(&dir.path().join(KNOWN_PEERS_FILE)).unwrap()
+    }
+
+    #[test]
+    fn test_trust_on_first_use() {
+        let dir = TempDir::new().unwrap();
+        let mut peers = store(&dir);
+        let fp = [1u8; 32];
+
+        assert_eq!(peers.check("10.0.0.2:4433", &fp), PeerCheck::New);
+        peers.record_seen("studio", &fp, "10.0.0.2:4433");
+        assert_eq!(peers.check("10.0.0.2:4433", &fp), PeerCheck::Known);
+        // The same certificate is the same peer wherever it is reached
+        assert_eq!(peers.check("10.0.0.9:4433", &fp), PeerCheck::Known);
+
+        match peers.check("10.0.0.2:4433", &[2u8; 32]) {
+            PeerCheck::Changed {
+                peer,
+                expected,
+                actual,
+            } => {
+                assert_eq!(peer, "studio");
+                assert_eq!(expected, hex::encode(fp));
+                assert_eq!(actual, hex::encode([2u8; 32]));
+            }
+            other => panic!("expected Changed, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_trust_is_keyed_by_address_not_reported_name() {
+        let dir = TempDir::new().unwrap();
+        let mut peers = store(&dir);
+        peers.record_seen("studio", &[1u8; 32], "10.0.0.2:4433");
+
+        // An impostor at a known address cannot pass as new by renaming itself
+        assert!(matches!(
+            peers.check("10.0.0.2:4433", &[2u8; 32]),
+            PeerCheck::Changed { .. }
+        ));
+
+        // A different host reusing the name elsewhere gets a label of its own
+        peers.record_seen("studio", &[2u8; 32], "10.0.0.3:4433");
+        assert_eq!(
+            peers.find("studio@10.0.0.3:4433").unwrap().fingerprint,
+            hex::encode([2u8; 32])
+        );
+        assert_eq!(
+            peers.find("studio").unwrap().fingerprint,
+            hex::encode([1u8; 32])
+        );
+    }
+
+    #[test]
+    fn test_persistence_roundtrip() {
+        let dir = TempDir::new().unwrap();
+        let mut peers = store(&dir);
+        peers.record_seen("studio", &[1u8; 32], "10.0.0.2:4433");
+        peers.save().unwrap();
+
+        let reloaded = store(&dir);
+        assert_eq!(reloaded.peers(), peers.peers());
+        assert_eq!(reloaded.peers()[0].addresses, vec!["10.0.0.2:4433"]);
+    }
+
+    #[test]
+    fn test_trust_accepts_pending_fingerprint() {
+        let dir = TempDir::new().unwrap();
+        let mut peers = store(&dir);
+        peers.record_seen("studio", &[1u8; 32], "10.0.0.2:4433");
+        peers.record_mismatch("10.0.0.2:4433", &[2u8; 32]);
+
+        let replaced = peers.trust("studio", TrustLevel::Full).unwrap();
+        assert_eq!(replaced, Some(hex::encode([1u8; 32])));
+        assert_eq!(peers.check("10.0.0.2:4433", &[2u8; 32]), PeerCheck::Known);
+        assert_eq!(peers.find("studio").unwrap().trust, TrustLevel::Full);
+    }
+
+    #[test]
+    fn test_block_by_fingerprint_prefix() {
+        let dir = TempDir::new().unwrap();
+        let mut peers = store(&dir);
+        let fp = [0xabu8; 32];
+        peers.record_seen("studio", &fp, "10.0.0.2:4433");
+
+        peers.set_blocked("abababab", true).unwrap();
+        assert_eq!(peers.check("10.0.0.2:4433", &fp), PeerCheck::Blocked);
+        // A blocked certificate stays blocked at another address
+        assert_eq!(peers.check("10.0.0.7:4433", &fp), PeerCheck::Blocked);
+
+        peers.set_blocked("studio", false).unwrap();
+        assert_eq!(peers.check("10.0.0.2:4433", &fp), PeerCheck::Known);
+    }
+
+    #[test]
+    fn test_add_rename_remove() {
+        let dir = TempDir::new().unwrap();
+        let mut peers = store(&dir);
+        peers.add("laptop", &[3u8; 32]).unwrap();
+        assert!(peers.add("laptop", &[4u8; 32]).is_err());
+
+        peers.rename("laptop", "work-laptop").unwrap();
+        assert!(peers.find("laptop").is_none());
+        assert_eq!(peers.remove("work-laptop").unwrap().trust, TrustLevel::Full);
+        assert!(peers.peers().is_empty());
+    }
+
+    #[test]
+    fn test_verify_peer_records_and_rejects_change() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(KNOWN_PEERS_FILE);
+
+        assert!(verify_peer(Some(&path), "studio", &[1u8; 32], "10.0.0.2:4433").is_ok());
+        assert!(verify_peer(Some(&path), "studio", &[1u8; 32], "10.0.0.2:4433").is_ok());
+        assert!(matches!(
+            verify_peer(Some(&path), "studio", &[2u8; 32], "10.0.0.2:4433"),
+            Err(PeerCheck::Changed { .. })
+        ));
+        assert!(matches!(
+            verify_peer(Some(&path), "new-name", &[2u8; 32], "10.0.0.2:4433"),
+            Err(PeerCheck::Changed { .. })
+        ));
+
+        // The mismatch is remembered for `peers trust`
+        let peers = KnownPeers::load_from(&path).unwrap();
+        assert_eq!(
+            peers.find("studio").unwrap().pending_fingerprint,
+            Some(hex::encode([2u8; 32]))
+        );
+    }
+
+    #[test]
+    fn test_verify_peer_fails_closed_without_store() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(KNOWN_PEERS_FILE);
+        fs::write(&path, "not [valid toml").unwrap();
+
+        assert!(matches!(
+            verify_peer(Some(&path), "studio", &[1u8; 32], "10.0.0.2:4433"),
+            Err(PeerCheck::Unavailable(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_fingerprint() {
+        let fp = [0x5au8; 32];
+        assert_eq!(parse_fingerprint(&hex::encode(fp)), Some(fp));
+        assert_eq!(parse_fingerprint("5a:5a"), None);
+        assert_eq!(parse_fingerprint("not hex"), None);
+    }
+}
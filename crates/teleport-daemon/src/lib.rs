@@ -49,24 +49,43 @@ pub mod multi_fuse;
 pub mod winfsp;
 
 // Platform-independent modules
+pub mod access;
+pub mod audit;
+pub mod bandwidth;
 pub mod bulk_transfer;
 pub mod cache;
 pub mod client;
+pub mod config_reload;
 pub mod connection_manager;
 pub mod dedup_index;
+pub mod discovery;
 pub mod disk_cache;
+pub mod fault_injection;
+pub mod file_handles;
 pub mod gc;
 pub mod global;
 pub mod governor;
 pub mod host;
+pub mod host_session;
+pub mod identity;
+pub mod known_peers;
+pub mod local_backend;
 pub mod lock_manager;
+#[cfg(test)]
+pub mod loopback;
+pub mod memory_backend;
 pub mod multi_host;
 pub mod net;
 pub mod rate_limiter;
 pub mod rendezvous;
+pub mod share_backend;
+pub mod share_filter;
+pub mod share_limits;
 pub mod stream_pool;
 pub mod sync_engine;
+pub mod tree_snapshot;
 pub mod updater;
+pub mod write_session;
 
 // Bridge re-export (platform-agnostic)
 pub use bridge::{BridgeHandler, FuseAsyncBridge, FuseError, FuseRequest};
@@ -84,12 +103,15 @@ pub use winfsp::{mount_winfsp, WormholeFileContext, WormholeWinFS};
 // Platform-independent re-exports
 pub use cache::{CacheManager, ChunkCache, HybridCacheManager, HybridChunkCache};
 pub use client::WormholeClient;
+pub use config_reload::ConfigReloader;
 pub use dedup_index::{ChunkLocation, DedupIndex, DedupStatsSnapshot};
 pub use connection_manager::{
     ConnectionError, ConnectionEvent, ConnectionManager, HostConnectionConfig, ReconnectConfig,
     RegisteredShare,
 };
 pub use disk_cache::DiskCache;
+pub use fault_injection::{FaultConfig, FaultInjector};
+pub use file_handles::{ChunkIo, FileHandles};
 pub use gc::GarbageCollector;
 pub use global::{
     connect_global, start_host_global, GlobalEvent, GlobalHostConfig, GlobalHostError,
@@ -97,14 +119,19 @@ pub use global::{
 };
 pub use governor::Governor;
 pub use host::WormholeHost;
+pub use local_backend::LocalBackend;
 pub use lock_manager::{LockError, LockHold, LockManager, LockStatus};
+pub use memory_backend::MemoryBackend;
 pub use multi_host::{MultiHostConfig, MultiShareHost, SharedFolder};
 pub use rendezvous::{RendezvousClient, RendezvousError, RendezvousResult};
+pub use share_backend::{dispatch, ShareBackend};
 pub use stream_pool::{
     PooledStream, StreamPool, StreamPoolConfig, StreamPoolStatsSnapshot, DEFAULT_STREAMS,
     MAX_STREAMS, MIN_STREAMS,
 };
 pub use sync_engine::{DirtyChunk, FileLock, SyncEngine, SyncRunner, SyncStatus};
+pub use tree_snapshot::TreeSnapshot;
+pub use write_session::{WriteSession, WriteSessions};
 pub use bulk_transfer::{
     BulkTransferConfig, BulkTransferCoordinator, TransferProgress, TransferProgressTracker,
     TransferResult, TransferStats, TransferStatsSnapshot,
@@ -0,0 +1,1216 @@
+//! Share backend serving a directory on the local file system
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use parking_lot::{Mutex, RwLock};
+use tracing::{error, info, warn};
+
+use teleport_core::{
+    io::{seek_data, seek_hole},
+    path::{safe_real_path, validate_filename},
+    ChunkId, DirEntry, ErrorCode, ErrorMessage, FileAttr, FileType, Inode, SeekWhence, CHUNK_SIZE,
+    FIRST_USER_INODE, ROOT_INODE,
+};
+
+use crate::share_backend::{
+    backend_error, io_error, new_epoch, AttrChanges, BackendResult, ChunkData, DirPage,
+    ShareBackend,
+};
+use crate::share_filter::ShareFilter;
+
+/// Maximum number of inode entries to prevent unbounded memory growth
+const MAX_INODE_ENTRIES: usize = 1_000_000;
+
+/// Warning threshold for inode table size (90% of max)
+const INODE_WARNING_THRESHOLD: usize = MAX_INODE_ENTRIES * 9 / 10;
+
+/// Largest inode a share may use; `GlobalInode` keeps 48 bits for it
+const MAX_LOCAL_INODE: Inode = 0x0000_FFFF_FFFF_FFFF;
+
+/// How often inodes of files that no longer exist are dropped
+const STALE_INODE_CLEANUP_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Ends the names of files staged by write sessions, which clients never see
+const STAGED_SUFFIX: &str = ".wormhole-partial";
+
+/// Inode table mapping inodes to paths
+struct InodeTable {
+    inode_to_path: DashMap<Inode, PathBuf>,
+    path_to_inode: DashMap<PathBuf, Inode>,
+    next_inode: RwLock<Inode>,
+    /// Track whether we've warned about table size
+    warned_high_usage: AtomicBool,
+    /// Include/exclude rules; excluded paths are treated as nonexistent
+    filter: Arc<ShareFilter>,
+}
+
+impl InodeTable {
+    fn new(root: PathBuf, filter: Arc<ShareFilter>) -> Self {
+        let table = Self {
+            inode_to_path: DashMap::new(),
+            path_to_inode: DashMap::new(),
+            next_inode: RwLock::new(FIRST_USER_INODE),
+            warned_high_usage: AtomicBool::new(false),
+            filter,
+        };
+
+        // Root is always inode 1
+        table.inode_to_path.insert(ROOT_INODE, root.clone());
+        table.path_to_inode.insert(root, ROOT_INODE);
+
+        table
+    }
+
+    /// Path of an inode, unless the share filter hides it (rules may change after lookup)
+    fn get_path(&self, inode: Inode) -> Option<PathBuf> {
+        let path = self.inode_to_path.get(&inode).map(|r| r.clone())?;
+        if self.filter.is_excluded_path(&path) {
+            return None;
+        }
+        Some(path)
+    }
+
+    /// Get current number of entries
+    fn len(&self) -> usize {
+        self.inode_to_path.len()
+    }
+
+    fn get_or_create_inode(&self, path: PathBuf) -> Option<Inode> {
+        if let Some(inode) = self.path_to_inode.get(&path) {
+            return Some(*inode);
+        }
+
+        // Check if we've hit the limit
+        let current_size = self.inode_to_path.len();
+        if current_size >= MAX_INODE_ENTRIES {
+            error!(
+                "Inode table full ({} entries) - cannot allocate new inodes. \
+                 Consider cleaning up deleted files or increasing MAX_INODE_ENTRIES.",
+                current_size
+            );
+            return None;
+        }
+
+        // Warn once when approaching the limit
+        if current_size >= INODE_WARNING_THRESHOLD
+            && !self.warned_high_usage.swap(true, Ordering::Relaxed)
+        {
+            warn!(
+                "Inode table is {}% full ({}/{} entries). \
+                     Performance may degrade as the table grows.",
+                current_size * 100 / MAX_INODE_ENTRIES,
+                current_size,
+                MAX_INODE_ENTRIES
+            );
+        }
+
+        let mut next = self.next_inode.write();
+        let inode = *next;
+
+        // SECURITY: Prevent inode overflow that could cause collisions
+        if inode >= MAX_LOCAL_INODE {
+            error!("Inode space exhausted - cannot allocate new inodes");
+            return None;
+        }
+        *next = inode + 1;
+
+        self.inode_to_path.insert(inode, path.clone());
+        self.path_to_inode.insert(path, inode);
+
+        Some(inode)
+    }
+
+    /// Remove an inode mapping (for deleted files)
+    fn remove_inode(&self, inode: Inode) {
+        if let Some((_, path)) = self.inode_to_path.remove(&inode) {
+            self.path_to_inode.remove(&path);
+        }
+    }
+
+    /// Clean up stale entries (paths that no longer exist on disk)
+    /// Returns the number of entries removed.
+    fn cleanup_stale_entries(&self) -> usize {
+        let mut removed = 0;
+        let stale_inodes: Vec<Inode> = self
+            .inode_to_path
+            .iter()
+            .filter(|entry| {
+                let path = entry.value();
+                // Don't remove root inode, and check if path still exists
+                *entry.key() != ROOT_INODE && !path.exists()
+            })
+            .map(|entry| *entry.key())
+            .collect();
+
+        for inode in stale_inodes {
+            self.remove_inode(inode);
+            removed += 1;
+        }
+
+        if removed > 0 {
+            info!(
+                "Cleaned up {} stale inode entries. Current table size: {}",
+                removed,
+                self.len()
+            );
+            // Reset warning flag after cleanup
+            self.warned_high_usage.store(false, Ordering::Relaxed);
+        }
+
+        removed
+    }
+}
+
+/// Serves a local directory, hiding whatever its share filter excludes
+///
+/// Symlinks are followed only as long as they stay inside the directory.
+pub struct LocalBackend {
+    root: PathBuf,
+    inodes: InodeTable,
+    /// When stale inodes were last cleaned up
+    last_cleanup: Mutex<Instant>,
+    /// Inode numbers last only as long as the backend
+    epoch: u64,
+}
+
+impl LocalBackend {
+    /// Serve `root`, honouring its `.wormholeignore`
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        let root = root.into();
+        let filter = ShareFilter::new(root.clone());
+        Self::with_filter(root, filter)
+    }
+
+    /// Serve `root`, hiding paths the filter excludes
+    pub fn with_filter(root: impl Into<PathBuf>, filter: ShareFilter) -> Self {
+        let root = root.into();
+        Self {
+            inodes: InodeTable::new(root.clone(), Arc::new(filter)),
+            root,
+            last_cleanup: Mutex::new(Instant::now()),
+            epoch: new_epoch(),
+        }
+    }
+
+    /// The shared directory
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn filter(&self) -> &ShareFilter {
+        &self.inodes.filter
+    }
+
+    fn path_of(&self, inode: Inode) -> BackendResult<PathBuf> {
+        self.inodes
+            .get_path(inode)
+            .ok_or_else(|| backend_error(ErrorCode::FileNotFound, "inode not found", inode))
+    }
+
+    /// Path of `name` in `parent`, for operations that create or remove it
+    fn child_path(&self, parent: Inode, name: &str) -> BackendResult<PathBuf> {
+        // SECURITY: Validate filename
+        validate_filename(name)
+            .map_err(|e| backend_error(ErrorCode::PathTraversal, e.to_string(), parent))?;
+
+        let parent_path = self.inodes.get_path(parent).ok_or_else(|| {
+            backend_error(
+                ErrorCode::FileNotFound,
+                "parent directory not found",
+                parent,
+            )
+        })?;
+        let path = parent_path.join(name);
+
+        // SECURITY: Validate path is within shared directory
+        validate_path_security(&path, &self.root, parent)?;
+        Ok(path)
+    }
+
+    /// SECURITY: Resolve symlinks before file contents are read or written
+    ///
+    /// Without this, a symlink could expose or overwrite any file on the host.
+    fn check_real_path(&self, path: &Path, inode: Inode) -> BackendResult<()> {
+        self.real_path(path, inode).map(|_| ())
+    }
+
+    /// Like `check_real_path`, returning the path with symlinks resolved
+    fn real_path(&self, path: &Path, inode: Inode) -> BackendResult<PathBuf> {
+        safe_real_path(&self.root, path).map_err(|e| {
+            warn!(
+                "Path traversal attempt via symlink: {}: {}",
+                path.display(),
+                e
+            );
+            backend_error(
+                ErrorCode::PathTraversal,
+                "symlink escapes shared directory",
+                inode,
+            )
+        })
+    }
+
+    fn new_attr(
+        &self,
+        path: PathBuf,
+        meta: &fs::Metadata,
+        parent: Inode,
+    ) -> BackendResult<FileAttr> {
+        let inode = self
+            .inodes
+            .get_or_create_inode(path)
+            .ok_or_else(|| backend_error(ErrorCode::IoError, "inode allocation failed", parent))?;
+        Ok(metadata_to_attr(inode, meta))
+    }
+}
+
+impl ShareBackend for LocalBackend {
+    fn lookup(&self, parent: Inode, name: &str) -> BackendResult<Option<FileAttr>> {
+        let Some(parent_path) = self.inodes.get_path(parent) else {
+            return Ok(None);
+        };
+
+        // Validate name
+        validate_filename(name)
+            .map_err(|e| backend_error(ErrorCode::PathTraversal, e.to_string(), parent))?;
+
+        let child_path = parent_path.join(name);
+
+        // Quick check: ensure path is within shared directory (lexical check)
+        if !child_path.starts_with(&self.root) {
+            return Err(backend_error(
+                ErrorCode::PathTraversal,
+                "path escapes shared directory",
+                parent,
+            ));
+        }
+
+        if self.filter().is_excluded_path(&child_path) || is_staged(&child_path) {
+            return Err(excluded_not_found(parent));
+        }
+
+        match fs::metadata(&child_path) {
+            Ok(meta) => {
+                // SECURITY: After confirming the file exists, verify symlinks don't escape
+                self.check_real_path(&child_path, parent)?;
+                self.new_attr(child_path, &meta, parent).map(Some)
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(backend_error(ErrorCode::IoError, e.to_string(), parent)),
+        }
+    }
+
+    fn getattr(&self, inode: Inode) -> BackendResult<Option<FileAttr>> {
+        let Some(path) = self.inodes.get_path(inode) else {
+            return Ok(None);
+        };
+
+        match fs::metadata(&path) {
+            Ok(meta) => Ok(Some(metadata_to_attr(inode, &meta))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(backend_error(ErrorCode::IoError, e.to_string(), inode)),
+        }
+    }
+
+    fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    fn list_dir(&self, inode: Inode, offset: u64, limit: u32) -> BackendResult<DirPage> {
+        let path = self.path_of(inode)?;
+
+        let entries = match fs::read_dir(&path) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(backend_error(
+                    ErrorCode::FileNotFound,
+                    "directory not found",
+                    inode,
+                ));
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotADirectory => {
+                return Err(backend_error(
+                    ErrorCode::NotADirectory,
+                    "not a directory",
+                    inode,
+                ));
+            }
+            Err(e) => return Err(backend_error(ErrorCode::IoError, e.to_string(), inode)),
+        };
+
+        let mut dir_entries = Vec::new();
+        // Fetch one extra entry to determine has_more accurately
+        let fetch_limit = limit.saturating_add(1) as usize;
+
+        // Offsets count visible entries only, so hidden ones are skipped first
+        let visible = entries.flatten().filter(|entry| {
+            let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+            !self.filter().is_excluded(&entry.path(), is_dir) && !is_staged(&entry.path())
+        });
+
+        for entry in visible.skip(offset as usize) {
+            if dir_entries.len() >= fetch_limit {
+                break;
+            }
+
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let entry_path = entry.path();
+
+            if let Ok(meta) = entry.metadata() {
+                let file_type = if meta.is_dir() {
+                    FileType::Directory
+                } else if meta.is_symlink() {
+                    FileType::Symlink
+                } else {
+                    FileType::File
+                };
+
+                match self.inodes.get_or_create_inode(entry_path) {
+                    Some(inode) => dir_entries.push(DirEntry::new(name, inode, file_type)),
+                    // Inode space exhausted - already logged by the table; skip this entry
+                    None => continue,
+                }
+            }
+        }
+
+        // If we got more than limit, there are more entries
+        let has_more = dir_entries.len() > limit as usize;
+        // Truncate to requested limit
+        dir_entries.truncate(limit as usize);
+
+        Ok(DirPage {
+            entries: dir_entries,
+            has_more,
+        })
+    }
+
+    fn read_chunk(&self, chunk_id: ChunkId) -> BackendResult<ChunkData> {
+        let inode = chunk_id.inode;
+        let mut file = self.open_file(inode, false)?;
+
+        let offset = chunk_id.byte_offset();
+        file.seek(SeekFrom::Start(offset))
+            .map_err(|_| backend_error(ErrorCode::IoError, "seek failed", inode))?;
+
+        let mut buffer = vec![0u8; CHUNK_SIZE];
+        let bytes_read = file
+            .read(&mut buffer)
+            .map_err(|e| backend_error(ErrorCode::IoError, e.to_string(), inode))?;
+        buffer.truncate(bytes_read);
+
+        // Check if this is the final chunk
+        // Use saturating_add to prevent overflow with very large offsets
+        let file_size = file.metadata().map(|m| m.len()).unwrap_or(0);
+        let is_final = offset.saturating_add(bytes_read as u64) >= file_size;
+
+        Ok(ChunkData {
+            data: buffer,
+            is_final,
+        })
+    }
+
+    fn write_chunk(&self, chunk_id: ChunkId, data: &[u8]) -> BackendResult<Option<u64>> {
+        let inode = chunk_id.inode;
+        let mut file = self.open_file(inode, true)?;
+
+        file.seek(SeekFrom::Start(chunk_id.byte_offset()))
+            .map_err(|e| backend_error(ErrorCode::IoError, format!("Seek failed: {}", e), inode))?;
+        file.write_all(data).map_err(|e| {
+            backend_error(ErrorCode::IoError, format!("Write failed: {}", e), inode)
+        })?;
+
+        // Sync to disk
+        if let Err(e) = file.sync_data() {
+            warn!("Failed to sync file data: {}", e);
+        }
+
+        Ok(file.metadata().map(|m| m.len()).ok())
+    }
+
+    fn create_file(&self, parent: Inode, name: &str, mode: u32) -> BackendResult<FileAttr> {
+        let path = self.child_path(parent, name)?;
+        if self.filter().is_excluded(&path, false) || is_staged(&path) {
+            return Err(excluded_name_denied(parent));
+        }
+
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(mode);
+        #[cfg(not(unix))]
+        let _ = mode;
+
+        options.open(&path).map_err(|e| io_error(&e, parent))?;
+        let meta = fs::metadata(&path).map_err(|e| io_error(&e, parent))?;
+        self.new_attr(path, &meta, parent)
+    }
+
+    fn create_dir(&self, parent: Inode, name: &str, mode: u32) -> BackendResult<FileAttr> {
+        let path = self.child_path(parent, name)?;
+        if self.filter().is_excluded(&path, true) {
+            return Err(excluded_name_denied(parent));
+        }
+
+        fs::create_dir(&path).map_err(|e| io_error(&e, parent))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let _ = fs::set_permissions(&path, fs::Permissions::from_mode(mode));
+        }
+        #[cfg(not(unix))]
+        let _ = mode;
+
+        let meta = fs::metadata(&path).map_err(|e| io_error(&e, parent))?;
+        self.new_attr(path, &meta, parent)
+    }
+
+    fn delete_file(&self, parent: Inode, name: &str) -> BackendResult<()> {
+        let path = self.child_path(parent, name)?;
+        if self.filter().is_excluded_path(&path) {
+            return Err(excluded_not_found(parent));
+        }
+
+        fs::remove_file(&path).map_err(|e| io_error(&e, parent))?;
+        if let Some(inode) = self.inodes.path_to_inode.get(&path).map(|i| *i) {
+            self.inodes.remove_inode(inode);
+        }
+        Ok(())
+    }
+
+    fn delete_dir(&self, parent: Inode, name: &str) -> BackendResult<()> {
+        let path = self.child_path(parent, name)?;
+        if self.filter().is_excluded(&path, true) {
+            return Err(excluded_not_found(parent));
+        }
+
+        // Fails unless the directory is empty
+        fs::remove_dir(&path).map_err(|e| io_error(&e, parent))?;
+        if let Some(inode) = self.inodes.path_to_inode.get(&path).map(|i| *i) {
+            self.inodes.remove_inode(inode);
+        }
+        Ok(())
+    }
+
+    fn rename(
+        &self,
+        old_parent: Inode,
+        old_name: &str,
+        new_parent: Inode,
+        new_name: &str,
+    ) -> BackendResult<()> {
+        let old_path = self.child_path(old_parent, old_name)?;
+        let new_path = self.child_path(new_parent, new_name)?;
+
+        // Hidden files can't be renamed into view, nor visible ones out of it
+        if self.filter().is_excluded_path(&old_path) {
+            return Err(excluded_not_found(old_parent));
+        }
+        if self.filter().is_excluded(&new_path, old_path.is_dir()) || is_staged(&new_path) {
+            return Err(excluded_name_denied(new_parent));
+        }
+
+        fs::rename(&old_path, &new_path).map_err(|e| io_error(&e, old_parent))?;
+        if let Some(inode) = self.inodes.path_to_inode.get(&old_path).map(|i| *i) {
+            self.inodes.remove_inode(inode);
+            self.inodes.get_or_create_inode(new_path);
+        }
+        Ok(())
+    }
+
+    fn set_attr(&self, inode: Inode, changes: AttrChanges) -> BackendResult<FileAttr> {
+        let path = self.path_of(inode)?;
+
+        // SECURITY: Truncating and chmod follow symlinks, so one must not lead
+        // out of the share
+        self.check_real_path(&path, inode)?;
+
+        if let Some(size) = changes.size {
+            let file = OpenOptions::new()
+                .write(true)
+                .open(&path)
+                .map_err(|e| io_error(&e, inode))?;
+            file.set_len(size).map_err(|e| io_error(&e, inode))?;
+        }
+
+        #[cfg(unix)]
+        if let Some(mode) = changes.mode {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(mode))
+                .map_err(|e| io_error(&e, inode))?;
+        }
+
+        // Note: This requires the filetime crate for cross-platform support
+        if changes.mtime.is_some() || changes.atime.is_some() {
+            warn!("Time modification not yet implemented");
+        }
+
+        let meta = fs::metadata(&path).map_err(|e| io_error(&e, inode))?;
+        Ok(metadata_to_attr(inode, &meta))
+    }
+
+    fn display_path(&self, inode: Inode, name: Option<&str>) -> Option<String> {
+        let mut path = self.inodes.get_path(inode)?;
+        if let Some(name) = name {
+            path.push(name);
+        }
+        let relative = path.strip_prefix(&self.root).ok()?;
+        let components: Vec<_> = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect();
+        Some(format!("/{}", components.join("/")))
+    }
+
+    fn local_path(&self, inode: Inode) -> Option<PathBuf> {
+        self.inodes.get_path(inode)
+    }
+
+    fn open_file(&self, inode: Inode, write: bool) -> BackendResult<fs::File> {
+        let path = self.path_of(inode)?;
+
+        // SECURITY: This check must happen before opening, which follows symlinks;
+        // for writes it is CRITICAL - a symlink could point anywhere on the host
+        self.check_real_path(&path, inode)?;
+
+        if !write {
+            return fs::File::open(&path)
+                .map_err(|e| backend_error(ErrorCode::IoError, e.to_string(), inode));
+        }
+        OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .map_err(|e| {
+                backend_error(
+                    ErrorCode::IoError,
+                    format!("Failed to open file for writing: {}", e),
+                    inode,
+                )
+            })
+    }
+
+    fn seek(&self, inode: Inode, offset: u64, whence: SeekWhence) -> BackendResult<Option<u64>> {
+        let file = self.open_file(inode, false)?;
+        match whence {
+            SeekWhence::Data => seek_data(&file, offset),
+            SeekWhence::Hole => seek_hole(&file, offset),
+        }
+        .map_err(|e| io_error(&e, inode))
+    }
+
+    fn stage_file(&self, inode: Inode) -> BackendResult<(PathBuf, fs::File)> {
+        let path = self.path_of(inode)?;
+        // SECURITY: Stage beside the real file, so the commit can't be redirected
+        let target = self.real_path(&path, inode)?;
+        let meta = fs::metadata(&target).map_err(|e| io_error(&e, inode))?;
+        if !meta.is_file() {
+            return Err(backend_error(ErrorCode::NotAFile, "not a file", inode));
+        }
+
+        let mut suffix = [0u8; 4];
+        getrandom::getrandom(&mut suffix)
+            .map_err(|e| backend_error(ErrorCode::IoError, e.to_string(), inode))?;
+        let name = target
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let staged = target.with_file_name(format!(
+            ".{}.{}{}",
+            name,
+            hex::encode(suffix),
+            STAGED_SUFFIX
+        ));
+
+        let mut options = OpenOptions::new();
+        options.read(true).write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            options.mode(meta.permissions().mode());
+        }
+        let file = options.open(&staged).map_err(|e| io_error(&e, inode))?;
+        // The umask may have narrowed the mode; the committed file keeps the original
+        if let Err(e) = file.set_permissions(meta.permissions()) {
+            warn!("Failed to copy permissions to {}: {}", staged.display(), e);
+        }
+        Ok((staged, file))
+    }
+
+    fn commit_staged(&self, inode: Inode, staged: &Path) -> BackendResult<FileAttr> {
+        let path = self.path_of(inode)?;
+        let target = self.real_path(&path, inode)?;
+        // SECURITY: Only a file staged beside this one may replace it
+        if !is_staged(staged) || staged.parent() != target.parent() {
+            return Err(backend_error(
+                ErrorCode::PermissionDenied,
+                "staged file does not belong to this file",
+                inode,
+            ));
+        }
+
+        fs::rename(staged, &target).map_err(|e| io_error(&e, inode))?;
+        if let Some(dir) = target.parent() {
+            sync_dir(dir);
+        }
+        let meta = fs::metadata(&target).map_err(|e| io_error(&e, inode))?;
+        Ok(metadata_to_attr(inode, &meta))
+    }
+
+    fn maintain(&self) {
+        // Pick up edits to the share's .wormholeignore
+        self.filter().reload_if_changed();
+
+        let mut last_cleanup = self.last_cleanup.lock();
+        if last_cleanup.elapsed() >= STALE_INODE_CLEANUP_INTERVAL {
+            *last_cleanup = Instant::now();
+            self.inodes.cleanup_stale_entries();
+        }
+    }
+}
+
+/// Helper: Validate path is within shared directory (SECURITY CRITICAL)
+fn validate_path_security(
+    path: &Path,
+    shared_path: &Path,
+    parent_inode: Inode,
+) -> BackendResult<()> {
+    // SECURITY: Ensure path doesn't escape shared directory via symlinks or traversal
+    // Use canonicalize to resolve symlinks and check containment
+    let shared_canonical = || {
+        shared_path
+            .canonicalize()
+            .unwrap_or_else(|_| shared_path.to_path_buf())
+    };
+    match path.canonicalize() {
+        Ok(canonical) => {
+            if !canonical.starts_with(shared_canonical()) {
+                return Err(backend_error(
+                    ErrorCode::PathTraversal,
+                    "path escapes shared directory",
+                    parent_inode,
+                ));
+            }
+        }
+        Err(_) => {
+            // Path doesn't exist yet - validate the parent instead
+            if let Some(parent_canonical) = path.parent().and_then(|p| p.canonicalize().ok()) {
+                if !parent_canonical.starts_with(shared_canonical()) {
+                    return Err(backend_error(
+                        ErrorCode::PathTraversal,
+                        "parent path escapes shared directory",
+                        parent_inode,
+                    ));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether a path names a file staged by a write session
+fn is_staged(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().ends_with(STAGED_SUFFIX))
+}
+
+/// Make a rename in `dir` durable
+#[cfg(unix)]
+fn sync_dir(dir: &Path) {
+    if let Err(e) = fs::File::open(dir).and_then(|d| d.sync_all()) {
+        warn!("Failed to sync directory {}: {}", dir.display(), e);
+    }
+}
+
+/// Directories can't be opened to sync them here
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) {}
+
+/// Reply for a name the share filter hides from clients
+fn excluded_not_found(parent: Inode) -> ErrorMessage {
+    backend_error(ErrorCode::FileNotFound, "no such file or directory", parent)
+}
+
+/// Reply for an attempt to create a name the share filter excludes
+fn excluded_name_denied(parent: Inode) -> ErrorMessage {
+    backend_error(
+        ErrorCode::PermissionDenied,
+        "name is excluded from this share",
+        parent,
+    )
+}
+
+/// Convert std::fs::Metadata to FileAttr (Unix)
+#[cfg(unix)]
+fn metadata_to_attr(inode: Inode, meta: &fs::Metadata) -> FileAttr {
+    use std::os::unix::fs::MetadataExt;
+
+    let file_type = if meta.is_dir() {
+        FileType::Directory
+    } else if meta.is_symlink() {
+        FileType::Symlink
+    } else {
+        FileType::File
+    };
+
+    // Safely convert timestamps: negative times (pre-1970) are clamped to 0
+    // and nanoseconds are clamped to valid range [0, 999_999_999]
+    FileAttr {
+        inode,
+        file_type,
+        size: meta.len(),
+        mode: meta.mode(),
+        nlink: meta.nlink() as u32,
+        uid: meta.uid(),
+        gid: meta.gid(),
+        atime: meta.atime().max(0) as u64,
+        atime_nsec: meta.atime_nsec().clamp(0, 999_999_999) as u32,
+        mtime: meta.mtime().max(0) as u64,
+        mtime_nsec: meta.mtime_nsec().clamp(0, 999_999_999) as u32,
+        ctime: meta.ctime().max(0) as u64,
+        ctime_nsec: meta.ctime_nsec().clamp(0, 999_999_999) as u32,
+    }
+}
+
+/// Convert std::fs::Metadata to FileAttr (Windows)
+#[cfg(windows)]
+fn metadata_to_attr(inode: Inode, meta: &fs::Metadata) -> FileAttr {
+    use std::os::windows::fs::MetadataExt;
+
+    let file_type = if meta.is_dir() {
+        FileType::Directory
+    } else if meta.is_symlink() {
+        FileType::Symlink
+    } else {
+        FileType::File
+    };
+
+    // Windows timestamps are in FILETIME (100-ns intervals since 1601)
+    // Convert to Unix timestamps
+    fn filetime_to_unix(filetime: u64) -> (u64, u32) {
+        const EPOCH_DIFF: u64 = 116444736000000000; // 100-ns intervals from 1601 to 1970
+        if filetime < EPOCH_DIFF {
+            return (0, 0);
+        }
+        let unix_100ns = filetime - EPOCH_DIFF;
+        let secs = unix_100ns / 10_000_000;
+        let nsecs = ((unix_100ns % 10_000_000) * 100) as u32;
+        (secs, nsecs)
+    }
+
+    let (atime, atime_nsec) = filetime_to_unix(meta.last_access_time());
+    let (mtime, mtime_nsec) = filetime_to_unix(meta.last_write_time());
+    let (ctime, ctime_nsec) = filetime_to_unix(meta.creation_time());
+
+    // Windows doesn't have Unix permissions, use sensible defaults
+    let mode = if meta.is_dir() { 0o755 } else { 0o644 };
+
+    FileAttr {
+        inode,
+        file_type,
+        size: meta.len(),
+        mode,
+        nlink: 1, // Windows doesn't expose hard link count easily
+        uid: 0,   // No Unix UID/GID on Windows
+        gid: 0,
+        atime,
+        atime_nsec,
+        mtime,
+        mtime_nsec,
+        ctime,
+        ctime_nsec,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lock_manager::LockManager;
+    use crate::share_backend::dispatch;
+    use teleport_core::{
+        ListDirRequest, LookupRequest, NetMessage, ReadChunkRequest, RenameRequest, SeekRequest,
+        SeekResponse,
+    };
+    use tempfile::TempDir;
+
+    fn serve(backend: &LocalBackend, request: NetMessage) -> NetMessage {
+        dispatch(request, backend, &LockManager::default(), "test")
+    }
+
+    fn read_chunk(backend: &LocalBackend, inode: Inode, index: u64) -> NetMessage {
+        let request = ReadChunkRequest {
+            chunk_id: ChunkId::new(inode, index),
+            priority: 0,
+        };
+        serve(backend, NetMessage::ReadChunk(request))
+    }
+
+    fn list_root(backend: &LocalBackend) -> NetMessage {
+        let request = ListDirRequest {
+            inode: ROOT_INODE,
+            offset: 0,
+            limit: 100,
+            with_attrs: false,
+        };
+        serve(backend, NetMessage::ListDir(request))
+    }
+
+    fn lookup(backend: &LocalBackend, parent: Inode, name: &str) -> NetMessage {
+        let request = LookupRequest {
+            parent,
+            name: name.into(),
+        };
+        serve(backend, NetMessage::Lookup(request))
+    }
+
+    #[test]
+    fn test_inode_table() {
+        let filter = Arc::new(ShareFilter::new("/shared"));
+        let table = InodeTable::new(PathBuf::from("/shared"), filter);
+
+        // Root is inode 1
+        assert_eq!(table.get_path(ROOT_INODE), Some(PathBuf::from("/shared")));
+
+        // New paths get new inodes
+        let inode = table
+            .get_or_create_inode(PathBuf::from("/shared/file.txt"))
+            .unwrap();
+        assert_eq!(inode, FIRST_USER_INODE);
+
+        // Same path returns same inode
+        let inode2 = table
+            .get_or_create_inode(PathBuf::from("/shared/file.txt"))
+            .unwrap();
+        assert_eq!(inode, inode2);
+
+        // Different path gets different inode
+        let inode3 = table
+            .get_or_create_inode(PathBuf::from("/shared/other.txt"))
+            .unwrap();
+        assert_ne!(inode, inode3);
+    }
+
+    #[test]
+    fn test_handle_read_chunk() {
+        // Create a temp directory with a test file
+        let temp_dir = TempDir::new().unwrap();
+        let test_file = temp_dir.path().join("test.txt");
+        let test_content = b"Hello, Wormhole Phase 2!";
+
+        std::fs::write(&test_file, test_content).unwrap();
+
+        let backend = LocalBackend::new(temp_dir.path());
+        let file_inode = backend.inodes.get_or_create_inode(test_file).unwrap();
+
+        match read_chunk(&backend, file_inode, 0) {
+            NetMessage::ReadChunkResponse(resp) => {
+                assert_eq!(resp.chunk_id.inode, file_inode);
+                assert_eq!(resp.data, test_content);
+                assert!(resp.is_final);
+                // Verify checksum
+                let expected_checksum = teleport_core::crypto::checksum(&resp.data);
+                assert_eq!(resp.checksum, expected_checksum);
+            }
+            NetMessage::Error(e) => panic!("Unexpected error: {:?}", e),
+            _ => panic!("Unexpected response type"),
+        }
+    }
+
+    #[test]
+    fn test_handle_read_chunk_large_file() {
+        // Create a temp directory with a file larger than one chunk
+        let temp_dir = TempDir::new().unwrap();
+        let test_file = temp_dir.path().join("large.bin");
+
+        // Create a file with 2.5 chunks worth of data
+        let chunk_size = CHUNK_SIZE;
+        let total_size = chunk_size * 2 + chunk_size / 2;
+        let test_data: Vec<u8> = (0..total_size).map(|i| (i % 256) as u8).collect();
+
+        std::fs::write(&test_file, &test_data).unwrap();
+
+        let backend = LocalBackend::new(temp_dir.path());
+        let file_inode = backend.inodes.get_or_create_inode(test_file).unwrap();
+
+        match read_chunk(&backend, file_inode, 0) {
+            NetMessage::ReadChunkResponse(r) => {
+                assert_eq!(r.data.len(), chunk_size);
+                assert!(!r.is_final);
+                assert_eq!(r.data, &test_data[..chunk_size]);
+            }
+            _ => panic!("Expected ReadChunkResponse"),
+        }
+
+        match read_chunk(&backend, file_inode, 1) {
+            NetMessage::ReadChunkResponse(r) => {
+                assert_eq!(r.data.len(), chunk_size);
+                assert!(!r.is_final);
+                assert_eq!(r.data, &test_data[chunk_size..chunk_size * 2]);
+            }
+            _ => panic!("Expected ReadChunkResponse"),
+        }
+
+        // Third (final) chunk
+        match read_chunk(&backend, file_inode, 2) {
+            NetMessage::ReadChunkResponse(r) => {
+                assert_eq!(r.data.len(), chunk_size / 2);
+                assert!(r.is_final);
+                assert_eq!(r.data, &test_data[chunk_size * 2..]);
+            }
+            _ => panic!("Expected ReadChunkResponse"),
+        }
+    }
+
+    #[test]
+    fn test_sparse_file_holes() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("disk.img");
+        let file = fs::File::create(&path).unwrap();
+        file.set_len(CHUNK_SIZE as u64 * 2).unwrap();
+        drop(file);
+
+        let backend = LocalBackend::new(temp_dir.path());
+        let inode = backend.inodes.get_or_create_inode(path).unwrap();
+
+        match read_chunk(&backend, inode, 1) {
+            NetMessage::ReadChunkResponse(r) => {
+                assert!(r.data.is_empty());
+                assert_eq!(r.zeroed, Some(CHUNK_SIZE as u32));
+                assert!(r.is_final);
+                let data = r.into_data();
+                assert_eq!(data, vec![0u8; CHUNK_SIZE]);
+            }
+            other => panic!("Expected ReadChunkResponse, got {:?}", other),
+        }
+
+        // Where holes are tracked the file is one big hole; elsewhere, all data
+        let seek = |offset, whence| match serve(
+            &backend,
+            NetMessage::Seek(SeekRequest {
+                inode,
+                offset,
+                whence,
+            }),
+        ) {
+            NetMessage::SeekResponse(SeekResponse { offset }) => offset,
+            other => panic!("Expected SeekResponse, got {:?}", other),
+        };
+        let hole = seek(10, SeekWhence::Hole).unwrap();
+        assert!(hole == 10 || hole == CHUNK_SIZE as u64 * 2);
+        assert_eq!(seek(CHUNK_SIZE as u64 * 2, SeekWhence::Data), None);
+    }
+
+    #[test]
+    fn test_handle_read_chunk_nonexistent_inode() {
+        let temp_dir = TempDir::new().unwrap();
+        let backend = LocalBackend::new(temp_dir.path());
+
+        match read_chunk(&backend, 9999, 0) {
+            NetMessage::Error(e) => {
+                assert_eq!(e.code, ErrorCode::FileNotFound);
+            }
+            _ => panic!("Expected error response"),
+        }
+    }
+
+    #[test]
+    fn test_handle_lookup() {
+        let temp_dir = TempDir::new().unwrap();
+        let subdir = temp_dir.path().join("subdir");
+        std::fs::create_dir(&subdir).unwrap();
+        std::fs::write(subdir.join("file.txt"), b"test content").unwrap();
+
+        let backend = LocalBackend::new(temp_dir.path());
+        let subdir_inode = backend.inodes.get_or_create_inode(subdir).unwrap();
+
+        match lookup(&backend, subdir_inode, "file.txt") {
+            NetMessage::LookupResponse(r) => {
+                let attr = r.attr.unwrap();
+                assert_eq!(attr.size, 12); // "test content"
+                assert_eq!(attr.file_type, FileType::File);
+            }
+            _ => panic!("Expected LookupResponse"),
+        }
+    }
+
+    #[test]
+    fn test_handle_lookup_nonexistent() {
+        let temp_dir = TempDir::new().unwrap();
+        let backend = LocalBackend::new(temp_dir.path());
+
+        assert!(backend
+            .lookup(ROOT_INODE, "nonexistent.txt")
+            .unwrap()
+            .is_none());
+        match lookup(&backend, ROOT_INODE, "nonexistent.txt") {
+            NetMessage::LookupResponse(r) => assert!(r.attr.is_none()),
+            _ => panic!("Expected LookupResponse"),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlink_escaping_share_is_refused() {
+        let outside = TempDir::new().unwrap();
+        std::fs::write(outside.path().join("secret"), b"secret").unwrap();
+        let temp_dir = TempDir::new().unwrap();
+        std::os::unix::fs::symlink(outside.path().join("secret"), temp_dir.path().join("link"))
+            .unwrap();
+
+        let backend = LocalBackend::new(temp_dir.path());
+        assert!(matches!(
+            lookup(&backend, ROOT_INODE, "link"),
+            NetMessage::Error(ErrorMessage {
+                code: ErrorCode::PathTraversal,
+                ..
+            })
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_set_attr_refuses_symlink_escaping_share() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let outside = TempDir::new().unwrap();
+        let secret = outside.path().join("secret");
+        std::fs::write(&secret, b"secret").unwrap();
+        std::fs::set_permissions(&secret, std::fs::Permissions::from_mode(0o600)).unwrap();
+        let temp_dir = TempDir::new().unwrap();
+        std::os::unix::fs::symlink(&secret, temp_dir.path().join("link")).unwrap();
+
+        // Listing hands out an inode for the link without following it
+        let backend = LocalBackend::new(temp_dir.path());
+        let page = backend.list_dir(ROOT_INODE, 0, 16).unwrap();
+        let link = page.entries[0].inode;
+
+        for changes in [
+            AttrChanges {
+                size: Some(0),
+                ..AttrChanges::default()
+            },
+            AttrChanges {
+                mode: Some(0o777),
+                ..AttrChanges::default()
+            },
+        ] {
+            let err = backend.set_attr(link, changes).unwrap_err();
+            assert_eq!(err.code, ErrorCode::PathTraversal);
+        }
+        assert_eq!(std::fs::read(&secret).unwrap(), b"secret");
+        let mode = std::fs::metadata(&secret).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn test_handle_listdir() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("file1.txt"), b"content1").unwrap();
+        std::fs::write(temp_dir.path().join("file2.txt"), b"content2").unwrap();
+        std::fs::create_dir(temp_dir.path().join("subdir")).unwrap();
+
+        let backend = LocalBackend::new(temp_dir.path());
+
+        match list_root(&backend) {
+            NetMessage::ListDirResponse(r) => {
+                assert_eq!(r.entries.len(), 3);
+                assert!(!r.has_more);
+                assert_eq!(r.next_offset, 3);
+                let names: Vec<_> = r.entries.iter().map(|e| &e.name).collect();
+                assert!(names.contains(&&"file1.txt".to_string()));
+                assert!(names.contains(&&"file2.txt".to_string()));
+                assert!(names.contains(&&"subdir".to_string()));
+            }
+            _ => panic!("Expected ListDirResponse"),
+        }
+    }
+
+    #[test]
+    fn test_handle_listdir_with_attrs() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("a.txt"), b"12345").unwrap();
+        std::fs::create_dir(temp_dir.path().join("sub")).unwrap();
+        let backend = LocalBackend::new(temp_dir.path());
+
+        match list_root(&backend) {
+            NetMessage::ListDirResponse(r) => assert!(r.attrs.is_empty()),
+            _ => panic!("Expected ListDirResponse"),
+        }
+
+        let request = ListDirRequest {
+            inode: ROOT_INODE,
+            offset: 0,
+            limit: u32::MAX,
+            with_attrs: true,
+        };
+        match serve(&backend, NetMessage::ListDir(request)) {
+            NetMessage::ListDirResponse(r) => {
+                assert_eq!(r.attrs.len(), r.entries.len());
+                for (entry, attr) in r.entries.iter().zip(&r.attrs) {
+                    let attr = attr.as_ref().unwrap();
+                    assert_eq!(attr.inode, entry.inode);
+                    assert_eq!(attr.file_type, entry.file_type);
+                    if entry.name == "a.txt" {
+                        assert_eq!(attr.size, 5);
+                    }
+                }
+            }
+            _ => panic!("Expected ListDirResponse"),
+        }
+    }
+
+    #[test]
+    fn test_share_filter_hides_excluded_entries() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().to_path_buf();
+        std::fs::create_dir(root.join(".git")).unwrap();
+        std::fs::write(root.join(".env"), b"SECRET=1").unwrap();
+        std::fs::write(root.join("README.md"), b"hello").unwrap();
+
+        let filter = ShareFilter::with_patterns(&root, &[] as &[&str], &[".git/", ".env"]).unwrap();
+        let backend = LocalBackend::with_filter(&root, filter);
+
+        match list_root(&backend) {
+            NetMessage::ListDirResponse(resp) => {
+                let names: Vec<_> = resp.entries.iter().map(|e| e.name.as_str()).collect();
+                assert_eq!(names, ["README.md"]);
+            }
+            _ => panic!("Expected ListDirResponse"),
+        }
+
+        assert!(matches!(
+            lookup(&backend, ROOT_INODE, ".env"),
+            NetMessage::Error(ErrorMessage {
+                code: ErrorCode::FileNotFound,
+                ..
+            })
+        ));
+
+        // Visible files can't be renamed into an excluded name
+        let rename = serve(
+            &backend,
+            NetMessage::Rename(RenameRequest {
+                old_parent: ROOT_INODE,
+                old_name: "README.md".into(),
+                new_parent: ROOT_INODE,
+                new_name: ".env".into(),
+                lock_token: None,
+            }),
+        );
+        assert!(matches!(
+            rename,
+            NetMessage::Error(ErrorMessage {
+                code: ErrorCode::PermissionDenied,
+                ..
+            })
+        ));
+        assert!(root.join("README.md").exists());
+    }
+}
@@ -0,0 +1,507 @@
+//! In-process host and client for end-to-end tests
+//!
+//! `Loopback` serves a backend (usually a `MemoryBackend`) from a
+//! `WormholeHost` on 127.0.0.1, connects a `WormholeClient` to it over QUIC
+//! and runs `handle_fuse_requests`. Tests send the same `FuseRequest`s a
+//! mount would, so client and host are exercised together without FUSE.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crossbeam_channel::{bounded, Sender};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+use teleport_core::{DirEntry, FallocateMode, FileAttr, Inode, SeekWhence};
+
+use crate::bridge::{DirListing, FuseError, FuseRequest};
+use crate::client::{ClientConfig, ClientError, WormholeClient};
+use crate::host::{HostConfig, HostError, WormholeHost};
+use crate::share_backend::ShareBackend;
+use crate::MAX_INFLIGHT_REQUESTS;
+
+/// How long `start` keeps trying to reach the host
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serve `backend` on a free port of 127.0.0.1
+///
+/// The host is adjusted by `configure` before it starts. Clients should
+/// retry their first connection, as the endpoint may not be bound yet.
+pub fn serve(
+    backend: Arc<dyn ShareBackend>,
+    configure: impl FnOnce(WormholeHost) -> WormholeHost,
+) -> (SocketAddr, JoinHandle<Result<(), HostError>>) {
+    let port = std::net::UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let addr: SocketAddr = format!("127.0.0.1:{}", port).parse().unwrap();
+
+    let host = configure(
+        WormholeHost::new(HostConfig {
+            bind_addr: addr,
+            max_connections: 4,
+            host_name: "loopback".into(),
+            ..HostConfig::default()
+        })
+        .with_backend(backend),
+    );
+    (addr, tokio::spawn(async move { host.serve().await }))
+}
+
+/// A host and a connected client in this process
+pub struct Loopback {
+    /// Address the host listens on
+    pub addr: SocketAddr,
+    requests: Sender<FuseRequest>,
+    host: JoinHandle<Result<(), HostError>>,
+    client: JoinHandle<Result<(), ClientError>>,
+    /// Holds the client's known-peers store
+    _state: tempfile::TempDir,
+}
+
+impl Loopback {
+    /// Serve `backend` and connect a client to it
+    pub async fn start(backend: Arc<dyn ShareBackend>) -> Self {
+        Self::start_with(backend, |host| host).await
+    }
+
+    /// Like `start`, with the host adjusted by `configure` before it serves
+    ///
+    /// Panics if the client can't connect.
+    pub async fn start_with(
+        backend: Arc<dyn ShareBackend>,
+        configure: impl FnOnce(WormholeHost) -> WormholeHost,
+    ) -> Self {
+        let (addr, host) = serve(backend, configure);
+
+        let state = tempfile::tempdir().unwrap();
+        let config = || ClientConfig {
+            server_addr: addr,
+            known_peers_path: Some(state.path().join("known_peers.toml")),
+            request_timeout: Duration::from_secs(5),
+            ..ClientConfig::default()
+        };
+
+        // The host may still be binding its endpoint
+        let started = tokio::time::Instant::now();
+        let client = loop {
+            let mut client = WormholeClient::new(config());
+            match client.connect().await {
+                Ok(()) => break client,
+                Err(e) if started.elapsed() > CONNECT_TIMEOUT => {
+                    panic!("loopback client failed to connect: {:?}", e)
+                }
+                Err(_) => tokio::time::sleep(Duration::from_millis(20)).await,
+            }
+        };
+
+        let (requests, request_rx) = bounded(MAX_INFLIGHT_REQUESTS);
+        let client = tokio::spawn(async move { client.handle_fuse_requests(request_rx).await });
+
+        Self {
+            addr,
+            requests,
+            host,
+            client,
+            _state: state,
+        }
+    }
+
+    /// Send a request to the client and wait for its reply
+    pub async fn request<T>(
+        &self,
+        request: impl FnOnce(oneshot::Sender<Result<T, FuseError>>) -> FuseRequest,
+    ) -> Result<T, FuseError> {
+        let (reply, response) = oneshot::channel();
+        self.requests
+            .send(request(reply))
+            .map_err(|_| FuseError::Shutdown)?;
+        response.await.unwrap_or(Err(FuseError::Shutdown))
+    }
+
+    pub async fn lookup(&self, parent: Inode, name: &str) -> Result<FileAttr, FuseError> {
+        let name = name.to_string();
+        self.request(|reply| FuseRequest::Lookup {
+            parent,
+            name,
+            reply,
+        })
+        .await
+    }
+
+    pub async fn getattr(&self, inode: Inode) -> Result<FileAttr, FuseError> {
+        self.request(|reply| FuseRequest::GetAttr { inode, reply })
+            .await
+    }
+
+    pub async fn readdir(&self, inode: Inode) -> Result<Vec<DirEntry>, FuseError> {
+        self.request(|reply| FuseRequest::ReadDir {
+            inode,
+            offset: 0,
+            reply,
+        })
+        .await
+    }
+
+    pub async fn readdirplus(&self, inode: Inode) -> Result<DirListing, FuseError> {
+        self.request(|reply| FuseRequest::ReadDirPlus { inode, reply })
+            .await
+    }
+
+    pub async fn read(&self, inode: Inode, offset: u64, size: u32) -> Result<Vec<u8>, FuseError> {
+        self.request(|reply| FuseRequest::Read {
+            inode,
+            offset,
+            size,
+            reply,
+        })
+        .await
+    }
+
+    /// Write through the client; needs an exclusive lock on `inode`
+    pub async fn write(&self, inode: Inode, offset: u64, data: &[u8]) -> Result<u32, FuseError> {
+        let data = data.to_vec();
+        self.request(|reply| FuseRequest::Write {
+            inode,
+            offset,
+            data,
+            reply,
+        })
+        .await
+    }
+
+    pub async fn acquire_lock(&self, inode: Inode, exclusive: bool) -> Result<(), FuseError> {
+        self.request(|reply| FuseRequest::AcquireLock {
+            inode,
+            exclusive,
+            reply,
+        })
+        .await
+    }
+
+    pub async fn release_lock(&self, inode: Inode) -> Result<(), FuseError> {
+        self.request(|reply| FuseRequest::ReleaseLock { inode, reply })
+            .await
+    }
+
+    /// Stage writes to `inode` until committed, taking its lock if needed
+    pub async fn begin_write(&self, inode: Inode) -> Result<(), FuseError> {
+        self.request(|reply| FuseRequest::BeginWrite { inode, reply })
+            .await
+    }
+
+    pub async fn commit_write(&self, inode: Inode) -> Result<Option<FileAttr>, FuseError> {
+        self.request(|reply| FuseRequest::CommitWrite { inode, reply })
+            .await
+    }
+
+    pub async fn abort_write(&self, inode: Inode) -> Result<(), FuseError> {
+        self.request(|reply| FuseRequest::AbortWrite { inode, reply })
+            .await
+    }
+
+    pub async fn create_file(
+        &self,
+        parent: Inode,
+        name: &str,
+        mode: u32,
+    ) -> Result<FileAttr, FuseError> {
+        let name = name.to_string();
+        self.request(|reply| FuseRequest::CreateFile {
+            parent,
+            name,
+            mode,
+            reply,
+        })
+        .await
+    }
+
+    pub async fn delete_file(&self, parent: Inode, name: &str) -> Result<(), FuseError> {
+        let name = name.to_string();
+        self.request(|reply| FuseRequest::DeleteFile {
+            parent,
+            name,
+            reply,
+        })
+        .await
+    }
+
+    pub async fn rename(
+        &self,
+        old_parent: Inode,
+        old_name: &str,
+        new_parent: Inode,
+        new_name: &str,
+    ) -> Result<(), FuseError> {
+        let (old_name, new_name) = (old_name.to_string(), new_name.to_string());
+        self.request(|reply| FuseRequest::Rename {
+            old_parent,
+            old_name,
+            new_parent,
+            new_name,
+            reply,
+        })
+        .await
+    }
+
+    pub async fn seek(
+        &self,
+        inode: Inode,
+        offset: u64,
+        whence: SeekWhence,
+    ) -> Result<Option<u64>, FuseError> {
+        self.request(|reply| FuseRequest::Seek {
+            inode,
+            offset,
+            whence,
+            reply,
+        })
+        .await
+    }
+
+    pub async fn fallocate(
+        &self,
+        inode: Inode,
+        offset: u64,
+        len: u64,
+        mode: FallocateMode,
+    ) -> Result<FileAttr, FuseError> {
+        self.request(|reply| FuseRequest::Fallocate {
+            inode,
+            offset,
+            len,
+            mode,
+            reply,
+        })
+        .await
+    }
+}
+
+impl Loopback {
+    /// Drop the client and its connection, as a crashed mount would; the host
+    /// keeps running
+    pub fn disconnect(&self) {
+        let _ = self.requests.try_send(FuseRequest::Shutdown);
+        self.client.abort();
+    }
+}
+
+impl Drop for Loopback {
+    fn drop(&mut self) {
+        let _ = self.requests.try_send(FuseRequest::Shutdown);
+        self.client.abort();
+        self.host.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::access::{AccessLevel, AccessList};
+    use crate::local_backend::LocalBackend;
+    use crate::memory_backend::MemoryBackend;
+    use teleport_core::{FileType, CHUNK_SIZE, MAX_LIST_DIR_ATTRS, ROOT_INODE};
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_loopback_file_flow() {
+        let share = Arc::new(MemoryBackend::new().with_file("docs/hello.txt", "hello, world"));
+        let lo = Loopback::start(share.clone()).await;
+
+        let docs = lo.lookup(ROOT_INODE, "docs").await.unwrap();
+        assert_eq!(docs.file_type, FileType::Directory);
+        let names: Vec<_> = lo
+            .readdir(docs.inode)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|e| e.name)
+            .collect();
+        assert_eq!(names, ["hello.txt"]);
+
+        let hello = lo.lookup(docs.inode, "hello.txt").await.unwrap();
+        assert_eq!(lo.read(hello.inode, 7, 100).await.unwrap(), b"world");
+
+        // Writing needs the file's lock
+        assert!(matches!(
+            lo.write(hello.inode, 0, b"HELLO").await,
+            Err(FuseError::LockRequired)
+        ));
+        lo.acquire_lock(hello.inode, true).await.unwrap();
+        assert_eq!(lo.write(hello.inode, 0, b"HELLO").await.unwrap(), 5);
+        lo.release_lock(hello.inode).await.unwrap();
+        assert_eq!(share.read_file("docs/hello.txt").unwrap(), b"HELLO, world");
+
+        let notes = lo.create_file(ROOT_INODE, "notes", 0o644).await.unwrap();
+        assert_eq!(lo.getattr(notes.inode).await.unwrap().size, 0);
+        lo.rename(docs.inode, "hello.txt", ROOT_INODE, "hello.txt")
+            .await
+            .unwrap();
+        assert!(share.exists("hello.txt"));
+        assert!(matches!(
+            lo.lookup(docs.inode, "hello.txt").await,
+            Err(FuseError::NotFound)
+        ));
+        lo.delete_file(ROOT_INODE, "notes").await.unwrap();
+        assert!(!share.exists("notes"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_loopback_readdirplus_pages() {
+        let count = MAX_LIST_DIR_ATTRS as usize + 10;
+        let share = (0..count).fold(MemoryBackend::new(), |share, i| {
+            share.with_file(&format!("logs/{:04}.log", i), "x".repeat(i % 7))
+        });
+        let lo = Loopback::start(Arc::new(share)).await;
+        let logs = lo.lookup(ROOT_INODE, "logs").await.unwrap().inode;
+
+        let listing = lo.readdirplus(logs).await.unwrap();
+        assert_eq!(listing.len(), count);
+        for (entry, attr) in &listing {
+            let attr = attr.as_ref().unwrap();
+            assert_eq!(attr.inode, entry.inode);
+            let i: usize = entry.name[..4].parse().unwrap();
+            assert_eq!(attr.size, (i % 7) as u64);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_loopback_read_only_client() {
+        let share = Arc::new(MemoryBackend::new().with_file("a.txt", "a"));
+        let mut access = AccessList::default();
+        access.set_default_level(AccessLevel::Read);
+        let lo = Loopback::start_with(share.clone(), |host| {
+            host.with_access_list(Arc::new(access))
+        })
+        .await;
+
+        assert!(lo.lookup(ROOT_INODE, "a.txt").await.is_ok());
+        assert!(matches!(
+            lo.create_file(ROOT_INODE, "b.txt", 0o644).await,
+            Err(FuseError::PermissionDenied)
+        ));
+        assert!(!share.exists("b.txt"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_loopback_sparse_files() {
+        let mut image = vec![0u8; CHUNK_SIZE * 2];
+        image[CHUNK_SIZE..].fill(b'x');
+        let share = Arc::new(MemoryBackend::new().with_file("disk.img", image));
+        let lo = Loopback::start(share.clone()).await;
+        let disk = lo.lookup(ROOT_INODE, "disk.img").await.unwrap().inode;
+        let size = (CHUNK_SIZE * 2) as u64;
+
+        // The zeroed chunk arrives without its data and is filled back in
+        assert_eq!(lo.read(disk, 10, 100).await.unwrap(), vec![0u8; 100]);
+        assert_eq!(lo.read(disk, size - 2, 100).await.unwrap(), b"xx");
+
+        // Memory shares have no holes but the end of the file
+        assert_eq!(lo.seek(disk, 5, SeekWhence::Data).await.unwrap(), Some(5));
+        assert_eq!(
+            lo.seek(disk, 5, SeekWhence::Hole).await.unwrap(),
+            Some(size)
+        );
+        assert_eq!(lo.seek(disk, size, SeekWhence::Data).await.unwrap(), None);
+
+        // Without a lock held, one is taken for the change
+        let attr = lo
+            .fallocate(disk, size - 4, 2, FallocateMode::PunchHole)
+            .await
+            .unwrap();
+        assert_eq!(attr.size, size);
+        assert_eq!(
+            &share.read_file("disk.img").unwrap()[CHUNK_SIZE * 2 - 5..],
+            b"x\0\0xx"
+        );
+        let attr = lo
+            .fallocate(disk, size, 10, FallocateMode::AllocateKeepSize)
+            .await
+            .unwrap();
+        assert_eq!(attr.size, size);
+        let attr = lo
+            .fallocate(disk, size, 10, FallocateMode::Allocate)
+            .await
+            .unwrap();
+        assert_eq!(attr.size, size + 10);
+        lo.acquire_lock(disk, true).await.unwrap();
+    }
+
+    /// Names in `dir`, which include staged writes
+    fn dir_names(dir: &std::path::Path) -> Vec<String> {
+        let mut names: Vec<String> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_loopback_save_interrupted_by_disconnect() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("doc.txt");
+        let original = vec![b'a'; CHUNK_SIZE * 2 + 10];
+        std::fs::write(&path, &original).unwrap();
+        let lo = Loopback::start(Arc::new(LocalBackend::new(dir.path()))).await;
+        let doc = lo.lookup(ROOT_INODE, "doc.txt").await.unwrap().inode;
+
+        lo.begin_write(doc).await.unwrap();
+        assert_eq!(lo.write(doc, 0, b"new").await.unwrap(), 3);
+        assert_eq!(std::fs::read(&path).unwrap(), original);
+        assert_eq!(dir_names(dir.path()).len(), 2);
+
+        // The mount goes away mid-save; the host discards the staged writes
+        lo.disconnect();
+        let started = tokio::time::Instant::now();
+        while dir_names(dir.path()).len() > 1 {
+            assert!(started.elapsed() < CONNECT_TIMEOUT, "staged file kept");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(dir_names(dir.path()), ["doc.txt"]);
+        assert_eq!(std::fs::read(&path).unwrap(), original);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_loopback_save_commit_and_abort() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("doc.txt");
+        std::fs::write(&path, vec![b'a'; CHUNK_SIZE + 10]).unwrap();
+        let lo = Loopback::start(Arc::new(LocalBackend::new(dir.path()))).await;
+        let doc = lo.lookup(ROOT_INODE, "doc.txt").await.unwrap().inode;
+
+        // Committing replaces the file with exactly what was written
+        lo.begin_write(doc).await.unwrap();
+        lo.write(doc, 0, b"short").await.unwrap();
+        let attr = lo.commit_write(doc).await.unwrap().unwrap();
+        assert_eq!(attr.size, 5);
+        assert_eq!(std::fs::read(&path).unwrap(), b"short");
+        assert!(lo.commit_write(doc).await.unwrap().is_none());
+
+        // Aborting leaves it as it was, and the session's lock is released
+        lo.begin_write(doc).await.unwrap();
+        lo.write(doc, 0, b"discarded").await.unwrap();
+        lo.abort_write(doc).await.unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"short");
+        assert_eq!(dir_names(dir.path()), ["doc.txt"]);
+        assert!(matches!(
+            lo.write(doc, 0, b"x").await,
+            Err(FuseError::LockRequired)
+        ));
+
+        // Memory shares can't stage files
+        let share = Arc::new(MemoryBackend::new().with_file("a.txt", "a"));
+        let lo = Loopback::start(share).await;
+        let a = lo.lookup(ROOT_INODE, "a.txt").await.unwrap().inode;
+        assert!(matches!(
+            lo.begin_write(a).await,
+            Err(FuseError::Unsupported)
+        ));
+        assert!(matches!(
+            lo.write(a, 0, b"x").await,
+            Err(FuseError::LockRequired)
+        ));
+    }
+}
@@ -0,0 +1,617 @@
+//! Share backend holding its files in memory
+//!
+//! Nothing touches the disk, which makes it the backend of choice for tests
+//! of the host and client (see `loopback`). Inodes are never reused.
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use parking_lot::RwLock;
+
+use teleport_core::{
+    path::validate_filename, ChunkId, DirEntry, ErrorCode, ErrorMessage, FallocateMode, FileAttr,
+    FileType, Inode, CHUNK_SIZE, FIRST_USER_INODE, ROOT_INODE,
+};
+
+use crate::share_backend::{
+    backend_error, new_epoch, AttrChanges, BackendResult, ChunkData, DirPage, ShareBackend,
+};
+
+/// Permissions of directories created by `with_dir` and `with_file`
+const DEFAULT_DIR_MODE: u32 = 0o755;
+
+/// Permissions of files created by `with_file`
+const DEFAULT_FILE_MODE: u32 = 0o644;
+
+/// Largest file clients may grow a file to, so a stray offset can't exhaust memory
+const MAX_FILE_SIZE: u64 = 1 << 30;
+
+enum Contents {
+    File(Vec<u8>),
+    Dir(BTreeMap<String, Inode>),
+}
+
+struct Node {
+    parent: Inode,
+    name: String,
+    contents: Contents,
+    mode: u32,
+    atime: u64,
+    mtime: u64,
+    ctime: u64,
+}
+
+impl Node {
+    fn new(parent: Inode, name: &str, contents: Contents, mode: u32) -> Self {
+        let now = now();
+        Self {
+            parent,
+            name: name.to_string(),
+            contents,
+            mode: mode & 0o7777,
+            atime: now,
+            mtime: now,
+            ctime: now,
+        }
+    }
+
+    fn attr(&self, inode: Inode) -> FileAttr {
+        let (file_type, size, nlink) = match &self.contents {
+            Contents::File(data) => (FileType::File, data.len() as u64, 1),
+            Contents::Dir(entries) => (FileType::Directory, entries.len() as u64, 2),
+        };
+        FileAttr {
+            inode,
+            file_type,
+            size,
+            mode: self.mode,
+            nlink,
+            uid: 0,
+            gid: 0,
+            atime: self.atime,
+            atime_nsec: 0,
+            mtime: self.mtime,
+            mtime_nsec: 0,
+            ctime: self.ctime,
+            ctime_nsec: 0,
+        }
+    }
+
+    fn touch(&mut self) {
+        let now = now();
+        self.mtime = now;
+        self.ctime = now;
+    }
+}
+
+struct Tree {
+    nodes: HashMap<Inode, Node>,
+    next_inode: Inode,
+}
+
+impl Tree {
+    fn node(&self, inode: Inode) -> BackendResult<&Node> {
+        self.nodes
+            .get(&inode)
+            .ok_or_else(|| backend_error(ErrorCode::FileNotFound, "inode not found", inode))
+    }
+
+    fn node_mut(&mut self, inode: Inode) -> BackendResult<&mut Node> {
+        self.nodes
+            .get_mut(&inode)
+            .ok_or_else(|| backend_error(ErrorCode::FileNotFound, "inode not found", inode))
+    }
+
+    fn entries(&self, inode: Inode) -> BackendResult<&BTreeMap<String, Inode>> {
+        match &self.node(inode)?.contents {
+            Contents::Dir(entries) => Ok(entries),
+            Contents::File(_) => Err(backend_error(
+                ErrorCode::NotADirectory,
+                "not a directory",
+                inode,
+            )),
+        }
+    }
+
+    fn entries_mut(&mut self, inode: Inode) -> BackendResult<&mut BTreeMap<String, Inode>> {
+        match &mut self.node_mut(inode)?.contents {
+            Contents::Dir(entries) => Ok(entries),
+            Contents::File(_) => Err(backend_error(
+                ErrorCode::NotADirectory,
+                "not a directory",
+                inode,
+            )),
+        }
+    }
+
+    fn child(&self, parent: Inode, name: &str) -> BackendResult<Option<Inode>> {
+        Ok(self.entries(parent)?.get(name).copied())
+    }
+
+    /// Add a new entry to a directory
+    fn insert(
+        &mut self,
+        parent: Inode,
+        name: &str,
+        contents: Contents,
+        mode: u32,
+    ) -> BackendResult<Inode> {
+        validate_filename(name)
+            .map_err(|e| backend_error(ErrorCode::PathTraversal, e.to_string(), parent))?;
+        if self.child(parent, name)?.is_some() {
+            return Err(backend_error(
+                ErrorCode::AlreadyExists,
+                "file exists",
+                parent,
+            ));
+        }
+
+        let inode = self.next_inode;
+        self.next_inode += 1;
+        self.nodes
+            .insert(inode, Node::new(parent, name, contents, mode));
+        self.entries_mut(parent)?.insert(name.to_string(), inode);
+        self.node_mut(parent)?.touch();
+        Ok(inode)
+    }
+
+    /// Remove an entry from its directory, along with everything below it
+    fn remove(&mut self, parent: Inode, name: &str, inode: Inode) {
+        if let Ok(entries) = self.entries_mut(parent) {
+            entries.remove(name);
+        }
+        if let Ok(node) = self.node_mut(parent) {
+            node.touch();
+        }
+        let mut pending = vec![inode];
+        while let Some(inode) = pending.pop() {
+            if let Some(Node {
+                contents: Contents::Dir(entries),
+                ..
+            }) = self.nodes.remove(&inode)
+            {
+                pending.extend(entries.into_values());
+            }
+        }
+    }
+
+    /// Whether `inode` is `ancestor` or lies below it
+    fn is_within(&self, mut inode: Inode, ancestor: Inode) -> bool {
+        loop {
+            if inode == ancestor {
+                return true;
+            }
+            match self.nodes.get(&inode) {
+                Some(node) if inode != ROOT_INODE => inode = node.parent,
+                _ => return false,
+            }
+        }
+    }
+
+    /// Inode at a `/`-separated path, creating missing directories
+    fn make_dirs(&mut self, path: &str) -> Inode {
+        let mut inode = ROOT_INODE;
+        for name in path.split('/').filter(|c| !c.is_empty()) {
+            inode = match self.child(inode, name) {
+                Ok(Some(child)) => child,
+                _ => self
+                    .insert(
+                        inode,
+                        name,
+                        Contents::Dir(BTreeMap::new()),
+                        DEFAULT_DIR_MODE,
+                    )
+                    .expect("invalid directory in memory share"),
+            };
+        }
+        inode
+    }
+
+    fn resolve(&self, path: &str) -> Option<Inode> {
+        let mut inode = ROOT_INODE;
+        for name in path.split('/').filter(|c| !c.is_empty()) {
+            inode = self.child(inode, name).ok()??;
+        }
+        Some(inode)
+    }
+}
+
+/// Share served from memory
+pub struct MemoryBackend {
+    tree: RwLock<Tree>,
+    epoch: u64,
+}
+
+impl Default for MemoryBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryBackend {
+    /// An empty share
+    pub fn new() -> Self {
+        let mut nodes = HashMap::new();
+        nodes.insert(
+            ROOT_INODE,
+            Node::new(
+                ROOT_INODE,
+                "",
+                Contents::Dir(BTreeMap::new()),
+                DEFAULT_DIR_MODE,
+            ),
+        );
+        Self {
+            tree: RwLock::new(Tree {
+                nodes,
+                next_inode: FIRST_USER_INODE,
+            }),
+            epoch: new_epoch(),
+        }
+    }
+
+    /// Add a directory (and any missing parents) at a `/`-separated path
+    ///
+    /// Panics if a component is not a valid file name or is a file.
+    pub fn with_dir(self, path: &str) -> Self {
+        self.tree.write().make_dirs(path);
+        self
+    }
+
+    /// Add a file at a `/`-separated path, creating missing directories
+    ///
+    /// Panics if a component is not a valid file name or if the path exists.
+    pub fn with_file(self, path: &str, data: impl Into<Vec<u8>>) -> Self {
+        {
+            let mut tree = self.tree.write();
+            let (dir, name) = path.rsplit_once('/').unwrap_or(("", path));
+            let parent = tree.make_dirs(dir);
+            tree.insert(parent, name, Contents::File(data.into()), DEFAULT_FILE_MODE)
+                .expect("invalid file in memory share");
+        }
+        self
+    }
+
+    /// Contents of the file at a `/`-separated path
+    pub fn read_file(&self, path: &str) -> Option<Vec<u8>> {
+        let tree = self.tree.read();
+        let inode = tree.resolve(path)?;
+        match &tree.nodes.get(&inode)?.contents {
+            Contents::File(data) => Some(data.clone()),
+            Contents::Dir(_) => None,
+        }
+    }
+
+    /// Whether a file or directory exists at a `/`-separated path
+    pub fn exists(&self, path: &str) -> bool {
+        self.tree.read().resolve(path).is_some()
+    }
+}
+
+impl ShareBackend for MemoryBackend {
+    fn lookup(&self, parent: Inode, name: &str) -> BackendResult<Option<FileAttr>> {
+        validate_filename(name)
+            .map_err(|e| backend_error(ErrorCode::PathTraversal, e.to_string(), parent))?;
+        let tree = self.tree.read();
+        if !tree.nodes.contains_key(&parent) {
+            return Ok(None);
+        }
+        Ok(tree
+            .child(parent, name)?
+            .map(|inode| tree.nodes[&inode].attr(inode)))
+    }
+
+    fn getattr(&self, inode: Inode) -> BackendResult<Option<FileAttr>> {
+        Ok(self.tree.read().nodes.get(&inode).map(|n| n.attr(inode)))
+    }
+
+    fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    fn list_dir(&self, inode: Inode, offset: u64, limit: u32) -> BackendResult<DirPage> {
+        let tree = self.tree.read();
+        let entries = tree.entries(inode)?;
+        let mut page: Vec<DirEntry> = entries
+            .iter()
+            .skip(offset as usize)
+            .take(limit as usize + 1)
+            .map(|(name, child)| {
+                let file_type = match tree.nodes[child].contents {
+                    Contents::File(_) => FileType::File,
+                    Contents::Dir(_) => FileType::Directory,
+                };
+                DirEntry::new(name.clone(), *child, file_type)
+            })
+            .collect();
+        let has_more = page.len() > limit as usize;
+        page.truncate(limit as usize);
+        Ok(DirPage {
+            entries: page,
+            has_more,
+        })
+    }
+
+    fn read_chunk(&self, chunk_id: ChunkId) -> BackendResult<ChunkData> {
+        let inode = chunk_id.inode;
+        let tree = self.tree.read();
+        let Contents::File(data) = &tree.node(inode)?.contents else {
+            return Err(backend_error(ErrorCode::NotAFile, "not a file", inode));
+        };
+
+        let start = (chunk_id.byte_offset() as usize).min(data.len());
+        let end = start.saturating_add(CHUNK_SIZE).min(data.len());
+        Ok(ChunkData {
+            data: data[start..end].to_vec(),
+            is_final: end >= data.len(),
+        })
+    }
+
+    fn write_chunk(&self, chunk_id: ChunkId, data: &[u8]) -> BackendResult<Option<u64>> {
+        let inode = chunk_id.inode;
+        let mut tree = self.tree.write();
+        let node = tree.node_mut(inode)?;
+        let Contents::File(contents) = &mut node.contents else {
+            return Err(backend_error(ErrorCode::NotAFile, "not a file", inode));
+        };
+
+        let end = chunk_id.byte_offset().saturating_add(data.len() as u64);
+        if end > MAX_FILE_SIZE {
+            return Err(file_too_large(inode));
+        }
+        let (start, end) = (chunk_id.byte_offset() as usize, end as usize);
+        if contents.len() < end {
+            contents.resize(end, 0);
+        }
+        contents[start..end].copy_from_slice(data);
+        let size = contents.len() as u64;
+        node.touch();
+        Ok(Some(size))
+    }
+
+    fn create_file(&self, parent: Inode, name: &str, mode: u32) -> BackendResult<FileAttr> {
+        let mut tree = self.tree.write();
+        let inode = tree.insert(parent, name, Contents::File(Vec::new()), mode)?;
+        Ok(tree.nodes[&inode].attr(inode))
+    }
+
+    fn create_dir(&self, parent: Inode, name: &str, mode: u32) -> BackendResult<FileAttr> {
+        let mut tree = self.tree.write();
+        let inode = tree.insert(parent, name, Contents::Dir(BTreeMap::new()), mode)?;
+        Ok(tree.nodes[&inode].attr(inode))
+    }
+
+    fn delete_file(&self, parent: Inode, name: &str) -> BackendResult<()> {
+        let mut tree = self.tree.write();
+        let inode = tree
+            .child(parent, name)?
+            .ok_or_else(|| backend_error(ErrorCode::FileNotFound, "no such file", parent))?;
+        if let Contents::Dir(_) = tree.nodes[&inode].contents {
+            return Err(backend_error(ErrorCode::NotAFile, "is a directory", inode));
+        }
+        tree.remove(parent, name, inode);
+        Ok(())
+    }
+
+    fn delete_dir(&self, parent: Inode, name: &str) -> BackendResult<()> {
+        let mut tree = self.tree.write();
+        let inode = tree
+            .child(parent, name)?
+            .ok_or_else(|| backend_error(ErrorCode::FileNotFound, "no such directory", parent))?;
+        if !tree.entries(inode)?.is_empty() {
+            return Err(backend_error(
+                ErrorCode::NotEmpty,
+                "directory not empty",
+                inode,
+            ));
+        }
+        tree.remove(parent, name, inode);
+        Ok(())
+    }
+
+    fn rename(
+        &self,
+        old_parent: Inode,
+        old_name: &str,
+        new_parent: Inode,
+        new_name: &str,
+    ) -> BackendResult<()> {
+        validate_filename(new_name)
+            .map_err(|e| backend_error(ErrorCode::PathTraversal, e.to_string(), new_parent))?;
+        let mut tree = self.tree.write();
+        let inode = tree
+            .child(old_parent, old_name)?
+            .ok_or_else(|| backend_error(ErrorCode::FileNotFound, "no such file", old_parent))?;
+        tree.entries(new_parent)?;
+        if tree.is_within(new_parent, inode) {
+            return Err(backend_error(
+                ErrorCode::IoError,
+                "cannot move a directory into itself",
+                inode,
+            ));
+        }
+
+        // Like rename(2): a file replaces a file, a directory an empty directory
+        if let Some(target) = tree.child(new_parent, new_name)? {
+            if target == inode {
+                return Ok(());
+            }
+            let is_dir = |node: &Node| matches!(node.contents, Contents::Dir(_));
+            match (is_dir(&tree.nodes[&inode]), is_dir(&tree.nodes[&target])) {
+                (false, true) => {
+                    return Err(backend_error(ErrorCode::NotAFile, "is a directory", target))
+                }
+                (true, false) => {
+                    return Err(backend_error(
+                        ErrorCode::NotADirectory,
+                        "not a directory",
+                        target,
+                    ))
+                }
+                (true, true) if !tree.entries(target)?.is_empty() => {
+                    return Err(backend_error(
+                        ErrorCode::NotEmpty,
+                        "directory not empty",
+                        target,
+                    ))
+                }
+                _ => tree.remove(new_parent, new_name, target),
+            }
+        }
+
+        tree.entries_mut(old_parent)?.remove(old_name);
+        tree.node_mut(old_parent)?.touch();
+        tree.entries_mut(new_parent)?
+            .insert(new_name.to_string(), inode);
+        tree.node_mut(new_parent)?.touch();
+        let node = tree.node_mut(inode)?;
+        node.parent = new_parent;
+        node.name = new_name.to_string();
+        node.ctime = now();
+        Ok(())
+    }
+
+    fn set_attr(&self, inode: Inode, changes: AttrChanges) -> BackendResult<FileAttr> {
+        let mut tree = self.tree.write();
+        let node = tree.node_mut(inode)?;
+
+        if let Some(size) = changes.size {
+            let Contents::File(data) = &mut node.contents else {
+                return Err(backend_error(ErrorCode::NotAFile, "not a file", inode));
+            };
+            if size > MAX_FILE_SIZE {
+                return Err(file_too_large(inode));
+            }
+            data.resize(size as usize, 0);
+            node.touch();
+        }
+        if let Some(mode) = changes.mode {
+            node.mode = mode & 0o7777;
+            node.ctime = now();
+        }
+        if let Some(mtime) = changes.mtime {
+            node.mtime = mtime;
+        }
+        if let Some(atime) = changes.atime {
+            node.atime = atime;
+        }
+
+        Ok(node.attr(inode))
+    }
+
+    fn fallocate(
+        &self,
+        inode: Inode,
+        offset: u64,
+        len: u64,
+        mode: FallocateMode,
+    ) -> BackendResult<FileAttr> {
+        let mut tree = self.tree.write();
+        let node = tree.node_mut(inode)?;
+        let Contents::File(data) = &mut node.contents else {
+            return Err(backend_error(ErrorCode::NotAFile, "not a file", inode));
+        };
+
+        let end = offset.saturating_add(len);
+        match mode {
+            FallocateMode::Allocate if end > data.len() as u64 => {
+                if end > MAX_FILE_SIZE {
+                    return Err(file_too_large(inode));
+                }
+                data.resize(end as usize, 0);
+            }
+            FallocateMode::Allocate | FallocateMode::AllocateKeepSize => {
+                return Ok(node.attr(inode))
+            }
+            FallocateMode::PunchHole => {
+                let end = end.min(data.len() as u64) as usize;
+                if let Some(range) = data.get_mut(offset as usize..end) {
+                    range.fill(0);
+                }
+            }
+        }
+        node.touch();
+        Ok(node.attr(inode))
+    }
+
+    fn display_path(&self, inode: Inode, name: Option<&str>) -> Option<String> {
+        let tree = self.tree.read();
+        let mut components: Vec<&str> = name.into_iter().collect();
+        let mut current = inode;
+        while current != ROOT_INODE {
+            let node = tree.nodes.get(&current)?;
+            components.push(&node.name);
+            current = node.parent;
+        }
+        components.reverse();
+        Some(format!("/{}", components.join("/")))
+    }
+}
+
+fn file_too_large(inode: Inode) -> ErrorMessage {
+    backend_error(
+        ErrorCode::ChunkOutOfRange,
+        "file too large for a memory share",
+        inode,
+    )
+}
+
+/// Seconds since the Unix epoch
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_tree_operations() {
+        let backend = MemoryBackend::new()
+            .with_file("docs/readme.md", "hello")
+            .with_dir("empty");
+
+        let docs = backend.lookup(ROOT_INODE, "docs").unwrap().unwrap();
+        assert_eq!(docs.file_type, FileType::Directory);
+        let readme = backend.lookup(docs.inode, "readme.md").unwrap().unwrap();
+        assert_eq!(readme.size, 5);
+        assert_eq!(
+            backend.display_path(readme.inode, None).as_deref(),
+            Some("/docs/readme.md")
+        );
+
+        let page = backend.list_dir(ROOT_INODE, 0, 1).unwrap();
+        assert_eq!(page.entries.len(), 1);
+        assert!(page.has_more);
+
+        // Writes past the end fill the gap with zeros
+        let size = backend
+            .write_chunk(ChunkId::new(readme.inode, 1), b"!")
+            .unwrap();
+        assert_eq!(size, Some(CHUNK_SIZE as u64 + 1));
+        let first = backend.read_chunk(ChunkId::new(readme.inode, 0)).unwrap();
+        assert_eq!(&first.data[..5], b"hello");
+        assert!(!first.is_final);
+
+        backend
+            .rename(docs.inode, "readme.md", ROOT_INODE, "README")
+            .unwrap();
+        assert!(!backend.exists("docs/readme.md"));
+        assert_eq!(backend.read_file("README").unwrap().len(), CHUNK_SIZE + 1);
+
+        // Directories can't move below themselves or be deleted while in use
+        let err = backend
+            .rename(ROOT_INODE, "docs", docs.inode, "inner")
+            .unwrap_err();
+        assert_eq!(err.code, ErrorCode::IoError);
+        backend.create_file(docs.inode, "a", 0o600).unwrap();
+        let err = backend.delete_dir(ROOT_INODE, "docs").unwrap_err();
+        assert_eq!(err.code, ErrorCode::NotEmpty);
+        backend.delete_file(docs.inode, "a").unwrap();
+        backend.delete_dir(ROOT_INODE, "docs").unwrap();
+        assert!(backend.lookup(ROOT_INODE, "docs").unwrap().is_none());
+    }
+}
@@ -6,35 +6,44 @@
 //! - Dynamic share management (add/remove shares at runtime)
 
 use std::collections::HashMap;
-use std::fs::{self, OpenOptions};
-use std::io::{Read, Seek, SeekFrom, Write};
 use std::net::SocketAddr;
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
-use dashmap::DashMap;
-use parking_lot::RwLock;
 use tokio::sync::Semaphore;
 use tracing::{debug, error, info, warn};
 
 use teleport_core::{
-    crypto::checksum, path::safe_real_path, DirEntry, ErrorCode, ErrorMessage, FileAttr, FileType,
-    GetAttrRequest, GetAttrResponse, HelloAckMessage, Inode, ListDirRequest, ListDirResponse,
-    ListSharesResponse, LockRequest, LockResponse, LockType, LookupRequest, LookupResponse,
-    NetMessage, ReadChunkRequest, ReadChunkResponse, ReleaseRequest, ReleaseResponse, ShareId,
-    ShareInfo, WriteChunkRequest, WriteChunkResponse, CHUNK_SIZE, FIRST_USER_INODE,
-    PROTOCOL_VERSION, ROOT_INODE,
+    DisconnectReason, ErrorCode, ErrorMessage, GoodbyeMessage, HelloAckMessage, ListSharesResponse,
+    NetMessage, ShareId, ShareInfo, PROTOCOL_VERSION, ROOT_INODE,
 };
 
+use crate::access::{client_level, AccessLevel, AccessList, PeerNames};
+use crate::audit::{AuditLog, AuditSession};
+use crate::bandwidth::BandwidthLimiter;
+use crate::file_handles::{ChunkIo, FileHandles};
+use crate::host_session::{
+    accept_hello, capabilities, holder_id, reject_handshake, send_final, serve_request,
+    Housekeeping, ShareSession,
+};
+use crate::local_backend::LocalBackend;
 use crate::lock_manager::LockManager;
-use crate::net::{create_server_endpoint, recv_message, send_message, ConnectionError};
+use crate::net::{
+    create_server_endpoint, peer_fingerprint, recv_message, send_goodbye, send_message,
+    ConnectionError,
+};
 use crate::rate_limiter::RateLimiter;
+use crate::share_backend::ShareBackend;
+use crate::share_limits::{ShareLimits, ShareUsage};
 
 /// SECURITY: Maximum session duration before forced re-authentication (24 hours)
 /// This prevents stale or compromised sessions from being used indefinitely.
 const MAX_SESSION_DURATION: Duration = Duration::from_secs(24 * 60 * 60);
 
+/// How long expired shares wait for sessions to say goodbye before `serve` returns
+const EXPIRY_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
 /// Configuration for a single shared folder
 #[derive(Clone, Debug)]
 pub struct SharedFolder {
@@ -46,6 +55,10 @@ pub struct SharedFolder {
     pub name: String,
     /// Whether writes are allowed
     pub writable: bool,
+    /// Access rules for this share (None = every client has full access)
+    pub access_list: Option<Arc<AccessList>>,
+    /// Expiry and usage limits for this share
+    pub limits: ShareLimits,
 }
 
 impl SharedFolder {
@@ -56,6 +69,8 @@ impl SharedFolder {
             path: path.into(),
             name: name.into(),
             writable: true,
+            access_list: None,
+            limits: ShareLimits::default(),
         }
     }
 
@@ -64,6 +79,18 @@ impl SharedFolder {
         self.writable = false;
         self
     }
+
+    /// Restrict this share with an access list
+    pub fn with_access_list(mut self, access_list: Arc<AccessList>) -> Self {
+        self.access_list = Some(access_list);
+        self
+    }
+
+    /// Close this share once it expires or reaches a usage limit
+    pub fn with_limits(mut self, limits: ShareLimits) -> Self {
+        self.limits = limits;
+        self
+    }
 }
 
 /// Multi-share host configuration
@@ -111,91 +138,40 @@ impl MultiHostConfig {
     }
 }
 
-/// Inode table for a single share
-struct ShareInodeTable {
-    /// Share ID
-    #[allow(dead_code)]
-    share_id: ShareId,
-    /// Path to the shared directory (used for symlink validation)
-    root_path: PathBuf,
-    /// Inode to path mapping
-    inode_to_path: DashMap<Inode, PathBuf>,
-    /// Path to inode mapping
-    path_to_inode: DashMap<PathBuf, Inode>,
-    /// Next available inode
-    next_inode: RwLock<Inode>,
-}
-
-impl ShareInodeTable {
-    fn new(share_id: ShareId, root_path: PathBuf) -> Self {
-        let table = Self {
-            share_id,
-            root_path: root_path.clone(),
-            inode_to_path: DashMap::new(),
-            path_to_inode: DashMap::new(),
-            next_inode: RwLock::new(FIRST_USER_INODE),
-        };
-
-        // Root is always inode 1
-        table.inode_to_path.insert(ROOT_INODE, root_path.clone());
-        table.path_to_inode.insert(root_path, ROOT_INODE);
-
-        table
-    }
-
-    fn get_path(&self, inode: Inode) -> Option<PathBuf> {
-        self.inode_to_path.get(&inode).map(|r| r.clone())
-    }
-
-    fn get_or_create_inode(&self, path: PathBuf) -> Option<Inode> {
-        if let Some(inode) = self.path_to_inode.get(&path) {
-            return Some(*inode);
-        }
-
-        let mut next = self.next_inode.write();
-        let inode = *next;
-
-        // Check for overflow - this is theoretical but prevents wraparound
-        // The max inode we can use is limited by GlobalInode's 48-bit local inode field
-        const MAX_LOCAL_INODE: u64 = 0x0000_FFFF_FFFF_FFFF;
-        if inode >= MAX_LOCAL_INODE {
-            warn!("Inode space exhausted, cannot create more inodes");
-            return None;
-        }
-
-        *next = inode + 1;
-
-        self.inode_to_path.insert(inode, path.clone());
-        self.path_to_inode.insert(path, inode);
-
-        Some(inode)
-    }
-}
-
 /// Multi-share host server
 pub struct MultiShareHost {
     config: MultiHostConfig,
-    /// Share ID to inode table mapping
-    share_tables: Arc<HashMap<ShareId, ShareInodeTable>>,
+    /// Where each share's files come from
+    share_backends: Arc<HashMap<ShareId, Arc<dyn ShareBackend>>>,
     /// Share info list
     share_infos: Vec<ShareInfo>,
+    /// Usage of each share against its limits
+    share_usage: Arc<HashMap<ShareId, Arc<ShareUsage>>>,
+    /// Bandwidth budgets; shares are named by `SharedFolder::name`
+    bandwidth: Arc<BandwidthLimiter>,
+    /// Logs of client activity, for the shares that are audited
+    audit_logs: Arc<HashMap<ShareId, Arc<AuditLog>>>,
     /// Connection semaphore
     connection_semaphore: Arc<Semaphore>,
     /// Lock manager (shared across all shares)
     lock_manager: Arc<LockManager>,
     /// Rate limiter for protection against brute-force attacks
     rate_limiter: Arc<RateLimiter>,
+    /// Disk I/O and read buffers for chunk requests (shared across all shares)
+    chunk_io: ChunkIo,
 }
 
 impl MultiShareHost {
     /// Create a new multi-share host
     pub fn new(config: MultiHostConfig) -> Self {
-        let mut share_tables = HashMap::new();
+        let mut share_backends: HashMap<ShareId, Arc<dyn ShareBackend>> = HashMap::new();
         let mut share_infos = Vec::new();
+        let mut share_usage = HashMap::new();
 
         for share in &config.shares {
-            let table = ShareInodeTable::new(share.id, share.path.clone());
-            share_tables.insert(share.id, table);
+            let backend = Arc::new(LocalBackend::new(share.path.clone()));
+            share_backends.insert(share.id, backend);
+            share_usage.insert(share.id, Arc::new(ShareUsage::new(share.limits.clone())));
 
             let mut info = ShareInfo::new(&share.name, &config.host_name);
             info.id = share.id;
@@ -206,10 +182,14 @@ impl MultiShareHost {
         Self {
             connection_semaphore: Arc::new(Semaphore::new(config.max_connections)),
             config,
-            share_tables: Arc::new(share_tables),
+            share_backends: Arc::new(share_backends),
             share_infos,
+            share_usage: Arc::new(share_usage),
+            bandwidth: Arc::new(BandwidthLimiter::default()),
+            audit_logs: Arc::new(HashMap::new()),
             lock_manager: Arc::new(LockManager::default()),
             rate_limiter: Arc::new(RateLimiter::new()),
+            chunk_io: ChunkIo::new(),
         }
     }
 
@@ -218,7 +198,32 @@ impl MultiShareHost {
         self.share_infos.clone()
     }
 
+    /// Pace file data sent to clients with a (possibly shared) bandwidth limiter
+    pub fn with_bandwidth_limiter(mut self, limiter: Arc<BandwidthLimiter>) -> Self {
+        self.bandwidth = limiter;
+        self
+    }
+
+    /// Serve a share's files from `backend` instead of its `path`
+    pub fn with_backend(mut self, share_id: ShareId, backend: Arc<dyn ShareBackend>) -> Self {
+        Arc::make_mut(&mut self.share_backends).insert(share_id, backend);
+        self
+    }
+
+    /// Record a share's sessions, modifications and (aggregated) reads in an audit log
+    pub fn with_audit_log(mut self, share_id: ShareId, audit: Arc<AuditLog>) -> Self {
+        Arc::make_mut(&mut self.audit_logs).insert(share_id, audit);
+        self
+    }
+
+    /// Usage of a share so far
+    pub fn usage(&self, share_id: &ShareId) -> Option<Arc<ShareUsage>> {
+        self.share_usage.get(share_id).cloned()
+    }
+
     /// Start serving connections
+    ///
+    /// Returns once every share has expired or used up its byte budget.
     pub async fn serve(&self) -> Result<(), MultiHostError> {
         let (endpoint, cert_fingerprint) = create_server_endpoint(self.config.bind_addr)
             .map_err(|e| MultiHostError::Bind(format!("{:?}", e)))?;
@@ -242,18 +247,40 @@ impl MultiShareHost {
             );
         }
 
-        // Spawn a background task to periodically clean up expired rate limiter entries
-        let cleanup_limiter = self.rate_limiter.clone();
-        tokio::spawn(async move {
-            let mut interval = tokio::time::interval(Duration::from_secs(60));
-            loop {
-                interval.tick().await;
-                cleanup_limiter.cleanup_expired();
-            }
-        });
+        // Stopped once serve() returns
+        let _housekeeping = Housekeeping::spawn(
+            self.rate_limiter.clone(),
+            self.bandwidth.clone(),
+            self.share_backends.values().cloned().collect(),
+        );
+
+        // Names for `peer:` access rules, read once rather than on every handshake
+        let peer_names = if self.config.shares.iter().any(|share| {
+            share
+                .access_list
+                .as_ref()
+                .is_some_and(|list| list.uses_peer_names())
+        }) {
+            tokio::task::spawn_blocking(PeerNames::load)
+                .await
+                .unwrap_or_default()
+        } else {
+            PeerNames::default()
+        };
+        let peer_names = Arc::new(peer_names);
+
+        let all_expired = all_expired(self.share_usage.clone());
+        tokio::pin!(all_expired);
 
         loop {
-            let incoming = endpoint.accept().await;
+            let incoming = tokio::select! {
+                incoming = endpoint.accept() => incoming,
+                _ = &mut all_expired => {
+                    info!("All shares expired, no longer accepting connections");
+                    let _ = tokio::time::timeout(EXPIRY_GRACE_PERIOD, endpoint.wait_idle()).await;
+                    break;
+                }
+            };
 
             match incoming {
                 Some(conn) => {
@@ -279,12 +306,16 @@ impl MultiShareHost {
                         }
                     };
 
-                    let share_tables = self.share_tables.clone();
+                    let share_backends = self.share_backends.clone();
                     let share_infos = self.share_infos.clone();
-                    let host_name = self.config.host_name.clone();
+                    let share_usage = self.share_usage.clone();
+                    let bandwidth = self.bandwidth.clone();
+                    let audit_logs = self.audit_logs.clone();
                     let lock_manager = self.lock_manager.clone();
                     let config = self.config.clone();
                     let rate_limiter = self.rate_limiter.clone();
+                    let chunk_io = self.chunk_io.clone();
+                    let peer_names = peer_names.clone();
 
                     tokio::spawn(async move {
                         match conn.await {
@@ -295,11 +326,15 @@ impl MultiShareHost {
 
                                 match handle_connection(
                                     connection,
-                                    share_tables,
+                                    share_backends,
                                     share_infos,
-                                    host_name,
+                                    share_usage,
+                                    bandwidth,
+                                    audit_logs,
+                                    chunk_io,
                                     lock_manager,
                                     config,
+                                    peer_names,
                                 )
                                 .await
                                 {
@@ -349,82 +384,148 @@ impl MultiShareHost {
 }
 
 /// Handle a single client connection
+#[allow(clippy::too_many_arguments)]
 async fn handle_connection(
     connection: quinn::Connection,
-    share_tables: Arc<HashMap<ShareId, ShareInodeTable>>,
+    share_backends: Arc<HashMap<ShareId, Arc<dyn ShareBackend>>>,
     share_infos: Vec<ShareInfo>,
-    host_name: String,
+    share_usage: Arc<HashMap<ShareId, Arc<ShareUsage>>>,
+    bandwidth: Arc<BandwidthLimiter>,
+    audit_logs: Arc<HashMap<ShareId, Arc<AuditLog>>>,
+    chunk_io: ChunkIo,
     lock_manager: Arc<LockManager>,
     config: MultiHostConfig,
+    peer_names: Arc<PeerNames>,
 ) -> Result<(), ConnectionError> {
-    // Wait for handshake stream
-    let (mut send, mut recv) = connection
-        .accept_bi()
-        .await
-        .map_err(|e| ConnectionError::StreamAccept(e.to_string()))?;
-
-    // Receive Hello
-    let hello = recv_message(&mut recv).await?;
-
-    let client_id = match hello {
-        NetMessage::Hello(h) => {
-            if h.protocol_version != PROTOCOL_VERSION {
-                let error = NetMessage::Error(ErrorMessage {
-                    code: ErrorCode::ProtocolError,
-                    message: format!(
-                        "protocol version mismatch: expected {}, got {}",
-                        PROTOCOL_VERSION, h.protocol_version
-                    ),
-                    related_inode: None,
-                });
-                send_message(&mut send, &error).await?;
-                return Err(ConnectionError::Protocol(
-                    teleport_core::ProtocolError::VersionMismatch {
-                        expected: PROTOCOL_VERSION,
-                        actual: h.protocol_version,
-                    },
-                ));
-            }
-            h.client_id
-        }
-        _ => {
-            return Err(ConnectionError::Receive("expected Hello".into()));
-        }
-    };
+    // Clients with a persistent certificate are identified by its fingerprint
+    let client_fingerprint = peer_fingerprint(&connection);
+
+    let (mut send, _recv, client_id) = accept_hello(&connection).await?;
 
     // Generate session ID
     let mut session_id = [0u8; 16];
     getrandom::getrandom(&mut session_id).expect("RNG failed - system entropy source unavailable");
 
-    // Create a unique holder ID for this client
-    let holder_id = format!(
-        "{:02x}{:02x}{:02x}{:02x}",
-        client_id[0], client_id[1], client_id[2], client_id[3]
-    );
+    let holder_id = holder_id(client_fingerprint.as_ref(), &client_id);
+
+    // Each share has its own access list
+    let remote_ip = connection.remote_address().ip();
+    let share_levels: HashMap<ShareId, AccessLevel> = config
+        .shares
+        .iter()
+        .map(|share| {
+            let level = client_level(
+                share.access_list.as_deref(),
+                &peer_names,
+                client_fingerprint.as_ref(),
+                remote_ip,
+            );
+            (share.id, level)
+        })
+        .collect();
+    if !config.shares.is_empty() && share_levels.values().all(|l| *l == AccessLevel::None) {
+        warn!("Client {} ({}) denied by access list", holder_id, remote_ip);
+        reject_handshake(
+            &mut send,
+            ErrorCode::PermissionDenied,
+            "access denied".into(),
+        )
+        .await?;
+        return Err(ConnectionError::Receive(
+            "client denied by access list".into(),
+        ));
+    }
+
+    // Shares that are closed to this client count as inaccessible
+    let share_levels: HashMap<ShareId, AccessLevel> = share_levels
+        .into_iter()
+        .map(|(id, level)| {
+            let admitted = level == AccessLevel::None || share_usage[&id].admit(&holder_id);
+            (id, if admitted { level } else { AccessLevel::None })
+        })
+        .collect();
+    if !config.shares.is_empty() && share_levels.values().all(|l| *l == AccessLevel::None) {
+        info!(
+            "Client {} ({}) turned away: shares are closed to new clients",
+            holder_id, remote_ip
+        );
+        let goodbye = NetMessage::Goodbye(GoodbyeMessage {
+            reason: DisconnectReason::ShareExpired,
+        });
+        send_final(&mut send, &goodbye).await?;
+        return Ok(());
+    }
+
+    let shares = config
+        .shares
+        .iter()
+        .filter_map(|share| {
+            let backend = share_backends.get(&share.id)?.clone();
+            let level = share_levels[&share.id];
+            // Only shares the client can reach are audited
+            let audit = audit_logs
+                .get(&share.id)
+                .filter(|_| level > AccessLevel::None)
+                .map(|log| {
+                    AuditSession::open(
+                        log.clone(),
+                        &session_id,
+                        holder_id.clone(),
+                        connection.remote_address(),
+                        level,
+                    )
+                });
+            let session = ShareSession {
+                level,
+                writable: share.writable,
+                files: FileHandles::new(backend.clone(), chunk_io.clone()),
+                backend,
+                usage: share_usage[&share.id].clone(),
+                bandwidth: bandwidth.clone(),
+                bandwidth_share: share.name.clone(),
+                audit,
+            };
+            Some((share.id, session))
+        })
+        .collect();
+    let session = Arc::new(ClientSession {
+        holder_id: holder_id.clone(),
+        // For backward compatibility, requests are served from the first share
+        default_share: config.shares.first().map(|share| share.id),
+        shares,
+    });
 
-    // Determine capabilities based on shares
-    let mut capabilities = vec!["read".into(), "multi-share".into()];
-    if config.shares.iter().any(|s| s.writable) {
-        capabilities.push("write".into());
-        capabilities.push("lock".into());
-    }
+    // The session ends once every share it can reach has expired
+    let session_usage: Arc<HashMap<ShareId, Arc<ShareUsage>>> = Arc::new(
+        session
+            .shares
+            .iter()
+            .filter(|(_, share)| share.level > AccessLevel::None)
+            .map(|(id, share)| (*id, share.usage.clone()))
+            .collect(),
+    );
 
-    // For backward compatibility, use first share as root
-    let root_inode = ROOT_INODE;
+    let mut capabilities = capabilities(session.default_share());
+    capabilities.push("multi-share".into());
 
     // Send HelloAck
     let ack = NetMessage::HelloAck(HelloAckMessage {
         protocol_version: PROTOCOL_VERSION,
         session_id,
-        root_inode,
-        host_name: host_name.clone(),
+        root_inode: ROOT_INODE,
+        host_name: config.host_name.clone(),
         capabilities,
     });
     send_message(&mut send, &ack).await?;
 
     info!(
-        "Client {:?} authenticated, session {:?}",
-        &client_id[..4],
+        "Client {} authenticated ({}), session {:?}",
+        holder_id,
+        if client_fingerprint.is_some() {
+            "certificate"
+        } else {
+            "anonymous"
+        },
         &session_id[..4]
     );
 
@@ -446,27 +547,26 @@ async fn handle_connection(
             break;
         }
 
-        let stream = connection.accept_bi().await;
+        let stream = tokio::select! {
+            stream = connection.accept_bi() => stream,
+            _ = all_expired(session_usage.clone()) => {
+                info!("Ending session {}: shares expired", holder_id);
+                send_goodbye(&connection, DisconnectReason::ShareExpired).await;
+                lock_manager.release_all_by_holder(&holder_id);
+                break;
+            }
+        };
 
         match stream {
             Ok((mut send, mut recv)) => {
-                let share_tables = share_tables.clone();
                 let share_infos = share_infos.clone();
                 let lock_manager = lock_manager.clone();
-                let holder_id = holder_id.clone();
-                let config = config.clone();
+                let session = session.clone();
 
                 tokio::spawn(async move {
-                    if let Err(e) = handle_request(
-                        &mut send,
-                        &mut recv,
-                        &share_tables,
-                        &share_infos,
-                        &lock_manager,
-                        &holder_id,
-                        &config,
-                    )
-                    .await
+                    if let Err(e) =
+                        handle_request(&mut send, &mut recv, &share_infos, &lock_manager, &session)
+                            .await
                     {
                         debug!("Request error: {:?}", e);
                     }
@@ -488,653 +588,67 @@ async fn handle_connection(
     Ok(())
 }
 
+/// Per-connection client state shared by its request handlers
+struct ClientSession {
+    /// Lock holder and audit identity
+    holder_id: String,
+    /// Share that file requests are served from
+    default_share: Option<ShareId>,
+    /// The client's access to each share, with its open files
+    shares: HashMap<ShareId, ShareSession>,
+}
+
+impl ClientSession {
+    fn default_share(&self) -> Option<&ShareSession> {
+        self.shares.get(self.default_share.as_ref()?)
+    }
+}
+
+/// Completes once every share in `usage` has expired (never, if there are none)
+async fn all_expired(usage: Arc<HashMap<ShareId, Arc<ShareUsage>>>) {
+    if usage.is_empty() {
+        return std::future::pending().await;
+    }
+    futures_util::future::join_all(usage.values().map(|u| u.expired())).await;
+}
+
 /// Handle a single request
 async fn handle_request(
     send: &mut quinn::SendStream,
     recv: &mut quinn::RecvStream,
-    share_tables: &HashMap<ShareId, ShareInodeTable>,
     share_infos: &[ShareInfo],
     lock_manager: &LockManager,
-    holder_id: &str,
-    config: &MultiHostConfig,
+    session: &ClientSession,
 ) -> Result<(), ConnectionError> {
     let request = recv_message(recv).await?;
 
-    // For now, use the first share as default (backward compatibility)
-    let default_share = config.shares.first();
-
     let response = match request {
         NetMessage::ListShares(req) => {
-            let shares = if let Some(filter) = req.filter_id {
-                share_infos
-                    .iter()
-                    .filter(|s| s.id == filter)
-                    .cloned()
-                    .collect()
-            } else {
-                share_infos.to_vec()
-            };
-            NetMessage::ListSharesResponse(ListSharesResponse { shares })
-        }
-        NetMessage::Lookup(req) => {
-            if let Some(share) = default_share {
-                if let Some(table) = share_tables.get(&share.id) {
-                    handle_lookup(req, table, &share.path)
-                } else {
-                    NetMessage::Error(ErrorMessage {
-                        code: ErrorCode::FileNotFound,
-                        message: "share not found".into(),
-                        related_inode: None,
-                    })
-                }
-            } else {
-                NetMessage::Error(ErrorMessage {
-                    code: ErrorCode::FileNotFound,
-                    message: "no shares available".into(),
-                    related_inode: None,
-                })
-            }
-        }
-        NetMessage::GetAttr(req) => {
-            if let Some(share) = default_share {
-                if let Some(table) = share_tables.get(&share.id) {
-                    handle_getattr(req, table)
-                } else {
-                    NetMessage::Error(ErrorMessage {
-                        code: ErrorCode::FileNotFound,
-                        message: "share not found".into(),
-                        related_inode: None,
-                    })
-                }
-            } else {
-                NetMessage::Error(ErrorMessage {
-                    code: ErrorCode::FileNotFound,
-                    message: "no shares available".into(),
-                    related_inode: None,
-                })
-            }
-        }
-        NetMessage::ListDir(req) => {
-            if let Some(share) = default_share {
-                if let Some(table) = share_tables.get(&share.id) {
-                    handle_listdir(req, table)
-                } else {
-                    NetMessage::Error(ErrorMessage {
-                        code: ErrorCode::FileNotFound,
-                        message: "share not found".into(),
-                        related_inode: None,
-                    })
-                }
-            } else {
-                NetMessage::Error(ErrorMessage {
-                    code: ErrorCode::FileNotFound,
-                    message: "no shares available".into(),
-                    related_inode: None,
-                })
-            }
-        }
-        NetMessage::ReadChunk(req) => {
-            if let Some(share) = default_share {
-                if let Some(table) = share_tables.get(&share.id) {
-                    handle_read_chunk(req, table)
-                } else {
-                    NetMessage::Error(ErrorMessage {
-                        code: ErrorCode::FileNotFound,
-                        message: "share not found".into(),
-                        related_inode: None,
-                    })
-                }
-            } else {
-                NetMessage::Error(ErrorMessage {
-                    code: ErrorCode::FileNotFound,
-                    message: "no shares available".into(),
-                    related_inode: None,
-                })
-            }
-        }
-        NetMessage::WriteChunk(req) => {
-            if let Some(share) = default_share {
-                if !share.writable {
-                    NetMessage::Error(ErrorMessage {
-                        code: ErrorCode::PermissionDenied,
-                        message: "share is read-only".into(),
-                        related_inode: Some(req.chunk_id.inode),
-                    })
-                } else if let Some(table) = share_tables.get(&share.id) {
-                    handle_write_chunk(req, table, lock_manager)
-                } else {
-                    NetMessage::Error(ErrorMessage {
-                        code: ErrorCode::FileNotFound,
-                        message: "share not found".into(),
-                        related_inode: None,
-                    })
-                }
-            } else {
-                NetMessage::Error(ErrorMessage {
-                    code: ErrorCode::FileNotFound,
-                    message: "no shares available".into(),
-                    related_inode: None,
+            // Shares the client has no access to are not listed
+            let shares = share_infos
+                .iter()
+                .filter(|s| req.filter_id.is_none() || req.filter_id == Some(s.id))
+                .filter(|s| {
+                    session
+                        .shares
+                        .get(&s.id)
+                        .is_some_and(|share| share.level() > AccessLevel::None)
                 })
-            }
-        }
-        NetMessage::AcquireLock(req) => handle_acquire_lock(req, lock_manager, holder_id),
-        NetMessage::ReleaseLock(req) => handle_release_lock(req, lock_manager),
-        NetMessage::Ping(p) => NetMessage::Pong(teleport_core::PongMessage {
-            client_timestamp: p.timestamp,
-            server_timestamp: std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_millis() as u64,
-            payload: p.payload,
-        }),
-        _ => NetMessage::Error(ErrorMessage {
-            code: ErrorCode::NotImplemented,
-            message: "request type not implemented".into(),
-            related_inode: None,
-        }),
-    };
-
-    send_message(send, &response).await
-}
-
-fn handle_lookup(req: LookupRequest, table: &ShareInodeTable, shared_path: &Path) -> NetMessage {
-    let parent_path = match table.get_path(req.parent) {
-        Some(p) => p,
-        None => {
-            return NetMessage::LookupResponse(LookupResponse { attr: None });
-        }
-    };
-
-    // Validate name
-    if let Err(e) = teleport_core::path::validate_filename(&req.name) {
-        return NetMessage::Error(ErrorMessage {
-            code: ErrorCode::PathTraversal,
-            message: e.to_string(),
-            related_inode: Some(req.parent),
-        });
-    }
-
-    let child_path = parent_path.join(&req.name);
-
-    // Quick check: ensure path is within shared directory (lexical check)
-    if !child_path.starts_with(shared_path) {
-        return NetMessage::Error(ErrorMessage {
-            code: ErrorCode::PathTraversal,
-            message: "path escapes shared directory".into(),
-            related_inode: Some(req.parent),
-        });
-    }
-
-    match fs::metadata(&child_path) {
-        Ok(meta) => {
-            // SECURITY: After confirming the file exists, verify symlinks don't escape
-            // the shared directory. This follows all symlinks and checks the real path.
-            if let Err(e) = safe_real_path(shared_path, &child_path) {
-                warn!(
-                    "Path traversal attempt via symlink: {}: {}",
-                    child_path.display(),
-                    e
-                );
-                return NetMessage::Error(ErrorMessage {
-                    code: ErrorCode::PathTraversal,
-                    message: "symlink escapes shared directory".into(),
-                    related_inode: Some(req.parent),
-                });
-            }
-
-            match table.get_or_create_inode(child_path) {
-                Some(inode) => {
-                    let attr = metadata_to_attr(inode, &meta);
-                    NetMessage::LookupResponse(LookupResponse { attr: Some(attr) })
-                }
-                None => NetMessage::Error(ErrorMessage {
-                    code: ErrorCode::IoError,
-                    message: "inode space exhausted".into(),
-                    related_inode: Some(req.parent),
-                }),
-            }
-        }
-        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
-            NetMessage::LookupResponse(LookupResponse { attr: None })
-        }
-        Err(e) => NetMessage::Error(ErrorMessage {
-            code: ErrorCode::IoError,
-            message: e.to_string(),
-            related_inode: Some(req.parent),
-        }),
-    }
-}
-
-fn handle_getattr(req: GetAttrRequest, table: &ShareInodeTable) -> NetMessage {
-    let path = match table.get_path(req.inode) {
-        Some(p) => p,
-        None => {
-            return NetMessage::GetAttrResponse(GetAttrResponse { attr: None });
-        }
-    };
-
-    match fs::metadata(&path) {
-        Ok(meta) => {
-            let attr = metadata_to_attr(req.inode, &meta);
-            NetMessage::GetAttrResponse(GetAttrResponse { attr: Some(attr) })
-        }
-        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
-            NetMessage::GetAttrResponse(GetAttrResponse { attr: None })
+                .cloned()
+                .collect();
+            NetMessage::ListSharesResponse(ListSharesResponse { shares })
         }
-        Err(e) => NetMessage::Error(ErrorMessage {
-            code: ErrorCode::IoError,
-            message: e.to_string(),
-            related_inode: Some(req.inode),
-        }),
-    }
-}
-
-fn handle_listdir(req: ListDirRequest, table: &ShareInodeTable) -> NetMessage {
-    let path = match table.get_path(req.inode) {
-        Some(p) => p,
-        None => {
-            return NetMessage::Error(ErrorMessage {
+        request => match session.default_share() {
+            Some(share) => serve_request(request, share, &session.holder_id, lock_manager).await,
+            None => NetMessage::Error(ErrorMessage {
                 code: ErrorCode::FileNotFound,
-                message: "inode not found".into(),
-                related_inode: Some(req.inode),
-            });
-        }
+                message: "no shares available".into(),
+                related_inode: None,
+            }),
+        },
     };
 
-    match fs::read_dir(&path) {
-        Ok(entries) => {
-            let mut dir_entries = Vec::new();
-            // Fetch one extra entry to determine has_more accurately
-            let fetch_limit = req.limit.saturating_add(1) as usize;
-
-            for (i, entry) in entries.enumerate() {
-                if i < req.offset as usize {
-                    continue;
-                }
-                if dir_entries.len() >= fetch_limit {
-                    break;
-                }
-
-                if let Ok(entry) = entry {
-                    let name = entry.file_name().to_string_lossy().into_owned();
-                    let entry_path = entry.path();
-
-                    if let Ok(meta) = entry.metadata() {
-                        let file_type = if meta.is_dir() {
-                            FileType::Directory
-                        } else if meta.is_symlink() {
-                            FileType::Symlink
-                        } else {
-                            FileType::File
-                        };
-
-                        if let Some(inode) = table.get_or_create_inode(entry_path.clone()) {
-                            dir_entries.push(DirEntry::new(name, inode, file_type));
-                        } else {
-                            // Inode space exhausted - log and skip this entry
-                            // This is a critical condition that should be investigated
-                            error!(
-                                "Inode space exhausted while listing directory, skipping entry: {:?}",
-                                entry_path
-                            );
-                        }
-                    }
-                }
-            }
-
-            // If we got more than limit, there are more entries
-            let has_more = dir_entries.len() > req.limit as usize;
-            // Truncate to requested limit
-            dir_entries.truncate(req.limit as usize);
-            let next_offset = req.offset + dir_entries.len() as u64;
-
-            NetMessage::ListDirResponse(ListDirResponse {
-                entries: dir_entries,
-                has_more,
-                next_offset,
-            })
-        }
-        Err(e) if e.kind() == std::io::ErrorKind::NotFound => NetMessage::Error(ErrorMessage {
-            code: ErrorCode::FileNotFound,
-            message: "directory not found".into(),
-            related_inode: Some(req.inode),
-        }),
-        Err(e) if e.kind() == std::io::ErrorKind::NotADirectory => {
-            NetMessage::Error(ErrorMessage {
-                code: ErrorCode::NotADirectory,
-                message: "not a directory".into(),
-                related_inode: Some(req.inode),
-            })
-        }
-        Err(e) => NetMessage::Error(ErrorMessage {
-            code: ErrorCode::IoError,
-            message: e.to_string(),
-            related_inode: Some(req.inode),
-        }),
-    }
-}
-
-fn handle_read_chunk(req: ReadChunkRequest, table: &ShareInodeTable) -> NetMessage {
-    let path = match table.get_path(req.chunk_id.inode) {
-        Some(p) => p,
-        None => {
-            return NetMessage::Error(ErrorMessage {
-                code: ErrorCode::FileNotFound,
-                message: "inode not found".into(),
-                related_inode: Some(req.chunk_id.inode),
-            });
-        }
-    };
-
-    // SECURITY: Verify symlinks don't escape shared directory before reading content.
-    // This check must happen before fs::File::open which follows symlinks.
-    if let Err(e) = safe_real_path(&table.root_path, &path) {
-        warn!(
-            "Path traversal attempt in read_chunk via symlink: {}: {}",
-            path.display(),
-            e
-        );
-        return NetMessage::Error(ErrorMessage {
-            code: ErrorCode::PathTraversal,
-            message: "symlink escapes shared directory".into(),
-            related_inode: Some(req.chunk_id.inode),
-        });
-    }
-
-    let file = match fs::File::open(&path) {
-        Ok(f) => f,
-        Err(e) => {
-            return NetMessage::Error(ErrorMessage {
-                code: ErrorCode::IoError,
-                message: e.to_string(),
-                related_inode: Some(req.chunk_id.inode),
-            });
-        }
-    };
-
-    let mut file = file;
-
-    let offset = req.chunk_id.byte_offset();
-    if file.seek(SeekFrom::Start(offset)).is_err() {
-        return NetMessage::Error(ErrorMessage {
-            code: ErrorCode::IoError,
-            message: "seek failed".into(),
-            related_inode: Some(req.chunk_id.inode),
-        });
-    }
-
-    let mut buffer = vec![0u8; CHUNK_SIZE];
-    let bytes_read = match file.read(&mut buffer) {
-        Ok(n) => n,
-        Err(e) => {
-            return NetMessage::Error(ErrorMessage {
-                code: ErrorCode::IoError,
-                message: e.to_string(),
-                related_inode: Some(req.chunk_id.inode),
-            });
-        }
-    };
-
-    buffer.truncate(bytes_read);
-    let hash = checksum(&buffer);
-
-    // Check if this is the final chunk
-    let file_size = file.metadata().map(|m| m.len()).unwrap_or(0);
-    let is_final = offset + bytes_read as u64 >= file_size;
-
-    NetMessage::ReadChunkResponse(ReadChunkResponse {
-        chunk_id: req.chunk_id,
-        data: buffer,
-        checksum: hash,
-        is_final,
-    })
-}
-
-fn handle_write_chunk(
-    req: WriteChunkRequest,
-    table: &ShareInodeTable,
-    lock_manager: &LockManager,
-) -> NetMessage {
-    let inode = req.chunk_id.inode;
-
-    // Verify the lock token is valid for exclusive write
-    if !lock_manager.validate(inode, &req.lock_token, LockType::Exclusive) {
-        return NetMessage::Error(ErrorMessage {
-            code: ErrorCode::LockRequired,
-            message: "Invalid or expired lock token".into(),
-            related_inode: Some(inode),
-        });
-    }
-
-    // Verify checksum
-    let computed_checksum = checksum(&req.data);
-    if computed_checksum != req.checksum {
-        return NetMessage::Error(ErrorMessage {
-            code: ErrorCode::ChecksumMismatch,
-            message: "Data checksum mismatch".into(),
-            related_inode: Some(inode),
-        });
-    }
-
-    let path = match table.get_path(inode) {
-        Some(p) => p,
-        None => {
-            return NetMessage::Error(ErrorMessage {
-                code: ErrorCode::FileNotFound,
-                message: "inode not found".into(),
-                related_inode: Some(inode),
-            });
-        }
-    };
-
-    // SECURITY: Verify symlinks don't escape shared directory before writing.
-    // This check is CRITICAL for write operations - without it, an attacker could
-    // overwrite arbitrary files on the host system via symlinks.
-    if let Err(e) = safe_real_path(&table.root_path, &path) {
-        warn!(
-            "Path traversal attempt in write_chunk via symlink: {}: {}",
-            path.display(),
-            e
-        );
-        return NetMessage::Error(ErrorMessage {
-            code: ErrorCode::PathTraversal,
-            message: "symlink escapes shared directory".into(),
-            related_inode: Some(inode),
-        });
-    }
-
-    // Open file for writing
-    let mut file = match OpenOptions::new().write(true).open(&path) {
-        Ok(f) => f,
-        Err(e) => {
-            return NetMessage::Error(ErrorMessage {
-                code: ErrorCode::IoError,
-                message: format!("Failed to open file for writing: {}", e),
-                related_inode: Some(inode),
-            });
-        }
-    };
-
-    // Seek to the chunk offset
-    let offset = req.chunk_id.byte_offset();
-    if let Err(e) = file.seek(SeekFrom::Start(offset)) {
-        return NetMessage::Error(ErrorMessage {
-            code: ErrorCode::IoError,
-            message: format!("Seek failed: {}", e),
-            related_inode: Some(inode),
-        });
-    }
-
-    // Write the data
-    if let Err(e) = file.write_all(&req.data) {
-        return NetMessage::Error(ErrorMessage {
-            code: ErrorCode::IoError,
-            message: format!("Write failed: {}", e),
-            related_inode: Some(inode),
-        });
-    }
-
-    // Sync to disk
-    if let Err(e) = file.sync_data() {
-        warn!("Failed to sync file data: {}", e);
-    }
-
-    // Get new file size
-    let new_size = file.metadata().map(|m| m.len()).ok();
-
-    info!(
-        "Write chunk: inode={}, offset={}, size={}",
-        inode,
-        offset,
-        req.data.len()
-    );
-
-    NetMessage::WriteChunkResponse(WriteChunkResponse {
-        chunk_id: req.chunk_id,
-        success: true,
-        new_size,
-    })
-}
-
-fn handle_acquire_lock(
-    req: LockRequest,
-    lock_manager: &LockManager,
-    holder_id: &str,
-) -> NetMessage {
-    let timeout = if req.timeout_ms > 0 {
-        Some(Duration::from_millis(req.timeout_ms as u64))
-    } else {
-        None
-    };
-
-    match lock_manager.acquire(req.inode, req.lock_type, holder_id, timeout) {
-        Ok(token) => {
-            info!(
-                "Lock acquired: inode={}, type={:?}, holder={}",
-                req.inode, req.lock_type, holder_id
-            );
-            NetMessage::AcquireLockResponse(LockResponse {
-                granted: true,
-                token: Some(token),
-                holder: None,
-                retry_after_ms: None,
-            })
-        }
-        Err(crate::lock_manager::LockError::Conflict {
-            holder,
-            retry_after,
-            ..
-        }) => {
-            debug!("Lock conflict: inode={}, holder={:?}", req.inode, holder);
-            NetMessage::AcquireLockResponse(LockResponse {
-                granted: false,
-                token: None,
-                holder,
-                retry_after_ms: retry_after.map(|d| d.as_millis() as u32),
-            })
-        }
-        Err(crate::lock_manager::LockError::TokenNotFound) => NetMessage::Error(ErrorMessage {
-            code: ErrorCode::LockRequired,
-            message: "Lock token not found".into(),
-            related_inode: Some(req.inode),
-        }),
-    }
-}
-
-fn handle_release_lock(req: ReleaseRequest, lock_manager: &LockManager) -> NetMessage {
-    match lock_manager.release(&req.token) {
-        Ok(()) => {
-            info!("Lock released");
-            NetMessage::ReleaseLockResponse(ReleaseResponse { success: true })
-        }
-        Err(crate::lock_manager::LockError::TokenNotFound) => {
-            warn!("Attempted to release non-existent lock");
-            NetMessage::ReleaseLockResponse(ReleaseResponse { success: false })
-        }
-        Err(_) => NetMessage::ReleaseLockResponse(ReleaseResponse { success: false }),
-    }
-}
-
-/// Convert std::fs::Metadata to FileAttr (Unix)
-#[cfg(unix)]
-fn metadata_to_attr(inode: Inode, meta: &fs::Metadata) -> FileAttr {
-    use std::os::unix::fs::MetadataExt;
-
-    let file_type = if meta.is_dir() {
-        FileType::Directory
-    } else if meta.is_symlink() {
-        FileType::Symlink
-    } else {
-        FileType::File
-    };
-
-    // Safely convert timestamps: negative times (pre-1970) are clamped to 0
-    // and nanoseconds are clamped to valid range [0, 999_999_999]
-    FileAttr {
-        inode,
-        file_type,
-        size: meta.len(),
-        mode: meta.mode(),
-        nlink: meta.nlink() as u32,
-        uid: meta.uid(),
-        gid: meta.gid(),
-        atime: meta.atime().max(0) as u64,
-        atime_nsec: meta.atime_nsec().clamp(0, 999_999_999) as u32,
-        mtime: meta.mtime().max(0) as u64,
-        mtime_nsec: meta.mtime_nsec().clamp(0, 999_999_999) as u32,
-        ctime: meta.ctime().max(0) as u64,
-        ctime_nsec: meta.ctime_nsec().clamp(0, 999_999_999) as u32,
-    }
-}
-
-/// Convert std::fs::Metadata to FileAttr (Windows)
-#[cfg(windows)]
-fn metadata_to_attr(inode: Inode, meta: &fs::Metadata) -> FileAttr {
-    use std::os::windows::fs::MetadataExt;
-
-    let file_type = if meta.is_dir() {
-        FileType::Directory
-    } else if meta.is_symlink() {
-        FileType::Symlink
-    } else {
-        FileType::File
-    };
-
-    // Windows timestamps are in FILETIME (100-ns intervals since 1601)
-    // Convert to Unix timestamps
-    fn filetime_to_unix(filetime: u64) -> (u64, u32) {
-        const EPOCH_DIFF: u64 = 116444736000000000; // 100-ns intervals from 1601 to 1970
-        if filetime < EPOCH_DIFF {
-            return (0, 0);
-        }
-        let unix_100ns = filetime - EPOCH_DIFF;
-        let secs = unix_100ns / 10_000_000;
-        let nsecs = ((unix_100ns % 10_000_000) * 100) as u32;
-        (secs, nsecs)
-    }
-
-    let (atime, atime_nsec) = filetime_to_unix(meta.last_access_time());
-    let (mtime, mtime_nsec) = filetime_to_unix(meta.last_write_time());
-    let (ctime, ctime_nsec) = filetime_to_unix(meta.creation_time());
-
-    // Windows doesn't have Unix permissions, use sensible defaults
-    let mode = if meta.is_dir() { 0o755 } else { 0o644 };
-
-    FileAttr {
-        inode,
-        file_type,
-        size: meta.len(),
-        mode,
-        nlink: 1, // Windows doesn't expose hard link count easily
-        uid: 0,   // No Unix UID/GID on Windows
-        gid: 0,
-        atime,
-        atime_nsec,
-        mtime,
-        mtime_nsec,
-        ctime,
-        ctime_nsec,
-    }
+    send_message(send, &response).await
 }
 
 /// Errors from multi-share host
@@ -1158,6 +672,37 @@ impl std::error::Error for MultiHostError {}
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::audit::read_log;
+    use crate::client::{ClientConfig, WormholeClient};
+    use crate::memory_backend::MemoryBackend;
+
+    fn free_addr() -> SocketAddr {
+        let port = std::net::UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        format!("127.0.0.1:{}", port).parse().unwrap()
+    }
+
+    /// Connect to a host that may still be starting
+    async fn connect(addr: SocketAddr, state: &std::path::Path) -> WormholeClient {
+        let started = Instant::now();
+        loop {
+            let mut client = WormholeClient::new(ClientConfig {
+                server_addr: addr,
+                known_peers_path: Some(state.join("known_peers.toml")),
+                ..ClientConfig::default()
+            });
+            match client.connect().await {
+                Ok(()) => return client,
+                Err(e) if started.elapsed() > Duration::from_secs(5) => {
+                    panic!("client failed to connect: {:?}", e)
+                }
+                Err(_) => tokio::time::sleep(Duration::from_millis(20)).await,
+            }
+        }
+    }
 
     #[test]
     fn test_shared_folder_new() {
@@ -1198,4 +743,76 @@ mod tests {
         assert_eq!(config.shares[0].name, "Share 1");
         assert_eq!(config.shares[1].name, "Share 2");
     }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_read_only_client_is_not_offered_write() {
+        let addr = free_addr();
+        let mut access = AccessList::default();
+        access.set_default_level(AccessLevel::Read);
+        let share = SharedFolder::new("/unused", "docs").with_access_list(Arc::new(access));
+        let share_id = share.id;
+        let config = MultiHostConfig {
+            bind_addr: addr,
+            ..MultiHostConfig::default()
+        }
+        .add_share(share)
+        .add_share(SharedFolder::new("/unused", "open"));
+        let host = MultiShareHost::new(config)
+            .with_backend(share_id, Arc::new(MemoryBackend::new().with_file("a", "a")));
+        let serving = tokio::spawn(async move { host.serve().await });
+
+        let state = tempfile::tempdir().unwrap();
+        let client = connect(addr, state.path()).await;
+        // The other share is writable, but requests go to the read-only one
+        assert!(!client.is_writable());
+        serving.abort();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_sessions_are_audited_on_reachable_shares() {
+        let addr = free_addr();
+        let mut closed = AccessList::default();
+        closed.set_default_level(AccessLevel::None);
+        let docs = SharedFolder::new("/unused", "docs");
+        let hidden = SharedFolder::new("/unused", "hidden").with_access_list(Arc::new(closed));
+        let (docs_id, hidden_id) = (docs.id, hidden.id);
+
+        let logs = tempfile::tempdir().unwrap();
+        let docs_log =
+            Arc::new(AuditLog::open_at(&logs.path().join("docs.jsonl"), "docs").unwrap());
+        let hidden_log =
+            Arc::new(AuditLog::open_at(&logs.path().join("hidden.jsonl"), "hidden").unwrap());
+        let config = MultiHostConfig {
+            bind_addr: addr,
+            ..MultiHostConfig::default()
+        }
+        .add_share(docs)
+        .add_share(hidden);
+        let host = MultiShareHost::new(config)
+            .with_backend(docs_id, Arc::new(MemoryBackend::new()))
+            .with_backend(hidden_id, Arc::new(MemoryBackend::new()))
+            .with_audit_log(docs_id, docs_log.clone())
+            .with_audit_log(hidden_id, hidden_log.clone());
+        let serving = tokio::spawn(async move { host.serve().await });
+
+        let state = tempfile::tempdir().unwrap();
+        connect(addr, state.path()).await.disconnect();
+
+        // The session is logged as closed once the host notices the disconnect
+        let started = Instant::now();
+        let ops = loop {
+            let ops: Vec<_> = read_log(docs_log.path())
+                .unwrap()
+                .into_iter()
+                .map(|r| r.event.name())
+                .collect();
+            if ops.len() == 2 || started.elapsed() > Duration::from_secs(5) {
+                break ops;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        };
+        assert_eq!(ops, ["session_open", "session_close"]);
+        assert!(read_log(hidden_log.path()).unwrap().is_empty());
+        serving.abort();
+    }
 }
@@ -21,6 +21,8 @@ use quinn::{
 use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
 use tracing::{debug, info, warn};
 
+use crate::identity::Identity;
+
 /// NAT-friendly keepalive interval (25 seconds is typically safe for most NATs)
 pub const NAT_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(25);
 
@@ -31,9 +33,16 @@ pub const IDLE_TIMEOUT: Duration = Duration::from_secs(120);
 pub const MAX_UDP_PAYLOAD_SIZE: u16 = 1350;
 
 use teleport_core::{
-    deserialize_message, serialize_message, NetMessage, ProtocolError, MAX_MESSAGE_SIZE,
+    crypto::PASSWORD_CHANNEL_LABEL, deserialize_message, serialize_message, DisconnectReason,
+    GoodbyeMessage, NetMessage, ProtocolError, MAX_MESSAGE_SIZE,
 };
 
+/// Application close code for connections ended with a `Goodbye`
+pub const GOODBYE_CLOSE_CODE: u32 = 0x03;
+
+/// How long to wait for the peer to read a `Goodbye` before closing anyway
+const GOODBYE_TIMEOUT: Duration = Duration::from_secs(2);
+
 /// QUIC connection wrapper
 #[derive(Clone)]
 pub struct QuicConnection {
@@ -65,17 +74,64 @@ impl QuicConnection {
         Ok((send, recv))
     }
 
+    /// Accept an incoming unidirectional stream
+    pub async fn accept_uni_stream(&self) -> Result<RecvStream, ConnectionError> {
+        self.connection
+            .accept_uni()
+            .await
+            .map_err(|e| ConnectionError::StreamAccept(e.to_string()))
+    }
+
     /// Get remote address
     pub fn remote_address(&self) -> SocketAddr {
         self.connection.remote_address()
     }
 
+    /// Fingerprint of the certificate the peer presented, if any
+    pub fn peer_fingerprint(&self) -> Option<CertFingerprint> {
+        peer_fingerprint(&self.connection)
+    }
+
+    /// Keying material that binds a password proof to this TLS session
+    pub fn channel_binding(&self) -> Option<[u8; 32]> {
+        channel_binding(&self.connection)
+    }
+
+    /// Identifier that distinguishes this connection from others to the same peer
+    pub fn stable_id(&self) -> usize {
+        self.connection.stable_id()
+    }
+
     /// Close the connection
     pub fn close(&self, code: u32, reason: &str) {
         self.connection.close(code.into(), reason.as_bytes());
     }
 }
 
+/// Fingerprint of the certificate presented by the other side of a connection
+///
+/// On the host this is the client's identity; it is `None` for clients that
+/// did not present a certificate.
+pub fn peer_fingerprint(connection: &Connection) -> Option<CertFingerprint> {
+    let certs = connection
+        .peer_identity()?
+        .downcast::<Vec<CertificateDer<'static>>>()
+        .ok()?;
+    certs.first().map(compute_cert_fingerprint)
+}
+
+/// Keying material exported from a connection's TLS session for password proofs
+///
+/// Both ends of one session derive the same value; a relay terminating two
+/// sessions sees two different ones.
+pub fn channel_binding(connection: &Connection) -> Option<[u8; 32]> {
+    let mut out = [0u8; 32];
+    connection
+        .export_keying_material(&mut out, PASSWORD_CHANNEL_LABEL, &[])
+        .ok()?;
+    Some(out)
+}
+
 /// Connection errors
 #[derive(Debug, Clone)]
 pub enum ConnectionError {
@@ -109,6 +165,24 @@ pub async fn send_message(
     Ok(())
 }
 
+/// End a session: send a `Goodbye` on its own stream, then close the connection
+///
+/// Waits briefly for the peer to read the message so the close doesn't
+/// discard it.
+pub async fn send_goodbye(connection: &Connection, reason: DisconnectReason) {
+    if let Ok(mut send) = connection.open_uni().await {
+        let goodbye = NetMessage::Goodbye(GoodbyeMessage { reason });
+        if send_message(&mut send, &goodbye).await.is_ok() {
+            let _ = send.finish();
+            let _ = tokio::time::timeout(GOODBYE_TIMEOUT, send.stopped()).await;
+        }
+    }
+    connection.close(
+        GOODBYE_CLOSE_CODE.into(),
+        format!("{:?}", reason).as_bytes(),
+    );
+}
+
 /// Receive a message from a stream
 pub async fn recv_message(stream: &mut RecvStream) -> Result<NetMessage, ConnectionError> {
     // Read length prefix
@@ -268,15 +342,58 @@ pub fn create_client_endpoint_with_pinned_cert(
         "Creating client endpoint with pinned cert: {}",
         hex::encode(expected_fingerprint)
     );
+    create_client_endpoint_with_verifier(
+        port,
+        Arc::new(PinnedCertVerifier::new(expected_fingerprint)),
+        None,
+    )
+}
+
+/// Create a QUIC client endpoint for trust-on-first-use connections
+///
+/// Any certificate is accepted during the TLS handshake, but the handshake
+/// signature is still checked, so the server must hold the certificate's key.
+/// The caller MUST compare `QuicConnection::peer_fingerprint` against the
+/// known-peers store before trusting the connection.
+pub fn create_client_endpoint_tofu(port: u16) -> Result<Endpoint, ConnectionError> {
+    create_client_endpoint_with_verifier(port, Arc::new(TofuCertVerifier), None)
+}
+
+/// Create a QUIC client endpoint that presents a client identity (mutual TLS)
+///
+/// The host records the identity's fingerprint as the client's lock holder and
+/// access-control identity. The server certificate is pinned when
+/// `expected_fingerprint` is set and trusted on first use otherwise.
+pub fn create_client_endpoint_with_identity(
+    port: u16,
+    expected_fingerprint: Option<CertFingerprint>,
+    identity: &Identity,
+) -> Result<Endpoint, ConnectionError> {
+    let verifier: Arc<dyn rustls::client::danger::ServerCertVerifier> = match expected_fingerprint {
+        Some(fingerprint) => Arc::new(PinnedCertVerifier::new(fingerprint)),
+        None => Arc::new(TofuCertVerifier),
+    };
+    create_client_endpoint_with_verifier(port, verifier, Some(identity))
+}
+
+fn create_client_endpoint_with_verifier(
+    port: u16,
+    verifier: Arc<dyn rustls::client::danger::ServerCertVerifier>,
+    identity: Option<&Identity>,
+) -> Result<Endpoint, ConnectionError> {
     let bind_addr: SocketAddr = format!("0.0.0.0:{}", port).parse().unwrap();
     let mut endpoint =
         Endpoint::client(bind_addr).map_err(|e| ConnectionError::Connect(e.to_string()))?;
 
-    // Configure with certificate pinning
-    let crypto = rustls::ClientConfig::builder()
+    let builder = rustls::ClientConfig::builder()
         .dangerous()
-        .with_custom_certificate_verifier(Arc::new(PinnedCertVerifier::new(expected_fingerprint)))
-        .with_no_client_auth();
+        .with_custom_certificate_verifier(verifier);
+    let crypto = match identity {
+        Some(identity) => builder
+            .with_client_auth_cert(identity.certs(), identity.key())
+            .map_err(|e| ConnectionError::Connect(e.to_string()))?,
+        None => builder.with_no_client_auth(),
+    };
 
     let mut config = ClientConfig::new(Arc::new(
         quinn::crypto::rustls::QuicClientConfig::try_from(crypto).unwrap(),
@@ -297,9 +414,35 @@ pub fn create_server_endpoint(
     bind_addr: SocketAddr,
 ) -> Result<(Endpoint, CertFingerprint), ConnectionError> {
     let (certs, key, fingerprint) = generate_self_signed_cert_with_fingerprint();
+    create_server_endpoint_with_cert(bind_addr, certs, key, fingerprint)
+}
+
+/// Create a QUIC server endpoint using a persistent identity
+///
+/// Unlike [`create_server_endpoint`], the fingerprint stays the same across
+/// restarts, so clients can pin it in a share link or their known peers.
+pub fn create_server_endpoint_with_identity(
+    bind_addr: SocketAddr,
+    identity: &Identity,
+) -> Result<(Endpoint, CertFingerprint), ConnectionError> {
+    create_server_endpoint_with_cert(
+        bind_addr,
+        identity.certs(),
+        identity.key(),
+        identity.fingerprint(),
+    )
+}
 
+fn create_server_endpoint_with_cert(
+    bind_addr: SocketAddr,
+    certs: Vec<CertificateDer<'static>>,
+    key: PrivateKeyDer<'static>,
+    fingerprint: CertFingerprint,
+) -> Result<(Endpoint, CertFingerprint), ConnectionError> {
+    // Clients may present their own certificate; anonymous clients are still
+    // accepted and identified by their Hello instead
     let crypto = rustls::ServerConfig::builder()
-        .with_no_client_auth()
+        .with_client_cert_verifier(Arc::new(ClientIdentityVerifier))
         .with_single_cert(certs, key)
         .map_err(|e| ConnectionError::Connect(e.to_string()))?;
 
@@ -384,42 +527,149 @@ impl rustls::client::danger::ServerCertVerifier for PinnedCertVerifier {
 
     fn verify_tls12_signature(
         &self,
-        _message: &[u8],
-        _cert: &CertificateDer<'_>,
-        _dss: &rustls::DigitallySignedStruct,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
     ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
-        // Since we've verified the cert fingerprint, we trust the signature
-        // This is secure because we're using certificate pinning - only the holder
-        // of the private key can create valid signatures for the pinned cert
-        Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
+        // The fingerprint only identifies the cert; the signature proves the
+        // server actually holds its private key
+        verify_handshake_tls12(message, cert, dss)
     }
 
     fn verify_tls13_signature(
         &self,
-        _message: &[u8],
-        _cert: &CertificateDer<'_>,
-        _dss: &rustls::DigitallySignedStruct,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
     ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
-        // Since we've verified the cert fingerprint, we trust the signature
-        Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
+        verify_handshake_tls13(message, cert, dss)
     }
 
     fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
-        vec![
-            rustls::SignatureScheme::RSA_PKCS1_SHA256,
-            rustls::SignatureScheme::ECDSA_NISTP256_SHA256,
-            rustls::SignatureScheme::RSA_PKCS1_SHA384,
-            rustls::SignatureScheme::ECDSA_NISTP384_SHA384,
-            rustls::SignatureScheme::RSA_PKCS1_SHA512,
-            rustls::SignatureScheme::ECDSA_NISTP521_SHA512,
-            rustls::SignatureScheme::RSA_PSS_SHA256,
-            rustls::SignatureScheme::RSA_PSS_SHA384,
-            rustls::SignatureScheme::RSA_PSS_SHA512,
-            rustls::SignatureScheme::ED25519,
-        ]
+        signature_algorithms().supported_schemes()
+    }
+}
+
+/// Trust-on-first-use verifier - accepts any certificate the server can prove it owns
+///
+/// The fingerprint decision is deferred to the caller, which checks it against
+/// the known-peers store once the connection is up.
+#[derive(Debug)]
+struct TofuCertVerifier;
+
+impl rustls::client::danger::ServerCertVerifier for TofuCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &rustls::pki_types::ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: rustls::pki_types::UnixTime,
+    ) -> Result<rustls::client::danger::ServerCertVerified, rustls::Error> {
+        debug!(
+            "Accepting certificate for TOFU check: {}",
+            hex::encode(compute_cert_fingerprint(end_entity))
+        );
+        Ok(rustls::client::danger::ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        verify_handshake_tls12(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        verify_handshake_tls13(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+        signature_algorithms().supported_schemes()
     }
 }
 
+/// Client certificate verifier for per-client identities
+///
+/// Client certificates are self-signed, so there is no chain to validate; the
+/// host only needs proof that the client holds the key for the certificate it
+/// presents. Authorization is decided later from the certificate fingerprint.
+#[derive(Debug)]
+struct ClientIdentityVerifier;
+
+impl rustls::server::danger::ClientCertVerifier for ClientIdentityVerifier {
+    fn client_auth_mandatory(&self) -> bool {
+        false
+    }
+
+    fn root_hint_subjects(&self) -> &[rustls::DistinguishedName] {
+        &[]
+    }
+
+    fn verify_client_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _now: rustls::pki_types::UnixTime,
+    ) -> Result<rustls::server::danger::ClientCertVerified, rustls::Error> {
+        debug!(
+            "Client presented certificate: {}",
+            hex::encode(compute_cert_fingerprint(end_entity))
+        );
+        Ok(rustls::server::danger::ClientCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        verify_handshake_tls12(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        verify_handshake_tls13(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+        signature_algorithms().supported_schemes()
+    }
+}
+
+/// Signature algorithms supported by the ring crypto provider
+fn signature_algorithms() -> rustls::crypto::WebPkiSupportedAlgorithms {
+    rustls::crypto::ring::default_provider().signature_verification_algorithms
+}
+
+fn verify_handshake_tls12(
+    message: &[u8],
+    cert: &CertificateDer<'_>,
+    dss: &rustls::DigitallySignedStruct,
+) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+    rustls::crypto::verify_tls12_signature(message, cert, dss, &signature_algorithms())
+}
+
+fn verify_handshake_tls13(
+    message: &[u8],
+    cert: &CertificateDer<'_>,
+    dss: &rustls::DigitallySignedStruct,
+) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+    rustls::crypto::verify_tls13_signature(message, cert, dss, &signature_algorithms())
+}
+
 /// Skip server certificate verification (DEVELOPMENT ONLY - INSECURE)
 ///
 /// WARNING: This verifier accepts ANY certificate without validation.
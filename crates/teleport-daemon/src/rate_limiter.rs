@@ -10,6 +10,9 @@
 //! - Blocks IPs that exceed the failure threshold
 //! - Automatically expires blocks after a cooldown period
 //! - Uses exponential backoff for repeated offenders
+//!
+//! The thresholds come from the `[host]` section of the config and can be
+//! changed while the host runs with `RateLimiter::set_config`.
 
 use std::collections::HashMap;
 use std::net::IpAddr;
@@ -19,6 +22,8 @@ use std::time::{Duration, Instant};
 use parking_lot::RwLock;
 use tracing::{debug, warn};
 
+use teleport_core::config::HostConfig;
+
 /// Maximum failed attempts before blocking an IP
 const DEFAULT_MAX_FAILURES: u32 = 5;
 
@@ -52,6 +57,17 @@ impl Default for RateLimiterConfig {
     }
 }
 
+impl RateLimiterConfig {
+    /// Thresholds from the `[host]` section of the config
+    pub fn from_config(config: &HostConfig) -> Self {
+        Self {
+            max_failures: config.max_failed_attempts,
+            window: Duration::from_secs(config.failed_attempt_window_secs),
+            block_duration: Duration::from_secs(config.block_secs),
+        }
+    }
+}
+
 /// Entry tracking failures for a single IP
 #[derive(Debug)]
 struct IpEntry {
@@ -103,7 +119,7 @@ impl IpEntry {
 /// Rate limiter for protecting against brute-force attacks
 #[derive(Clone)]
 pub struct RateLimiter {
-    config: RateLimiterConfig,
+    config: Arc<RwLock<RateLimiterConfig>>,
     entries: Arc<RwLock<HashMap<IpAddr, IpEntry>>>,
 }
 
@@ -116,11 +132,16 @@ impl RateLimiter {
     /// Create a new rate limiter with custom configuration
     pub fn with_config(config: RateLimiterConfig) -> Self {
         Self {
-            config,
+            config: Arc::new(RwLock::new(config)),
             entries: Arc::new(RwLock::new(HashMap::new())),
         }
     }
 
+    /// Change the thresholds; blocks already in place run their course
+    pub fn set_config(&self, config: RateLimiterConfig) {
+        *self.config.write() = config;
+    }
+
     /// Check if a connection from this IP is allowed
     ///
     /// Returns `true` if allowed, `false` if rate limited.
@@ -147,11 +168,12 @@ impl RateLimiter {
     ///
     /// Returns `true` if the IP is now blocked as a result.
     pub fn record_failure(&self, ip: IpAddr) -> bool {
+        let config = self.config.read().clone();
         let mut entries = self.entries.write();
         let entry = entries.entry(ip).or_insert_with(IpEntry::new);
 
         // Clean up old failures
-        entry.cleanup(self.config.window);
+        entry.cleanup(config.window);
 
         // If already blocked, extend block
         if entry.is_blocked() {
@@ -162,10 +184,10 @@ impl RateLimiter {
         entry.failures.push(Instant::now());
 
         // Check if we should block
-        if entry.failures.len() as u32 >= self.config.max_failures {
+        if entry.failures.len() as u32 >= config.max_failures {
             // Calculate block duration with exponential backoff
             let multiplier = 2u64.pow(entry.block_count.min(6)); // Cap at 2^6 = 64x
-            let block_duration = self.config.block_duration * multiplier as u32;
+            let block_duration = config.block_duration * multiplier as u32;
             let capped_duration = block_duration.min(Duration::from_secs(MAX_BLOCK_DURATION_SECS));
 
             entry.blocked_until = Some(Instant::now() + capped_duration);
@@ -177,7 +199,7 @@ impl RateLimiter {
                 ip,
                 capped_duration.as_secs(),
                 entry.block_count,
-                self.config.max_failures
+                config.max_failures
             );
 
             true
@@ -186,7 +208,7 @@ impl RateLimiter {
                 "Rate limiter: recorded failure for {} ({}/{})",
                 ip,
                 entry.failures.len(),
-                self.config.max_failures
+                config.max_failures
             );
             false
         }
@@ -200,12 +222,13 @@ impl RateLimiter {
 
     /// Clean up expired entries to prevent memory growth
     pub fn cleanup_expired(&self) {
+        let window = self.config.read().window;
         let mut entries = self.entries.write();
         let now = Instant::now();
 
         entries.retain(|ip, entry| {
             // Remove entries that are not blocked and have no recent failures
-            entry.cleanup(self.config.window);
+            entry.cleanup(window);
             let should_keep =
                 entry.is_blocked() || !entry.failures.is_empty() || entry.block_count > 0;
 
@@ -335,4 +358,18 @@ mod tests {
         assert!(limiter.check(ip2));
         assert!(!limiter.check(ip1));
     }
+
+    #[test]
+    fn test_set_config_applies_to_clones() {
+        let limiter = RateLimiter::new();
+        let shared = limiter.clone();
+        let ip = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1));
+
+        limiter.set_config(RateLimiterConfig {
+            max_failures: 1,
+            ..RateLimiterConfig::default()
+        });
+        assert!(shared.record_failure(ip));
+        assert!(!limiter.check(ip));
+    }
 }
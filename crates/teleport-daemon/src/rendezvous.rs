@@ -353,7 +353,7 @@ impl RendezvousClient {
 }
 
 /// Detect local network addresses
-fn detect_local_addresses() -> Vec<SocketAddr> {
+pub(crate) fn detect_local_addresses() -> Vec<SocketAddr> {
     let mut addrs = Vec::new();
 
     // Try to detect local IP by creating a UDP socket
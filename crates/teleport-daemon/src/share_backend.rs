@@ -0,0 +1,793 @@
+//! Share storage and request dispatch
+//!
+//! Hosts take care of the connection: handshake, access checks, limits,
+//! bandwidth and auditing. Everything a request does to the shared files goes
+//! through a `ShareBackend`, and `dispatch` turns requests into backend calls
+//! and the results into responses, so every host supports every operation.
+//!
+//! `LocalBackend` (see `local_backend`) serves a directory on disk; other
+//! backends can serve a snapshot or an in-memory tree for tests. Chunk reads
+//! and writes of files on disk bypass the backend once it has opened them (see
+//! `file_handles`).
+
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use tracing::{debug, info, warn};
+
+use teleport_core::{
+    crypto::checksum,
+    io::{allocate, copy_range, punch_hole},
+    ChunkId, CopyRangeRequest, CopyRangeResponse, CreateDirResponse, CreateFileResponse,
+    DeleteDirResponse, DeleteFileResponse, DirEntry, ErrorCode, ErrorMessage, FallocateMode,
+    FallocateRequest, FallocateResponse, FileAttr, GetAttrResponse, Inode, ListDirRequest,
+    ListDirResponse, LockRequest, LockResponse, LockType, LookupResponse, NetMessage,
+    ReadChunkResponse, ReleaseRequest, ReleaseResponse, RenameResponse, SeekResponse, SeekWhence,
+    SetAttrResponse, TruncateResponse, WriteChunkRequest, WriteChunkResponse, MAX_COPY_RANGE,
+    MAX_LIST_DIR_ATTRS,
+};
+
+use crate::lock_manager::{LockError, LockManager};
+
+/// Result of a backend operation; errors are sent to the client as they are
+pub type BackendResult<T> = Result<T, ErrorMessage>;
+
+/// One page of a directory listing
+#[derive(Clone, Debug, Default)]
+pub struct DirPage {
+    pub entries: Vec<DirEntry>,
+    /// More entries follow this page
+    pub has_more: bool,
+}
+
+/// Contents of one chunk of a file
+#[derive(Clone, Debug, Default)]
+pub struct ChunkData {
+    pub data: Vec<u8>,
+    /// The chunk reaches the end of the file
+    pub is_final: bool,
+}
+
+/// Attribute changes requested by `set_attr` (`None` = unchanged)
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AttrChanges {
+    pub size: Option<u64>,
+    pub mode: Option<u32>,
+    pub mtime: Option<u64>,
+    pub atime: Option<u64>,
+}
+
+impl AttrChanges {
+    /// Whether anything is changed
+    pub fn is_empty(&self) -> bool {
+        self.size.is_none() && self.mode.is_none() && self.mtime.is_none() && self.atime.is_none()
+    }
+}
+
+/// Storage a share is served from
+///
+/// Inodes are assigned by the backend; `ROOT_INODE` is the share root. Names
+/// come straight from clients, so implementations must reject names that are
+/// not a single path component.
+pub trait ShareBackend: Send + Sync {
+    /// Attributes of `name` in `parent`, `None` if it doesn't exist
+    fn lookup(&self, parent: Inode, name: &str) -> BackendResult<Option<FileAttr>>;
+
+    /// Attributes of an inode, `None` if it no longer exists
+    fn getattr(&self, inode: Inode) -> BackendResult<Option<FileAttr>>;
+
+    /// Up to `limit` entries of a directory, starting at entry `offset`
+    fn list_dir(&self, inode: Inode, offset: u64, limit: u32) -> BackendResult<DirPage>;
+
+    /// Identifies the backend's inode numbering, which starts over when the
+    /// host restarts; inodes handed out under another epoch mean nothing here
+    fn epoch(&self) -> u64;
+
+    /// Read one chunk (`CHUNK_SIZE` bytes, less at the end of the file)
+    fn read_chunk(&self, chunk_id: ChunkId) -> BackendResult<ChunkData>;
+
+    /// Write data at the start of a chunk, returning the new file size if known
+    fn write_chunk(&self, chunk_id: ChunkId, data: &[u8]) -> BackendResult<Option<u64>>;
+
+    /// Create an empty file; fails if the name exists
+    fn create_file(&self, parent: Inode, name: &str, mode: u32) -> BackendResult<FileAttr>;
+
+    /// Create a directory; fails if the name exists
+    fn create_dir(&self, parent: Inode, name: &str, mode: u32) -> BackendResult<FileAttr>;
+
+    fn delete_file(&self, parent: Inode, name: &str) -> BackendResult<()>;
+
+    /// Delete an empty directory
+    fn delete_dir(&self, parent: Inode, name: &str) -> BackendResult<()>;
+
+    fn rename(
+        &self,
+        old_parent: Inode,
+        old_name: &str,
+        new_parent: Inode,
+        new_name: &str,
+    ) -> BackendResult<()>;
+
+    /// Change size, permissions or timestamps, returning the new attributes
+    fn set_attr(&self, inode: Inode, changes: AttrChanges) -> BackendResult<FileAttr>;
+
+    /// `/`-separated path of an inode (or of `name` inside it) relative to the share root
+    fn display_path(&self, inode: Inode, name: Option<&str>) -> Option<String>;
+
+    /// Where the contents of an inode are on the local disk, `None` if they aren't
+    ///
+    /// Hosts read and write such files directly, keeping them open between
+    /// requests until this no longer names the path they were opened at.
+    fn local_path(&self, _inode: Inode) -> Option<PathBuf> {
+        None
+    }
+
+    /// Open the file behind an inode for direct reads (and writes, if `write`)
+    ///
+    /// Must check the file as `read_chunk` and `write_chunk` do.
+    fn open_file(&self, inode: Inode, _write: bool) -> BackendResult<File> {
+        Err(backend_error(
+            ErrorCode::NotImplemented,
+            "file contents are not on disk",
+            inode,
+        ))
+    }
+
+    /// Copy up to `len` bytes between files, returning the bytes copied and the new size of `dst`
+    ///
+    /// Copies fewer bytes only where `src` ends. The default copies between the
+    /// files `open_file` opens, in the kernel where the platform can.
+    fn copy_range(
+        &self,
+        src: Inode,
+        src_offset: u64,
+        dst: Inode,
+        dst_offset: u64,
+        len: u64,
+    ) -> BackendResult<(u64, Option<u64>)> {
+        let src_file = self.open_file(src, false)?;
+        let dst_file = self.open_file(dst, true)?;
+        let copied = copy_range(&src_file, src_offset, &dst_file, dst_offset, len)
+            .map_err(|e| io_error(&e, dst))?;
+        Ok((copied, dst_file.metadata().map(|m| m.len()).ok()))
+    }
+
+    /// Start of the data or hole at or after `offset`, `None` if there is none
+    ///
+    /// The default knows of no holes but the one at the end of the file.
+    fn seek(&self, inode: Inode, offset: u64, whence: SeekWhence) -> BackendResult<Option<u64>> {
+        let size = self
+            .getattr(inode)?
+            .ok_or_else(|| backend_error(ErrorCode::FileNotFound, "file not found", inode))?
+            .size;
+        if offset >= size {
+            return Ok(None);
+        }
+        Ok(Some(match whence {
+            SeekWhence::Data => offset,
+            SeekWhence::Hole => size,
+        }))
+    }
+
+    /// Allocate or deallocate `len` bytes at `offset`, returning the new attributes
+    ///
+    /// The default changes the file `open_file` opens.
+    fn fallocate(
+        &self,
+        inode: Inode,
+        offset: u64,
+        len: u64,
+        mode: FallocateMode,
+    ) -> BackendResult<FileAttr> {
+        let file = self.open_file(inode, true)?;
+        match mode {
+            FallocateMode::Allocate => allocate(&file, offset, len, false),
+            FallocateMode::AllocateKeepSize => allocate(&file, offset, len, true),
+            FallocateMode::PunchHole => punch_hole(&file, offset, len),
+        }
+        .map_err(|e| io_error(&e, inode))?;
+        self.getattr(inode)?
+            .ok_or_else(|| backend_error(ErrorCode::FileNotFound, "file not found", inode))
+    }
+
+    /// Create an empty file beside an inode's file, for a write session to fill
+    ///
+    /// Returns where it was created; `commit_staged` moves it over the inode's
+    /// file. Staged files must be hidden from clients.
+    fn stage_file(&self, inode: Inode) -> BackendResult<(PathBuf, File)> {
+        Err(backend_error(
+            ErrorCode::NotImplemented,
+            "write sessions need files on disk",
+            inode,
+        ))
+    }
+
+    /// Atomically replace an inode's file with a file made by `stage_file`
+    fn commit_staged(&self, inode: Inode, _staged: &Path) -> BackendResult<FileAttr> {
+        Err(backend_error(
+            ErrorCode::NotImplemented,
+            "write sessions need files on disk",
+            inode,
+        ))
+    }
+
+    /// Periodic housekeeping, called every few seconds while serving
+    fn maintain(&self) {}
+}
+
+/// Epoch for a backend whose inode numbering starts afresh
+pub fn new_epoch() -> u64 {
+    let mut bytes = [0u8; 8];
+    getrandom::getrandom(&mut bytes).expect("RNG failed - system entropy source unavailable");
+    u64::from_le_bytes(bytes)
+}
+
+/// Error reply about `inode`
+pub fn backend_error(code: ErrorCode, message: impl Into<String>, inode: Inode) -> ErrorMessage {
+    ErrorMessage {
+        code,
+        message: message.into(),
+        related_inode: Some(inode),
+    }
+}
+
+/// Error reply for a failed file system call on `inode`
+pub fn io_error(e: &io::Error, inode: Inode) -> ErrorMessage {
+    let code = match e.kind() {
+        io::ErrorKind::NotFound => ErrorCode::FileNotFound,
+        io::ErrorKind::PermissionDenied => ErrorCode::PermissionDenied,
+        io::ErrorKind::AlreadyExists => ErrorCode::AlreadyExists,
+        _ => ErrorCode::IoError,
+    };
+    backend_error(code, e.to_string(), inode)
+}
+
+/// Serve a request from `backend`
+///
+/// Access checks happen before this; lock tokens and checksums are verified
+/// here. Requests that are not file operations get `NotImplemented`.
+pub fn dispatch(
+    request: NetMessage,
+    backend: &dyn ShareBackend,
+    lock_manager: &LockManager,
+    holder_id: &str,
+) -> NetMessage {
+    let result = match request {
+        NetMessage::Lookup(req) => backend
+            .lookup(req.parent, &req.name)
+            .map(|attr| NetMessage::LookupResponse(LookupResponse { attr })),
+        NetMessage::GetAttr(req) => backend
+            .getattr(req.inode)
+            .map(|attr| NetMessage::GetAttrResponse(GetAttrResponse { attr })),
+        NetMessage::ListDir(req) => list_dir(&req, backend),
+        NetMessage::ReadChunk(req) => backend
+            .read_chunk(req.chunk_id)
+            .map(|chunk| read_response(req.chunk_id, chunk)),
+        NetMessage::WriteChunk(req) => check_write(&req, lock_manager)
+            .and_then(|()| backend.write_chunk(req.chunk_id, &req.data))
+            .map(|new_size| write_response(req.chunk_id, req.data.len(), new_size)),
+        NetMessage::CopyRange(req) => check_copy(&req, lock_manager)
+            .and_then(|len| {
+                backend.copy_range(
+                    req.src_inode,
+                    req.src_offset,
+                    req.dst_inode,
+                    req.dst_offset,
+                    len,
+                )
+            })
+            .map(|(copied, new_size)| copy_response(&req, copied, new_size)),
+        NetMessage::Seek(req) => backend
+            .seek(req.inode, req.offset, req.whence)
+            .map(|offset| NetMessage::SeekResponse(SeekResponse { offset })),
+        NetMessage::Fallocate(req) => check_fallocate(&req, lock_manager)
+            .and_then(|()| backend.fallocate(req.inode, req.offset, req.len, req.mode))
+            .map(|attr| {
+                info!(
+                    "Fallocate: inode={}, offset={}, len={}, mode={:?}",
+                    req.inode, req.offset, req.len, req.mode
+                );
+                NetMessage::FallocateResponse(FallocateResponse {
+                    success: true,
+                    attr: Some(attr),
+                    error: None,
+                })
+            }),
+        NetMessage::AcquireLock(req) => Ok(acquire_lock(req, lock_manager, holder_id)),
+        NetMessage::ReleaseLock(req) => Ok(release_lock(req, lock_manager)),
+        NetMessage::CreateFile(req) => validate_lock(
+            lock_manager,
+            req.parent,
+            req.lock_token.as_ref(),
+            "Invalid lock token for parent directory",
+        )
+        .and_then(|()| backend.create_file(req.parent, &req.name, req.mode))
+        .map(|attr| {
+            info!("Created file: {:?} (inode {})", req.name, attr.inode);
+            NetMessage::CreateFileResponse(CreateFileResponse {
+                success: true,
+                attr: Some(attr),
+                error: None,
+            })
+        }),
+        NetMessage::DeleteFile(req) => validate_child_lock(
+            backend,
+            lock_manager,
+            req.parent,
+            &req.name,
+            req.lock_token.as_ref(),
+            "Invalid lock token for file",
+        )
+        .and_then(|()| backend.delete_file(req.parent, &req.name))
+        .map(|()| {
+            info!("Deleted file: {:?}", req.name);
+            NetMessage::DeleteFileResponse(DeleteFileResponse {
+                success: true,
+                error: None,
+            })
+        }),
+        NetMessage::CreateDir(req) => {
+            backend
+                .create_dir(req.parent, &req.name, req.mode)
+                .map(|attr| {
+                    info!("Created directory: {:?} (inode {})", req.name, attr.inode);
+                    NetMessage::CreateDirResponse(CreateDirResponse {
+                        success: true,
+                        attr: Some(attr),
+                        error: None,
+                    })
+                })
+        }
+        NetMessage::DeleteDir(req) => backend.delete_dir(req.parent, &req.name).map(|()| {
+            info!("Deleted directory: {:?}", req.name);
+            NetMessage::DeleteDirResponse(DeleteDirResponse {
+                success: true,
+                error: None,
+            })
+        }),
+        NetMessage::Rename(req) => validate_child_lock(
+            backend,
+            lock_manager,
+            req.old_parent,
+            &req.old_name,
+            req.lock_token.as_ref(),
+            "Invalid lock token for source file",
+        )
+        .and_then(|()| backend.rename(req.old_parent, &req.old_name, req.new_parent, &req.new_name))
+        .map(|()| {
+            info!("Renamed {:?} to {:?}", req.old_name, req.new_name);
+            NetMessage::RenameResponse(RenameResponse {
+                success: true,
+                error: None,
+            })
+        }),
+        NetMessage::Truncate(req) => validate_lock(
+            lock_manager,
+            req.inode,
+            req.lock_token.as_ref(),
+            "Invalid lock token for truncate",
+        )
+        .and_then(|()| {
+            let changes = AttrChanges {
+                size: Some(req.size),
+                ..Default::default()
+            };
+            backend.set_attr(req.inode, changes)
+        })
+        .map(|attr| {
+            info!("Truncated inode {} to {} bytes", req.inode, req.size);
+            NetMessage::TruncateResponse(TruncateResponse {
+                success: true,
+                new_attr: Some(attr),
+                error: None,
+            })
+        }),
+        NetMessage::SetAttr(req) => {
+            let changes = AttrChanges {
+                size: req.size,
+                mode: req.mode,
+                mtime: req.mtime,
+                atime: req.atime,
+            };
+            // SECURITY: Modifications need the file's lock, if one is presented
+            let token = req.lock_token.as_ref().filter(|_| !changes.is_empty());
+            validate_lock(
+                lock_manager,
+                req.inode,
+                token,
+                "Invalid lock token for setattr",
+            )
+            .and_then(|()| backend.set_attr(req.inode, changes))
+            .map(|attr| {
+                info!("Updated attributes for inode {}", req.inode);
+                NetMessage::SetAttrResponse(SetAttrResponse {
+                    success: true,
+                    attr: Some(attr),
+                    error: None,
+                })
+            })
+        }
+        NetMessage::Ping(p) => Ok(NetMessage::Pong(teleport_core::PongMessage {
+            client_timestamp: p.timestamp,
+            // Safe conversion: millis since epoch won't overflow u64 until year 584 million,
+            // but we use min() for safety against edge cases
+            server_timestamp: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_millis().min(u64::MAX as u128) as u64)
+                .unwrap_or(0),
+            payload: p.payload,
+        })),
+        _ => Err(ErrorMessage {
+            code: ErrorCode::NotImplemented,
+            message: "request type not implemented".into(),
+            related_inode: None,
+        }),
+    };
+
+    result.unwrap_or_else(NetMessage::Error)
+}
+
+/// List a directory, with the attributes of its entries if they were asked for
+fn list_dir(req: &ListDirRequest, backend: &dyn ShareBackend) -> BackendResult<NetMessage> {
+    let limit = if req.with_attrs {
+        req.limit.min(MAX_LIST_DIR_ATTRS)
+    } else {
+        req.limit
+    };
+    let page = backend.list_dir(req.inode, req.offset, limit)?;
+    // An entry that can't be stat-ed is left for the client to look up
+    // rather than failing the whole listing
+    let attrs = if req.with_attrs {
+        page.entries
+            .iter()
+            .map(|entry| backend.getattr(entry.inode).ok().flatten())
+            .collect()
+    } else {
+        Vec::new()
+    };
+    Ok(NetMessage::ListDirResponse(ListDirResponse {
+        next_offset: req.offset + page.entries.len() as u64,
+        entries: page.entries,
+        has_more: page.has_more,
+        attrs,
+    }))
+}
+
+/// Response carrying a chunk read from a backend
+///
+/// Chunks of zeros, such as the holes of sparse files, are sent without their
+/// data (see `ReadChunkResponse::zeroed`).
+pub fn read_response(chunk_id: ChunkId, chunk: ChunkData) -> NetMessage {
+    let checksum = checksum(&chunk.data);
+    let zeroed = (!chunk.data.is_empty() && chunk.data.iter().all(|&b| b == 0))
+        .then_some(chunk.data.len() as u32);
+    NetMessage::ReadChunkResponse(ReadChunkResponse {
+        chunk_id,
+        checksum,
+        data: if zeroed.is_some() {
+            Vec::new()
+        } else {
+            chunk.data
+        },
+        is_final: chunk.is_final,
+        zeroed,
+    })
+}
+
+/// SECURITY: A chunk write needs the file's lock and data matching its checksum
+pub fn check_write(req: &WriteChunkRequest, lock_manager: &LockManager) -> BackendResult<()> {
+    let inode = req.chunk_id.inode;
+    validate_lock(
+        lock_manager,
+        inode,
+        Some(&req.lock_token),
+        "Invalid or expired lock token",
+    )?;
+    if checksum(&req.data) != req.checksum {
+        return Err(backend_error(
+            ErrorCode::ChecksumMismatch,
+            "Data checksum mismatch",
+            inode,
+        ));
+    }
+    Ok(())
+}
+
+/// Response to a chunk write of `len` bytes
+pub fn write_response(chunk_id: ChunkId, len: usize, new_size: Option<u64>) -> NetMessage {
+    info!(
+        "Write chunk: inode={}, offset={}, size={}",
+        chunk_id.inode,
+        chunk_id.byte_offset(),
+        len
+    );
+    NetMessage::WriteChunkResponse(WriteChunkResponse {
+        chunk_id,
+        success: true,
+        new_size,
+    })
+}
+
+/// SECURITY: A copy needs the destination's lock; returns how much to copy
+pub fn check_copy(req: &CopyRangeRequest, lock_manager: &LockManager) -> BackendResult<u64> {
+    validate_lock(
+        lock_manager,
+        req.dst_inode,
+        Some(&req.lock_token),
+        "Invalid or expired lock token",
+    )?;
+    Ok(req.len.min(MAX_COPY_RANGE))
+}
+
+/// SECURITY: Allocating needs the file's lock, and a range file offsets can hold
+pub fn check_fallocate(req: &FallocateRequest, lock_manager: &LockManager) -> BackendResult<()> {
+    validate_lock(
+        lock_manager,
+        req.inode,
+        Some(&req.lock_token),
+        "Invalid or expired lock token",
+    )?;
+    match req.offset.checked_add(req.len) {
+        Some(end) if end <= i64::MAX as u64 => Ok(()),
+        _ => Err(backend_error(
+            ErrorCode::ChunkOutOfRange,
+            "range past the largest file offset",
+            req.inode,
+        )),
+    }
+}
+
+/// Response to a copy of `copied` bytes
+pub fn copy_response(req: &CopyRangeRequest, copied: u64, new_size: Option<u64>) -> NetMessage {
+    info!(
+        "Copy range: inode={}, offset={} -> inode={}, offset={}, size={}",
+        req.src_inode, req.src_offset, req.dst_inode, req.dst_offset, copied
+    );
+    NetMessage::CopyRangeResponse(CopyRangeResponse {
+        success: true,
+        copied,
+        new_size,
+    })
+}
+
+/// Whether a response reports success
+pub fn succeeded(response: &NetMessage) -> bool {
+    match response {
+        NetMessage::Error(_) => false,
+        NetMessage::WriteChunkResponse(r) => r.success,
+        NetMessage::CopyRangeResponse(r) => r.success,
+        NetMessage::FallocateResponse(r) => r.success,
+        NetMessage::CreateFileResponse(r) => r.success,
+        NetMessage::DeleteFileResponse(r) => r.success,
+        NetMessage::CreateDirResponse(r) => r.success,
+        NetMessage::DeleteDirResponse(r) => r.success,
+        NetMessage::RenameResponse(r) => r.success,
+        NetMessage::TruncateResponse(r) => r.success,
+        NetMessage::SetAttrResponse(r) => r.success,
+        _ => true,
+    }
+}
+
+/// SECURITY: A presented lock token must hold an exclusive lock on `inode`
+fn validate_lock(
+    lock_manager: &LockManager,
+    inode: Inode,
+    token: Option<&teleport_core::LockToken>,
+    message: &str,
+) -> BackendResult<()> {
+    match token {
+        Some(token) if !lock_manager.validate(inode, token, LockType::Exclusive) => {
+            Err(backend_error(ErrorCode::LockRequired, message, inode))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Like `validate_lock`, for the entry `name` in `parent`
+fn validate_child_lock(
+    backend: &dyn ShareBackend,
+    lock_manager: &LockManager,
+    parent: Inode,
+    name: &str,
+    token: Option<&teleport_core::LockToken>,
+    message: &str,
+) -> BackendResult<()> {
+    if token.is_none() {
+        return Ok(());
+    }
+    match backend.lookup(parent, name) {
+        Ok(Some(attr)) => validate_lock(lock_manager, attr.inode, token, message),
+        // Nothing to lock; the operation itself reports the problem
+        _ => Ok(()),
+    }
+}
+
+fn acquire_lock(req: LockRequest, lock_manager: &LockManager, holder_id: &str) -> NetMessage {
+    let timeout = if req.timeout_ms > 0 {
+        Some(Duration::from_millis(req.timeout_ms as u64))
+    } else {
+        None
+    };
+
+    match lock_manager.acquire(req.inode, req.lock_type, holder_id, timeout) {
+        Ok(token) => {
+            info!(
+                "Lock acquired: inode={}, type={:?}, holder={}",
+                req.inode, req.lock_type, holder_id
+            );
+            NetMessage::AcquireLockResponse(LockResponse {
+                granted: true,
+                token: Some(token),
+                holder: None,
+                retry_after_ms: None,
+            })
+        }
+        Err(LockError::Conflict {
+            holder,
+            retry_after,
+            ..
+        }) => {
+            debug!("Lock conflict: inode={}, holder={:?}", req.inode, holder);
+            NetMessage::AcquireLockResponse(LockResponse {
+                granted: false,
+                token: None,
+                holder,
+                retry_after_ms: retry_after.map(|d| d.as_millis() as u32),
+            })
+        }
+        Err(LockError::TokenNotFound) => NetMessage::Error(ErrorMessage {
+            code: ErrorCode::LockRequired,
+            message: "Lock token not found".into(),
+            related_inode: Some(req.inode),
+        }),
+    }
+}
+
+fn release_lock(req: ReleaseRequest, lock_manager: &LockManager) -> NetMessage {
+    match lock_manager.release(&req.token) {
+        Ok(()) => {
+            info!("Lock released");
+            NetMessage::ReleaseLockResponse(ReleaseResponse { success: true })
+        }
+        Err(LockError::TokenNotFound) => {
+            warn!("Attempted to release non-existent lock");
+            NetMessage::ReleaseLockResponse(ReleaseResponse { success: false })
+        }
+        Err(_) => NetMessage::ReleaseLockResponse(ReleaseResponse { success: false }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::local_backend::LocalBackend;
+    use teleport_core::{
+        CreateFileRequest, DeleteFileRequest, RenameRequest, TruncateRequest, ROOT_INODE,
+    };
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_dispatch_file_lifecycle() {
+        let temp_dir = TempDir::new().unwrap();
+        let backend = LocalBackend::new(temp_dir.path());
+        let locks = LockManager::default();
+        let serve = |request| dispatch(request, &backend, &locks, "alice");
+
+        let inode = match serve(NetMessage::CreateFile(CreateFileRequest {
+            parent: ROOT_INODE,
+            name: "notes.txt".into(),
+            mode: 0o644,
+            lock_token: None,
+        })) {
+            NetMessage::CreateFileResponse(r) => r.attr.unwrap().inode,
+            other => panic!("Expected CreateFileResponse, got {:?}", other),
+        };
+
+        let token = match serve(NetMessage::AcquireLock(LockRequest {
+            inode,
+            lock_type: LockType::Exclusive,
+            timeout_ms: 0,
+        })) {
+            NetMessage::AcquireLockResponse(r) => r.token.unwrap(),
+            other => panic!("Expected AcquireLockResponse, got {:?}", other),
+        };
+        let data = b"hello".to_vec();
+        let write = serve(NetMessage::WriteChunk(WriteChunkRequest {
+            chunk_id: ChunkId::new(inode, 0),
+            checksum: checksum(&data),
+            data,
+            lock_token: token.clone(),
+        }));
+        assert!(succeeded(&write));
+
+        let truncate = serve(NetMessage::Truncate(TruncateRequest {
+            inode,
+            size: 2,
+            lock_token: Some(token),
+        }));
+        match truncate {
+            NetMessage::TruncateResponse(r) => assert_eq!(r.new_attr.unwrap().size, 2),
+            other => panic!("Expected TruncateResponse, got {:?}", other),
+        }
+
+        let rename = serve(NetMessage::Rename(RenameRequest {
+            old_parent: ROOT_INODE,
+            old_name: "notes.txt".into(),
+            new_parent: ROOT_INODE,
+            new_name: "renamed.txt".into(),
+            lock_token: None,
+        }));
+        assert!(succeeded(&rename));
+        assert_eq!(
+            std::fs::read(temp_dir.path().join("renamed.txt")).unwrap(),
+            b"he"
+        );
+
+        let delete = serve(NetMessage::DeleteFile(DeleteFileRequest {
+            parent: ROOT_INODE,
+            name: "renamed.txt".into(),
+            lock_token: None,
+        }));
+        assert!(succeeded(&delete));
+        assert!(!temp_dir.path().join("renamed.txt").exists());
+
+        // Deleting it again reports the missing file
+        let again = serve(NetMessage::DeleteFile(DeleteFileRequest {
+            parent: ROOT_INODE,
+            name: "renamed.txt".into(),
+            lock_token: None,
+        }));
+        assert!(matches!(
+            again,
+            NetMessage::Error(ErrorMessage {
+                code: ErrorCode::FileNotFound,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_dispatch_rejects_invalid_lock_token() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("file.txt"), b"data").unwrap();
+        let backend = LocalBackend::new(temp_dir.path());
+        let locks = LockManager::default();
+
+        let inode = backend
+            .lookup(ROOT_INODE, "file.txt")
+            .unwrap()
+            .unwrap()
+            .inode;
+        let other = backend.create_file(ROOT_INODE, "other.txt", 0o644).unwrap();
+        let token = locks
+            .acquire(other.inode, LockType::Exclusive, "alice", None)
+            .unwrap();
+
+        // A lock on another file doesn't allow writing this one
+        let data = b"oops".to_vec();
+        let write = dispatch(
+            NetMessage::WriteChunk(WriteChunkRequest {
+                chunk_id: ChunkId::new(inode, 0),
+                checksum: checksum(&data),
+                data,
+                lock_token: token,
+            }),
+            &backend,
+            &locks,
+            "alice",
+        );
+        assert!(matches!(
+            write,
+            NetMessage::Error(ErrorMessage {
+                code: ErrorCode::LockRequired,
+                ..
+            })
+        ));
+        assert_eq!(
+            std::fs::read(temp_dir.path().join("file.txt")).unwrap(),
+            b"data"
+        );
+    }
+}
@@ -0,0 +1,335 @@
+//! Include/exclude rules for hosted shares
+//!
+//! Rules use gitignore syntax and come from the command line (`--exclude`,
+//! `--include`) and from a `.wormholeignore` file at the share root, which is
+//! re-read whenever it changes:
+//!
+//! ```text
+//! # .wormholeignore
+//! .git/
+//! node_modules/
+//! .env*
+//! !.env.example
+//! /build
+//! ```
+//!
+//! A pattern without a slash matches a name at any depth; one with a leading
+//! or inner slash is anchored to the share root. A trailing slash matches
+//! directories only, `!` re-includes a previously excluded path, and the last
+//! matching rule wins. Everything below an excluded directory is excluded.
+//!
+//! Include patterns restrict which files are shared at all; directories are
+//! always traversable so matching files deeper in the tree stay reachable.
+//! The ignore file itself is never shared.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use glob::{MatchOptions, Pattern};
+use parking_lot::RwLock;
+use tracing::{info, warn};
+
+/// Name of the per-share ignore file
+pub const IGNORE_FILE: &str = ".wormholeignore";
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// A single gitignore-style pattern
+#[derive(Debug, Clone)]
+struct Rule {
+    pattern: Pattern,
+    /// `!pattern`: re-include
+    negated: bool,
+    /// `pattern/`: directories only
+    dir_only: bool,
+    /// Pattern contains a slash, so it matches the path from the root
+    anchored: bool,
+}
+
+impl Rule {
+    /// Parse one line; blank lines and comments yield `None`
+    fn parse(line: &str) -> Result<Option<Self>, FilterError> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(None);
+        }
+
+        let (negated, rest) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, rest) = match rest.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, rest),
+        };
+        let anchored = rest.contains('/');
+        let rest = rest.strip_prefix('/').unwrap_or(rest);
+        if rest.is_empty() {
+            return Ok(None);
+        }
+
+        let pattern =
+            Pattern::new(rest).map_err(|e| FilterError::InvalidPattern(line.into(), e.msg))?;
+        Ok(Some(Self {
+            pattern,
+            negated,
+            dir_only,
+            anchored,
+        }))
+    }
+
+    fn matches(&self, relative: &str, name: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let subject = if self.anchored { relative } else { name };
+        self.pattern.matches_with(subject, MATCH_OPTIONS)
+    }
+}
+
+fn parse_rules<S: AsRef<str>>(patterns: &[S]) -> Result<Vec<Rule>, FilterError> {
+    let mut rules = Vec::new();
+    for pattern in patterns {
+        rules.extend(Rule::parse(pattern.as_ref())?);
+    }
+    Ok(rules)
+}
+
+/// Rules loaded from the ignore file, with the modification time they were read at
+#[derive(Default)]
+struct IgnoreFile {
+    modified: Option<SystemTime>,
+    rules: Vec<Rule>,
+}
+
+/// Include/exclude rules for one share
+pub struct ShareFilter {
+    root: PathBuf,
+    include: Vec<Rule>,
+    exclude: Vec<Rule>,
+    ignore_file: RwLock<IgnoreFile>,
+}
+
+impl ShareFilter {
+    /// Filter for `root` using only its `.wormholeignore` file
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        let filter = Self {
+            root: root.into(),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            ignore_file: RwLock::new(IgnoreFile::default()),
+        };
+        filter.reload_if_changed();
+        filter
+    }
+
+    /// Filter for `root` with additional include and exclude patterns
+    ///
+    /// The ignore file is applied after the exclude patterns, so it can
+    /// re-include paths they exclude.
+    pub fn with_patterns<S: AsRef<str>>(
+        root: impl Into<PathBuf>,
+        include: &[S],
+        exclude: &[S],
+    ) -> Result<Self, FilterError> {
+        let mut filter = Self::new(root);
+        filter.include = parse_rules(include)?;
+        filter.exclude = parse_rules(exclude)?;
+        Ok(filter)
+    }
+
+    /// Path of the share's ignore file
+    pub fn ignore_file_path(&self) -> PathBuf {
+        self.root.join(IGNORE_FILE)
+    }
+
+    /// Re-read the ignore file if it was created, modified or removed
+    ///
+    /// Returns whether the rules changed. Invalid lines are logged and skipped.
+    pub fn reload_if_changed(&self) -> bool {
+        let path = self.ignore_file_path();
+        let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+        if modified == self.ignore_file.read().modified {
+            return false;
+        }
+
+        let contents = fs::read_to_string(&path).unwrap_or_default();
+        let rules: Vec<Rule> = contents
+            .lines()
+            .filter_map(|line| match Rule::parse(line) {
+                Ok(rule) => rule,
+                Err(e) => {
+                    warn!("{}: {}", path.display(), e);
+                    None
+                }
+            })
+            .collect();
+
+        info!("Loaded {} rule(s) from {}", rules.len(), path.display());
+        *self.ignore_file.write() = IgnoreFile { modified, rules };
+        true
+    }
+
+    /// Whether `path` (absolute, inside the share) is hidden from clients
+    pub fn is_excluded(&self, path: &Path, is_dir: bool) -> bool {
+        self.excludes(path, || is_dir)
+    }
+
+    /// Like `is_excluded`, looking up whether the path is a directory only if a rule needs it
+    pub fn is_excluded_path(&self, path: &Path) -> bool {
+        self.excludes(path, || path.is_dir())
+    }
+
+    fn excludes(&self, path: &Path, is_dir: impl Fn() -> bool) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return false;
+        };
+        if relative.as_os_str().is_empty() {
+            return false;
+        }
+        if relative == Path::new(IGNORE_FILE) {
+            return true;
+        }
+
+        let ignore_file = self.ignore_file.read();
+        if self.include.is_empty() && self.exclude.is_empty() && ignore_file.rules.is_empty() {
+            return false;
+        }
+
+        let components: Vec<String> = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        let last = components.len() - 1;
+
+        // Every ancestor is a directory; an excluded one hides everything below it
+        let mut prefix = String::new();
+        for (i, name) in components.iter().enumerate() {
+            if i > 0 {
+                prefix.push('/');
+            }
+            prefix.push_str(name);
+
+            let is_leaf = i == last;
+            let rules = || self.exclude.iter().chain(&ignore_file.rules);
+            // Only look up the leaf's type when a rule depends on it
+            let dir = !is_leaf
+                || ((!self.include.is_empty()
+                    || rules().any(|r| r.dir_only && r.matches(&prefix, name, true)))
+                    && is_dir());
+
+            let excluded = rules()
+                .rev()
+                .find(|r| r.matches(&prefix, name, dir))
+                .is_some_and(|r| !r.negated);
+            if excluded {
+                return true;
+            }
+            if is_leaf && !dir && !self.include.is_empty() {
+                return !self.include.iter().any(|r| r.matches(&prefix, name, false));
+            }
+        }
+        false
+    }
+}
+
+/// Share filter errors
+#[derive(Debug, Clone)]
+pub enum FilterError {
+    /// Pattern could not be parsed
+    InvalidPattern(String, &'static str),
+}
+
+impl std::fmt::Display for FilterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FilterError::InvalidPattern(pattern, e) => {
+                write!(f, "Invalid pattern '{}': {}", pattern, e)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FilterError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_gitignore_semantics() {
+        let filter = ShareFilter::with_patterns(
+            "/share",
+            &[] as &[&str],
+            &[
+                ".git/",
+                "node_modules",
+                ".env*",
+                "!.env.example",
+                "/build",
+                "docs/*.tmp",
+            ],
+        )
+        .unwrap();
+        let excluded = |p: &str, is_dir| filter.is_excluded(Path::new(p), is_dir);
+
+        // Unanchored patterns match at any depth, and hide everything below
+        assert!(excluded("/share/.git", true));
+        assert!(excluded("/share/.git/config", false));
+        assert!(excluded("/share/web/node_modules/react/index.js", false));
+        // Directory-only rules don't match files
+        assert!(!excluded("/share/.git", false));
+        // Negation re-includes
+        assert!(excluded("/share/app/.env.local", false));
+        assert!(!excluded("/share/app/.env.example", false));
+        // Anchored patterns only match from the root
+        assert!(excluded("/share/build", true));
+        assert!(!excluded("/share/src/build", true));
+        assert!(excluded("/share/docs/draft.tmp", false));
+        assert!(!excluded("/share/docs/old/draft.tmp", false));
+
+        assert!(!excluded("/share", true));
+        assert!(!excluded("/elsewhere/.git", true));
+        assert!(excluded("/share/.wormholeignore", false));
+    }
+
+    #[test]
+    fn test_include_patterns_only_restrict_files() {
+        let filter =
+            ShareFilter::with_patterns("/share", &["*.jpg", "*.png"], &["thumbs"]).unwrap();
+        let excluded = |p: &str, is_dir| filter.is_excluded(Path::new(p), is_dir);
+
+        assert!(!excluded("/share/2024/trip/beach.jpg", false));
+        assert!(excluded("/share/2024/trip/notes.txt", false));
+        assert!(!excluded("/share/2024/trip", true));
+        assert!(excluded("/share/thumbs/beach.jpg", false));
+    }
+
+    #[test]
+    fn test_ignore_file_hot_reload() {
+        let dir = TempDir::new().unwrap();
+        let secret = dir.path().join("secret.key");
+        let filter = ShareFilter::new(dir.path());
+        assert!(!filter.is_excluded(&secret, false));
+
+        fs::write(filter.ignore_file_path(), "# keys\n*.key\n[invalid\n").unwrap();
+        assert!(filter.reload_if_changed());
+        assert!(filter.is_excluded(&secret, false));
+        assert!(!filter.reload_if_changed());
+
+        fs::remove_file(filter.ignore_file_path()).unwrap();
+        assert!(filter.reload_if_changed());
+        assert!(!filter.is_excluded(&secret, false));
+    }
+
+    #[test]
+    fn test_invalid_pattern() {
+        assert!(ShareFilter::with_patterns("/share", &["[oops"], &[]).is_err());
+    }
+}
@@ -0,0 +1,325 @@
+//! Expiry and usage limits for hosted shares
+//!
+//! A share can be given a lifetime, a maximum number of distinct clients and
+//! a budget of bytes served. Once the client limit is reached no new clients
+//! are admitted, although clients that already joined may reconnect. When the
+//! share expires or its byte budget is used up it is closed for good: every
+//! session ends with a `Goodbye` carrying `DisconnectReason::ShareExpired`.
+
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
+
+use parking_lot::Mutex;
+use tokio::sync::watch;
+use tracing::info;
+
+/// Limits for one share (all unlimited by default)
+#[derive(Clone, Debug, Default)]
+pub struct ShareLimits {
+    /// When the share closes
+    pub expires_at: Option<SystemTime>,
+    /// Number of distinct clients admitted
+    pub max_clients: Option<usize>,
+    /// Total share data served (chunks, listings, snapshots), across all clients
+    pub max_bytes: Option<u64>,
+}
+
+impl ShareLimits {
+    /// Close the share at `expires_at`
+    pub fn with_expiry(mut self, expires_at: SystemTime) -> Self {
+        self.expires_at = Some(expires_at);
+        self
+    }
+
+    /// Close the share `lifetime` from now
+    pub fn with_lifetime(self, lifetime: Duration) -> Self {
+        self.with_expiry(SystemTime::now() + lifetime)
+    }
+
+    /// Admit at most `max_clients` distinct clients
+    pub fn with_max_clients(mut self, max_clients: usize) -> Self {
+        self.max_clients = Some(max_clients);
+        self
+    }
+
+    /// Close the share once `max_bytes` of share data have been served
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Whether no limit is set
+    pub fn is_unlimited(&self) -> bool {
+        self.expires_at.is_none() && self.max_clients.is_none() && self.max_bytes.is_none()
+    }
+}
+
+/// Whether a share still accepts clients
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShareState {
+    /// Accepting new clients
+    Open,
+    /// Client limit reached; only clients that already joined are admitted
+    Full,
+    /// Expired or byte budget used up; all sessions are ended
+    Expired,
+}
+
+/// Usage of one share, checked against its limits
+pub struct ShareUsage {
+    limits: ShareLimits,
+    /// Identities of the clients admitted so far
+    clients: Mutex<HashSet<String>>,
+    bytes_served: AtomicU64,
+    state: watch::Sender<ShareState>,
+}
+
+impl ShareUsage {
+    pub fn new(limits: ShareLimits) -> Self {
+        let (state, _) = watch::channel(ShareState::Open);
+        Self {
+            limits,
+            clients: Mutex::new(HashSet::new()),
+            bytes_served: AtomicU64::new(0),
+            state,
+        }
+    }
+
+    /// Limits this usage is checked against
+    pub fn limits(&self) -> &ShareLimits {
+        &self.limits
+    }
+
+    /// Current state, expiring the share if its lifetime has passed
+    pub fn state(&self) -> ShareState {
+        if self.time_left() == Some(Duration::ZERO) {
+            self.expire();
+        }
+        *self.state.borrow()
+    }
+
+    /// Whether the share has closed for good
+    pub fn is_expired(&self) -> bool {
+        self.state() == ShareState::Expired
+    }
+
+    /// Number of distinct clients admitted so far
+    pub fn client_count(&self) -> usize {
+        self.clients.lock().len()
+    }
+
+    /// Bytes of share data served so far
+    pub fn bytes_served(&self) -> u64 {
+        self.bytes_served.load(Ordering::Relaxed)
+    }
+
+    /// Admit a client to the share
+    ///
+    /// Clients that were admitted before are always let back in while the
+    /// share has not expired.
+    pub fn admit(&self, client_id: &str) -> bool {
+        if self.is_expired() {
+            return false;
+        }
+        let mut clients = self.clients.lock();
+        if clients.contains(client_id) {
+            return true;
+        }
+        if self
+            .limits
+            .max_clients
+            .is_some_and(|max| clients.len() >= max)
+        {
+            return false;
+        }
+        clients.insert(client_id.to_string());
+
+        if self
+            .limits
+            .max_clients
+            .is_some_and(|max| clients.len() >= max)
+        {
+            info!("Share reached its limit of {} client(s)", clients.len());
+            self.state.send_if_modified(|state| {
+                let changed = *state == ShareState::Open;
+                if changed {
+                    *state = ShareState::Full;
+                }
+                changed
+            });
+        }
+        true
+    }
+
+    /// Count share data sent to a client, expiring the share once the budget is used up
+    ///
+    /// The chunk that crosses the budget is still delivered.
+    pub fn record_bytes(&self, bytes: u64) {
+        let total = self.bytes_served.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        if self.limits.max_bytes.is_some_and(|max| total >= max) {
+            if !self.is_expired() {
+                info!("Share used up its budget of {} bytes", total);
+            }
+            self.expire();
+        }
+    }
+
+    /// Close the share for good
+    pub fn expire(&self) {
+        self.state.send_if_modified(|state| {
+            let changed = *state != ShareState::Expired;
+            *state = ShareState::Expired;
+            changed
+        });
+    }
+
+    /// Completes once no new clients may join
+    pub async fn closed(&self) {
+        self.wait_for(|state| state != ShareState::Open).await
+    }
+
+    /// Completes once the share has expired
+    pub async fn expired(&self) {
+        self.wait_for(|state| state == ShareState::Expired).await
+    }
+
+    async fn wait_for(&self, done: impl Fn(ShareState) -> bool) {
+        let mut state = self.state.subscribe();
+        let deadline = async {
+            match self.time_left() {
+                Some(left) => tokio::time::sleep(left).await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            _ = state.wait_for(|s| done(*s)) => {}
+            _ = deadline => {
+                info!("Share expired");
+                self.expire();
+            }
+        }
+    }
+
+    fn time_left(&self) -> Option<Duration> {
+        self.limits.expires_at.map(|at| {
+            at.duration_since(SystemTime::now())
+                .unwrap_or(Duration::ZERO)
+        })
+    }
+}
+
+impl Default for ShareUsage {
+    fn default() -> Self {
+        Self::new(ShareLimits::default())
+    }
+}
+
+/// Split `"30m"` into `(30, "m")`
+fn split_number(s: &str) -> Option<(u64, &str)> {
+    let s = s.trim();
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let number = s[..end].parse().ok()?;
+    Some((number, s[end..].trim()))
+}
+
+/// Parse a duration such as `90s`, `30m`, `2h` or `7d`, for use as a clap value parser
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let error = || format!("invalid duration '{}' (expected e.g. 90s, 30m, 2h, 7d)", s);
+    let (number, unit) = split_number(s).ok_or_else(error)?;
+    let seconds = match unit {
+        "s" | "" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(error()),
+    };
+    number
+        .checked_mul(seconds)
+        .map(Duration::from_secs)
+        .ok_or_else(error)
+}
+
+/// Parse a byte size such as `500MB` or `2G` (binary units), for use as a clap value parser
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let error = || format!("invalid size '{}' (expected e.g. 512K, 500MB, 2G)", s);
+    let (number, unit) = split_number(s).ok_or_else(error)?;
+    let shift = match unit.to_ascii_uppercase().trim_end_matches(['B', 'I']) {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        "T" => 40,
+        _ => return Err(error()),
+    };
+    number.checked_mul(1 << shift).ok_or_else(error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_limit() {
+        let usage = ShareUsage::new(ShareLimits::default().with_max_clients(2));
+        assert!(usage.admit("alice"));
+        assert_eq!(usage.state(), ShareState::Open);
+        assert!(usage.admit("bob"));
+        assert_eq!(usage.state(), ShareState::Full);
+
+        assert!(!usage.admit("carol"));
+        // Returning clients are let back in
+        assert!(usage.admit("alice"));
+        assert_eq!(usage.client_count(), 2);
+    }
+
+    #[test]
+    fn test_byte_budget() {
+        let usage = ShareUsage::new(ShareLimits::default().with_max_bytes(100));
+        assert!(usage.admit("alice"));
+        usage.record_bytes(60);
+        assert!(!usage.is_expired());
+        usage.record_bytes(60);
+        assert!(usage.is_expired());
+        assert_eq!(usage.bytes_served(), 120);
+        assert!(!usage.admit("alice"));
+    }
+
+    #[test]
+    fn test_unlimited() {
+        let usage = ShareUsage::default();
+        assert!(usage.limits().is_unlimited());
+        for i in 0..100 {
+            assert!(usage.admit(&i.to_string()));
+        }
+        usage.record_bytes(u32::MAX as u64);
+        assert_eq!(usage.state(), ShareState::Open);
+    }
+
+    #[test]
+    fn test_parse_duration_and_size() {
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("30m"), Ok(Duration::from_secs(30 * 60)));
+        assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(2 * 60 * 60)));
+        assert!(parse_duration("2 weeks").is_err());
+        assert!(parse_duration("h").is_err());
+
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("500MB"), Ok(500 << 20));
+        assert_eq!(parse_size("2G"), Ok(2 << 30));
+        assert_eq!(parse_size("1KiB"), Ok(1024));
+        assert!(parse_size("lots").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_lifetime() {
+        let usage =
+            ShareUsage::new(ShareLimits::default().with_lifetime(Duration::from_millis(50)));
+        assert!(!usage.is_expired());
+        tokio::time::timeout(Duration::from_secs(2), usage.expired())
+            .await
+            .unwrap();
+        assert!(usage.is_expired());
+        assert!(!usage.admit("late"));
+    }
+}
@@ -224,6 +224,17 @@ impl SyncEngine {
         info!("Lock removed: inode={}", inode);
     }
 
+    /// Drop unsynced writes to an inode, keeping its lock
+    pub fn discard_dirty(&self, inode: Inode) {
+        let mut dirty = self.dirty_chunks.write();
+        let mut inodes = self.dirty_inodes.write();
+
+        dirty.retain(|id, _| id.inode != inode);
+        inodes.remove(&inode);
+
+        debug!("Discarded dirty chunks for inode {}", inode);
+    }
+
     /// Clear all dirty state for an inode (e.g., on close)
     pub fn clear_inode(&self, inode: Inode) {
         let mut dirty = self.dirty_chunks.write();
@@ -498,6 +509,26 @@ mod tests {
         assert!(!engine.has_lock(1, LockType::Exclusive));
     }
 
+    #[test]
+    fn test_discard_dirty_keeps_lock() {
+        let engine = SyncEngine::default();
+        let chunk = ChunkId::new(1, 0);
+
+        engine.mark_dirty(chunk, vec![1]);
+        engine.store_lock(
+            1,
+            LockToken::generate(),
+            LockType::Exclusive,
+            Duration::from_secs(30),
+        );
+
+        engine.discard_dirty(1);
+
+        assert!(!engine.is_dirty(&chunk));
+        assert!(engine.get_dirty_chunks_for_inode(1).is_empty());
+        assert!(engine.has_lock(1, LockType::Exclusive));
+    }
+
     #[test]
     fn test_sync_status() {
         let engine = SyncEngine::default();
@@ -0,0 +1,570 @@
+//! Tree snapshots: a subtree's metadata in a few round trips
+//!
+//! Browsing a share directory by directory costs a `ListDir` (and, without
+//! readdirplus, a `Lookup` per entry) for every directory opened, which over a
+//! slow link dominates opening a large project. A `TreeSnapshot` request has
+//! the host walk a subtree breadth first and send every directory in it with
+//! the attributes of its entries, compressed and a page at a time. Walks go
+//! through `ShareBackend::list_dir`, so excluded paths stay hidden. The host
+//! keeps each session's unfinished walks in `TreeWalks` and serves their
+//! pages on blocking threads (see `FileHandles`).
+//!
+//! Clients keep the result in a `TreeSnapshot`, which fills the attribute and
+//! directory caches, answers for the host while it is unreachable, and is
+//! saved so the next connection only asks for what changed since.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::io;
+use std::num::NonZeroUsize;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use lru::LruCache;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use teleport_core::{
+    DirEntry, ErrorCode, FileAttr, FileType, Inode, SmartCompressor, SnapshotMark, TreeDir,
+    TreeSnapshotRequest, TreeSnapshotResponse, MAX_TREE_SNAPSHOT_ENTRIES,
+};
+
+use crate::cache::HybridCacheManager;
+use crate::share_backend::{backend_error, BackendResult, ShareBackend};
+
+/// Walks a session keeps for continuing; starting another drops the oldest
+pub const MAX_TREE_WALKS: usize = 4;
+
+/// A tree snapshot in progress: the directories left to visit, breadth first
+///
+/// Every directory is listed once per walk, apart from the one that starts
+/// each page after the first. A page covers directories holding up to
+/// `MAX_TREE_SNAPSHOT_ENTRIES` entries between them, whether they are sent
+/// or, being unchanged, left out of an incremental snapshot.
+pub struct TreeWalk {
+    root: Inode,
+    max_depth: u32,
+    queue: VecDeque<(Inode, u32)>,
+    /// Only directories changed at or after this are sent
+    since: Option<u64>,
+    mark: SnapshotMark,
+}
+
+impl TreeWalk {
+    /// Start the walk `req` asks for
+    pub fn new(req: &TreeSnapshotRequest, backend: &dyn ShareBackend) -> Self {
+        let mark = SnapshotMark {
+            epoch: backend.epoch(),
+            taken_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+        };
+        // Inodes from another epoch can't be diffed against
+        let since = req
+            .since
+            .filter(|since| since.epoch == mark.epoch)
+            .map(|since| since.taken_at);
+        Self {
+            root: req.root,
+            max_depth: req.max_depth,
+            queue: VecDeque::from([(req.root, 0)]),
+            since,
+            mark,
+        }
+    }
+
+    /// List and stat the next page of directories
+    ///
+    /// Blocks on the backend for the whole page. The response's `next_cursor`
+    /// is left for the caller to fill in.
+    pub fn next_page(&mut self, backend: &dyn ShareBackend) -> BackendResult<TreeSnapshotResponse> {
+        let mut covered = 0;
+        let mut dirs = Vec::new();
+        let mut left_out = Vec::new();
+
+        while let Some((inode, depth)) = self.queue.pop_front() {
+            let page = match backend.list_dir(inode, 0, MAX_TREE_SNAPSHOT_ENTRIES) {
+                Ok(page) => page,
+                Err(e) if inode == self.root => return Err(e),
+                // Removed since its parent was listed
+                Err(_) => continue,
+            };
+            // Too large to send whole, so left for the client to list itself
+            if page.has_more {
+                left_out.push(inode);
+                continue;
+            }
+
+            let size = page.entries.len().max(1);
+            if covered > 0 && covered + size > MAX_TREE_SNAPSHOT_ENTRIES as usize {
+                self.queue.push_front((inode, depth));
+                break;
+            }
+            covered += size;
+            if depth < self.max_depth {
+                self.queue.extend(
+                    page.entries
+                        .iter()
+                        .filter(|entry| entry.file_type == FileType::Directory)
+                        .map(|entry| (entry.inode, depth + 1)),
+                );
+            }
+
+            let Some(attr) = backend.getattr(inode).ok().flatten() else {
+                continue;
+            };
+            let attrs: Vec<_> = page
+                .entries
+                .iter()
+                .map(|entry| backend.getattr(entry.inode).ok().flatten())
+                .collect();
+            if self
+                .since
+                .is_some_and(|since| !changed_since(since, &attr, &attrs))
+            {
+                continue;
+            }
+            dirs.push(TreeDir {
+                attr,
+                entries: page.entries,
+                attrs,
+            });
+        }
+
+        let packed = TreeDir::pack(&dirs)
+            .map_err(|e| backend_error(ErrorCode::IoError, e.to_string(), self.root))?;
+        Ok(TreeSnapshotResponse {
+            dirs: packed,
+            left_out,
+            has_more: !self.queue.is_empty(),
+            next_cursor: 0,
+            mark: self.mark,
+            incremental: self.since.is_some(),
+        })
+    }
+}
+
+/// A session's unfinished tree walks, by the cursor that continues each
+pub struct TreeWalks {
+    walks: Mutex<LruCache<u64, TreeWalk>>,
+}
+
+impl TreeWalks {
+    pub fn new() -> Self {
+        let capacity = NonZeroUsize::new(MAX_TREE_WALKS).expect("MAX_TREE_WALKS is not zero");
+        Self {
+            walks: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+
+    /// Take the walk `req.cursor` continues, or start one for cursor 0
+    pub fn take(
+        &self,
+        req: &TreeSnapshotRequest,
+        backend: &dyn ShareBackend,
+    ) -> BackendResult<TreeWalk> {
+        if req.cursor == 0 {
+            return Ok(TreeWalk::new(req, backend));
+        }
+        self.walks.lock().pop(&req.cursor).ok_or_else(|| {
+            backend_error(
+                ErrorCode::ProtocolError,
+                "unknown or expired tree snapshot cursor",
+                req.root,
+            )
+        })
+    }
+
+    /// Keep an unfinished walk, returning the cursor that continues it
+    pub fn put(&self, walk: TreeWalk) -> u64 {
+        let mut cursor = 0;
+        while cursor == 0 {
+            let mut bytes = [0u8; 8];
+            getrandom::getrandom(&mut bytes)
+                .expect("RNG failed - system entropy source unavailable");
+            cursor = u64::from_le_bytes(bytes);
+        }
+        self.walks.lock().put(cursor, walk);
+        cursor
+    }
+
+    /// Number of walks kept
+    pub fn len(&self) -> usize {
+        self.walks.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.walks.lock().is_empty()
+    }
+}
+
+impl Default for TreeWalks {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Whether a directory or any of its entries changed at or after `since`
+///
+/// Adding, removing or renaming entries changes the directory's mtime.
+/// Entries that couldn't be stat-ed count as changed.
+fn changed_since(since: u64, attr: &FileAttr, attrs: &[Option<FileAttr>]) -> bool {
+    let changed = |attr: &FileAttr| attr.mtime >= since || attr.ctime >= since;
+    changed(attr)
+        || attrs.iter().any(|attr| match attr {
+            Some(attr) => changed(attr),
+            None => true,
+        })
+}
+
+/// What a client knows of a share's tree, as of its last snapshot
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct TreeSnapshot {
+    /// Where the next refresh continues from; `None` until there has been one
+    mark: Option<SnapshotMark>,
+    dirs: HashMap<Inode, TreeDir>,
+    /// Attributes of every inode in `dirs`, rebuilt after changes
+    #[serde(skip)]
+    attrs: HashMap<Inode, FileAttr>,
+}
+
+impl TreeSnapshot {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load a snapshot saved by `save`; a missing file is an empty snapshot
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let packed = match fs::read(path) {
+            Ok(packed) => packed,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::new()),
+            Err(e) => return Err(e),
+        };
+        let raw = SmartCompressor::new().decompress(&packed)?;
+        let mut snapshot: Self = bincode::deserialize(&raw)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        snapshot.reindex();
+        Ok(snapshot)
+    }
+
+    /// Save the snapshot, replacing `path` only once it is written in full
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let raw = bincode::serialize(self).map_err(io::Error::other)?;
+        let packed = SmartCompressor::new().compress(&raw)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let partial = path.with_extension("partial");
+        fs::write(&partial, packed)?;
+        fs::rename(&partial, path)
+    }
+
+    /// Pass as `since` when refreshing
+    pub fn mark(&self) -> Option<SnapshotMark> {
+        self.mark
+    }
+
+    /// Number of directories in the snapshot
+    pub fn len(&self) -> usize {
+        self.dirs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dirs.is_empty()
+    }
+
+    /// Bring the snapshot up to date with the pages of one walk from `root`
+    ///
+    /// A full walk replaces the snapshot and an incremental one replaces the
+    /// directories it sent. Directories no longer reachable from `root` are
+    /// dropped.
+    pub fn update(
+        &mut self,
+        root: Inode,
+        mark: SnapshotMark,
+        incremental: bool,
+        dirs: Vec<TreeDir>,
+        left_out: &[Inode],
+    ) {
+        if !incremental {
+            self.dirs.clear();
+        }
+        for dir in dirs {
+            self.dirs.insert(dir.attr.inode, dir);
+        }
+        for inode in left_out {
+            self.dirs.remove(inode);
+        }
+        self.prune(root);
+        self.reindex();
+        self.mark = Some(mark);
+    }
+
+    /// Drop directories that can't be reached from `root`
+    fn prune(&mut self, root: Inode) {
+        let mut reachable = HashSet::new();
+        let mut queue = VecDeque::from([root]);
+        while let Some(inode) = queue.pop_front() {
+            let Some(dir) = self.dirs.get(&inode) else {
+                continue;
+            };
+            if reachable.insert(inode) {
+                queue.extend(
+                    dir.entries
+                        .iter()
+                        .filter(|entry| entry.file_type == FileType::Directory)
+                        .map(|entry| entry.inode),
+                );
+            }
+        }
+        self.dirs.retain(|inode, _| reachable.contains(inode));
+    }
+
+    fn reindex(&mut self) {
+        self.attrs.clear();
+        for dir in self.dirs.values() {
+            let entries = dir.attrs.iter().flatten();
+            for attr in std::iter::once(&dir.attr).chain(entries) {
+                self.attrs.insert(attr.inode, attr.clone());
+            }
+        }
+    }
+
+    /// Entries of the directory `inode`, if it is in the snapshot
+    pub fn entries(&self, inode: Inode) -> Option<&[DirEntry]> {
+        self.dirs.get(&inode).map(|dir| dir.entries.as_slice())
+    }
+
+    /// Attributes of `inode` as of the snapshot
+    pub fn attr(&self, inode: Inode) -> Option<&FileAttr> {
+        self.attrs.get(&inode)
+    }
+
+    /// Attributes of `name` in `parent`, `None` if the snapshot doesn't say
+    pub fn lookup(&self, parent: Inode, name: &str) -> Option<&FileAttr> {
+        let entries = self.entries(parent)?;
+        let entry = entries.iter().find(|entry| entry.name == name)?;
+        self.attr(entry.inode)
+    }
+
+    /// Prefill the client caches with every directory and attribute
+    pub fn fill(&self, cache: &HybridCacheManager) {
+        for (&inode, dir) in &self.dirs {
+            cache.dirs.insert(inode, dir.entries.clone());
+        }
+        for (&inode, attr) in &self.attrs {
+            cache.attrs.insert(inode, attr.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::local_backend::LocalBackend;
+    use crate::memory_backend::MemoryBackend;
+    use crate::share_filter::ShareFilter;
+    use std::time::Duration;
+    use teleport_core::ROOT_INODE;
+    use tempfile::TempDir;
+
+    /// Every page of one walk, and the snapshot they add up to
+    fn walk(
+        backend: &dyn ShareBackend,
+        snapshot: &mut TreeSnapshot,
+        max_depth: u32,
+    ) -> Vec<TreeSnapshotResponse> {
+        let req = TreeSnapshotRequest {
+            root: ROOT_INODE,
+            max_depth,
+            cursor: 0,
+            since: snapshot.mark(),
+        };
+        let mut tree = TreeWalk::new(&req, backend);
+        let mut pages: Vec<TreeSnapshotResponse> = Vec::new();
+        loop {
+            let page = tree.next_page(backend).unwrap();
+            let done = !page.has_more;
+            pages.push(page);
+            if done {
+                break;
+            }
+        }
+        let dirs = pages.iter().flat_map(|p| p.dirs().unwrap()).collect();
+        let left_out: Vec<_> = pages.iter().flat_map(|p| p.left_out.clone()).collect();
+        snapshot.update(
+            ROOT_INODE,
+            pages[0].mark,
+            pages[0].incremental,
+            dirs,
+            &left_out,
+        );
+        pages
+    }
+
+    #[test]
+    fn test_snapshot_pages_and_depth() {
+        let share = (0..3000).fold(MemoryBackend::new(), |share, i| {
+            share.with_file(&format!("src/m{}/{:04}.rs", i % 3, i), "fn main() {}")
+        });
+        let share = share.with_file("src/m0/deep/er/file.rs", "");
+
+        let mut snapshot = TreeSnapshot::new();
+        let pages = walk(&share, &mut snapshot, 3);
+        assert!(pages.len() > 1);
+        assert!(!pages[0].incremental);
+        // The root, src, m0..m2 and deep, but not er below deep
+        assert_eq!(snapshot.len(), 6);
+
+        let src = snapshot.lookup(ROOT_INODE, "src").unwrap().inode;
+        let m1 = snapshot.lookup(src, "m1").unwrap().inode;
+        assert_eq!(snapshot.entries(m1).unwrap().len(), 1000);
+        let file = snapshot.lookup(m1, "0001.rs").unwrap();
+        assert_eq!(file.size, 12);
+        let m0 = snapshot.lookup(src, "m0").unwrap().inode;
+        let deep = snapshot.lookup(m0, "deep").unwrap().inode;
+        let er = snapshot.lookup(deep, "er").unwrap().inode;
+        assert!(snapshot.entries(er).is_none());
+    }
+
+    #[test]
+    fn test_walks_continue_by_cursor() {
+        let share = (0..3000).fold(MemoryBackend::new(), |share, i| {
+            share.with_file(&format!("m{}/{:04}.rs", i % 3, i), "")
+        });
+        let walks = TreeWalks::new();
+        let mut req = TreeSnapshotRequest {
+            root: ROOT_INODE,
+            max_depth: 1,
+            cursor: 0,
+            since: None,
+        };
+
+        let mut pages = 0;
+        loop {
+            let mut tree = walks.take(&req, &share).unwrap();
+            let page = tree.next_page(&share).unwrap();
+            pages += 1;
+            if !page.has_more {
+                break;
+            }
+            req.cursor = walks.put(tree);
+        }
+        // The root and two of its 1000-entry directories fill the first page
+        assert_eq!(pages, 2);
+        assert!(walks.is_empty());
+
+        // Continuing a walk the host no longer has fails
+        req.cursor = 42;
+        let err = walks.take(&req, &share).err().unwrap();
+        assert_eq!(err.code, ErrorCode::ProtocolError);
+
+        // Only the newest few unfinished walks are kept
+        req.cursor = 0;
+        let cursors: Vec<_> = (0..=MAX_TREE_WALKS)
+            .map(|_| walks.put(walks.take(&req, &share).unwrap()))
+            .collect();
+        assert_eq!(walks.len(), MAX_TREE_WALKS);
+        req.cursor = cursors[0];
+        assert!(walks.take(&req, &share).is_err());
+    }
+
+    #[test]
+    fn test_large_and_excluded_dirs_are_left_out() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join(".git/objects")).unwrap();
+        fs::create_dir(root.join("big")).unwrap();
+        for i in 0..=MAX_TREE_SNAPSHOT_ENTRIES {
+            fs::write(root.join(format!("big/{}", i)), b"").unwrap();
+        }
+        fs::write(root.join("README.md"), b"hello").unwrap();
+        let filter = ShareFilter::with_patterns(root, &[] as &[&str], &[".git/"]).unwrap();
+        let backend = LocalBackend::with_filter(root, filter);
+
+        let mut snapshot = TreeSnapshot::new();
+        let pages = walk(&backend, &mut snapshot, 4);
+        let big = snapshot.lookup(ROOT_INODE, "big").unwrap().inode;
+        assert_eq!(pages[0].left_out, [big]);
+        assert!(snapshot.entries(big).is_none());
+        assert!(snapshot.lookup(ROOT_INODE, ".git").is_none());
+        assert_eq!(snapshot.len(), 1);
+    }
+
+    #[test]
+    fn test_incremental_snapshots() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::create_dir(root.join("c")).unwrap();
+        fs::write(root.join("a/b/notes.txt"), b"v1").unwrap();
+        let backend = LocalBackend::new(root);
+
+        let mut snapshot = TreeSnapshot::new();
+        walk(&backend, &mut snapshot, 4);
+        assert_eq!(snapshot.len(), 4);
+
+        // Pretend the snapshot is from a minute from now, so nothing changed since
+        let mut mark = snapshot.mark().unwrap();
+        mark.taken_at += 60;
+        snapshot.mark = Some(mark);
+        let pages = walk(&backend, &mut snapshot, 4);
+        assert!(pages[0].incremental);
+        assert!(pages[0].dirs().unwrap().is_empty());
+        assert_eq!(snapshot.len(), 4);
+
+        // Only the directory holding the changed file is sent
+        let later = SystemTime::now() + Duration::from_secs(3600);
+        let file = fs::File::options()
+            .write(true)
+            .open(root.join("a/b/notes.txt"))
+            .unwrap();
+        file.set_len(5).unwrap();
+        file.set_modified(later).unwrap();
+        snapshot.mark = Some(mark);
+        let pages = walk(&backend, &mut snapshot, 4);
+        let dirs = pages[0].dirs().unwrap();
+        assert_eq!(dirs.len(), 1);
+        assert_eq!(dirs[0].entries[0].name, "notes.txt");
+        let a = snapshot.lookup(ROOT_INODE, "a").unwrap().inode;
+        let b = snapshot.lookup(a, "b").unwrap().inode;
+        assert_eq!(snapshot.lookup(b, "notes.txt").unwrap().size, 5);
+
+        // Snapshots from another epoch are replaced
+        mark.epoch ^= 1;
+        snapshot.mark = Some(mark);
+        let pages = walk(&backend, &mut snapshot, 4);
+        assert!(!pages[0].incremental);
+        assert_eq!(snapshot.len(), 4);
+    }
+
+    #[test]
+    fn test_snapshot_prunes_and_persists() {
+        let share = MemoryBackend::new()
+            .with_file("a/one.txt", "1")
+            .with_file("b/two.txt", "22");
+        let mut snapshot = TreeSnapshot::new();
+        walk(&share, &mut snapshot, 4);
+        assert_eq!(snapshot.len(), 3);
+
+        // A root listing without `b` drops it, and everything under it
+        let mut root = snapshot.dirs[&ROOT_INODE].clone();
+        let b = snapshot.lookup(ROOT_INODE, "b").unwrap().inode;
+        root.attrs.retain(|attr| attr.as_ref().unwrap().inode != b);
+        root.entries.retain(|entry| entry.inode != b);
+        snapshot.update(ROOT_INODE, snapshot.mark().unwrap(), true, vec![root], &[]);
+        assert_eq!(snapshot.len(), 2);
+        assert!(snapshot.entries(b).is_none());
+        assert!(snapshot.attr(b).is_none());
+
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("snapshots/host.snap");
+        assert!(TreeSnapshot::load(&path).unwrap().is_empty());
+        snapshot.save(&path).unwrap();
+        let loaded = TreeSnapshot::load(&path).unwrap();
+        assert_eq!(loaded.mark(), snapshot.mark());
+        assert_eq!(loaded.len(), 2);
+        let a = loaded.lookup(ROOT_INODE, "a").unwrap().inode;
+        assert_eq!(loaded.lookup(a, "one.txt").unwrap().size, 1);
+    }
+}
@@ -122,6 +122,12 @@ impl WormholeWinFS {
         }
     }
 
+    /// Use `cache` instead of the default one
+    pub fn with_cache(mut self, cache: Arc<HybridCacheManager>) -> Self {
+        self.cache = cache;
+        self
+    }
+
     /// Get the sync engine
     pub fn sync_engine(&self) -> Arc<SyncEngine> {
         self.sync_engine.clone()
@@ -0,0 +1,185 @@
+//! Write sessions: chunk writes staged beside a file until they are committed
+//!
+//! Chunk writes normally land in the file as they arrive, so a client that
+//! disconnects halfway through saving leaves a file that is half old and half
+//! new. A client that opens a write session instead has its writes go to a
+//! file the backend staged beside the target (see `ShareBackend::stage_file`);
+//! committing syncs it and renames it over the target, so the target is either
+//! entirely old or entirely new. Sessions belong to a lock token and end with
+//! it: aborting, releasing or losing the lock, or disconnecting deletes the
+//! staged file.
+//!
+//! Mounts open a session when a file is opened with `O_TRUNC`, or emptied
+//! while its lock is held, and commit it when the file is flushed. In-place
+//! writes remain the default, for files like databases and logs that are
+//! updated rather than rewritten.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use tracing::{info, warn};
+
+use teleport_core::{Inode, LockToken, LockType};
+
+use crate::lock_manager::LockManager;
+
+/// Writes staged for one file
+pub struct WriteSession {
+    inode: Inode,
+    staged: PathBuf,
+    file: Arc<File>,
+    /// Set once the staged file has replaced the target
+    committed: bool,
+}
+
+impl WriteSession {
+    pub fn new(inode: Inode, staged: PathBuf, file: File) -> Self {
+        Self {
+            inode,
+            staged,
+            file: Arc::new(file),
+            committed: false,
+        }
+    }
+
+    pub fn inode(&self) -> Inode {
+        self.inode
+    }
+
+    /// Where the writes are staged
+    pub fn staged(&self) -> &Path {
+        &self.staged
+    }
+
+    pub fn file(&self) -> &Arc<File> {
+        &self.file
+    }
+
+    /// Keep the staged file once the session is dropped
+    pub fn mark_committed(&mut self) {
+        self.committed = true;
+    }
+}
+
+impl Drop for WriteSession {
+    fn drop(&mut self) {
+        if self.committed {
+            return;
+        }
+        match fs::remove_file(&self.staged) {
+            Ok(()) => info!("Discarded staged writes for inode {}", self.inode),
+            Err(e) => warn!(
+                "Failed to remove staged file {}: {}",
+                self.staged.display(),
+                e
+            ),
+        }
+    }
+}
+
+/// A client session's open write sessions, by lock token
+#[derive(Default)]
+pub struct WriteSessions {
+    sessions: Mutex<HashMap<LockToken, WriteSession>>,
+}
+
+impl WriteSessions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of open sessions
+    pub fn len(&self) -> usize {
+        self.sessions.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sessions.lock().is_empty()
+    }
+
+    /// Open a session, discarding any the token already had
+    pub fn insert(&self, token: LockToken, session: WriteSession) {
+        self.sessions.lock().insert(token, session);
+    }
+
+    /// File staging writes to `inode` made with `token`, if a session is open
+    pub fn file(&self, token: &LockToken, inode: Inode) -> Option<Arc<File>> {
+        self.sessions
+            .lock()
+            .get(token)
+            .filter(|session| session.inode == inode)
+            .map(|session| session.file.clone())
+    }
+
+    /// End the session `token` has for `inode`, handing it over to be committed
+    pub fn take(&self, token: &LockToken, inode: Inode) -> Option<WriteSession> {
+        let mut sessions = self.sessions.lock();
+        match sessions.get(token) {
+            Some(session) if session.inode == inode => sessions.remove(token),
+            _ => None,
+        }
+    }
+
+    /// Discard sessions whose lock was released or has expired
+    pub fn expire(&self, lock_manager: &LockManager) {
+        let mut sessions = self.sessions.lock();
+        if sessions.is_empty() {
+            return;
+        }
+        sessions.retain(|token, session| {
+            lock_manager.validate(session.inode, token, LockType::Exclusive)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_sessions_end_with_their_lock() {
+        let temp_dir = TempDir::new().unwrap();
+        let staged = temp_dir.path().join(".a.wormhole-partial");
+        let locks = LockManager::default();
+        let token = locks
+            .acquire(7, LockType::Exclusive, "alice", None)
+            .unwrap();
+        let sessions = WriteSessions::new();
+        sessions.insert(
+            token.clone(),
+            WriteSession::new(7, staged.clone(), File::create(&staged).unwrap()),
+        );
+
+        assert!(sessions.file(&token, 7).is_some());
+        assert!(sessions.file(&token, 8).is_none());
+        sessions.expire(&locks);
+        assert_eq!(sessions.len(), 1);
+
+        locks.release(&token).unwrap();
+        sessions.expire(&locks);
+        assert!(sessions.is_empty());
+        assert!(!staged.exists());
+    }
+
+    #[test]
+    fn test_committed_files_are_kept() {
+        let temp_dir = TempDir::new().unwrap();
+        let staged = temp_dir.path().join(".a.wormhole-partial");
+        let token = LockToken::generate();
+        let sessions = WriteSessions::new();
+        sessions.insert(
+            token.clone(),
+            WriteSession::new(7, staged.clone(), File::create(&staged).unwrap()),
+        );
+
+        assert!(sessions.take(&token, 8).is_none());
+        let mut session = sessions.take(&token, 7).unwrap();
+        session.mark_committed();
+        drop(session);
+        assert!(staged.exists());
+    }
+}
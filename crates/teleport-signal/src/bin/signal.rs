@@ -11,6 +11,9 @@
 //! # With SQLite persistence
 //! wormhole-signal --port 8080 --db /var/lib/wormhole/signal.db
 //! ```
+//!
+//! Flags override the `[signal]` section of the config file and the
+//! `WORMHOLE_SIGNAL_*` environment variables.
 
 use std::net::SocketAddr;
 use std::path::PathBuf;
@@ -19,24 +22,29 @@ use clap::Parser;
 use tracing::{info, Level};
 use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 
-use teleport_signal::{SignalServer, Storage, DEFAULT_PORT};
+use teleport_core::LayeredConfig;
+use teleport_signal::{SignalServer, Storage};
 
 #[derive(Parser, Debug)]
 #[command(name = "wormhole-signal")]
 #[command(about = "Wormhole signaling server for peer discovery")]
 #[command(version)]
 struct Args {
-    /// Port to listen on
-    #[arg(short, long, default_value_t = DEFAULT_PORT)]
-    port: u16,
+    /// Port to listen on [default: 8080]
+    #[arg(short, long)]
+    port: Option<u16>,
 
-    /// Bind address
-    #[arg(short, long, default_value = "0.0.0.0")]
-    bind: String,
+    /// Bind address [default: 0.0.0.0]
+    #[arg(short, long)]
+    bind: Option<String>,
 
     /// SQLite database path for persistence (optional, uses in-memory if not specified)
     #[arg(short, long)]
     db: Option<PathBuf>,
+
+    /// Configuration file path
+    #[arg(long, env = "WORMHOLE_CONFIG")]
+    config: Option<PathBuf>,
 }
 
 #[tokio::main]
@@ -53,13 +61,25 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let args = Args::parse();
 
-    let addr: SocketAddr = format!("{}:{}", args.bind, args.port).parse()?;
+    let mut settings = LayeredConfig::load(args.config.as_deref())?;
+    settings.apply_cli([
+        ("signal.port", args.port.map(|p| p.to_string())),
+        ("signal.bind", args.bind.clone()),
+        (
+            "signal.db_path",
+            args.db.as_ref().map(|p| p.display().to_string()),
+        ),
+    ])?;
+    settings.validate()?;
+    let config = &settings.config().signal;
+
+    let addr = SocketAddr::new(config.bind, config.port);
 
     info!("Starting Wormhole Signal Server");
     info!("Listening on {}", addr);
 
     // Initialize storage if database path provided
-    let storage = if let Some(db_path) = &args.db {
+    let storage = if let Some(db_path) = &config.db_path {
         // Create parent directory if it doesn't exist
         if let Some(parent) = db_path.parent() {
             std::fs::create_dir_all(parent)?;
@@ -81,7 +101,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         );
     }
 
-    let server = SignalServer::new();
+    let server = SignalServer::new()
+        .with_room_idle_timeout(config.room_idle_timeout_secs)
+        .with_max_peers_per_room(config.max_peers_per_room);
     server.serve(addr).await?;
 
     Ok(())
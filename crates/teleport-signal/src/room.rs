@@ -22,6 +22,9 @@ pub struct Room {
 
     /// Last activity time
     last_activity: Instant,
+
+    /// Most peers the room admits
+    max_peers: usize,
 }
 
 /// A peer in a room
@@ -40,12 +43,19 @@ impl Room {
             host_id: None,
             created_at: now,
             last_activity: now,
+            max_peers: crate::MAX_PEERS_PER_ROOM,
         }
     }
 
+    /// Admit at most `max_peers` peers instead of `MAX_PEERS_PER_ROOM`
+    pub fn with_max_peers(mut self, max_peers: usize) -> Self {
+        self.max_peers = max_peers;
+        self
+    }
+
     /// Add a peer to the room
     pub fn add_peer(&mut self, info: PeerInfo) -> Result<(), RoomError> {
-        if self.peers.len() >= crate::MAX_PEERS_PER_ROOM {
+        if self.peers.len() >= self.max_peers {
             return Err(RoomError::RoomFull);
         }
 
@@ -195,4 +205,15 @@ mod tests {
         let extra = make_peer("extra", false);
         assert!(matches!(room.add_peer(extra), Err(RoomError::RoomFull)));
     }
+
+    #[test]
+    fn test_room_max_peers() {
+        let mut room = Room::new("ABC-123".into()).with_max_peers(2);
+        room.add_peer(make_peer("host", true)).unwrap();
+        room.add_peer(make_peer("client", false)).unwrap();
+        assert!(matches!(
+            room.add_peer(make_peer("extra", false)),
+            Err(RoomError::RoomFull)
+        ));
+    }
 }
@@ -15,7 +15,7 @@ use teleport_core::crypto::{generate_join_code, normalize_join_code, validate_jo
 
 use crate::messages::{ErrorCode, PeerInfo, SignalMessage};
 use crate::room::Room;
-use crate::ROOM_IDLE_TIMEOUT_SECS;
+use crate::{MAX_PEERS_PER_ROOM, ROOM_IDLE_TIMEOUT_SECS};
 
 /// Channel for sending messages to a peer
 type PeerSender = mpsc::UnboundedSender<SignalMessage>;
@@ -28,6 +28,10 @@ pub struct SignalServer {
     peer_rooms: Arc<DashMap<String, String>>,
     /// Peer connections for message relay
     peer_senders: Arc<DashMap<String, PeerSender>>,
+    /// Seconds without activity before a room is removed
+    room_idle_timeout_secs: u64,
+    /// Most peers a room admits
+    max_peers_per_room: usize,
 }
 
 impl SignalServer {
@@ -36,9 +40,23 @@ impl SignalServer {
             rooms: Arc::new(DashMap::new()),
             peer_rooms: Arc::new(DashMap::new()),
             peer_senders: Arc::new(DashMap::new()),
+            room_idle_timeout_secs: ROOM_IDLE_TIMEOUT_SECS,
+            max_peers_per_room: MAX_PEERS_PER_ROOM,
         }
     }
 
+    /// Remove rooms after `secs` seconds without activity
+    pub fn with_room_idle_timeout(mut self, secs: u64) -> Self {
+        self.room_idle_timeout_secs = secs;
+        self
+    }
+
+    /// Admit at most `max_peers` peers to each room
+    pub fn with_max_peers_per_room(mut self, max_peers: usize) -> Self {
+        self.max_peers_per_room = max_peers;
+        self
+    }
+
     /// Start the signal server
     pub async fn serve(&self, addr: SocketAddr) -> Result<(), std::io::Error> {
         let listener = TcpListener::bind(addr).await?;
@@ -46,10 +64,11 @@ impl SignalServer {
 
         // Start room cleanup task
         let rooms = self.rooms.clone();
+        let idle_timeout_secs = self.room_idle_timeout_secs;
         tokio::spawn(async move {
             loop {
                 tokio::time::sleep(tokio::time::Duration::from_secs(60)).await;
-                cleanup_idle_rooms(&rooms);
+                cleanup_idle_rooms(&rooms, idle_timeout_secs);
             }
         });
 
@@ -58,12 +77,11 @@ impl SignalServer {
             let rooms = self.rooms.clone();
             let peer_rooms = self.peer_rooms.clone();
             let peer_senders = self.peer_senders.clone();
-            let room_count = self.rooms.len();
-            let peer_count = self.peer_rooms.len();
+            let max_peers = self.max_peers_per_room;
 
             tokio::spawn(async move {
                 if let Err(e) =
-                    handle_connection(stream, peer_addr, rooms, peer_rooms, peer_senders, room_count, peer_count)
+                    handle_connection(stream, peer_addr, rooms, peer_rooms, peer_senders, max_peers)
                         .await
                 {
                     debug!("Connection error from {}: {:?}", peer_addr, e);
@@ -96,8 +114,7 @@ async fn handle_connection(
     rooms: Arc<DashMap<String, Room>>,
     peer_rooms: Arc<DashMap<String, String>>,
     peer_senders: Arc<DashMap<String, PeerSender>>,
-    room_count: usize,
-    peer_count: usize,
+    max_peers: usize,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     // Peek at request to check if it's an HTTP health check or WebSocket upgrade
     // WebSocket upgrades include "Upgrade: websocket" header
@@ -107,7 +124,7 @@ async fn handle_connection(
 
     // Check for plain HTTP requests (health checks) - these don't have Upgrade header
     if peek_str.starts_with("GET ") && !peek_str.to_lowercase().contains("upgrade: websocket") {
-        return handle_http_request(&mut stream, room_count, peer_count).await;
+        return handle_http_request(&mut stream, rooms.len(), peer_rooms.len()).await;
     }
 
     let ws_stream = accept_async(stream).await?;
@@ -200,6 +217,7 @@ async fn handle_connection(
                     &rooms,
                     &peer_rooms,
                     &mut current_room,
+                    max_peers,
                 );
 
                 if let Some(response) = response {
@@ -282,6 +300,7 @@ fn handle_message(
     rooms: &DashMap<String, Room>,
     peer_rooms: &DashMap<String, String>,
     current_room: &mut Option<String>,
+    max_peers: usize,
 ) -> Option<SignalMessage> {
     match msg {
         SignalMessage::CreateRoom { join_code, peer_info } => {
@@ -306,7 +325,7 @@ fn handle_message(
                 ));
             }
 
-            let mut room = Room::new(code.clone());
+            let mut room = Room::new(code.clone()).with_max_peers(max_peers);
 
             // Use provided peer_info if available, otherwise create minimal info
             let info = if let Some(mut provided_info) = peer_info {
@@ -446,10 +465,10 @@ fn leave_room(
 }
 
 /// Cleanup idle rooms
-fn cleanup_idle_rooms(rooms: &DashMap<String, Room>) {
+fn cleanup_idle_rooms(rooms: &DashMap<String, Room>, idle_timeout_secs: u64) {
     let to_remove: Vec<String> = rooms
         .iter()
-        .filter(|r| r.is_idle(ROOM_IDLE_TIMEOUT_SECS))
+        .filter(|r| r.is_idle(idle_timeout_secs))
         .map(|r| r.join_code.clone())
         .collect();
 
@@ -0,0 +1,18 @@
+//! Bytes from any QUIC peer go straight into `deserialize_message`
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use teleport_core::{deserialize_message, serialize_message};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(message) = deserialize_message(data) else {
+        return;
+    };
+
+    // Anything we accept must survive a roundtrip unchanged
+    let bytes = serialize_message(&message).expect("decoded message must serialize");
+    let again = deserialize_message(&bytes[4..]).expect("serialized message must decode");
+    let bytes_again = serialize_message(&again).unwrap();
+    assert_eq!(bytes, bytes_again);
+});
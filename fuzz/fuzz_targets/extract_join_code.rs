@@ -0,0 +1,13 @@
+//! Join codes and share links are pasted in by users and arrive in deep links
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use teleport_core::crypto::extract_join_code;
+
+fuzz_target!(|input: &str| {
+    // An extracted code is already canonical
+    if let Some(code) = extract_join_code(input) {
+        assert_eq!(extract_join_code(&code).as_deref(), Some(code.as_str()));
+    }
+});
@@ -0,0 +1,22 @@
+//! Relative paths from clients are resolved against the share root by `safe_path`
+
+#![no_main]
+
+use std::path::{Component, Path};
+
+use libfuzzer_sys::fuzz_target;
+use teleport_core::path::safe_path;
+
+fuzz_target!(|relative: &str| {
+    let base = Path::new("/srv/share");
+    if let Ok(resolved) = safe_path(base, relative) {
+        assert!(
+            resolved.starts_with(base),
+            "{:?} escapes the base",
+            resolved
+        );
+        assert!(!resolved
+            .components()
+            .any(|c| matches!(c, Component::ParentDir | Component::CurDir)));
+    }
+});
@@ -0,0 +1,16 @@
+//! WebSocket text frames from any signal client go into `SignalMessage::from_json`
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use teleport_signal::messages::SignalMessage;
+
+fuzz_target!(|json: &str| {
+    let Ok(message) = SignalMessage::from_json(json) else {
+        return;
+    };
+
+    let encoded = message.to_json().expect("parsed message must serialize");
+    let again = SignalMessage::from_json(&encoded).expect("serialized message must parse");
+    assert_eq!(encoded, again.to_json().unwrap());
+});